@@ -0,0 +1,128 @@
+// RPC connection, signer and program account access shared by the commands
+use std::fmt::Write as _;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountDeserialize;
+use anyhow::{anyhow, Result};
+use matrix_client::rpc;
+use matrix_system::{ProgramState, UserAccount};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_request::{RpcError, RpcResponseErrorData};
+use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::{v0, VersionedMessage};
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+
+pub struct Client {
+    pub rpc: RpcClient,
+    pub payer: Keypair,
+    state: Option<Pubkey>,
+}
+
+impl Client {
+    pub fn new(url: String, commitment: CommitmentConfig, payer: Keypair, state: Option<Pubkey>) -> Self {
+        Self {
+            rpc: RpcClient::new_with_commitment(url, commitment),
+            payer,
+            state,
+        }
+    }
+
+    // Program state address, from --state or the config file
+    pub fn state_address(&self) -> Result<Pubkey> {
+        self.state.ok_or_else(|| anyhow!("No state account: pass --state or run `matrix-cli init` to write the config file"))
+    }
+
+    pub fn account(&self, address: &Pubkey) -> Result<Option<Account>> {
+        Ok(self.rpc.get_account_with_commitment(address, self.rpc.commitment())?.value)
+    }
+
+    // Function to fetch and deserialize an Anchor account, None if it doesn't exist
+    pub fn fetch<T: AccountDeserialize>(&self, address: &Pubkey) -> Result<Option<T>> {
+        Ok(rpc::fetch(&self.rpc, address)?)
+    }
+
+    pub fn program_state(&self) -> Result<(Pubkey, ProgramState)> {
+        let address = self.state_address()?;
+        Ok((address, rpc::program_state(&self.rpc, &address)?))
+    }
+
+    pub fn user_account(&self, wallet: &Pubkey) -> Result<UserAccount> {
+        Ok(rpc::user_account(&self.rpc, wallet)?)
+    }
+
+    pub fn donut_token_program(&self) -> Result<Pubkey> {
+        Ok(rpc::donut_token_program(&self.rpc)?)
+    }
+
+    pub fn lookup_table(&self, address: &Pubkey) -> Result<AddressLookupTableAccount> {
+        Ok(rpc::lookup_table(&self.rpc, address)?)
+    }
+
+    // Send instructions paid by the payer, with an optional compute unit limit
+    // A v0 message is used when lookup tables are given
+    pub fn send(
+        &self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        compute_unit_limit: Option<u32>,
+        lookup_tables: &[AddressLookupTableAccount],
+    ) -> Result<Signature> {
+        let mut all_instructions = Vec::with_capacity(instructions.len() + 1);
+        if let Some(limit) = compute_unit_limit {
+            all_instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(limit));
+        }
+        all_instructions.extend_from_slice(instructions);
+
+        let mut all_signers: Vec<&Keypair> = vec![&self.payer];
+        for signer in signers {
+            if signer.pubkey() != self.payer.pubkey() {
+                all_signers.push(signer);
+            }
+        }
+
+        let blockhash = self.rpc.get_latest_blockhash()?;
+
+        let result = if lookup_tables.is_empty() {
+            let transaction = Transaction::new_signed_with_payer(
+                &all_instructions,
+                Some(&self.payer.pubkey()),
+                &all_signers,
+                blockhash,
+            );
+            self.rpc.send_and_confirm_transaction_with_spinner(&transaction)
+        } else {
+            let message = v0::Message::try_compile(&self.payer.pubkey(), &all_instructions, lookup_tables, blockhash)?;
+            let transaction = VersionedTransaction::try_new(VersionedMessage::V0(message), &all_signers)?;
+            self.rpc.send_and_confirm_transaction_with_spinner(&transaction)
+        };
+
+        result.map_err(with_program_logs)
+    }
+}
+
+// Function to append the simulation logs of a failed preflight to the error
+fn with_program_logs(error: ClientError) -> anyhow::Error {
+    let logs = match error.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) => result.logs.clone().unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    if logs.is_empty() {
+        return error.into();
+    }
+
+    let mut message = format!("{}\n\nProgram logs:", error);
+    for log in logs {
+        let _ = write!(message, "\n  {}", log);
+    }
+    anyhow!(message)
+}
@@ -0,0 +1,32 @@
+// check: verify the program's account invariants
+use anyhow::{bail, Result};
+use matrix_client::invariants;
+use matrix_client::rpc;
+
+use crate::client::Client;
+use crate::commands::{donut, field, sol};
+
+pub fn run(client: &Client) -> Result<()> {
+    let state_address = client.state_address()?;
+    let snapshot = rpc::snapshot(&client.rpc, &state_address)?;
+
+    println!("Snapshot of state {}", state_address);
+    field("Users", snapshot.users.len());
+    field("Reserved SOL", sol(snapshot.reserved_sol()));
+    field("SOL vault above rent", sol(snapshot.available_sol()));
+    field("Tracked reserved SOL", sol(snapshot.state.total_reserved_sol));
+    field("Reserved DONUT", donut(snapshot.reserved_tokens()));
+    field("Token vault", donut(snapshot.token_vault_balance));
+
+    let violations = invariants::check(&snapshot);
+    if violations.is_empty() {
+        println!("All invariants hold");
+        return Ok(());
+    }
+
+    println!("Violations:");
+    for violation in &violations {
+        println!("  {}", violation);
+    }
+    bail!("{} invariant violations", violations.len())
+}
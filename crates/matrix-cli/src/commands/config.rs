@@ -0,0 +1,17 @@
+// config export: write matriz-config.json from the on-chain state
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::client::Client;
+use crate::config::MatrixConfig;
+
+pub fn export(client: &Client, output: &Path) -> Result<()> {
+    let (state_address, state) = client.program_state()?;
+    let config = MatrixConfig::new(&state_address, &state, &client.donut_token_program()?);
+
+    config.save(output)?;
+    println!("Config for state {} written to {}", state_address, output.display());
+
+    Ok(())
+}
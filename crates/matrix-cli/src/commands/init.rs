@@ -0,0 +1,52 @@
+// init: create the program state and the program token vault, then write the config file
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use matrix_client::initialize_instructions;
+use matrix_system::admin_addresses;
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::client::Client;
+use crate::commands::{field, print_signature};
+use crate::config::MatrixConfig;
+use crate::read_keypair;
+
+pub fn run(client: &Client, state_keypair: Option<&Path>, config_path: &Path) -> Result<()> {
+    if client.payer.pubkey() != admin_addresses::AUTHORIZED_INITIALIZER {
+        bail!(
+            "Signer {} is not the authorized initializer {}",
+            client.payer.pubkey(),
+            admin_addresses::AUTHORIZED_INITIALIZER
+        );
+    }
+
+    let state_keypair = match state_keypair {
+        Some(path) => read_keypair(path)?,
+        None => Keypair::new(),
+    };
+    if client.account(&state_keypair.pubkey())?.is_some() {
+        bail!("State account {} already exists", state_keypair.pubkey());
+    }
+
+    let donut_token_program = client.donut_token_program()?;
+    // The program token vault holds the slot-2 DONUT reservations
+    let instructions = initialize_instructions(&state_keypair.pubkey(), &client.payer.pubkey(), &donut_token_program);
+
+    let signature = client.send(&instructions, &[&state_keypair], None, &[])?;
+    print_signature("Initialized", &signature);
+
+    let state_address = state_keypair.pubkey();
+    let state = client.fetch(&state_address)?
+        .ok_or_else(|| anyhow!("State account {} not found after initialize", state_address))?;
+
+    let config = MatrixConfig::new(&state_address, &state, &donut_token_program);
+    config.save(config_path)?;
+
+    field("State", state_address);
+    field("Owner", &config.owner_wallet);
+    field("Multisig treasury", &config.multisig_treasury);
+    field("Program token vault", &config.program_token_vault);
+    println!("Config written to {}", config_path.display());
+
+    Ok(())
+}
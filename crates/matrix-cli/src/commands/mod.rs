@@ -0,0 +1,32 @@
+// Subcommand implementations
+pub mod check;
+pub mod config;
+pub mod init;
+pub mod register;
+pub mod show;
+pub mod tree;
+
+use anchor_lang::prelude::Pubkey;
+use solana_sdk::signature::Signature;
+
+// Function to format lamports as SOL
+pub fn sol(lamports: u64) -> String {
+    format!("{}.{:09} SOL", lamports / 1_000_000_000, lamports % 1_000_000_000)
+}
+
+// Function to format DONUT base units (9 decimals)
+pub fn donut(amount: u64) -> String {
+    format!("{}.{:09} DONUT", amount / 1_000_000_000, amount % 1_000_000_000)
+}
+
+pub fn field(label: &str, value: impl std::fmt::Display) {
+    println!("  {:<26}{}", format!("{}:", label), value);
+}
+
+pub fn optional_key(key: Option<Pubkey>) -> String {
+    key.map(|key| key.to_string()).unwrap_or_else(|| "-".to_string())
+}
+
+pub fn print_signature(what: &str, signature: &Signature) {
+    println!("{}: {}", what, signature);
+}
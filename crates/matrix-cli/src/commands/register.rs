@@ -0,0 +1,78 @@
+// register-root and register: user registration with a SOL deposit
+use anchor_lang::prelude::Pubkey;
+use anyhow::{bail, Result};
+use matrix_client::{pda, root_registration_instructions, rpc};
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::client::Client;
+use crate::commands::{field, print_signature, sol};
+
+// Compute limit of the root registration, the most the runtime allows
+const ROOT_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+// Register `user` without referrer, signed by the multisig treasury
+pub fn root(client: &Client, user: &Keypair, deposit: u64) -> Result<()> {
+    let (state_address, state) = client.program_state()?;
+    if client.payer.pubkey() != state.multisig_treasury {
+        bail!("Signer {} is not the multisig treasury {}", client.payer.pubkey(), state.multisig_treasury);
+    }
+
+    let user_wallet = user.pubkey();
+    let instructions = root_registration_instructions(&state_address, &state.multisig_treasury, &user_wallet, deposit);
+
+    let signature = client.send(&instructions, &[user], Some(ROOT_COMPUTE_UNIT_LIMIT), &[])?;
+    print_signature("Registered root user", &signature);
+    field("Wallet", user_wallet);
+    field("User account", pda::user_account(&user_wallet));
+    field("Deposit", sol(deposit));
+
+    Ok(())
+}
+
+// Register `user` (the signer by default) under `referrer_wallet`
+pub fn referred(
+    client: &Client,
+    user: Option<&Keypair>,
+    referrer_wallet: &Pubkey,
+    deposit: u64,
+    lookup_table: Option<Pubkey>,
+    compute_unit_limit: u32,
+) -> Result<()> {
+    let user = user.unwrap_or(&client.payer);
+    let user_wallet = user.pubkey();
+
+    let state_address = client.state_address()?;
+    let referrer = client.user_account(referrer_wallet)?;
+    let registration = rpc::registration(&client.rpc, &state_address, &user_wallet, referrer_wallet, deposit)?;
+
+    // DONUT ATAs that receive payouts in this transaction are created when missing
+    let missing = rpc::missing_payout_atas(&client.rpc, &registration)?;
+    let mut instructions = registration.create_payout_ata_instructions(&user_wallet, &missing);
+    instructions.push(registration.instruction());
+
+    let lookup_tables = match lookup_table {
+        Some(address) => vec![client.lookup_table(&address)?],
+        None => Vec::new(),
+    };
+
+    let signature = client.send(&instructions, &[user], Some(compute_unit_limit), &lookup_tables)?;
+    print_signature("Registered", &signature);
+    field("Wallet", user_wallet);
+    field("User account", registration.accounts.user);
+    field("Referrer", referrer_wallet);
+    field("Referrer slot", referrer.chain.filled_slots + 1);
+    field("Deposit", sol(deposit));
+
+    // Accounts worth adding to the lookup table for the next registrations under this referrer
+    if !lookup_tables.is_empty() {
+        let missing = registration.lookup_table_suggestions().missing_from(&lookup_tables);
+        if !missing.is_empty() {
+            println!("Addresses missing from the lookup table:");
+            for address in missing {
+                println!("  {}", address);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+// state show, user show and upline show
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountDeserialize;
+use anyhow::Result;
+use matrix_client::pda;
+use matrix_system::{MintBudget, UserAccount};
+use solana_sdk::signature::Signer;
+
+use crate::client::Client;
+use crate::commands::{donut, field, optional_key, sol};
+
+pub fn state(client: &Client) -> Result<()> {
+    let (state_address, state) = client.program_state()?;
+    let donut_token_program = client.donut_token_program()?;
+
+    println!("Program state {}", state_address);
+    field("Owner", state.owner);
+    field("Multisig treasury", state.multisig_treasury);
+    field("Next upline id", state.next_upline_id);
+    field("Next chain id", state.next_chain_id);
+    field("Slot-1 policy", format!("{:?}", state.slot1_policy));
+    field("Buyback slippage", format!("{} bps", state.buyback_slippage_bps));
+    field("Treasury fee", format!("{} bps", state.treasury_fee_bps));
+    field("Staking fee", format!("{} bps", state.staking_fee_bps));
+    field("TWAP window", format!("{} s", state.twap_window));
+    field("Price observations", state.observation_count);
+    field("Vesting duration", format!("{} s", state.vesting_duration));
+    field("Matrix expiry", format!("{} s", state.matrix_expiry));
+    field("Expiry policy", format!("{:?}", state.expiry_policy));
+
+    let program_sol_vault = pda::program_sol_vault();
+    let program_token_vault = pda::program_token_vault(&donut_token_program);
+    let vault_lamports = client.rpc.get_balance(&program_sol_vault)?;
+
+    println!("Vaults");
+    field("Program SOL vault", program_sol_vault);
+    field("  Balance", sol(vault_lamports));
+    field("  Reserved", sol(state.total_reserved_sol));
+    field("Program token vault", program_token_vault);
+    match client.rpc.get_token_account_balance(&program_token_vault) {
+        Ok(balance) => field("  Balance", format!("{} DONUT", balance.ui_amount_string)),
+        Err(_) => field("  Balance", "not created"),
+    }
+
+    println!("Mint budget");
+    match client.fetch::<MintBudget>(&pda::mint_budget())? {
+        Some(budget) => {
+            field("Epoch duration", format!("{} s", budget.epoch_duration));
+            field("Max per epoch", donut(budget.max_tokens_per_epoch));
+            field("Max per USD", donut(budget.max_tokens_per_usd));
+            field("Epoch start", budget.epoch_start);
+            field("Minted in epoch", donut(budget.minted_in_epoch));
+        },
+        None => println!("  not initialized"),
+    }
+
+    println!("PDAs");
+    field("Token mint authority", pda::token_mint_authority());
+    field("Token vault authority", pda::token_vault_authority());
+    field("Mint budget", pda::mint_budget());
+
+    Ok(())
+}
+
+pub fn user(client: &Client, wallet: Option<Pubkey>) -> Result<()> {
+    let wallet = wallet.unwrap_or_else(|| client.payer.pubkey());
+    let user = client.user_account(&wallet)?;
+
+    println!("User {}", wallet);
+    field("User account", pda::user_account(&wallet));
+    field("Registered", user.is_registered);
+    field("Referrer account", optional_key(user.referrer));
+    field("Owner wallet", user.owner_wallet);
+    field("Upline id", user.upline.id);
+    field("Upline depth", user.upline.depth);
+    field("Stored uplines", user.upline.upline.len());
+    field("Chain id", user.chain.id);
+    field("Filled slots", format!("{}/3", user.chain.filled_slots));
+    for (index, slot) in user.chain.slots.iter().enumerate() {
+        field(&format!("  Slot {}", index + 1), optional_key(*slot));
+    }
+    field("Expires at", if user.matrix_expires_at == 0 { "never".to_string() } else { user.matrix_expires_at.to_string() });
+    field("Reserved SOL", sol(user.reserved_sol));
+    field("Reserved tokens", donut(user.reserved_tokens));
+
+    Ok(())
+}
+
+pub fn upline(client: &Client, wallet: Option<Pubkey>) -> Result<()> {
+    let wallet = wallet.unwrap_or_else(|| client.payer.pubkey());
+    let user = client.user_account(&wallet)?;
+
+    // Stored oldest first; shown closest first, the order of the remaining_accounts trios
+    let entries: Vec<_> = user.upline.upline.iter().rev().collect();
+    let pdas: Vec<Pubkey> = entries.iter().map(|entry| entry.pda).collect();
+    let upline_accounts = client.rpc.get_multiple_accounts(&pdas)?;
+
+    println!("Upline of {} ({} stored, depth {})", wallet, entries.len(), user.upline.depth);
+    for (level, (entry, account)) in entries.iter().zip(upline_accounts).enumerate() {
+        let matrix = account
+            .and_then(|account| UserAccount::try_deserialize(&mut account.data.as_slice()).ok())
+            .map(|upline| format!("chain {} {}/3", upline.chain.id, upline.chain.filled_slots))
+            .unwrap_or_else(|| "account missing".to_string());
+
+        println!("  {}. wallet {}  account {}  {}", level + 1, entry.wallet, entry.pda, matrix);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// tree export: referral tree of every user account as Graphviz DOT or JSON
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use matrix_client::{rpc, ReferralTree};
+
+use crate::client::Client;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    Dot,
+    Json,
+}
+
+// Export from the RPC, or from a saved getProgramAccounts result when `snapshot` is given
+pub fn export(client: &Client, format: Format, snapshot: Option<&Path>, output: Option<&Path>) -> Result<()> {
+    let accounts = match snapshot {
+        Some(path) => {
+            let json = fs::read_to_string(path).with_context(|| format!("Can't read {}", path.display()))?;
+            rpc::user_accounts_from_snapshot(&json)?
+        },
+        None => rpc::user_accounts(&client.rpc)?,
+    };
+
+    let tree = ReferralTree::new(&accounts);
+    let rendered = match format {
+        Format::Dot => tree.to_dot(),
+        Format::Json => format!("{:#}\n", tree.to_json()),
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, rendered).with_context(|| format!("Can't write {}", path.display()))?;
+            eprintln!("Tree of {} users written to {}", accounts.len(), path.display());
+        },
+        None => print!("{}", rendered),
+    }
+
+    Ok(())
+}
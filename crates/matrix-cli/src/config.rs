@@ -0,0 +1,73 @@
+// matriz-config.json: program addresses and state counters shared with the operators' tooling
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{Context, Result};
+use matrix_client::pda;
+use matrix_system::{verified_addresses, ProgramState};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatrixConfig {
+    pub program_id: String,
+    pub state_address: String,
+    pub token_mint: String,
+    pub token_mint_authority: String,
+    pub token_mint_authority_bump: u8,
+    pub program_sol_vault: String,
+    pub program_sol_vault_bump: u8,
+    pub vault_authority: String,
+    pub vault_authority_bump: u8,
+    pub program_token_vault: String,
+    pub owner_wallet: String,
+    pub multisig_treasury: String,
+    pub next_upline_id: String,
+    pub next_chain_id: String,
+}
+
+impl MatrixConfig {
+    // Config for the state account at `state_address`
+    pub fn new(state_address: &Pubkey, state: &ProgramState, donut_token_program: &Pubkey) -> Self {
+        let (token_mint_authority, token_mint_authority_bump) = pda::find(&[pda::TOKEN_MINT_AUTHORITY_SEED]);
+        let (program_sol_vault, program_sol_vault_bump) = pda::find(&[pda::PROGRAM_SOL_VAULT_SEED]);
+        let (vault_authority, vault_authority_bump) = pda::find(&[pda::TOKEN_VAULT_AUTHORITY_SEED]);
+
+        Self {
+            program_id: matrix_system::ID.to_string(),
+            state_address: state_address.to_string(),
+            token_mint: verified_addresses::TOKEN_MINT.to_string(),
+            token_mint_authority: token_mint_authority.to_string(),
+            token_mint_authority_bump,
+            program_sol_vault: program_sol_vault.to_string(),
+            program_sol_vault_bump,
+            vault_authority: vault_authority.to_string(),
+            vault_authority_bump,
+            program_token_vault: pda::program_token_vault(donut_token_program).to_string(),
+            owner_wallet: state.owner.to_string(),
+            multisig_treasury: state.multisig_treasury.to_string(),
+            next_upline_id: state.next_upline_id.to_string(),
+            next_chain_id: state.next_chain_id.to_string(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path).with_context(|| format!("Can't read {}", path.display()))?;
+        serde_json::from_str(&data).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            fs::create_dir_all(directory)?;
+        }
+
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Can't write {}", path.display()))
+    }
+
+    pub fn state_address(&self) -> Result<Pubkey> {
+        Pubkey::from_str(&self.state_address).context("Invalid stateAddress in the config file")
+    }
+}
@@ -0,0 +1,237 @@
+// matrix-cli: initialize, register and inspect the referral matrix
+mod client;
+mod commands;
+mod config;
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, Subcommand};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::{read_keypair_file, Keypair};
+
+use client::Client;
+use commands::tree::Format as TreeFormat;
+use config::MatrixConfig;
+
+/// Command line client for the referral matrix program
+#[derive(Parser, Debug)]
+#[command(name = "matrix-cli", version)]
+struct Args {
+    /// RPC URL or moniker (localhost, devnet, testnet, mainnet-beta), defaults to the Solana CLI config
+    #[arg(long, short = 'u', global = true)]
+    url: Option<String>,
+
+    /// Signer and fee payer keypair, defaults to the Solana CLI config
+    #[arg(long, short = 'k', global = true)]
+    keypair: Option<PathBuf>,
+
+    /// Program state account, defaults to stateAddress in the config file
+    #[arg(long, global = true)]
+    state: Option<Pubkey>,
+
+    /// Matrix config file read for the state address and written by init
+    #[arg(long, global = true, default_value = "matriz-config.json")]
+    config: PathBuf,
+
+    #[arg(long, global = true, default_value = "confirmed")]
+    commitment: CommitmentConfig,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Create the program state (authorized initializer only) and the program token vault
+    Init {
+        /// Keypair of the new state account (generated if omitted)
+        #[arg(long)]
+        state_keypair: Option<PathBuf>,
+    },
+
+    /// Register a user without referrer (multisig treasury only)
+    RegisterRoot {
+        /// Keypair of the user wallet
+        #[arg(long)]
+        user: PathBuf,
+
+        /// Deposit in SOL
+        #[arg(long)]
+        deposit: String,
+    },
+
+    /// Register a user under a referrer with a SOL deposit
+    Register {
+        /// Wallet of the referrer
+        #[arg(long)]
+        referrer: Pubkey,
+
+        /// Keypair of the user wallet, defaults to the signer
+        #[arg(long)]
+        user: Option<PathBuf>,
+
+        /// Deposit in SOL
+        #[arg(long)]
+        deposit: String,
+
+        /// Address lookup table used to compile a v0 transaction
+        #[arg(long)]
+        lookup_table: Option<Pubkey>,
+
+        #[arg(long, default_value_t = 1_400_000)]
+        compute_unit_limit: u32,
+    },
+
+    /// Program state
+    State {
+        #[command(subcommand)]
+        command: ShowCommand,
+    },
+
+    /// User account of a wallet
+    User {
+        #[command(subcommand)]
+        command: WalletCommand,
+    },
+
+    /// Stored upline of a wallet, closest first
+    Upline {
+        #[command(subcommand)]
+        command: WalletCommand,
+    },
+
+    /// Matrix config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+
+    /// Check the state, user accounts and vaults against the program's invariants
+    Check,
+
+    /// Referral tree of every user account
+    Tree {
+        #[command(subcommand)]
+        command: TreeCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ShowCommand {
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum WalletCommand {
+    Show {
+        /// Wallet to show, defaults to the signer
+        wallet: Option<Pubkey>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Write the program addresses and state counters to the config file
+    Export {
+        /// Output file, defaults to --config
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TreeCommand {
+    /// Write the tree with each user's matrix and reservations
+    Export {
+        #[arg(long, value_enum, default_value_t = TreeFormat::Dot)]
+        format: TreeFormat,
+
+        /// Saved getProgramAccounts result (base64) read instead of the RPC
+        #[arg(long)]
+        snapshot: Option<PathBuf>,
+
+        /// Output file, defaults to stdout
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+}
+
+// Function to resolve an RPC moniker to its URL
+fn normalize_url(url: &str) -> String {
+    match url {
+        "localhost" | "l" => "http://localhost:8899".to_string(),
+        "devnet" | "d" => "https://api.devnet.solana.com".to_string(),
+        "testnet" | "t" => "https://api.testnet.solana.com".to_string(),
+        "mainnet-beta" | "m" => "https://api.mainnet-beta.solana.com".to_string(),
+        url => url.to_string(),
+    }
+}
+
+pub fn read_keypair(path: &Path) -> Result<Keypair> {
+    read_keypair_file(path).map_err(|error| anyhow!("Can't read keypair {}: {}", path.display(), error))
+}
+
+// Function to convert a SOL amount like "0.2" to lamports
+pub fn parse_sol(amount: &str) -> Result<u64> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    if fraction.len() > 9 || (whole.is_empty() && fraction.is_empty()) {
+        bail!("Invalid SOL amount: {}", amount);
+    }
+
+    let invalid = || format!("Invalid SOL amount: {}", amount);
+    let whole = if whole.is_empty() { 0 } else { u64::from_str(whole).with_context(invalid)? };
+    let fraction = if fraction.is_empty() { 0 } else { u64::from_str(&format!("{:0<9}", fraction)).with_context(invalid)? };
+
+    whole.checked_mul(1_000_000_000)
+        .and_then(|lamports| lamports.checked_add(fraction))
+        .ok_or_else(|| anyhow!("SOL amount too large: {}", amount))
+}
+
+fn run(args: Args) -> Result<()> {
+    let cli_config = solana_cli_config::CONFIG_FILE.as_ref()
+        .and_then(|file| solana_cli_config::Config::load(file).ok())
+        .unwrap_or_default();
+
+    let url = normalize_url(args.url.as_deref().unwrap_or(&cli_config.json_rpc_url));
+    let keypair_path = args.keypair.clone().unwrap_or_else(|| PathBuf::from(&cli_config.keypair_path));
+    let payer = read_keypair(&keypair_path)?;
+
+    let state = match args.state {
+        Some(state) => Some(state),
+        None if args.config.exists() => Some(MatrixConfig::load(&args.config)?.state_address()?),
+        None => None,
+    };
+
+    let client = Client::new(url, args.commitment, payer, state);
+
+    match args.command {
+        Command::Init { state_keypair } => commands::init::run(&client, state_keypair.as_deref(), &args.config),
+        Command::RegisterRoot { user, deposit } => {
+            commands::register::root(&client, &read_keypair(&user)?, parse_sol(&deposit)?)
+        },
+        Command::Register { referrer, user, deposit, lookup_table, compute_unit_limit } => {
+            let user = user.as_deref().map(read_keypair).transpose()?;
+            commands::register::referred(&client, user.as_ref(), &referrer, parse_sol(&deposit)?, lookup_table, compute_unit_limit)
+        },
+        Command::State { command: ShowCommand::Show } => commands::show::state(&client),
+        Command::User { command: WalletCommand::Show { wallet } } => commands::show::user(&client, wallet),
+        Command::Upline { command: WalletCommand::Show { wallet } } => commands::show::upline(&client, wallet),
+        Command::Config { command: ConfigCommand::Export { output } } => {
+            commands::config::export(&client, output.as_deref().unwrap_or(&args.config))
+        },
+        Command::Check => commands::check::run(&client),
+        Command::Tree { command: TreeCommand::Export { format, snapshot, output } } => {
+            commands::tree::export(&client, format, snapshot.as_deref(), output.as_deref())
+        },
+    }
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("Error: {:#}", error);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,34 @@
+// Errors of the client SDK
+use anchor_lang::prelude::Pubkey;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Account {0} not found")]
+    AccountNotFound(Pubkey),
+
+    #[error("Account {0} can't be decoded")]
+    InvalidAccountData(Pubkey),
+
+    #[error("Wallet {0} is not registered")]
+    NotRegistered(Pubkey),
+
+    #[error("Wallet {0} is already registered")]
+    AlreadyRegistered(Pubkey),
+
+    #[cfg(feature = "rpc")]
+    #[error("Invalid user account snapshot: {0}")]
+    InvalidSnapshot(String),
+
+    #[cfg(feature = "rpc")]
+    #[error(transparent)]
+    Rpc(Box<solana_client::client_error::ClientError>),
+}
+
+#[cfg(feature = "rpc")]
+impl From<solana_client::client_error::ClientError> for Error {
+    fn from(error: solana_client::client_error::ClientError) -> Self {
+        Self::Rpc(Box::new(error))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,217 @@
+// Consistency checks over a snapshot of the program's accounts
+// The program keeps these invariants on every instruction; a violation means a bug or a migration gap
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anchor_lang::prelude::Pubkey;
+use matrix_system::matrix::MATRIX_SLOTS;
+use matrix_system::{ProgramState, UserAccount, MAX_UPLINE_DEPTH};
+
+// State, user accounts and vault balances read at one point
+pub struct Snapshot {
+    pub state: ProgramState,
+    pub users: Vec<(Pubkey, UserAccount)>,
+    pub sol_vault_lamports: u64,
+    pub sol_vault_rent_exempt_minimum: u64,
+    pub token_vault_balance: u64,
+}
+
+impl Snapshot {
+    pub fn reserved_sol(&self) -> u64 {
+        self.users.iter().fold(0u64, |sum, (_, user)| sum.saturating_add(user.reserved_sol))
+    }
+
+    pub fn reserved_tokens(&self) -> u64 {
+        self.users.iter().fold(0u64, |sum, (_, user)| sum.saturating_add(user.reserved_tokens))
+    }
+
+    // Vault lamports that can back reservations
+    pub fn available_sol(&self) -> u64 {
+        self.sol_vault_lamports.saturating_sub(self.sol_vault_rent_exempt_minimum)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    // The SOL vault above rent doesn't cover the users' reservations
+    ReservedSolExceedsVault { reserved: u64, available: u64 },
+    // state.total_reserved_sol differs from the users' reservations
+    TotalReservedSolMismatch { tracked: u64, reserved: u64 },
+    // The token vault doesn't cover the users' DONUT reservations
+    ReservedTokensExceedVault { reserved: u64, balance: u64 },
+    // filled_slots doesn't match the leading occupied slots, or a full matrix wasn't reset
+    SlotsMismatch { user: Pubkey, filled_slots: u8, occupied: [bool; 3] },
+    // Reservations are only held between the second and third slot
+    UnexpectedReservation { user: Pubkey, filled_slots: u8, reserved_sol: u64, reserved_tokens: u64 },
+    UplineTooLong { user: Pubkey, len: usize },
+    // The stored upline holds depth - 1 entries, capped at MAX_UPLINE_DEPTH
+    UplineDepthMismatch { user: Pubkey, depth: u8, len: usize },
+    // The closest upline entry is the referrer
+    ReferrerNotInUpline { user: Pubkey, referrer: Option<Pubkey>, closest: Option<Pubkey> },
+    DuplicateChainId { chain_id: u32, users: Vec<Pubkey> },
+    ChainIdOutOfRange { user: Pubkey, chain_id: u32, next_chain_id: u32 },
+    DuplicateUplineId { upline_id: u32, users: Vec<Pubkey> },
+    UplineIdOutOfRange { user: Pubkey, upline_id: u32, next_upline_id: u32 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ReservedSolExceedsVault { reserved, available } => {
+                write!(f, "Reserved SOL {} exceeds the vault's {} lamports above rent", reserved, available)
+            },
+            Self::TotalReservedSolMismatch { tracked, reserved } => {
+                write!(f, "State tracks {} reserved lamports, users hold {}", tracked, reserved)
+            },
+            Self::ReservedTokensExceedVault { reserved, balance } => {
+                write!(f, "Reserved DONUT {} exceeds the token vault balance {}", reserved, balance)
+            },
+            Self::SlotsMismatch { user, filled_slots, occupied } => {
+                write!(f, "User {}: filled_slots {} with occupied slots {:?}", user, filled_slots, occupied)
+            },
+            Self::UnexpectedReservation { user, filled_slots, reserved_sol, reserved_tokens } => write!(
+                f,
+                "User {}: {} lamports and {} DONUT reserved with {} filled slots",
+                user, reserved_sol, reserved_tokens, filled_slots
+            ),
+            Self::UplineTooLong { user, len } => {
+                write!(f, "User {}: upline of {} entries exceeds {}", user, len, MAX_UPLINE_DEPTH)
+            },
+            Self::UplineDepthMismatch { user, depth, len } => {
+                write!(f, "User {}: depth {} with {} upline entries", user, depth, len)
+            },
+            Self::ReferrerNotInUpline { user, referrer, closest } => {
+                write!(f, "User {}: referrer {:?} but closest upline {:?}", user, referrer, closest)
+            },
+            Self::DuplicateChainId { chain_id, users } => {
+                write!(f, "Chain id {} used by {} users: {:?}", chain_id, users.len(), users)
+            },
+            Self::ChainIdOutOfRange { user, chain_id, next_chain_id } => {
+                write!(f, "User {}: chain id {} not below next_chain_id {}", user, chain_id, next_chain_id)
+            },
+            Self::DuplicateUplineId { upline_id, users } => {
+                write!(f, "Upline id {} used by {} users: {:?}", upline_id, users.len(), users)
+            },
+            Self::UplineIdOutOfRange { user, upline_id, next_upline_id } => {
+                write!(f, "User {}: upline id {} not below next_upline_id {}", user, upline_id, next_upline_id)
+            },
+        }
+    }
+}
+
+// Function to list every invariant the snapshot violates
+pub fn check(snapshot: &Snapshot) -> Vec<Violation> {
+    let state = &snapshot.state;
+    let mut violations = Vec::new();
+
+    let reserved_sol = snapshot.reserved_sol();
+    if reserved_sol > snapshot.available_sol() {
+        violations.push(Violation::ReservedSolExceedsVault {
+            reserved: reserved_sol,
+            available: snapshot.available_sol(),
+        });
+    }
+    if state.total_reserved_sol != reserved_sol {
+        violations.push(Violation::TotalReservedSolMismatch {
+            tracked: state.total_reserved_sol,
+            reserved: reserved_sol,
+        });
+    }
+
+    let reserved_tokens = snapshot.reserved_tokens();
+    if reserved_tokens > snapshot.token_vault_balance {
+        violations.push(Violation::ReservedTokensExceedVault {
+            reserved: reserved_tokens,
+            balance: snapshot.token_vault_balance,
+        });
+    }
+
+    let mut chain_ids: BTreeMap<u32, Vec<Pubkey>> = BTreeMap::new();
+    let mut upline_ids: BTreeMap<u32, Vec<Pubkey>> = BTreeMap::new();
+
+    for (address, user) in &snapshot.users {
+        check_user(address, user, state, &mut violations);
+        chain_ids.entry(user.chain.id).or_default().push(*address);
+        upline_ids.entry(user.upline.id).or_default().push(*address);
+    }
+
+    for (chain_id, users) in chain_ids {
+        if users.len() > 1 {
+            violations.push(Violation::DuplicateChainId { chain_id, users });
+        }
+    }
+    for (upline_id, users) in upline_ids {
+        if users.len() > 1 {
+            violations.push(Violation::DuplicateUplineId { upline_id, users });
+        }
+    }
+
+    violations
+}
+
+fn check_user(address: &Pubkey, user: &UserAccount, state: &ProgramState, violations: &mut Vec<Violation>) {
+    let chain = &user.chain;
+    let occupied = [chain.slots[0].is_some(), chain.slots[1].is_some(), chain.slots[2].is_some()];
+    let leading = occupied.iter().take_while(|slot| **slot).count();
+    let occupied_count = occupied.iter().filter(|slot| **slot).count();
+
+    // A completed matrix resets at once, so at most two slots are ever stored
+    if chain.filled_slots >= MATRIX_SLOTS || leading != chain.filled_slots as usize || occupied_count != leading {
+        violations.push(Violation::SlotsMismatch {
+            user: *address,
+            filled_slots: chain.filled_slots,
+            occupied,
+        });
+    }
+
+    if (user.reserved_sol > 0 || user.reserved_tokens > 0) && chain.filled_slots != 2 {
+        violations.push(Violation::UnexpectedReservation {
+            user: *address,
+            filled_slots: chain.filled_slots,
+            reserved_sol: user.reserved_sol,
+            reserved_tokens: user.reserved_tokens,
+        });
+    }
+
+    let upline = &user.upline.upline;
+    if upline.len() > MAX_UPLINE_DEPTH {
+        violations.push(Violation::UplineTooLong {
+            user: *address,
+            len: upline.len(),
+        });
+    }
+
+    let expected_len = (user.upline.depth as usize).saturating_sub(1).min(MAX_UPLINE_DEPTH);
+    if user.upline.depth == 0 || upline.len() != expected_len {
+        violations.push(Violation::UplineDepthMismatch {
+            user: *address,
+            depth: user.upline.depth,
+            len: upline.len(),
+        });
+    }
+
+    let closest = upline.last().map(|entry| entry.pda);
+    if user.referrer != closest {
+        violations.push(Violation::ReferrerNotInUpline {
+            user: *address,
+            referrer: user.referrer,
+            closest,
+        });
+    }
+
+    if chain.id >= state.next_chain_id {
+        violations.push(Violation::ChainIdOutOfRange {
+            user: *address,
+            chain_id: chain.id,
+            next_chain_id: state.next_chain_id,
+        });
+    }
+
+    if user.upline.id >= state.next_upline_id {
+        violations.push(Violation::UplineIdOutOfRange {
+            user: *address,
+            upline_id: user.upline.id,
+            next_upline_id: state.next_upline_id,
+        });
+    }
+}
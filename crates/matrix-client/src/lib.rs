@@ -0,0 +1,17 @@
+// Client SDK for the referral matrix
+// PDAs, instruction builders that assemble the register_with_sol_deposit remaining_accounts,
+// address lookup table suggestions for the accounts registrations share, the referral tree export
+// and the account invariant checks
+pub mod error;
+pub mod invariants;
+pub mod lookup_table;
+pub mod pda;
+pub mod register;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+pub mod tree;
+
+pub use error::{Error, Result};
+pub use lookup_table::LookupTableSuggestions;
+pub use register::{initialize_instructions, root_registration_instructions, Registration, RegistrationParams};
+pub use tree::ReferralTree;
@@ -0,0 +1,83 @@
+// Address lookup table suggestions
+// A registration with a full upline passes more accounts than a legacy transaction fits;
+// the accounts every registration shares belong in one long-lived table, and the referrer's
+// accounts and upline trios in a table per branch, reused by all of the referrer's referrals
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::address_lookup_table::instruction::{create_lookup_table, extend_lookup_table};
+use anchor_lang::solana_program::address_lookup_table::AddressLookupTableAccount;
+use anchor_lang::solana_program::instruction::Instruction;
+
+use crate::register::Registration;
+
+// Addresses added per extend instruction, to keep each transaction under the size limit
+pub const MAX_ADDRESSES_PER_EXTEND: usize = 20;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LookupTableSuggestions {
+    pub shared: Vec<Pubkey>,      // Program-wide accounts passed by every registration
+    pub referrer: Vec<Pubkey>,    // Referrer accounts and upline trios
+}
+
+impl LookupTableSuggestions {
+    pub fn new(registration: &Registration) -> Self {
+        let user = registration.user_addresses();
+        let referrer = registration.referrer_addresses();
+
+        // Absent optional accounts are passed as the program id, which stays a static key
+        let mut shared = Vec::new();
+        for meta in registration.instruction().accounts {
+            let address = meta.pubkey;
+            if address != matrix_system::ID && !user.contains(&address) && !referrer.contains(&address) && !shared.contains(&address) {
+                shared.push(address);
+            }
+        }
+
+        let mut unique_referrer = Vec::with_capacity(referrer.len());
+        for address in referrer {
+            if !unique_referrer.contains(&address) {
+                unique_referrer.push(address);
+            }
+        }
+
+        Self {
+            shared,
+            referrer: unique_referrer,
+        }
+    }
+
+    pub fn all(&self) -> Vec<Pubkey> {
+        self.shared.iter().chain(&self.referrer).copied().collect()
+    }
+
+    // Suggested addresses that none of `tables` holds yet
+    pub fn missing_from(&self, tables: &[AddressLookupTableAccount]) -> Vec<Pubkey> {
+        self.all()
+            .into_iter()
+            .filter(|address| !tables.iter().any(|table| table.addresses.contains(address)))
+            .collect()
+    }
+}
+
+// Instructions creating a lookup table holding `addresses`, and the table address
+// The first instruction creates the table; each following one extends it and can go in its own transaction
+pub fn create_instructions(
+    authority: &Pubkey,
+    payer: &Pubkey,
+    recent_slot: u64,
+    addresses: &[Pubkey],
+) -> (Pubkey, Vec<Instruction>) {
+    let (create, table) = create_lookup_table(*authority, *payer, recent_slot);
+
+    let mut instructions = vec![create];
+    instructions.extend(extend_instructions(&table, authority, payer, addresses));
+
+    (table, instructions)
+}
+
+// Instructions adding `addresses` to an existing table
+pub fn extend_instructions(table: &Pubkey, authority: &Pubkey, payer: &Pubkey, addresses: &[Pubkey]) -> Vec<Instruction> {
+    addresses
+        .chunks(MAX_ADDRESSES_PER_EXTEND)
+        .map(|chunk| extend_lookup_table(*table, *authority, Some(*payer), chunk.to_vec()))
+        .collect()
+}
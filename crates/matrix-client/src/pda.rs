@@ -0,0 +1,64 @@
+// Program derived addresses and the associated token accounts the program uses
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use matrix_system::verified_addresses;
+
+pub const USER_ACCOUNT_SEED: &[u8] = b"user_account";
+pub const PROGRAM_SOL_VAULT_SEED: &[u8] = b"program_sol_vault";
+pub const TOKEN_MINT_AUTHORITY_SEED: &[u8] = b"token_mint_authority";
+pub const TOKEN_VAULT_AUTHORITY_SEED: &[u8] = b"token_vault_authority";
+pub const MINT_BUDGET_SEED: &[u8] = b"mint_budget";
+pub const VESTING_SEED: &[u8] = b"vesting";
+pub const STAKING_POOL_SEED: &[u8] = b"staking_pool";
+pub const STAKING_REWARD_VAULT_SEED: &[u8] = b"staking_reward_vault";
+
+// Function to derive a program PDA and its bump
+pub fn find(seeds: &[&[u8]]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(seeds, &matrix_system::ID)
+}
+
+pub fn user_account(wallet: &Pubkey) -> Pubkey {
+    find(&[USER_ACCOUNT_SEED, wallet.as_ref()]).0
+}
+
+pub fn program_sol_vault() -> Pubkey {
+    find(&[PROGRAM_SOL_VAULT_SEED]).0
+}
+
+pub fn token_mint_authority() -> Pubkey {
+    find(&[TOKEN_MINT_AUTHORITY_SEED]).0
+}
+
+pub fn token_vault_authority() -> Pubkey {
+    find(&[TOKEN_VAULT_AUTHORITY_SEED]).0
+}
+
+pub fn mint_budget() -> Pubkey {
+    find(&[MINT_BUDGET_SEED]).0
+}
+
+pub fn vesting_account(wallet: &Pubkey) -> Pubkey {
+    find(&[VESTING_SEED, wallet.as_ref()]).0
+}
+
+pub fn staking_pool() -> Pubkey {
+    find(&[STAKING_POOL_SEED]).0
+}
+
+pub fn staking_reward_vault() -> Pubkey {
+    find(&[STAKING_REWARD_VAULT_SEED]).0
+}
+
+// DONUT ATA of a wallet, for the token program that owns the mint (SPL Token or Token-2022)
+pub fn donut_ata(wallet: &Pubkey, donut_token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(wallet, &verified_addresses::TOKEN_MINT, donut_token_program)
+}
+
+pub fn wsol_ata(wallet: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(wallet, &verified_addresses::WSOL_MINT, &spl_token::ID)
+}
+
+// DONUT ATA of the token vault authority, holding slot-2 reservations
+pub fn program_token_vault(donut_token_program: &Pubkey) -> Pubkey {
+    donut_ata(&token_vault_authority(), donut_token_program)
+}
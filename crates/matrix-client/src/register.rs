@@ -0,0 +1,293 @@
+// Registration instructions
+// register_with_sol_deposit takes, after its named accounts, these remaining_accounts:
+//   [0..4]  vault A: LP token account, LP mint, token vault, vault (priced from the vault total)
+//   [4]     Chainlink SOL/USD feed
+//   [5]     Chainlink program
+//   [6..]   (user PDA, wallet, DONUT ATA or vesting PDA) per upline, closest first,
+//           only when the referrer's matrix completes with this registration
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::{system_instruction, system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use matrix_system::{accounts, instruction, meteora, verified_addresses, ProgramState, Slot1Policy, UserAccount};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+
+use crate::error::{Error, Result};
+use crate::lookup_table::LookupTableSuggestions;
+use crate::pda;
+
+// Number of remaining_accounts before the upline trios
+pub const FIXED_REMAINING_ACCOUNTS: usize = matrix_system::VAULT_A_ACCOUNTS_COUNT + 2;
+
+// On-chain data a registration is built from
+pub struct RegistrationParams<'a> {
+    pub state_address: Pubkey,
+    pub state: &'a ProgramState,
+    pub user_wallet: Pubkey,
+    pub referrer_wallet: Pubkey,
+    pub referrer: &'a UserAccount,
+    pub deposit_amount: u64,
+    pub donut_token_program: Pubkey,          // Owner of the DONUT mint
+    pub pool: &'a meteora::Pool,              // Source of vault A and the buyback fee account
+}
+
+// Accounts and arguments of a register_with_sol_deposit call
+pub struct Registration {
+    pub accounts: accounts::RegisterWithSolDeposit,
+    pub remaining_accounts: Vec<AccountMeta>,
+    pub deposit_amount: u64,
+    pub payout_wallets: Vec<Pubkey>,          // Wallets paid DONUT to their ATA by this registration
+}
+
+impl Registration {
+    pub fn new(params: RegistrationParams) -> Result<Self> {
+        let RegistrationParams {
+            state_address,
+            state,
+            user_wallet,
+            referrer_wallet,
+            referrer,
+            deposit_amount,
+            donut_token_program,
+            pool,
+        } = params;
+
+        if !referrer.is_registered {
+            return Err(Error::NotRegistered(referrer_wallet));
+        }
+
+        // The pool's SOL fee account is only passed for the slot-1 buyback
+        let buyback = needs_buyback_accounts(state).then_some(pool.protocol_token_b_fee);
+
+        let vesting_enabled = state.vesting_duration > 0;
+        let staking_enabled = state.staking_fee_bps > 0;
+
+        let accounts = accounts::RegisterWithSolDeposit {
+            state: state_address,
+            user_wallet,
+            referrer: pda::user_account(&referrer_wallet),
+            referrer_wallet,
+            user: pda::user_account(&user_wallet),
+            user_wsol_account: pda::wsol_ata(&user_wallet),
+            wsol_mint: verified_addresses::WSOL_MINT,
+            pool: verified_addresses::POOL_ADDRESS,
+            b_vault: verified_addresses::B_VAULT,
+            b_token_vault: verified_addresses::B_TOKEN_VAULT,
+            b_vault_lp_mint: verified_addresses::B_VAULT_LP_MINT,
+            b_vault_lp: verified_addresses::B_VAULT_LP,
+            vault_program: verified_addresses::VAULT_PROGRAM,
+            protocol_token_b_fee: buyback,
+            treasury_token_account: (state.slot1_policy == Slot1Policy::BuybackToTreasury)
+                .then(|| pda::donut_ata(&state.multisig_treasury, &donut_token_program)),
+            amm_program: buyback.map(|_| verified_addresses::AMM_PROGRAM),
+            treasury_wallet: (state.treasury_fee_bps > 0).then_some(state.multisig_treasury),
+            treasury_wsol_account: None,
+            staking_pool: staking_enabled.then(pda::staking_pool),
+            staking_reward_vault: staking_enabled.then(pda::staking_reward_vault),
+            program_sol_vault: pda::program_sol_vault(),
+            mint_budget: pda::mint_budget(),
+            token_mint: verified_addresses::TOKEN_MINT,
+            program_token_vault: pda::program_token_vault(&donut_token_program),
+            referrer_token_account: pda::donut_ata(&referrer_wallet, &donut_token_program),
+            referrer_vesting: vesting_enabled.then(|| pda::vesting_account(&referrer_wallet)),
+            token_mint_authority: pda::token_mint_authority(),
+            vault_authority: pda::token_vault_authority(),
+            token_program: spl_token::ID,
+            donut_token_program,
+            system_program: system_program::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            rent: sysvar::rent::ID,
+        };
+
+        let mut remaining_accounts = fixed_remaining_accounts(&pool.a_vault, buyback.is_some());
+        remaining_accounts.extend(upline_remaining_accounts(referrer, state, &donut_token_program));
+
+        // Slot 3 pays the referrer, and the recursion its uplines, unless vesting holds the DONUT
+        let mut payout_wallets = vec![referrer_wallet];
+        if completes_matrix(referrer) && !vesting_enabled {
+            payout_wallets.extend(referrer.upline.upline.iter().rev().map(|entry| entry.wallet));
+        }
+
+        Ok(Self {
+            accounts,
+            remaining_accounts,
+            deposit_amount,
+            payout_wallets,
+        })
+    }
+
+    pub fn instruction(&self) -> Instruction {
+        let mut metas = self.accounts.to_account_metas(None);
+        metas.extend_from_slice(&self.remaining_accounts);
+
+        Instruction {
+            program_id: matrix_system::ID,
+            accounts: metas,
+            data: instruction::RegisterWithSolDeposit {
+                deposit_amount: self.deposit_amount,
+            }
+            .data(),
+        }
+    }
+
+    // Idempotent creation of the DONUT ATAs this registration pays into, for the wallets in `missing`
+    pub fn create_payout_ata_instructions(&self, payer: &Pubkey, missing: &[Pubkey]) -> Vec<Instruction> {
+        self.payout_wallets
+            .iter()
+            .filter(|wallet| missing.contains(wallet))
+            .map(|wallet| {
+                create_associated_token_account_idempotent(
+                    payer,
+                    wallet,
+                    &verified_addresses::TOKEN_MINT,
+                    &self.accounts.donut_token_program,
+                )
+            })
+            .collect()
+    }
+
+    // DONUT ATAs of the payout wallets, in the same order
+    pub fn payout_token_accounts(&self) -> Vec<Pubkey> {
+        self.payout_wallets
+            .iter()
+            .map(|wallet| pda::donut_ata(wallet, &self.accounts.donut_token_program))
+            .collect()
+    }
+
+    // Split of the accounts into ones shared by every registration and ones tied to the referrer
+    pub fn lookup_table_suggestions(&self) -> LookupTableSuggestions {
+        LookupTableSuggestions::new(self)
+    }
+
+    // Accounts that only this user passes: the signer, its new user PDA and its new WSOL ATA
+    pub fn user_addresses(&self) -> [Pubkey; 3] {
+        [self.accounts.user_wallet, self.accounts.user, self.accounts.user_wsol_account]
+    }
+
+    // Accounts of the referrer and of the upline trios
+    pub fn referrer_addresses(&self) -> Vec<Pubkey> {
+        let mut addresses = vec![
+            self.accounts.referrer,
+            self.accounts.referrer_wallet,
+            self.accounts.referrer_token_account,
+        ];
+        addresses.extend(self.accounts.referrer_vesting);
+        addresses.extend(self.remaining_accounts[FIXED_REMAINING_ACCOUNTS..].iter().map(|meta| meta.pubkey));
+        addresses
+    }
+}
+
+// Whether this registration fills the last slot of the referrer's matrix
+pub fn completes_matrix(referrer: &UserAccount) -> bool {
+    referrer.chain.filled_slots == 2
+}
+
+// Whether slot-1 SOL is swapped for DONUT, which needs the buyback accounts
+pub fn needs_buyback_accounts(state: &ProgramState) -> bool {
+    state.slot1_policy != Slot1Policy::DepositLiquidity
+}
+
+// Vault A (LP, LP mint, token vault, vault), SOL/USD feed and Chainlink program
+// The buyback swap writes to vault A, so it is only writable when the slot-1 policy buys DONUT
+pub fn fixed_remaining_accounts(a_vault: &Pubkey, buyback: bool) -> Vec<AccountMeta> {
+    let vault_a = |address| if buyback {
+        AccountMeta::new(address, false)
+    } else {
+        AccountMeta::new_readonly(address, false)
+    };
+
+    vec![
+        vault_a(verified_addresses::A_VAULT_LP),
+        vault_a(verified_addresses::A_VAULT_LP_MINT),
+        vault_a(verified_addresses::A_TOKEN_VAULT),
+        vault_a(*a_vault),
+        AccountMeta::new_readonly(verified_addresses::SOL_USD_FEED, false),
+        AccountMeta::new_readonly(verified_addresses::CHAINLINK_PROGRAM, false),
+    ]
+}
+
+// Upline trios, closest upline first, when the referrer's matrix completes
+// The stored upline is oldest first and holds at most MAX_UPLINE_DEPTH entries
+pub fn upline_remaining_accounts(referrer: &UserAccount, state: &ProgramState, donut_token_program: &Pubkey) -> Vec<AccountMeta> {
+    if !completes_matrix(referrer) {
+        return Vec::new();
+    }
+
+    let mut accounts = Vec::with_capacity(referrer.upline.upline.len() * 3);
+    for entry in referrer.upline.upline.iter().rev() {
+        let token_account = if state.vesting_duration > 0 {
+            pda::vesting_account(&entry.wallet)
+        } else {
+            pda::donut_ata(&entry.wallet, donut_token_program)
+        };
+
+        accounts.push(AccountMeta::new(entry.pda, false));
+        accounts.push(AccountMeta::new(entry.wallet, false));
+        accounts.push(AccountMeta::new(token_account, false));
+    }
+
+    accounts
+}
+
+// register_without_referrer for `user_wallet`, signed by the multisig treasury
+// The deposit is wrapped into the user's WSOL ATA first; the instruction moves it to the pool
+pub fn root_registration_instructions(
+    state_address: &Pubkey,
+    multisig_treasury: &Pubkey,
+    user_wallet: &Pubkey,
+    deposit_amount: u64,
+) -> Vec<Instruction> {
+    let user_wsol_account = pda::wsol_ata(user_wallet);
+
+    let accounts = accounts::RegisterWithoutReferrerDeposit {
+        state: *state_address,
+        owner: *multisig_treasury,
+        user_wallet: *user_wallet,
+        user: pda::user_account(user_wallet),
+        user_source_token: user_wsol_account,
+        wsol_mint: verified_addresses::WSOL_MINT,
+        pool: verified_addresses::POOL_ADDRESS,
+        b_vault: verified_addresses::B_VAULT,
+        b_token_vault: verified_addresses::B_TOKEN_VAULT,
+        b_vault_lp_mint: verified_addresses::B_VAULT_LP_MINT,
+        b_vault_lp: verified_addresses::B_VAULT_LP,
+        vault_program: verified_addresses::VAULT_PROGRAM,
+        token_mint: verified_addresses::TOKEN_MINT,
+        token_program: spl_token::ID,
+        system_program: system_program::ID,
+        associated_token_program: anchor_spl::associated_token::ID,
+        rent: sysvar::rent::ID,
+    };
+
+    vec![
+        create_associated_token_account_idempotent(user_wallet, user_wallet, &verified_addresses::WSOL_MINT, &spl_token::ID),
+        system_instruction::transfer(user_wallet, &user_wsol_account, deposit_amount),
+        Instruction {
+            program_id: matrix_system::ID,
+            accounts: accounts.to_account_metas(None),
+            data: instruction::RegisterWithoutReferrer { deposit_amount }.data(),
+        },
+    ]
+}
+
+// initialize for a new state account, plus the program token vault ATA
+pub fn initialize_instructions(state_address: &Pubkey, owner: &Pubkey, donut_token_program: &Pubkey) -> Vec<Instruction> {
+    vec![
+        Instruction {
+            program_id: matrix_system::ID,
+            accounts: accounts::Initialize {
+                state: *state_address,
+                owner: *owner,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::Initialize {}.data(),
+        },
+        create_associated_token_account_idempotent(
+            owner,
+            &pda::token_vault_authority(),
+            &verified_addresses::TOKEN_MINT,
+            donut_token_program,
+        ),
+    ]
+}
@@ -0,0 +1,189 @@
+// Account fetching over JSON-RPC and registrations built from live data
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::address_lookup_table::state::AddressLookupTable;
+use anchor_lang::solana_program::address_lookup_table::AddressLookupTableAccount;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{AccountDeserialize, Discriminator};
+use matrix_system::{meteora, verified_addresses, ProgramState, UplineEntry, UserAccount};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_response::RpcKeyedAccount;
+
+use crate::error::{Error, Result};
+use crate::invariants::Snapshot;
+use crate::pda;
+use crate::register::{Registration, RegistrationParams};
+
+// Function to fetch an account's data, None if it doesn't exist
+pub fn fetch_data(rpc: &RpcClient, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+    let account = rpc.get_account_with_commitment(address, rpc.commitment())?.value;
+    Ok(account.map(|account| account.data))
+}
+
+// Function to fetch and deserialize an Anchor account, None if it doesn't exist
+pub fn fetch<T: AccountDeserialize>(rpc: &RpcClient, address: &Pubkey) -> Result<Option<T>> {
+    match fetch_data(rpc, address)? {
+        Some(data) => T::try_deserialize(&mut data.as_slice())
+            .map(Some)
+            .map_err(|_| Error::InvalidAccountData(*address)),
+        None => Ok(None),
+    }
+}
+
+pub fn program_state(rpc: &RpcClient, state_address: &Pubkey) -> Result<ProgramState> {
+    fetch(rpc, state_address)?.ok_or(Error::AccountNotFound(*state_address))
+}
+
+pub fn user_account(rpc: &RpcClient, wallet: &Pubkey) -> Result<UserAccount> {
+    fetch(rpc, &pda::user_account(wallet))?.ok_or(Error::NotRegistered(*wallet))
+}
+
+// Stored upline of a wallet, closest first (the order of the remaining_accounts trios)
+pub fn upline(rpc: &RpcClient, wallet: &Pubkey) -> Result<Vec<UplineEntry>> {
+    let user = user_account(rpc, wallet)?;
+    Ok(user.upline.upline.into_iter().rev().collect())
+}
+
+// Every user account of the program
+pub fn user_accounts(rpc: &RpcClient) -> Result<Vec<(Pubkey, UserAccount)>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, UserAccount::DISCRIMINATOR.to_vec()))]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    rpc.get_program_accounts_with_config(&matrix_system::ID, config)?
+        .into_iter()
+        .map(|(address, account)| {
+            UserAccount::try_deserialize(&mut account.data.as_slice())
+                .map(|user| (address, user))
+                .map_err(|_| Error::InvalidAccountData(address))
+        })
+        .collect()
+}
+
+// User accounts from a saved getProgramAccounts result; other program accounts are skipped
+pub fn user_accounts_from_snapshot(json: &str) -> Result<Vec<(Pubkey, UserAccount)>> {
+    let keyed: Vec<RpcKeyedAccount> = serde_json::from_str(json).map_err(|error| Error::InvalidSnapshot(error.to_string()))?;
+
+    let mut users = Vec::new();
+    for keyed in keyed {
+        let address: Pubkey = keyed
+            .pubkey
+            .parse()
+            .map_err(|_| Error::InvalidSnapshot(format!("invalid address {}", keyed.pubkey)))?;
+        let data = keyed
+            .account
+            .data
+            .decode()
+            .ok_or_else(|| Error::InvalidSnapshot(format!("account {} is not binary encoded", address)))?;
+
+        if let Ok(user) = UserAccount::try_deserialize(&mut data.as_slice()) {
+            users.push((address, user));
+        }
+    }
+
+    Ok(users)
+}
+
+// State, user accounts and vault balances for the invariant checks
+// A vault that doesn't exist yet counts as empty
+pub fn snapshot(rpc: &RpcClient, state_address: &Pubkey) -> Result<Snapshot> {
+    let state = program_state(rpc, state_address)?;
+    let users = user_accounts(rpc)?;
+
+    let sol_vault = rpc.get_account_with_commitment(&pda::program_sol_vault(), rpc.commitment())?.value;
+    let (sol_vault_lamports, sol_vault_data_len) = sol_vault.map_or((0, 0), |vault| (vault.lamports, vault.data.len()));
+
+    let token_vault_address = pda::program_token_vault(&donut_token_program(rpc)?);
+    let token_vault_balance = match fetch_data(rpc, &token_vault_address)? {
+        // Token-2022 extensions follow the base account layout
+        Some(data) if data.len() >= spl_token::state::Account::LEN => {
+            spl_token::state::Account::unpack_from_slice(&data[..spl_token::state::Account::LEN])
+                .map_err(|_| Error::InvalidAccountData(token_vault_address))?
+                .amount
+        },
+        Some(_) => return Err(Error::InvalidAccountData(token_vault_address)),
+        None => 0,
+    };
+
+    Ok(Snapshot {
+        state,
+        users,
+        sol_vault_lamports,
+        sol_vault_rent_exempt_minimum: rpc.get_minimum_balance_for_rent_exemption(sol_vault_data_len)?,
+        token_vault_balance,
+    })
+}
+
+// Token program that owns the DONUT mint (SPL Token or Token-2022)
+pub fn donut_token_program(rpc: &RpcClient) -> Result<Pubkey> {
+    let mint = rpc
+        .get_account_with_commitment(&verified_addresses::TOKEN_MINT, rpc.commitment())?
+        .value
+        .ok_or(Error::AccountNotFound(verified_addresses::TOKEN_MINT))?;
+    Ok(mint.owner)
+}
+
+pub fn pool(rpc: &RpcClient) -> Result<meteora::Pool> {
+    let address = verified_addresses::POOL_ADDRESS;
+    let data = fetch_data(rpc, &address)?.ok_or(Error::AccountNotFound(address))?;
+    meteora::Pool::from_account_data(&data).ok_or(Error::InvalidAccountData(address))
+}
+
+pub fn lookup_table(rpc: &RpcClient, address: &Pubkey) -> Result<AddressLookupTableAccount> {
+    let data = fetch_data(rpc, address)?.ok_or(Error::AccountNotFound(*address))?;
+    let table = AddressLookupTable::deserialize(&data).map_err(|_| Error::InvalidAccountData(*address))?;
+
+    Ok(AddressLookupTableAccount {
+        key: *address,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+// register_with_sol_deposit for `user_wallet` under `referrer_wallet`, from the current state,
+// the referrer's account and upline, and the pool
+pub fn registration(
+    rpc: &RpcClient,
+    state_address: &Pubkey,
+    user_wallet: &Pubkey,
+    referrer_wallet: &Pubkey,
+    deposit_amount: u64,
+) -> Result<Registration> {
+    if fetch_data(rpc, &pda::user_account(user_wallet))?.is_some() {
+        return Err(Error::AlreadyRegistered(*user_wallet));
+    }
+
+    let state = program_state(rpc, state_address)?;
+    let referrer = user_account(rpc, referrer_wallet)?;
+    let pool = pool(rpc)?;
+
+    Registration::new(RegistrationParams {
+        state_address: *state_address,
+        state: &state,
+        user_wallet: *user_wallet,
+        referrer_wallet: *referrer_wallet,
+        referrer: &referrer,
+        deposit_amount,
+        donut_token_program: donut_token_program(rpc)?,
+        pool: &pool,
+    })
+}
+
+// Payout wallets of a registration whose DONUT ATA doesn't exist yet
+pub fn missing_payout_atas(rpc: &RpcClient, registration: &Registration) -> Result<Vec<Pubkey>> {
+    let accounts = rpc.get_multiple_accounts(&registration.payout_token_accounts())?;
+
+    Ok(registration
+        .payout_wallets
+        .iter()
+        .zip(accounts)
+        .filter(|(_, account)| account.is_none())
+        .map(|(wallet, _)| *wallet)
+        .collect())
+}
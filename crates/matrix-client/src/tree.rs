@@ -0,0 +1,183 @@
+// Referral tree rebuilt from user accounts, exported to Graphviz DOT or JSON
+// Each user hangs under its referrer; referrers missing from the accounts (partial snapshots)
+// are recovered from the users' stored upline, closest entry first
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use anchor_lang::prelude::Pubkey;
+use matrix_system::UserAccount;
+use serde_json::{json, Value};
+
+// Matrix and reservation fields of a node's user account
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeAccount {
+    pub upline_id: u32,
+    pub depth: u8,
+    pub chain_id: u32,
+    pub filled_slots: u8,
+    pub slots: [Option<Pubkey>; 3],
+    pub expires_at: i64,
+    pub reserved_sol: u64,
+    pub reserved_tokens: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeNode {
+    pub pda: Pubkey,
+    pub wallet: Option<Pubkey>,
+    pub referrer: Option<Pubkey>,      // PDA of the referrer, None for roots and unknown ancestors
+    pub account: Option<NodeAccount>,  // None when only known from another user's upline
+    pub children: Vec<usize>,          // Indexes into `nodes`, in registration order
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReferralTree {
+    pub nodes: Vec<TreeNode>,
+    pub roots: Vec<usize>,
+}
+
+impl ReferralTree {
+    pub fn new(accounts: &[(Pubkey, UserAccount)]) -> Self {
+        let mut nodes: Vec<TreeNode> = accounts
+            .iter()
+            .map(|(pda, account)| TreeNode {
+                pda: *pda,
+                wallet: Some(account.owner_wallet),
+                referrer: account.referrer,
+                account: Some(NodeAccount {
+                    upline_id: account.upline.id,
+                    depth: account.upline.depth,
+                    chain_id: account.chain.id,
+                    filled_slots: account.chain.filled_slots,
+                    slots: account.chain.slots,
+                    expires_at: account.matrix_expires_at,
+                    reserved_sol: account.reserved_sol,
+                    reserved_tokens: account.reserved_tokens,
+                }),
+                children: Vec::new(),
+            })
+            .collect();
+
+        let mut index: HashMap<Pubkey, usize> = nodes.iter().enumerate().map(|(position, node)| (node.pda, position)).collect();
+
+        // Placeholders for ancestors without an account; each upline entry's parent is the entry before it
+        for (_, account) in accounts {
+            let entries = &account.upline.upline;
+            for (position, entry) in entries.iter().enumerate().rev() {
+                if index.contains_key(&entry.pda) {
+                    break;
+                }
+
+                index.insert(entry.pda, nodes.len());
+                nodes.push(TreeNode {
+                    pda: entry.pda,
+                    wallet: Some(entry.wallet),
+                    referrer: position.checked_sub(1).map(|parent| entries[parent].pda),
+                    account: None,
+                    children: Vec::new(),
+                });
+            }
+        }
+
+        let mut roots = Vec::new();
+        for position in 0..nodes.len() {
+            match nodes[position].referrer.and_then(|referrer| index.get(&referrer).copied()) {
+                Some(parent) => nodes[parent].children.push(position),
+                None => roots.push(position),
+            }
+        }
+
+        // Upline ids follow registration order; placeholders go last
+        let order = |nodes: &[TreeNode], position: usize| {
+            let node = &nodes[position];
+            (node.account.as_ref().map_or(u32::MAX, |account| account.upline_id), node.pda.to_bytes())
+        };
+        for position in 0..nodes.len() {
+            let mut children = std::mem::take(&mut nodes[position].children);
+            children.sort_by_key(|child| order(&nodes, *child));
+            nodes[position].children = children;
+        }
+        roots.sort_by_key(|root| order(&nodes, *root));
+
+        Self { nodes, roots }
+    }
+
+    pub fn node(&self, pda: &Pubkey) -> Option<&TreeNode> {
+        self.nodes.iter().find(|node| node.pda == *pda)
+    }
+
+    // Graphviz digraph with one record per user: wallet, matrix and reservations
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph referral_tree {\n    rankdir=TB;\n    node [shape=record, fontname=\"monospace\"];\n");
+
+        for node in &self.nodes {
+            let name = short_key(&node.wallet.unwrap_or(node.pda));
+            match &node.account {
+                Some(account) => {
+                    let _ = writeln!(
+                        dot,
+                        "    \"{}\" [label=\"{{{}|chain {} ({}/3)|{} SOL reserved|{} DONUT reserved}}\"];",
+                        node.pda,
+                        name,
+                        account.chain_id,
+                        account.filled_slots,
+                        amount(account.reserved_sol),
+                        amount(account.reserved_tokens),
+                    );
+                },
+                None => {
+                    let _ = writeln!(dot, "    \"{}\" [label=\"{{{}|no account}}\", style=dashed];", node.pda, name);
+                },
+            }
+        }
+
+        for node in &self.nodes {
+            for child in &node.children {
+                let _ = writeln!(dot, "    \"{}\" -> \"{}\";", node.pda, self.nodes[*child].pda);
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Nested JSON: the roots, each with its children
+    pub fn to_json(&self) -> Value {
+        Value::Array(self.roots.iter().map(|root| self.node_json(*root)).collect())
+    }
+
+    fn node_json(&self, position: usize) -> Value {
+        let node = &self.nodes[position];
+        let account = node.account.as_ref().map(|account| {
+            json!({
+                "uplineId": account.upline_id,
+                "depth": account.depth,
+                "chainId": account.chain_id,
+                "filledSlots": account.filled_slots,
+                "slots": account.slots.iter().map(|slot| slot.map(|member| member.to_string())).collect::<Vec<_>>(),
+                "expiresAt": account.expires_at,
+                "reservedSol": account.reserved_sol,
+                "reservedTokens": account.reserved_tokens,
+            })
+        });
+
+        json!({
+            "pda": node.pda.to_string(),
+            "wallet": node.wallet.map(|wallet| wallet.to_string()),
+            "referrer": node.referrer.map(|referrer| referrer.to_string()),
+            "account": account,
+            "children": node.children.iter().map(|child| self.node_json(*child)).collect::<Vec<_>>(),
+        })
+    }
+}
+
+// Function to abbreviate a key for graph labels
+fn short_key(key: &Pubkey) -> String {
+    let key = key.to_string();
+    format!("{}..{}", &key[..4], &key[key.len() - 4..])
+}
+
+// Function to format a 9-decimal amount
+fn amount(value: u64) -> String {
+    format!("{}.{:09}", value / 1_000_000_000, value % 1_000_000_000)
+}
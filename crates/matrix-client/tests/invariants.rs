@@ -0,0 +1,196 @@
+// invariants: a consistent snapshot passes and each broken invariant is reported
+use anchor_lang::prelude::Pubkey;
+use matrix_client::invariants::{check, Snapshot, Violation};
+use matrix_client::pda;
+use matrix_system::{
+    ExpiryPolicy, ProgramState, ReferralChain, ReferralUpline, Slot1Policy, UplineEntry, UserAccount, STATE_LAYOUT_VERSION,
+};
+
+const RENT: u64 = 890_880;
+const RESERVED_SOL: u64 = 100_000_000;
+const RESERVED_TOKENS: u64 = 5_000_000_000;
+
+fn wallet(seed: u8) -> Pubkey {
+    Pubkey::new_from_array([seed; 32])
+}
+
+fn program_state() -> ProgramState {
+    ProgramState {
+        owner: Pubkey::new_unique(),
+        multisig_treasury: Pubkey::new_unique(),
+        next_upline_id: 4,
+        next_chain_id: 4,
+        twap_window: 0,
+        observation_index: 0,
+        observation_count: 0,
+        price_observations: Default::default(),
+        slot1_policy: Slot1Policy::DepositLiquidity,
+        buyback_slippage_bps: 0,
+        treasury_fee_bps: 0,
+        total_reserved_sol: RESERVED_SOL,
+        vesting_duration: 0,
+        staking_fee_bps: 0,
+        matrix_expiry: 0,
+        expiry_policy: ExpiryPolicy::ReleaseToOwner,
+        layout_version: STATE_LAYOUT_VERSION,
+        reserved_sol_reconciled: true,
+    }
+}
+
+// Root 1 with users 2 and 3 in the first two slots of its matrix, holding the slot-2 reservation
+fn snapshot() -> Snapshot {
+    let root = UserAccount {
+        is_registered: true,
+        referrer: None,
+        owner_wallet: wallet(1),
+        upline: ReferralUpline { id: 1, depth: 1, upline: vec![] },
+        chain: ReferralChain {
+            id: 1,
+            slots: [Some(wallet(2)), Some(wallet(3)), None],
+            filled_slots: 2,
+        },
+        reserved_sol: RESERVED_SOL,
+        reserved_tokens: RESERVED_TOKENS,
+        matrix_expires_at: 0,
+    };
+
+    let referred = |seed: u8, id: u32| UserAccount {
+        is_registered: true,
+        referrer: Some(pda::user_account(&wallet(1))),
+        owner_wallet: wallet(seed),
+        upline: ReferralUpline {
+            id,
+            depth: 2,
+            upline: vec![UplineEntry { pda: pda::user_account(&wallet(1)), wallet: wallet(1) }],
+        },
+        chain: ReferralChain { id, ..ReferralChain::default() },
+        reserved_sol: 0,
+        reserved_tokens: 0,
+        matrix_expires_at: 0,
+    };
+
+    Snapshot {
+        state: program_state(),
+        users: vec![
+            (pda::user_account(&wallet(1)), root),
+            (pda::user_account(&wallet(2)), referred(2, 2)),
+            (pda::user_account(&wallet(3)), referred(3, 3)),
+        ],
+        sol_vault_lamports: RENT + RESERVED_SOL,
+        sol_vault_rent_exempt_minimum: RENT,
+        token_vault_balance: RESERVED_TOKENS,
+    }
+}
+
+#[test]
+fn consistent_snapshot_has_no_violations() {
+    assert_eq!(check(&snapshot()), vec![]);
+}
+
+#[test]
+fn vaults_must_cover_the_reservations() {
+    let mut snapshot = snapshot();
+    snapshot.sol_vault_lamports = RENT + RESERVED_SOL - 1;
+    snapshot.token_vault_balance = RESERVED_TOKENS - 1;
+
+    assert_eq!(
+        check(&snapshot),
+        vec![
+            Violation::ReservedSolExceedsVault { reserved: RESERVED_SOL, available: RESERVED_SOL - 1 },
+            Violation::ReservedTokensExceedVault { reserved: RESERVED_TOKENS, balance: RESERVED_TOKENS - 1 },
+        ]
+    );
+}
+
+#[test]
+fn tracked_total_must_match_the_reservations() {
+    let mut snapshot = snapshot();
+    snapshot.state.total_reserved_sol = 0;
+
+    assert_eq!(check(&snapshot), vec![Violation::TotalReservedSolMismatch { tracked: 0, reserved: RESERVED_SOL }]);
+}
+
+#[test]
+fn filled_slots_must_match_the_occupied_slots() {
+    let mut snapshot = snapshot();
+    let user = snapshot.users[1].0;
+    snapshot.users[1].1.chain.slots[1] = Some(wallet(9));
+
+    assert_eq!(
+        check(&snapshot),
+        vec![Violation::SlotsMismatch { user, filled_slots: 0, occupied: [false, true, false] }]
+    );
+
+    // A full matrix is never stored
+    let mut snapshot = self::snapshot();
+    let root = snapshot.users[0].0;
+    snapshot.users[0].1.chain.slots[2] = Some(wallet(4));
+    snapshot.users[0].1.chain.filled_slots = 3;
+
+    let violations = check(&snapshot);
+    assert!(violations.contains(&Violation::SlotsMismatch { user: root, filled_slots: 3, occupied: [true; 3] }));
+}
+
+#[test]
+fn reservations_only_exist_with_two_filled_slots() {
+    let mut snapshot = snapshot();
+    let user = snapshot.users[2].0;
+    snapshot.users[2].1.reserved_tokens = 1;
+    snapshot.token_vault_balance += 1;
+
+    assert_eq!(
+        check(&snapshot),
+        vec![Violation::UnexpectedReservation { user, filled_slots: 0, reserved_sol: 0, reserved_tokens: 1 }]
+    );
+}
+
+#[test]
+fn upline_must_follow_depth_and_referrer() {
+    let mut snapshot = snapshot();
+    let user = snapshot.users[1].0;
+    let entry = snapshot.users[1].1.upline.upline[0].clone();
+    snapshot.users[1].1.upline.upline = vec![entry; 7];
+    snapshot.users[1].1.upline.depth = 8;
+
+    assert_eq!(
+        check(&snapshot),
+        vec![
+            Violation::UplineTooLong { user, len: 7 },
+            Violation::UplineDepthMismatch { user, depth: 8, len: 7 },
+        ]
+    );
+
+    let mut snapshot = self::snapshot();
+    snapshot.users[2].1.referrer = Some(wallet(8));
+
+    assert_eq!(
+        check(&snapshot),
+        vec![Violation::ReferrerNotInUpline {
+            user: snapshot.users[2].0,
+            referrer: Some(wallet(8)),
+            closest: Some(pda::user_account(&wallet(1))),
+        }]
+    );
+}
+
+#[test]
+fn ids_must_be_unique_and_below_the_counters() {
+    let mut snapshot = snapshot();
+    let (second, third) = (snapshot.users[1].0, snapshot.users[2].0);
+    snapshot.users[2].1.chain.id = 2;
+    snapshot.users[1].1.upline.id = 9;
+
+    assert_eq!(
+        check(&snapshot),
+        vec![
+            Violation::UplineIdOutOfRange { user: second, upline_id: 9, next_upline_id: 4 },
+            Violation::DuplicateChainId { chain_id: 2, users: vec![second, third] },
+        ]
+    );
+
+    let mut snapshot = self::snapshot();
+    snapshot.state.next_chain_id = 3;
+    let third = snapshot.users[2].0;
+
+    assert_eq!(check(&snapshot), vec![Violation::ChainIdOutOfRange { user: third, chain_id: 3, next_chain_id: 3 }]);
+}
@@ -0,0 +1,296 @@
+// registration: account layout of the instruction builders and lookup table suggestions
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::address_lookup_table::AddressLookupTableAccount;
+use anchor_lang::InstructionData;
+use matrix_client::{lookup_table, pda, root_registration_instructions, Error, Registration, RegistrationParams};
+use matrix_system::meteora::{Pool, PoolFees};
+use matrix_system::{
+    instruction, verified_addresses, ExpiryPolicy, ProgramState, ReferralChain, ReferralUpline, Slot1Policy,
+    UplineEntry, UserAccount, MAX_UPLINE_DEPTH, STATE_LAYOUT_VERSION,
+};
+
+const DEPOSIT: u64 = 200_000_000;
+
+const STATE: Pubkey = Pubkey::new_from_array([7; 32]);
+
+// Vault A of the pool, the same for every registration
+const A_VAULT: Pubkey = Pubkey::new_from_array([8; 32]);
+
+fn program_state() -> ProgramState {
+    ProgramState {
+        owner: Pubkey::new_unique(),
+        multisig_treasury: Pubkey::new_unique(),
+        next_upline_id: 1,
+        next_chain_id: 1,
+        twap_window: 1800,
+        observation_index: 0,
+        observation_count: 0,
+        price_observations: Default::default(),
+        slot1_policy: Slot1Policy::DepositLiquidity,
+        buyback_slippage_bps: 100,
+        treasury_fee_bps: 0,
+        total_reserved_sol: 0,
+        vesting_duration: 0,
+        staking_fee_bps: 0,
+        matrix_expiry: 0,
+        expiry_policy: ExpiryPolicy::ReleaseToOwner,
+        layout_version: STATE_LAYOUT_VERSION,
+        reserved_sol_reconciled: true,
+    }
+}
+
+// Registered referrer with `uplines` stored uplines (oldest first) and `filled_slots` slots taken
+fn referrer(uplines: usize, filled_slots: u8) -> UserAccount {
+    let upline = (0..uplines)
+        .map(|_| {
+            let wallet = Pubkey::new_unique();
+            UplineEntry { pda: pda::user_account(&wallet), wallet }
+        })
+        .collect();
+
+    UserAccount {
+        is_registered: true,
+        referrer: Some(Pubkey::new_unique()),
+        owner_wallet: Pubkey::new_unique(),
+        upline: ReferralUpline { id: 1, depth: uplines as u8 + 1, upline },
+        chain: ReferralChain { id: 1, slots: [None; 3], filled_slots },
+        reserved_sol: 0,
+        reserved_tokens: 0,
+        matrix_expires_at: 0,
+    }
+}
+
+fn pool() -> Pool {
+    Pool {
+        lp_mint: Pubkey::new_unique(),
+        token_a_mint: verified_addresses::TOKEN_MINT,
+        token_b_mint: verified_addresses::WSOL_MINT,
+        a_vault: A_VAULT,
+        b_vault: verified_addresses::B_VAULT,
+        a_vault_lp: verified_addresses::A_VAULT_LP,
+        b_vault_lp: verified_addresses::B_VAULT_LP,
+        a_vault_lp_bump: 255,
+        enabled: true,
+        protocol_token_a_fee: Pubkey::new_unique(),
+        protocol_token_b_fee: Pubkey::new_unique(),
+        fee_last_updated_at: 0,
+        padding0: [0; 24],
+        fees: PoolFees::default(),
+    }
+}
+
+struct Setup {
+    state: ProgramState,
+    referrer: UserAccount,
+    referrer_wallet: Pubkey,
+    user_wallet: Pubkey,
+    pool: Pool,
+}
+
+impl Setup {
+    fn new(uplines: usize, filled_slots: u8) -> Self {
+        Self {
+            state: program_state(),
+            referrer: referrer(uplines, filled_slots),
+            referrer_wallet: Pubkey::new_unique(),
+            user_wallet: Pubkey::new_unique(),
+            pool: pool(),
+        }
+    }
+
+    fn build(&self) -> matrix_client::Result<Registration> {
+        Registration::new(RegistrationParams {
+            state_address: STATE,
+            state: &self.state,
+            user_wallet: self.user_wallet,
+            referrer_wallet: self.referrer_wallet,
+            referrer: &self.referrer,
+            deposit_amount: DEPOSIT,
+            donut_token_program: spl_token::ID,
+            pool: &self.pool,
+        })
+    }
+}
+
+#[test]
+fn pdas_use_the_program_seeds() {
+    let wallet = Pubkey::new_unique();
+    let find = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, &matrix_system::ID).0;
+
+    assert_eq!(pda::user_account(&wallet), find(&[b"user_account", wallet.as_ref()]));
+    assert_eq!(pda::vesting_account(&wallet), find(&[b"vesting", wallet.as_ref()]));
+    assert_eq!(pda::program_sol_vault(), find(&[b"program_sol_vault"]));
+    assert_eq!(pda::token_mint_authority(), find(&[b"token_mint_authority"]));
+    assert_eq!(pda::token_vault_authority(), find(&[b"token_vault_authority"]));
+    assert_eq!(pda::mint_budget(), find(&[b"mint_budget"]));
+    assert_eq!(pda::staking_pool(), find(&[b"staking_pool"]));
+    assert_eq!(pda::staking_reward_vault(), find(&[b"staking_reward_vault"]));
+}
+
+#[test]
+fn slots_one_and_two_pass_only_the_fixed_remaining_accounts() {
+    for filled_slots in [0, 1] {
+        let setup = Setup::new(MAX_UPLINE_DEPTH, filled_slots);
+        let registration = setup.build().unwrap();
+
+        let remaining: Vec<Pubkey> = registration.remaining_accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(remaining, vec![
+            verified_addresses::A_VAULT_LP,
+            verified_addresses::A_VAULT_LP_MINT,
+            verified_addresses::A_TOKEN_VAULT,
+            A_VAULT,
+            verified_addresses::SOL_USD_FEED,
+            verified_addresses::CHAINLINK_PROGRAM,
+        ]);
+        assert!(registration.remaining_accounts.iter().all(|meta| !meta.is_writable && !meta.is_signer));
+        assert_eq!(registration.payout_wallets, vec![registration.accounts.referrer_wallet]);
+    }
+}
+
+#[test]
+fn completing_a_matrix_adds_upline_trios_closest_first() {
+    for uplines in 0..=MAX_UPLINE_DEPTH {
+        let setup = Setup::new(uplines, 2);
+        let registration = setup.build().unwrap();
+
+        let trios = &registration.remaining_accounts[matrix_client::register::FIXED_REMAINING_ACCOUNTS..];
+        assert_eq!(trios.len(), uplines * 3);
+
+        for (trio, entry) in trios.chunks(3).zip(setup.referrer.upline.upline.iter().rev()) {
+            assert_eq!(trio[0].pubkey, entry.pda);
+            assert_eq!(trio[1].pubkey, entry.wallet);
+            assert_eq!(trio[2].pubkey, pda::donut_ata(&entry.wallet, &spl_token::ID));
+            assert!(trio.iter().all(|meta| meta.is_writable && !meta.is_signer));
+        }
+
+        let mut payout_wallets = vec![setup.referrer_wallet];
+        payout_wallets.extend(setup.referrer.upline.upline.iter().rev().map(|entry| entry.wallet));
+        assert_eq!(registration.payout_wallets, payout_wallets);
+    }
+}
+
+#[test]
+fn vesting_passes_vesting_pdas_instead_of_atas() {
+    let mut setup = Setup::new(3, 2);
+    setup.state.vesting_duration = 86_400;
+    let registration = setup.build().unwrap();
+
+    assert_eq!(registration.accounts.referrer_vesting, Some(pda::vesting_account(&setup.referrer_wallet)));
+    for (trio, entry) in registration.remaining_accounts[matrix_client::register::FIXED_REMAINING_ACCOUNTS..].chunks(3).zip(setup.referrer.upline.upline.iter().rev()) {
+        assert_eq!(trio[2].pubkey, pda::vesting_account(&entry.wallet));
+    }
+
+    // Only the referrer's ATA is still passed
+    assert_eq!(registration.payout_wallets, vec![setup.referrer_wallet]);
+}
+
+#[test]
+fn optional_accounts_follow_the_state() {
+    let setup = Setup::new(1, 0);
+    let registration = setup.build().unwrap();
+    assert_eq!(registration.accounts.treasury_wallet, None);
+    assert_eq!(registration.accounts.staking_pool, None);
+    assert_eq!(registration.accounts.staking_reward_vault, None);
+    assert_eq!(registration.accounts.protocol_token_b_fee, None);
+    assert_eq!(registration.accounts.referrer_vesting, None);
+
+    let mut setup = Setup::new(1, 0);
+    setup.state.treasury_fee_bps = 100;
+    setup.state.staking_fee_bps = 500;
+    let registration = setup.build().unwrap();
+    assert_eq!(registration.accounts.treasury_wallet, Some(setup.state.multisig_treasury));
+    assert_eq!(registration.accounts.staking_pool, Some(pda::staking_pool()));
+    assert_eq!(registration.accounts.staking_reward_vault, Some(pda::staking_reward_vault()));
+}
+
+#[test]
+fn buyback_policies_pass_the_pool_accounts() {
+    let mut setup = Setup::new(1, 0);
+    setup.state.slot1_policy = Slot1Policy::BuybackAndBurn;
+    let registration = setup.build().unwrap();
+    assert_eq!(registration.accounts.protocol_token_b_fee, Some(setup.pool.protocol_token_b_fee));
+    assert_eq!(registration.accounts.amm_program, Some(verified_addresses::AMM_PROGRAM));
+    assert_eq!(registration.accounts.treasury_token_account, None);
+
+    // The swap writes to vault A
+    let vault_a = &registration.remaining_accounts[..matrix_system::VAULT_A_ACCOUNTS_COUNT];
+    assert!(vault_a.iter().all(|meta| meta.is_writable && !meta.is_signer));
+
+    setup.state.slot1_policy = Slot1Policy::BuybackToTreasury;
+    let registration = setup.build().unwrap();
+    assert_eq!(
+        registration.accounts.treasury_token_account,
+        Some(pda::donut_ata(&setup.state.multisig_treasury, &spl_token::ID))
+    );
+}
+
+#[test]
+fn unregistered_referrer_is_rejected() {
+    let mut setup = Setup::new(1, 0);
+    setup.referrer.is_registered = false;
+    assert!(matches!(setup.build(), Err(Error::NotRegistered(wallet)) if wallet == setup.referrer_wallet));
+}
+
+#[test]
+fn instruction_appends_remaining_accounts_after_the_named_accounts() {
+    let registration = Setup::new(MAX_UPLINE_DEPTH, 2).build().unwrap();
+    let instruction = registration.instruction();
+
+    assert_eq!(instruction.program_id, matrix_system::ID);
+    assert_eq!(instruction.data, instruction::RegisterWithSolDeposit { deposit_amount: DEPOSIT }.data());
+
+    let named = instruction.accounts.len() - registration.remaining_accounts.len();
+    assert_eq!(&instruction.accounts[named..], registration.remaining_accounts.as_slice());
+    assert_eq!(instruction.accounts[1].pubkey, registration.accounts.user_wallet);
+    assert!(instruction.accounts[1].is_signer);
+    assert_eq!(instruction.accounts.iter().filter(|meta| meta.is_signer).count(), 1);
+}
+
+#[test]
+fn lookup_suggestions_split_shared_and_referrer_accounts() {
+    let registration = Setup::new(MAX_UPLINE_DEPTH, 2).build().unwrap();
+    let suggestions = registration.lookup_table_suggestions();
+
+    let user = registration.user_addresses();
+    for meta in registration.instruction().accounts {
+        let suggested = suggestions.all().contains(&meta.pubkey);
+        let expected = meta.pubkey != matrix_system::ID && !user.contains(&meta.pubkey);
+        assert_eq!(suggested, expected, "{}", meta.pubkey);
+    }
+
+    assert!(suggestions.referrer.contains(&registration.accounts.referrer_wallet));
+    assert_eq!(suggestions.referrer.len(), 3 + MAX_UPLINE_DEPTH * 3);
+    assert!(suggestions.shared.iter().all(|address| !suggestions.referrer.contains(address)));
+
+    // Shared accounts don't depend on the referrer or the user
+    let other = Setup::new(2, 2).build().unwrap();
+    assert_eq!(other.lookup_table_suggestions().shared, suggestions.shared);
+
+    let table = AddressLookupTableAccount { key: Pubkey::new_unique(), addresses: suggestions.shared.clone() };
+    assert_eq!(suggestions.missing_from(&[table]), suggestions.referrer);
+}
+
+#[test]
+fn lookup_table_creation_extends_in_chunks() {
+    let addresses: Vec<Pubkey> = (0..45).map(|_| Pubkey::new_unique()).collect();
+    let authority = Pubkey::new_unique();
+
+    let (table, instructions) = lookup_table::create_instructions(&authority, &authority, 1_000, &addresses);
+    assert_eq!(instructions.len(), 1 + addresses.len().div_ceil(lookup_table::MAX_ADDRESSES_PER_EXTEND));
+    assert!(instructions.iter().all(|instruction| instruction.accounts[0].pubkey == table));
+}
+
+#[test]
+fn root_registration_wraps_the_deposit_first() {
+    let treasury = Pubkey::new_unique();
+    let user = Pubkey::new_unique();
+
+    let instructions = root_registration_instructions(&STATE, &treasury, &user, DEPOSIT);
+    assert_eq!(instructions.len(), 3);
+    assert_eq!(instructions[0].program_id, anchor_spl::associated_token::ID);
+    assert_eq!(instructions[1].accounts[1].pubkey, pda::wsol_ata(&user));
+    assert_eq!(instructions[2].data, instruction::RegisterWithoutReferrer { deposit_amount: DEPOSIT }.data());
+
+    let signers: Vec<Pubkey> = instructions[2].accounts.iter().filter(|meta| meta.is_signer).map(|meta| meta.pubkey).collect();
+    assert_eq!(signers, vec![treasury, user]);
+}
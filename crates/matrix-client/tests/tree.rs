@@ -0,0 +1,140 @@
+// tree: referral tree reconstruction, placeholders from the upline and DOT / JSON export
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountSerialize;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use matrix_client::{pda, rpc, ReferralTree};
+use matrix_system::{ReferralChain, ReferralUpline, UplineEntry, UserAccount};
+
+fn wallet(seed: u8) -> Pubkey {
+    Pubkey::new_from_array([seed; 32])
+}
+
+fn entry(seed: u8) -> UplineEntry {
+    UplineEntry {
+        pda: pda::user_account(&wallet(seed)),
+        wallet: wallet(seed),
+    }
+}
+
+// User `seed` registered as the `id`th user, with its upline oldest first
+fn user(seed: u8, id: u32, upline: &[u8]) -> (Pubkey, UserAccount) {
+    let account = UserAccount {
+        is_registered: true,
+        referrer: upline.last().map(|referrer| pda::user_account(&wallet(*referrer))),
+        owner_wallet: wallet(seed),
+        upline: ReferralUpline {
+            id,
+            depth: upline.len() as u8 + 1,
+            upline: upline.iter().map(|seed| entry(*seed)).collect(),
+        },
+        chain: ReferralChain {
+            id: 100 + id,
+            slots: [Some(wallet(seed + 100)), None, None],
+            filled_slots: 1,
+        },
+        reserved_sol: id as u64 * 1_000,
+        reserved_tokens: 0,
+        matrix_expires_at: 0,
+    };
+    (pda::user_account(&wallet(seed)), account)
+}
+
+// Root 1 with users 2 and 3 below it, and 4 below 2; listed out of order
+fn accounts() -> Vec<(Pubkey, UserAccount)> {
+    vec![user(4, 4, &[1, 2]), user(3, 3, &[1]), user(1, 1, &[]), user(2, 2, &[1])]
+}
+
+#[test]
+fn users_hang_under_their_referrer_in_registration_order() {
+    let tree = ReferralTree::new(&accounts());
+
+    assert_eq!(tree.roots.len(), 1);
+    let root = &tree.nodes[tree.roots[0]];
+    assert_eq!(root.wallet, Some(wallet(1)));
+
+    let children: Vec<_> = root.children.iter().map(|child| tree.nodes[*child].wallet.unwrap()).collect();
+    assert_eq!(children, vec![wallet(2), wallet(3)]);
+
+    let second = tree.node(&pda::user_account(&wallet(2))).unwrap();
+    assert_eq!(second.children.len(), 1);
+    assert_eq!(tree.nodes[second.children[0]].wallet, Some(wallet(4)));
+
+    let account = second.account.as_ref().unwrap();
+    assert_eq!((account.chain_id, account.filled_slots, account.reserved_sol), (102, 1, 2_000));
+}
+
+#[test]
+fn missing_referrers_come_from_the_upline() {
+    // Only user 4 is in the snapshot: 2 and 1 are recovered from its upline
+    let tree = ReferralTree::new(&[user(4, 4, &[1, 2])]);
+
+    assert_eq!(tree.nodes.len(), 3);
+    assert_eq!(tree.roots.len(), 1);
+
+    let root = &tree.nodes[tree.roots[0]];
+    assert_eq!(root.pda, pda::user_account(&wallet(1)));
+    assert!(root.account.is_none());
+
+    let middle = tree.node(&pda::user_account(&wallet(2))).unwrap();
+    assert_eq!(middle.referrer, Some(root.pda));
+    assert!(middle.account.is_none());
+    assert_eq!(tree.nodes[middle.children[0]].wallet, Some(wallet(4)));
+}
+
+#[test]
+fn dot_has_a_record_per_user_and_an_edge_per_referral() {
+    let dot = ReferralTree::new(&accounts()).to_dot();
+
+    assert!(dot.starts_with("digraph referral_tree {"));
+    assert_eq!(dot.matches(" -> ").count(), 3);
+    assert!(dot.contains(&format!("\"{}\" -> \"{}\";", pda::user_account(&wallet(2)), pda::user_account(&wallet(4)))));
+    assert!(dot.contains("chain 103 (1/3)|0.000003000 SOL reserved"));
+
+    let partial = ReferralTree::new(&[user(4, 4, &[1, 2])]).to_dot();
+    assert_eq!(partial.matches("style=dashed").count(), 2);
+}
+
+#[test]
+fn json_nests_children_with_matrix_fields() {
+    let json = ReferralTree::new(&accounts()).to_json();
+
+    let roots = json.as_array().unwrap();
+    assert_eq!(roots.len(), 1);
+    assert_eq!(roots[0]["wallet"], wallet(1).to_string());
+    assert_eq!(roots[0]["account"]["chainId"], 101);
+
+    let second = &roots[0]["children"][0];
+    assert_eq!(second["wallet"], wallet(2).to_string());
+    assert_eq!(second["account"]["slots"][0], wallet(102).to_string());
+    assert_eq!(second["account"]["slots"][1], serde_json::Value::Null);
+    assert_eq!(second["children"][0]["account"]["reservedSol"], 4_000);
+}
+
+#[test]
+fn snapshot_files_decode_user_accounts() {
+    let keyed: Vec<_> = accounts()
+        .iter()
+        .map(|(address, account)| {
+            let mut data = Vec::new();
+            account.try_serialize(&mut data).unwrap();
+            serde_json::json!({
+                "pubkey": address.to_string(),
+                "account": {
+                    "lamports": 1_000_000,
+                    "data": [STANDARD.encode(&data), "base64"],
+                    "owner": matrix_system::ID.to_string(),
+                    "executable": false,
+                    "rentEpoch": 0,
+                    "space": data.len(),
+                },
+            })
+        })
+        .collect();
+
+    let users = rpc::user_accounts_from_snapshot(&serde_json::to_string(&keyed).unwrap()).unwrap();
+    assert_eq!(users.len(), 4);
+    assert_eq!(ReferralTree::new(&users), ReferralTree::new(&accounts()));
+
+    assert!(rpc::user_accounts_from_snapshot("{}").is_err());
+}
@@ -0,0 +1,50 @@
+// Anchor events of the program
+// An event is logged as "Program data: <base64>" holding its 8-byte discriminator and Borsh fields
+use anchor_lang::{AnchorDeserialize, Discriminator};
+
+// Events the indexer decodes; a new event only needs its name added here
+macro_rules! matrix_events {
+    ($($name:ident),* $(,)?) => {
+        pub enum MatrixEvent {
+            $($name(matrix_system::$name),)*
+        }
+
+        impl MatrixEvent {
+            // Decode an event from its logged data, None for unknown discriminators
+            pub fn decode(data: &[u8]) -> Option<Self> {
+                if data.len() < 8 {
+                    return None;
+                }
+
+                let (discriminator, mut fields) = data.split_at(8);
+                $(
+                    if discriminator == matrix_system::$name::DISCRIMINATOR {
+                        return AnchorDeserialize::deserialize(&mut fields).ok().map(Self::$name);
+                    }
+                )*
+
+                None
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Self::$name(_) => stringify!($name),)*
+                }
+            }
+        }
+    };
+}
+
+matrix_events!(
+    SlotFilled,
+    SlotOneBuyback,
+    TreasuryFeeCollected,
+    ExcessSolSwept,
+    ReservedSolReconciled,
+    TokensVested,
+    StakingRewardsDistributed,
+    StakeUpdated,
+    StakingRewardsClaimed,
+    MatrixExpired,
+    VestedTokensClaimed,
+);
@@ -0,0 +1,13 @@
+// Event indexer for the referral matrix
+// Program transactions and UserAccount snapshots, from JSON-RPC or dump files,
+// are materialized into SQLite tables for users, matrices, slot fills and payouts
+pub mod events;
+pub mod queries;
+pub mod source;
+pub mod store;
+pub mod transaction;
+
+pub use events::MatrixEvent;
+pub use queries::{DownlineMember, Earnings};
+pub use store::Store;
+pub use transaction::TransactionRecord;
@@ -0,0 +1,187 @@
+// matrix-indexer: index program events and user accounts into SQLite and query the tree
+use std::path::PathBuf;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use matrix_client::pda;
+use matrix_indexer::{source, Store, TransactionRecord};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+
+/// Index the referral matrix into SQLite
+#[derive(Parser, Debug)]
+#[command(name = "matrix-indexer", version)]
+struct Args {
+    /// SQLite database, created if missing
+    #[arg(long, global = true, default_value = "matrix-index.sqlite")]
+    db: PathBuf,
+
+    /// RPC URL or moniker (localhost, devnet, testnet, mainnet-beta), defaults to the Solana CLI config
+    #[arg(long, short = 'u', global = true)]
+    url: Option<String>,
+
+    #[arg(long, global = true, default_value = "confirmed")]
+    commitment: CommitmentConfig,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Index program transactions, from the RPC (resuming after the last indexed one) or a ledger dump
+    Index {
+        /// Ledger dump: one getTransaction result per line
+        #[arg(long)]
+        ledger: Option<PathBuf>,
+    },
+
+    /// Store snapshots of every user account, from the RPC or an accounts dump
+    Snapshot {
+        /// Accounts dump: a getProgramAccounts result
+        #[arg(long)]
+        accounts: Option<PathBuf>,
+    },
+
+    /// Write the program's transactions and user accounts from the RPC to dump files
+    Dump {
+        #[arg(long, default_value = "ledger.jsonl")]
+        ledger: PathBuf,
+
+        #[arg(long, default_value = "accounts.json")]
+        accounts: PathBuf,
+    },
+
+    /// Users referred by a wallet, recursively
+    Downline {
+        wallet: Pubkey,
+
+        /// Levels to include, all when omitted
+        #[arg(long)]
+        depth: Option<u32>,
+    },
+
+    /// Slot-3 payouts received by a wallet
+    Earnings {
+        wallet: Pubkey,
+    },
+}
+
+// Function to resolve an RPC moniker to its URL
+fn normalize_url(url: &str) -> String {
+    match url {
+        "localhost" | "l" => "http://localhost:8899".to_string(),
+        "devnet" | "d" => "https://api.devnet.solana.com".to_string(),
+        "testnet" | "t" => "https://api.testnet.solana.com".to_string(),
+        "mainnet-beta" | "m" => "https://api.mainnet-beta.solana.com".to_string(),
+        url => url.to_string(),
+    }
+}
+
+fn field(label: &str, value: impl std::fmt::Display) {
+    println!("  {:<26}{}", format!("{}:", label), value);
+}
+
+fn rpc_client(args: &Args) -> RpcClient {
+    let url = match &args.url {
+        Some(url) => url.clone(),
+        None => solana_cli_config::CONFIG_FILE.as_ref()
+            .and_then(|file| solana_cli_config::Config::load(file).ok())
+            .unwrap_or_default()
+            .json_rpc_url,
+    };
+
+    RpcClient::new_with_commitment(normalize_url(&url), args.commitment)
+}
+
+fn run(args: Args) -> Result<()> {
+    let mut store = Store::open(&args.db)?;
+
+    match &args.command {
+        Command::Index { ledger } => {
+            let transactions = match ledger {
+                Some(path) => source::read_ledger(path)?,
+                None => source::fetch_transactions(&rpc_client(&args), store.latest_signature()?.as_deref())?,
+            };
+
+            let mut indexed = 0;
+            let mut events = 0;
+            for transaction in &transactions {
+                let Some(record) = TransactionRecord::from_encoded(transaction) else {
+                    continue;
+                };
+                if store.is_indexed(&record.signature)? {
+                    continue;
+                }
+
+                events += store.ingest_transaction(&record)?;
+                indexed += 1;
+            }
+
+            println!("Indexed {} transactions ({} events) into {}", indexed, events, args.db.display());
+        },
+        Command::Snapshot { accounts } => {
+            let (accounts, slot) = match accounts {
+                Some(path) => (source::read_accounts(path)?, 0),
+                None => {
+                    let rpc = rpc_client(&args);
+                    let slot = rpc.get_slot()?;
+                    (source::fetch_user_accounts(&rpc)?, slot)
+                },
+            };
+
+            let users = source::decode_user_accounts(&accounts);
+            for (address, user) in &users {
+                store.ingest_user_account(address, user, slot)?;
+            }
+
+            println!("Stored {} user accounts into {}", users.len(), args.db.display());
+        },
+        Command::Dump { ledger, accounts } => {
+            let rpc = rpc_client(&args);
+
+            let transactions = source::fetch_transactions(&rpc, None)?;
+            source::write_ledger(ledger, &transactions)?;
+            println!("Wrote {} transactions to {}", transactions.len(), ledger.display());
+
+            let user_accounts = source::fetch_user_accounts(&rpc)?;
+            source::write_accounts(accounts, &user_accounts)?;
+            println!("Wrote {} user accounts to {}", user_accounts.len(), accounts.display());
+        },
+        Command::Downline { wallet, depth } => {
+            let members = store.downline(&pda::user_account(wallet), *depth)?;
+
+            println!("Downline of {} ({} users)", wallet, members.len());
+            for member in members {
+                println!(
+                    "  {:<6}{:<46}referrer {}",
+                    member.level,
+                    member.wallet.unwrap_or(member.pda),
+                    member.referrer_pda,
+                );
+            }
+        },
+        Command::Earnings { wallet } => {
+            let earnings = store.earnings(&pda::user_account(wallet))?;
+
+            println!("Earnings of {}", wallet);
+            field("Completed matrices", earnings.completed_matrices);
+            field("SOL", format!("{}.{:09} SOL", earnings.sol / 1_000_000_000, earnings.sol % 1_000_000_000));
+            field("DONUT", format!("{}.{:09} DONUT", earnings.donut / 1_000_000_000, earnings.donut % 1_000_000_000));
+            field("Vested DONUT", format!("{}.{:09} DONUT", earnings.vested / 1_000_000_000, earnings.vested % 1_000_000_000));
+            if earnings.unresolved_payouts > 0 {
+                field("Payouts without amounts", earnings.unresolved_payouts);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("Error: {:#}", error);
+        std::process::exit(1);
+    }
+}
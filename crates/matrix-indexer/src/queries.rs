@@ -0,0 +1,74 @@
+// Downline and earnings queries over the indexed tree
+use anchor_lang::prelude::Pubkey;
+use rusqlite::{params, Result};
+
+use crate::store::Store;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DownlineMember {
+    pub pda: String,
+    pub wallet: Option<String>,
+    pub referrer_pda: String,
+    pub level: u32,                  // 1 for direct referrals
+    pub registered_at: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Earnings {
+    pub completed_matrices: u64,
+    pub sol: u64,                    // Lamports paid to the wallet
+    pub donut: u64,                  // DONUT paid to the wallet's ATA
+    pub vested: u64,                 // DONUT credited to the vesting account
+    pub unresolved_payouts: u64,     // Payouts whose wallet wasn't known, without amounts
+}
+
+impl Store {
+    // Users referred by `owner` and, recursively, by them; up to `max_depth` levels when given
+    pub fn downline(&self, owner: &Pubkey, max_depth: Option<u32>) -> Result<Vec<DownlineMember>> {
+        let mut statement = self.conn.prepare(
+            "WITH RECURSIVE tree (pda, level) AS (
+                SELECT pda, 1 FROM users WHERE referrer_pda = ?1
+                UNION ALL
+                SELECT users.pda, tree.level + 1 FROM users JOIN tree ON users.referrer_pda = tree.pda
+                WHERE ?2 IS NULL OR tree.level < ?2
+             )
+             SELECT users.pda, users.wallet, users.referrer_pda, tree.level, users.registered_at
+             FROM tree JOIN users ON users.pda = tree.pda
+             ORDER BY tree.level, users.registered_slot, users.pda",
+        )?;
+
+        let rows = statement.query_map(params![owner.to_string(), max_depth], |row| {
+            Ok(DownlineMember {
+                pda: row.get(0)?,
+                wallet: row.get(1)?,
+                referrer_pda: row.get(2)?,
+                level: row.get(3)?,
+                registered_at: row.get(4)?,
+            })
+        })?;
+
+        rows.collect()
+    }
+
+    // Slot-3 payouts received by `owner`
+    pub fn earnings(&self, owner: &Pubkey) -> Result<Earnings> {
+        self.conn.query_row(
+            "SELECT COUNT(*),
+                    COALESCE(SUM(sol_amount), 0),
+                    COALESCE(SUM(donut_amount), 0),
+                    COALESCE(SUM(vested_amount), 0),
+                    COUNT(*) - COUNT(wallet)
+             FROM payouts WHERE owner_pda = ?1",
+            [owner.to_string()],
+            |row| {
+                Ok(Earnings {
+                    completed_matrices: row.get::<_, i64>(0)? as u64,
+                    sol: row.get::<_, i64>(1)? as u64,
+                    donut: row.get::<_, i64>(2)? as u64,
+                    vested: row.get::<_, i64>(3)? as u64,
+                    unresolved_payouts: row.get::<_, i64>(4)? as u64,
+                })
+            },
+        )
+    }
+}
@@ -0,0 +1,157 @@
+// Transaction and account sources: a JSON-RPC endpoint or local dump files
+// A ledger dump holds one getTransaction result (JSON encoding) per line;
+// an accounts dump holds a getProgramAccounts result (base64 encoding)
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AccountDeserialize, Discriminator};
+use anyhow::{Context, Result};
+use matrix_system::UserAccount;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcTransactionConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_response::RpcKeyedAccount;
+use solana_sdk::account::Account;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+
+// Signatures per getSignaturesForAddress page, the RPC maximum
+const SIGNATURES_PAGE: usize = 1_000;
+
+// Transactions of the program after `until` (all when None), oldest first
+pub fn fetch_transactions(rpc: &RpcClient, until: Option<&str>) -> Result<Vec<EncodedConfirmedTransactionWithStatusMeta>> {
+    let until = until.map(Signature::from_str).transpose().context("Invalid resume signature")?;
+
+    // Pages run newest to oldest
+    let mut signatures = Vec::new();
+    let mut before = None;
+    loop {
+        let page = rpc.get_signatures_for_address_with_config(
+            &matrix_system::ID,
+            GetConfirmedSignaturesForAddress2Config {
+                before,
+                until,
+                limit: Some(SIGNATURES_PAGE),
+                commitment: Some(rpc.commitment()),
+            },
+        )?;
+
+        let Some(last) = page.last() else {
+            break;
+        };
+        before = Some(Signature::from_str(&last.signature)?);
+
+        let full = page.len() == SIGNATURES_PAGE;
+        signatures.extend(page.into_iter().map(|status| status.signature));
+        if !full {
+            break;
+        }
+    }
+
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(rpc.commitment()),
+        max_supported_transaction_version: Some(0),
+    };
+
+    signatures
+        .iter()
+        .rev()
+        .map(|signature| {
+            let signature = Signature::from_str(signature)?;
+            rpc.get_transaction_with_config(&signature, config)
+                .with_context(|| format!("Can't fetch transaction {}", signature))
+        })
+        .collect()
+}
+
+// User accounts of the program
+pub fn fetch_user_accounts(rpc: &RpcClient) -> Result<Vec<(Pubkey, Account)>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, UserAccount::DISCRIMINATOR.to_vec()))]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(rpc.commitment()),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    Ok(rpc.get_program_accounts_with_config(&matrix_system::ID, config)?)
+}
+
+// Decode user accounts, skipping other program accounts
+pub fn decode_user_accounts(accounts: &[(Pubkey, Account)]) -> Vec<(Pubkey, UserAccount)> {
+    accounts
+        .iter()
+        .filter_map(|(address, account)| {
+            UserAccount::try_deserialize(&mut account.data.as_slice())
+                .ok()
+                .map(|user| (*address, user))
+        })
+        .collect()
+}
+
+pub fn read_ledger(path: &Path) -> Result<Vec<EncodedConfirmedTransactionWithStatusMeta>> {
+    let file = File::open(path).with_context(|| format!("Can't read {}", path.display()))?;
+
+    let mut transactions = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let transaction = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid transaction at {}:{}", path.display(), number + 1))?;
+        transactions.push(transaction);
+    }
+
+    Ok(transactions)
+}
+
+pub fn write_ledger(path: &Path, transactions: &[EncodedConfirmedTransactionWithStatusMeta]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path).with_context(|| format!("Can't write {}", path.display()))?);
+    for transaction in transactions {
+        serde_json::to_writer(&mut writer, transaction)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_accounts(path: &Path) -> Result<Vec<(Pubkey, Account)>> {
+    let data = fs::read_to_string(path).with_context(|| format!("Can't read {}", path.display()))?;
+    let keyed: Vec<RpcKeyedAccount> =
+        serde_json::from_str(&data).with_context(|| format!("Invalid accounts file {}", path.display()))?;
+
+    keyed
+        .iter()
+        .map(|keyed| {
+            let address = Pubkey::from_str(&keyed.pubkey)?;
+            let account = keyed
+                .account
+                .decode::<Account>()
+                .with_context(|| format!("Can't decode account {}", address))?;
+            Ok((address, account))
+        })
+        .collect()
+}
+
+pub fn write_accounts(path: &Path, accounts: &[(Pubkey, Account)]) -> Result<()> {
+    let keyed: Vec<RpcKeyedAccount> = accounts
+        .iter()
+        .map(|(address, account)| RpcKeyedAccount {
+            pubkey: address.to_string(),
+            account: UiAccount::encode(address, account, UiAccountEncoding::Base64, None, None),
+        })
+        .collect();
+
+    let writer = BufWriter::new(File::create(path).with_context(|| format!("Can't write {}", path.display()))?);
+    serde_json::to_writer_pretty(writer, &keyed)?;
+    Ok(())
+}
@@ -0,0 +1,378 @@
+// SQLite store of the referral tree
+// Events fill slot_fills, payouts and the matrices and users they touch; UserAccount snapshots
+// complete the users (root users, upline ids, reservations) and their current matrices
+use std::path::Path;
+
+use anchor_lang::prelude::Pubkey;
+use matrix_client::pda;
+use matrix_system::{verified_addresses, UserAccount};
+use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
+
+use crate::events::MatrixEvent;
+use crate::transaction::TransactionRecord;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+    signature TEXT PRIMARY KEY,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    succeeded INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS events (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    name TEXT,
+    data BLOB NOT NULL,
+    PRIMARY KEY (signature, event_index)
+);
+
+CREATE TABLE IF NOT EXISTS users (
+    pda TEXT PRIMARY KEY,
+    wallet TEXT UNIQUE,
+    referrer_pda TEXT,
+    upline_id INTEGER,
+    depth INTEGER,
+    chain_id INTEGER,
+    filled_slots INTEGER,
+    reserved_sol INTEGER,
+    reserved_tokens INTEGER,
+    registered_signature TEXT,
+    registered_slot INTEGER,
+    registered_at INTEGER,
+    snapshot_slot INTEGER
+);
+CREATE INDEX IF NOT EXISTS users_referrer ON users (referrer_pda);
+
+CREATE TABLE IF NOT EXISTS matrices (
+    owner_pda TEXT NOT NULL,
+    chain_id INTEGER NOT NULL,
+    slot_0 TEXT,
+    slot_1 TEXT,
+    slot_2 TEXT,
+    filled_slots INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER,
+    completed_signature TEXT,
+    expired_signature TEXT,
+    PRIMARY KEY (owner_pda, chain_id)
+);
+
+CREATE TABLE IF NOT EXISTS slot_fills (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    owner_pda TEXT NOT NULL,
+    chain_id INTEGER NOT NULL,
+    slot_idx INTEGER NOT NULL,
+    member TEXT NOT NULL,
+    member_pda TEXT NOT NULL,
+    PRIMARY KEY (signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS slot_fills_owner ON slot_fills (owner_pda);
+
+CREATE TABLE IF NOT EXISTS payouts (
+    signature TEXT NOT NULL,
+    owner_pda TEXT NOT NULL,
+    chain_id INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    wallet TEXT,
+    sol_amount INTEGER,
+    donut_amount INTEGER,
+    vested_amount INTEGER,
+    PRIMARY KEY (signature, owner_pda)
+);
+";
+
+pub struct Store {
+    pub(crate) conn: Connection,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn is_indexed(&self, signature: &str) -> Result<bool> {
+        self.conn
+            .query_row("SELECT 1 FROM transactions WHERE signature = ?1", [signature], |_| Ok(()))
+            .optional()
+            .map(|row| row.is_some())
+    }
+
+    // Most recent indexed transaction, where an RPC sync resumes
+    pub fn latest_signature(&self) -> Result<Option<String>> {
+        self.conn
+            .query_row("SELECT signature FROM transactions ORDER BY slot DESC LIMIT 1", [], |row| row.get(0))
+            .optional()
+    }
+
+    // Index a transaction's events; transactions already indexed are skipped
+    // Returns the number of events stored
+    pub fn ingest_transaction(&mut self, record: &TransactionRecord) -> Result<usize> {
+        if self.is_indexed(&record.signature)? {
+            return Ok(0);
+        }
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO transactions (signature, slot, block_time, succeeded) VALUES (?1, ?2, ?3, ?4)",
+            params![record.signature, record.slot as i64, record.block_time, record.succeeded],
+        )?;
+
+        // A failed transaction changed nothing
+        if !record.succeeded {
+            tx.commit()?;
+            return Ok(0);
+        }
+
+        let data = record.program_data();
+        let mut completed = Vec::new();
+        let mut vested = Vec::new();
+
+        for (index, bytes) in data.iter().enumerate() {
+            let event = MatrixEvent::decode(bytes);
+            tx.execute(
+                "INSERT INTO events (signature, event_index, name, data) VALUES (?1, ?2, ?3, ?4)",
+                params![record.signature, index as i64, event.as_ref().map(MatrixEvent::name), bytes],
+            )?;
+
+            match event {
+                Some(MatrixEvent::SlotFilled(event)) => {
+                    apply_slot_filled(&tx, record, index, &event)?;
+                    if event.slot_idx == 2 {
+                        completed.push((event.owner, event.chain_id));
+                    }
+                },
+                Some(MatrixEvent::TokensVested(event)) => vested.push((event.wallet, event.amount)),
+                Some(MatrixEvent::MatrixExpired(event)) => {
+                    tx.execute(
+                        "INSERT INTO matrices (owner_pda, chain_id, expired_signature) VALUES (?1, ?2, ?3)
+                         ON CONFLICT (owner_pda, chain_id) DO UPDATE SET expired_signature = excluded.expired_signature",
+                        params![event.owner.to_string(), event.chain_id, record.signature],
+                    )?;
+                },
+                _ => {},
+            }
+        }
+
+        for (owner, chain_id) in completed {
+            apply_payout(&tx, record, &owner, chain_id, &vested)?;
+        }
+
+        tx.commit()?;
+        Ok(data.len())
+    }
+
+    // Store a snapshot of a user account read at `slot`
+    pub fn ingest_user_account(&mut self, address: &Pubkey, account: &UserAccount, slot: u64) -> Result<()> {
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO users (pda, wallet, referrer_pda, upline_id, depth, chain_id, filled_slots,
+                                reserved_sol, reserved_tokens, snapshot_slot)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (pda) DO UPDATE SET
+                wallet = excluded.wallet,
+                referrer_pda = excluded.referrer_pda,
+                upline_id = excluded.upline_id,
+                depth = excluded.depth,
+                chain_id = excluded.chain_id,
+                filled_slots = excluded.filled_slots,
+                reserved_sol = excluded.reserved_sol,
+                reserved_tokens = excluded.reserved_tokens,
+                snapshot_slot = excluded.snapshot_slot",
+            params![
+                address.to_string(),
+                account.owner_wallet.to_string(),
+                account.referrer.map(|referrer| referrer.to_string()),
+                account.upline.id,
+                account.upline.depth,
+                account.chain.id,
+                account.chain.filled_slots,
+                account.reserved_sol as i64,
+                account.reserved_tokens as i64,
+                slot as i64,
+            ],
+        )?;
+
+        upsert_snapshot_matrix(&tx, address, account)?;
+
+        tx.commit()
+    }
+}
+
+// Key a matrix slot refers to as a user account PDA
+// A registration stores the user's wallet in the referrer's slot; the recursion stores the PDA of the
+// completed matrix's owner in the upline's slot. Wallets are on the curve, PDAs never are
+pub fn member_pda(member: &Pubkey) -> Pubkey {
+    if member.is_on_curve() {
+        pda::user_account(member)
+    } else {
+        *member
+    }
+}
+
+fn apply_slot_filled(tx: &Transaction, record: &TransactionRecord, index: usize, event: &matrix_system::SlotFilled) -> Result<()> {
+    let owner = event.owner.to_string();
+    let member = member_pda(&event.user).to_string();
+
+    tx.execute(
+        "INSERT INTO slot_fills (signature, event_index, slot, block_time, owner_pda, chain_id, slot_idx, member, member_pda)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            record.signature,
+            index as i64,
+            record.slot as i64,
+            record.block_time,
+            owner,
+            event.chain_id,
+            event.slot_idx,
+            event.user.to_string(),
+            member,
+        ],
+    )?;
+
+    tx.execute(
+        "INSERT INTO matrices (owner_pda, chain_id, slot_0, slot_1, slot_2, filled_slots)
+         VALUES (?1, ?2, CASE WHEN ?3 = 0 THEN ?4 END, CASE WHEN ?3 = 1 THEN ?4 END, CASE WHEN ?3 = 2 THEN ?4 END, ?3 + 1)
+         ON CONFLICT (owner_pda, chain_id) DO UPDATE SET
+            slot_0 = COALESCE(excluded.slot_0, slot_0),
+            slot_1 = COALESCE(excluded.slot_1, slot_1),
+            slot_2 = COALESCE(excluded.slot_2, slot_2),
+            filled_slots = MAX(filled_slots, excluded.filled_slots)",
+        params![owner, event.chain_id, event.slot_idx, member],
+    )?;
+
+    if event.slot_idx == 2 {
+        tx.execute(
+            "UPDATE matrices SET completed_signature = ?3 WHERE owner_pda = ?1 AND chain_id = ?2",
+            params![owner, event.chain_id, record.signature],
+        )?;
+    }
+
+    tx.execute("INSERT OR IGNORE INTO users (pda) VALUES (?1)", [&owner])?;
+
+    // Only a registration fills a slot with a wallet: the user joins under the matrix owner
+    if event.user.is_on_curve() {
+        tx.execute(
+            "INSERT INTO users (pda, wallet, referrer_pda, registered_signature, registered_slot, registered_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (pda) DO UPDATE SET
+                wallet = excluded.wallet,
+                referrer_pda = excluded.referrer_pda,
+                registered_signature = excluded.registered_signature,
+                registered_slot = excluded.registered_slot,
+                registered_at = excluded.registered_at",
+            params![
+                member,
+                event.user.to_string(),
+                owner,
+                record.signature,
+                record.slot as i64,
+                record.block_time,
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Slot 3 pays the owner's reservations: SOL to the wallet, DONUT to its ATA or vesting account
+// Amounts come from the transaction's balance changes and TokensVested events
+fn apply_payout(
+    tx: &Transaction,
+    record: &TransactionRecord,
+    owner: &Pubkey,
+    chain_id: u32,
+    vested: &[(Pubkey, u64)],
+) -> Result<()> {
+    let known_wallet: Option<String> = tx
+        .query_row("SELECT wallet FROM users WHERE pda = ?1", [owner.to_string()], |row| row.get(0))
+        .optional()?
+        .flatten();
+
+    // The owner's wallet is passed to the transaction as the referrer or in its upline trio
+    let wallet = known_wallet
+        .and_then(|wallet| wallet.parse::<Pubkey>().ok())
+        .or_else(|| {
+            record
+                .account_keys
+                .iter()
+                .find(|key| key.is_on_curve() && pda::user_account(key) == *owner)
+                .copied()
+        });
+
+    let amounts = wallet.map(|wallet| {
+        let sol = record.lamport_change(&wallet).unwrap_or(0).max(0);
+        let donut = record.token_change(&wallet, &verified_addresses::TOKEN_MINT).max(0);
+        let vested: u64 = vested.iter().filter(|(vested_wallet, _)| *vested_wallet == wallet).map(|(_, amount)| amount).sum();
+        (sol, donut, vested as i64)
+    });
+
+    tx.execute(
+        "INSERT OR REPLACE INTO payouts (signature, owner_pda, chain_id, slot, block_time, wallet, sol_amount, donut_amount, vested_amount)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            record.signature,
+            owner.to_string(),
+            chain_id,
+            record.slot as i64,
+            record.block_time,
+            wallet.map(|wallet| wallet.to_string()),
+            amounts.map(|(sol, _, _)| sol),
+            amounts.map(|(_, donut, _)| donut),
+            amounts.map(|(_, _, vested)| vested),
+        ],
+    )?;
+
+    if let Some(wallet) = wallet {
+        tx.execute(
+            "UPDATE users SET wallet = ?2 WHERE pda = ?1 AND wallet IS NULL",
+            params![owner.to_string(), wallet.to_string()],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn upsert_snapshot_matrix(tx: &Transaction, owner: &Pubkey, account: &UserAccount) -> Result<()> {
+    let chain = &account.chain;
+    let slot = |index: usize| chain.slots[index].map(|member| member_pda(&member).to_string());
+
+    tx.execute(
+        "INSERT INTO matrices (owner_pda, chain_id, slot_0, slot_1, slot_2, filled_slots, expires_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (owner_pda, chain_id) DO UPDATE SET
+            slot_0 = COALESCE(excluded.slot_0, slot_0),
+            slot_1 = COALESCE(excluded.slot_1, slot_1),
+            slot_2 = COALESCE(excluded.slot_2, slot_2),
+            filled_slots = MAX(filled_slots, excluded.filled_slots),
+            expires_at = excluded.expires_at",
+        params![
+            owner.to_string(),
+            chain.id,
+            slot(0),
+            slot(1),
+            slot(2),
+            chain.filled_slots,
+            account.matrix_expires_at,
+        ],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+// Transactions of the program as the indexer consumes them: logs and balance changes
+use std::str::FromStr;
+
+use anchor_lang::prelude::Pubkey;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiMessage};
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenBalance {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub pre: u64,
+    pub post: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionRecord {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub succeeded: bool,
+    pub logs: Vec<String>,
+    pub account_keys: Vec<Pubkey>,       // Static keys, then loaded writable and readonly keys
+    pub pre_balances: Vec<u64>,
+    pub post_balances: Vec<u64>,
+    pub token_balances: Vec<TokenBalance>,
+}
+
+impl TransactionRecord {
+    // Record of a transaction fetched with the JSON encoding, None for other encodings or without meta
+    pub fn from_encoded(transaction: &EncodedConfirmedTransactionWithStatusMeta) -> Option<Self> {
+        let EncodedTransaction::Json(ui_transaction) = &transaction.transaction.transaction else {
+            return None;
+        };
+        let meta = transaction.transaction.meta.as_ref()?;
+
+        let mut account_keys: Vec<Pubkey> = match &ui_transaction.message {
+            UiMessage::Raw(message) => parse_keys(&message.account_keys),
+            UiMessage::Parsed(message) => parse_keys(message.account_keys.iter().map(|account| &account.pubkey)),
+        };
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            account_keys.extend(parse_keys(&loaded.writable));
+            account_keys.extend(parse_keys(&loaded.readonly));
+        }
+
+        let logs = match &meta.log_messages {
+            OptionSerializer::Some(logs) => logs.clone(),
+            _ => Vec::new(),
+        };
+
+        Some(Self {
+            signature: ui_transaction.signatures.first()?.clone(),
+            slot: transaction.slot,
+            block_time: transaction.block_time,
+            succeeded: meta.err.is_none(),
+            logs,
+            account_keys,
+            pre_balances: meta.pre_balances.clone(),
+            post_balances: meta.post_balances.clone(),
+            token_balances: token_balances(&meta.pre_token_balances, &meta.post_token_balances),
+        })
+    }
+
+    // Data of the events logged by the program itself, in order
+    // CPIs print their own "Program data:" lines, so the invocation stack decides whose line it is
+    pub fn program_data(&self) -> Vec<Vec<u8>> {
+        let program_id = matrix_system::ID.to_string();
+        let mut stack: Vec<&str> = Vec::new();
+        let mut data = Vec::new();
+
+        for log in &self.logs {
+            if let Some(encoded) = log.strip_prefix(PROGRAM_DATA_PREFIX) {
+                if stack.last() == Some(&program_id.as_str()) {
+                    if let Ok(bytes) = STANDARD.decode(encoded) {
+                        data.push(bytes);
+                    }
+                }
+            } else if let Some(rest) = log.strip_prefix("Program ") {
+                let mut words = rest.split_whitespace();
+                let (Some(program), Some(action)) = (words.next(), words.next()) else {
+                    continue;
+                };
+
+                match action {
+                    "invoke" => stack.push(program),
+                    "success" | "failed:" => {
+                        stack.pop();
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        data
+    }
+
+    // Lamport change of an account, None if the transaction doesn't touch it
+    pub fn lamport_change(&self, account: &Pubkey) -> Option<i64> {
+        let index = self.account_keys.iter().position(|key| key == account)?;
+        let pre = *self.pre_balances.get(index)?;
+        let post = *self.post_balances.get(index)?;
+        Some(post as i64 - pre as i64)
+    }
+
+    // Change of the tokens of `mint` held by `owner` across its token accounts
+    pub fn token_change(&self, owner: &Pubkey, mint: &Pubkey) -> i64 {
+        self.token_balances
+            .iter()
+            .filter(|balance| balance.owner == *owner && balance.mint == *mint)
+            .map(|balance| balance.post as i64 - balance.pre as i64)
+            .sum()
+    }
+}
+
+fn parse_keys<'a>(keys: impl IntoIterator<Item = &'a String>) -> Vec<Pubkey> {
+    keys.into_iter().map(|key| Pubkey::from_str(key).unwrap_or_default()).collect()
+}
+
+// Function to pair the pre and post token balances by account index
+fn token_balances(
+    pre: &OptionSerializer<Vec<solana_transaction_status::UiTransactionTokenBalance>>,
+    post: &OptionSerializer<Vec<solana_transaction_status::UiTransactionTokenBalance>>,
+) -> Vec<TokenBalance> {
+    let empty = Vec::new();
+    let pre = match pre {
+        OptionSerializer::Some(balances) => balances,
+        _ => &empty,
+    };
+    let post = match post {
+        OptionSerializer::Some(balances) => balances,
+        _ => &empty,
+    };
+
+    let amount = |balance: &solana_transaction_status::UiTransactionTokenBalance| {
+        u64::from_str(&balance.ui_token_amount.amount).unwrap_or(0)
+    };
+
+    let mut balances: Vec<(u8, TokenBalance)> = Vec::new();
+    for (balance, is_post) in pre.iter().map(|balance| (balance, false)).chain(post.iter().map(|balance| (balance, true))) {
+        let OptionSerializer::Some(owner) = &balance.owner else {
+            continue;
+        };
+        let (Ok(owner), Ok(mint)) = (Pubkey::from_str(owner), Pubkey::from_str(&balance.mint)) else {
+            continue;
+        };
+
+        let entry = match balances.iter_mut().find(|(index, _)| *index == balance.account_index) {
+            Some((_, entry)) => entry,
+            None => {
+                balances.push((balance.account_index, TokenBalance { owner, mint, pre: 0, post: 0 }));
+                &mut balances.last_mut().unwrap().1
+            },
+        };
+
+        if is_post {
+            entry.post = amount(balance);
+        } else {
+            entry.pre = amount(balance);
+        }
+    }
+
+    balances.into_iter().map(|(_, balance)| balance).collect()
+}
@@ -0,0 +1,289 @@
+// indexer: event decoding, tree materialization, payouts and snapshots
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::Event;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use matrix_client::pda;
+use matrix_indexer::transaction::TokenBalance;
+use matrix_indexer::{MatrixEvent, Store, TransactionRecord};
+use matrix_system::{verified_addresses, ReferralChain, ReservedSolReconciled, SlotFilled, TokensVested, UserAccount};
+use solana_sdk::signature::Signer;
+use solana_sdk::signer::keypair::keypair_from_seed;
+
+const DEPOSIT: u64 = 100_000_000;
+
+fn wallet(seed: u8) -> Pubkey {
+    keypair_from_seed(&[seed; 32]).unwrap().pubkey()
+}
+
+fn program_data(event: &impl Event) -> String {
+    format!("Program data: {}", STANDARD.encode(event.data()))
+}
+
+// Logs of a program invocation emitting `events`, with a CPI logging its own data in between
+fn logs(events: &[String]) -> Vec<String> {
+    let program = matrix_system::ID.to_string();
+    let mut logs = vec![format!("Program {} invoke [1]", program)];
+    logs.push(format!("Program {} invoke [2]", verified_addresses::VAULT_PROGRAM));
+    logs.push(format!("Program data: {}", STANDARD.encode([1u8; 16])));
+    logs.push(format!("Program {} success", verified_addresses::VAULT_PROGRAM));
+    logs.extend(events.iter().cloned());
+    logs.push(format!("Program {} success", program));
+    logs
+}
+
+fn record(signature: &str, slot: u64, events: &[String]) -> TransactionRecord {
+    TransactionRecord {
+        signature: signature.to_string(),
+        slot,
+        block_time: Some(1_700_000_000 + slot as i64),
+        succeeded: true,
+        logs: logs(events),
+        ..TransactionRecord::default()
+    }
+}
+
+fn slot_filled(slot_idx: u8, chain_id: u32, user: Pubkey, owner_wallet: &Pubkey) -> String {
+    program_data(&SlotFilled {
+        slot_idx,
+        chain_id,
+        user,
+        owner: pda::user_account(owner_wallet),
+    })
+}
+
+// Root wallet 1 referring wallets 2, 3 and 4, whose registration completes the root's matrix
+fn index_root_matrix(store: &mut Store) {
+    let root = wallet(1);
+    for (index, seed) in [2u8, 3, 4].into_iter().enumerate() {
+        let mut record = record(&format!("register-{}", seed), 10 + index as u64, &[slot_filled(index as u8, 1, wallet(seed), &root)]);
+
+        if index == 2 {
+            record.account_keys = vec![wallet(seed), root];
+            record.pre_balances = vec![5 * DEPOSIT, DEPOSIT];
+            record.post_balances = vec![3 * DEPOSIT, 2 * DEPOSIT];
+            record.token_balances = vec![TokenBalance {
+                owner: root,
+                mint: verified_addresses::TOKEN_MINT,
+                pre: 0,
+                post: 7_000,
+            }];
+        }
+
+        store.ingest_transaction(&record).unwrap();
+    }
+}
+
+#[test]
+fn decodes_only_the_programs_own_events() {
+    let record = record("sig", 1, &[slot_filled(0, 1, wallet(2), &wallet(1))]);
+
+    let data = record.program_data();
+    assert_eq!(data.len(), 1);
+
+    match MatrixEvent::decode(&data[0]) {
+        Some(MatrixEvent::SlotFilled(event)) => {
+            assert_eq!(event.slot_idx, 0);
+            assert_eq!(event.user, wallet(2));
+            assert_eq!(event.owner, pda::user_account(&wallet(1)));
+        },
+        _ => panic!("expected SlotFilled"),
+    }
+
+    assert!(MatrixEvent::decode(&[0; 12]).is_none());
+}
+
+#[test]
+fn registrations_build_the_downline() {
+    let mut store = Store::open_in_memory().unwrap();
+    index_root_matrix(&mut store);
+
+    // Wallet 5 joins under wallet 2
+    store.ingest_transaction(&record("register-5", 20, &[slot_filled(0, 2, wallet(5), &wallet(2))])).unwrap();
+
+    let downline = store.downline(&pda::user_account(&wallet(1)), None).unwrap();
+    let members: Vec<(String, u32)> = downline.iter().map(|member| (member.wallet.clone().unwrap(), member.level)).collect();
+    assert_eq!(
+        members,
+        vec![
+            (wallet(2).to_string(), 1),
+            (wallet(3).to_string(), 1),
+            (wallet(4).to_string(), 1),
+            (wallet(5).to_string(), 2),
+        ]
+    );
+    assert_eq!(downline[3].referrer_pda, pda::user_account(&wallet(2)).to_string());
+
+    let direct = store.downline(&pda::user_account(&wallet(1)), Some(1)).unwrap();
+    assert_eq!(direct.len(), 3);
+
+    let (slots, filled, completed): (Option<String>, u8, Option<String>) = store
+        .connection()
+        .query_row(
+            "SELECT slot_2, filled_slots, completed_signature FROM matrices WHERE owner_pda = ?1 AND chain_id = 1",
+            [pda::user_account(&wallet(1)).to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(slots, Some(pda::user_account(&wallet(4)).to_string()));
+    assert_eq!(filled, 3);
+    assert_eq!(completed.as_deref(), Some("register-4"));
+}
+
+#[test]
+fn slot_three_records_the_payout_from_balance_changes() {
+    let mut store = Store::open_in_memory().unwrap();
+    index_root_matrix(&mut store);
+
+    // The root registered without referrer, so its wallet comes from the transaction's keys
+    let earnings = store.earnings(&pda::user_account(&wallet(1))).unwrap();
+    assert_eq!(earnings.completed_matrices, 1);
+    assert_eq!(earnings.sol, DEPOSIT);
+    assert_eq!(earnings.donut, 7_000);
+    assert_eq!(earnings.vested, 0);
+    assert_eq!(earnings.unresolved_payouts, 0);
+
+    let root_wallet: Option<String> = store
+        .connection()
+        .query_row("SELECT wallet FROM users WHERE pda = ?1", [pda::user_account(&wallet(1)).to_string()], |row| row.get(0))
+        .unwrap();
+    assert_eq!(root_wallet, Some(wallet(1).to_string()));
+
+    assert_eq!(store.earnings(&pda::user_account(&wallet(2))).unwrap(), Default::default());
+}
+
+#[test]
+fn vested_payouts_and_recursive_fills() {
+    let mut store = Store::open_in_memory().unwrap();
+    let upline = wallet(1);
+    let referrer = wallet(2);
+
+    // Wallet 5 completes wallet 2's matrix, which fills the last slot of wallet 1's matrix
+    let events = [
+        slot_filled(2, 4, wallet(5), &referrer),
+        program_data(&TokensVested { wallet: referrer, amount: 300, start_time: 0, duration: 60 }),
+        slot_filled(2, 3, pda::user_account(&referrer), &upline),
+        program_data(&TokensVested { wallet: upline, amount: 500, start_time: 0, duration: 60 }),
+    ];
+    let mut record = record("recursion", 30, &events);
+    record.account_keys = vec![wallet(5), referrer, upline];
+    record.pre_balances = vec![DEPOSIT, 0, 0];
+    record.post_balances = vec![0, 0, DEPOSIT];
+    store.ingest_transaction(&record).unwrap();
+
+    let referrer_earnings = store.earnings(&pda::user_account(&referrer)).unwrap();
+    assert_eq!((referrer_earnings.sol, referrer_earnings.donut, referrer_earnings.vested), (0, 0, 300));
+
+    let upline_earnings = store.earnings(&pda::user_account(&upline)).unwrap();
+    assert_eq!((upline_earnings.sol, upline_earnings.donut, upline_earnings.vested), (DEPOSIT, 0, 500));
+
+    // The recursive fill stores the completed matrix's PDA and is not a registration
+    let (member, member_pda): (String, String) = store
+        .connection()
+        .query_row("SELECT member, member_pda FROM slot_fills WHERE chain_id = 3", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    assert_eq!(member, member_pda);
+    assert_eq!(member, pda::user_account(&referrer).to_string());
+
+    let downline = store.downline(&pda::user_account(&upline), None).unwrap();
+    assert!(downline.is_empty());
+    assert_eq!(store.downline(&pda::user_account(&referrer), None).unwrap().len(), 1);
+}
+
+#[test]
+fn ingestion_is_idempotent_and_skips_failed_transactions() {
+    let mut store = Store::open_in_memory().unwrap();
+    index_root_matrix(&mut store);
+    index_root_matrix(&mut store);
+
+    let mut failed = record("failed", 40, &[slot_filled(0, 2, wallet(9), &wallet(2))]);
+    failed.succeeded = false;
+    assert_eq!(store.ingest_transaction(&failed).unwrap(), 0);
+    assert!(store.is_indexed("failed").unwrap());
+
+    let count = |table: &str| -> i64 {
+        store.connection().query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    };
+    assert_eq!(count("slot_fills"), 3);
+    assert_eq!(count("payouts"), 1);
+    assert_eq!(count("users"), 4);
+    assert_eq!(store.latest_signature().unwrap().as_deref(), Some("failed"));
+}
+
+#[test]
+fn unknown_events_are_kept_raw() {
+    let mut store = Store::open_in_memory().unwrap();
+    let unknown = format!("Program data: {}", STANDARD.encode([9u8; 20]));
+    assert_eq!(store.ingest_transaction(&record("future", 1, &[unknown])).unwrap(), 1);
+
+    let (name, data): (Option<String>, Vec<u8>) = store
+        .connection()
+        .query_row("SELECT name, data FROM events", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    assert_eq!(name, None);
+    assert_eq!(data, vec![9u8; 20]);
+}
+
+#[test]
+fn reserved_sol_reconciliation_is_named() {
+    let mut store = Store::open_in_memory().unwrap();
+    let event = program_data(&ReservedSolReconciled { total_reserved_sol: 3 * DEPOSIT, vault_balance: 5 * DEPOSIT });
+    let record = record("reconcile", 1, &[event]);
+
+    match MatrixEvent::decode(&record.program_data()[0]) {
+        Some(MatrixEvent::ReservedSolReconciled(event)) => {
+            assert_eq!(event.total_reserved_sol, 3 * DEPOSIT);
+            assert_eq!(event.vault_balance, 5 * DEPOSIT);
+        },
+        _ => panic!("expected ReservedSolReconciled"),
+    }
+
+    assert_eq!(store.ingest_transaction(&record).unwrap(), 1);
+    let name: Option<String> = store
+        .connection()
+        .query_row("SELECT name FROM events", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(name.as_deref(), Some("ReservedSolReconciled"));
+}
+
+#[test]
+fn snapshots_complete_users_and_matrices() {
+    let mut store = Store::open_in_memory().unwrap();
+    index_root_matrix(&mut store);
+
+    let root = wallet(1);
+    let mut account = UserAccount {
+        is_registered: true,
+        referrer: None,
+        owner_wallet: root,
+        chain: ReferralChain {
+            id: 5,
+            slots: [Some(wallet(6)), None, None],
+            filled_slots: 1,
+        },
+        reserved_sol: 0,
+        reserved_tokens: 0,
+        ..UserAccount::default()
+    };
+    account.upline.id = 1;
+    store.ingest_user_account(&pda::user_account(&root), &account, 99).unwrap();
+
+    let (upline_id, chain_id, snapshot_slot): (u32, u32, i64) = store
+        .connection()
+        .query_row(
+            "SELECT upline_id, chain_id, snapshot_slot FROM users WHERE pda = ?1",
+            [pda::user_account(&root).to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!((upline_id, chain_id, snapshot_slot), (1, 5, 99));
+
+    let slot_0: String = store
+        .connection()
+        .query_row("SELECT slot_0 FROM matrices WHERE chain_id = 5", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(slot_0, pda::user_account(&wallet(6)).to_string());
+
+    // The event-built downline is unchanged
+    assert_eq!(store.downline(&pda::user_account(&root), None).unwrap().len(), 3);
+}
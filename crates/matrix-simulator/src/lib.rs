@@ -0,0 +1,22 @@
+// Economic simulator for the 3x1 matrix
+// Registrations run through the program's own matrix engine, pool pricing and mint budget,
+// against a modelled pool, oracle and program vaults
+pub mod report;
+pub mod simulation;
+pub mod topology;
+
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+
+pub use simulation::{Config, Simulation};
+
+// Syscall stubs that drop the program's `msg!` logs, which would otherwise go to stdout
+struct QuietStubs;
+
+impl SyscallStubs for QuietStubs {
+    fn sol_log(&self, _message: &str) {}
+}
+
+// Silence the program's logs for the rest of the process
+pub fn silence_program_logs() {
+    set_syscall_stubs(Box::new(QuietStubs));
+}
@@ -0,0 +1,180 @@
+// matrix-simulator: model SOL and DONUT flows through the 3x1 matrix
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::process;
+
+use clap::{Parser, ValueEnum};
+use matrix_simulator::report::Report;
+use matrix_simulator::simulation::LAMPORTS_PER_SOL;
+use matrix_simulator::topology::{Growth, Topology};
+use matrix_simulator::{silence_program_logs, Config, Simulation};
+use matrix_system::{Slot1Policy, DEFAULT_TWAP_WINDOW, MAX_TWAP_WINDOW};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Slot1 {
+    Deposit,
+    BuybackBurn,
+    BuybackTreasury,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+}
+
+/// Simulate registrations through the program's matrix and pricing logic
+#[derive(Parser, Debug)]
+#[command(name = "matrix-simulator", version)]
+struct Args {
+    /// Referred registrations to simulate
+    #[arg(long, short = 'n', default_value_t = 1_000)]
+    registrations: usize,
+
+    /// Users registered without referrer before the others
+    #[arg(long, default_value_t = 1)]
+    roots: usize,
+
+    #[arg(long, value_enum, default_value_t = Topology::Random)]
+    topology: Topology,
+
+    /// Referrals per user for the tree topology
+    #[arg(long, default_value_t = 3)]
+    branching: usize,
+
+    #[arg(long, value_enum, default_value_t = Growth::Constant)]
+    growth: Growth,
+
+    /// Length of the simulated period in days
+    #[arg(long, default_value_t = 30.0)]
+    days: f64,
+
+    /// Daily growth rate for exponential growth (0.1 = 10% per day)
+    #[arg(long, default_value_t = 0.1)]
+    growth_rate: f64,
+
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    /// USD deposited per registration (raised to the program minimum if below)
+    #[arg(long, default_value_t = 10.0)]
+    deposit_usd: f64,
+
+    /// Oracle SOL/USD price at the start
+    #[arg(long, default_value_t = 100.0)]
+    sol_price: f64,
+
+    /// Oracle SOL/USD price at the end (defaults to the start price)
+    #[arg(long)]
+    sol_price_end: Option<f64>,
+
+    /// Initial pool SOL reserve
+    #[arg(long, default_value_t = 1_000.0)]
+    pool_sol: f64,
+
+    /// Initial pool DONUT reserve
+    #[arg(long, default_value_t = 1_000_000.0)]
+    pool_donut: f64,
+
+    #[arg(long, default_value_t = 9)]
+    donut_decimals: u32,
+
+    #[arg(long, default_value_t = 25)]
+    trade_fee_bps: u64,
+
+    /// TWAP window in seconds, as accepted by set_twap_window
+    #[arg(long, default_value_t = DEFAULT_TWAP_WINDOW, value_parser = clap::value_parser!(u32).range(1..=MAX_TWAP_WINDOW as i64))]
+    twap_window: u32,
+
+    /// Mint budget window in seconds
+    #[arg(long, default_value_t = 86_400)]
+    epoch_duration: i64,
+
+    /// Maximum DONUT minted per window
+    #[arg(long, default_value_t = 1_000_000.0)]
+    max_tokens_per_epoch: f64,
+
+    /// Maximum DONUT minted per USD deposited
+    #[arg(long, default_value_t = 100.0)]
+    max_tokens_per_usd: f64,
+
+    #[arg(long, default_value_t = 0)]
+    treasury_fee_bps: u16,
+
+    #[arg(long, value_enum, default_value_t = Slot1::Deposit)]
+    slot1_policy: Slot1,
+
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+
+    /// JSON output file, or directory for users.csv and summary.csv (default: stdout / current directory)
+    #[arg(long, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+impl Args {
+    fn config(&self) -> Config {
+        let donut_unit = 10f64.powi(self.donut_decimals as i32);
+
+        Config {
+            registrations: self.registrations,
+            roots: self.roots,
+            topology: self.topology,
+            branching: self.branching,
+            growth: self.growth,
+            days: self.days,
+            growth_rate: self.growth_rate,
+            seed: self.seed,
+            deposit_usd: self.deposit_usd,
+            sol_price_start: self.sol_price,
+            sol_price_end: self.sol_price_end.unwrap_or(self.sol_price),
+            pool_sol: (self.pool_sol * LAMPORTS_PER_SOL) as u64,
+            pool_donut: (self.pool_donut * donut_unit) as u64,
+            trade_fee_bps: self.trade_fee_bps,
+            twap_window: self.twap_window,
+            epoch_duration: self.epoch_duration,
+            max_tokens_per_epoch: (self.max_tokens_per_epoch * donut_unit) as u64,
+            max_tokens_per_usd: (self.max_tokens_per_usd * donut_unit) as u64,
+            treasury_fee_bps: self.treasury_fee_bps,
+            slot1_policy: match self.slot1_policy {
+                Slot1::Deposit => Slot1Policy::DepositLiquidity,
+                Slot1::BuybackBurn => Slot1Policy::BuybackAndBurn,
+                Slot1::BuybackTreasury => Slot1Policy::BuybackToTreasury,
+            },
+        }
+    }
+}
+
+fn write_report(report: &Report, format: Format, output: Option<PathBuf>) -> io::Result<()> {
+    match format {
+        Format::Json => match output {
+            Some(path) => report.write_json(BufWriter::new(File::create(path)?)),
+            None => report.write_json(io::stdout().lock()),
+        },
+        Format::Csv => {
+            let directory = output.unwrap_or_else(|| PathBuf::from("."));
+            fs::create_dir_all(&directory)?;
+
+            report.write_users_csv(BufWriter::new(File::create(directory.join("users.csv"))?))?;
+            report.write_summary_csv(BufWriter::new(File::create(directory.join("summary.csv"))?))
+        },
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    silence_program_logs();
+
+    let mut simulation = Simulation::new(args.config());
+    if let Err(error) = simulation.run() {
+        eprintln!("Simulation failed: {}", error);
+        process::exit(1);
+    }
+
+    let report = Report::new(&simulation);
+    if let Err(error) = write_report(&report, args.format, args.output) {
+        eprintln!("Failed to write the report: {}", error);
+        process::exit(1);
+    }
+}
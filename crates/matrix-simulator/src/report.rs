@@ -0,0 +1,151 @@
+// Per-user P&L and summary of a simulation, as CSV or JSON
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::simulation::{Config, Simulation, Totals};
+
+// Profit and loss of one user, in lamports and DONUT base units
+#[derive(Clone, Debug, Serialize)]
+pub struct UserReport {
+    pub user: usize,
+    pub referrer: Option<usize>,
+    pub depth: usize,                // Length of the stored upline
+    pub registered_at: i64,
+    pub referrals: u32,
+    pub matrices_completed: u32,
+    pub sol_deposited: u64,          // Deposit less what stayed in the user's WSOL account
+    pub sol_received: u64,
+    pub donut_received: u64,
+    pub donut_value_sol: u64,        // DONUT received, valued at the final pool ratio
+    pub reserved_sol: u64,           // Still reserved in the user's matrix
+    pub reserved_tokens: u64,
+    pub net_sol: i128,               // SOL received less SOL deposited
+    pub net_with_donut: i128,        // Net SOL plus the value of the DONUT received
+}
+
+// Pool, vault and mint figures at the end of a simulation
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+    #[serde(flatten)]
+    pub totals: Totals,
+    pub mint_limit_rejection_rate: f64,
+    pub pool_sol_start: u64,
+    pub pool_sol_end: u64,
+    pub pool_donut_start: u64,
+    pub pool_donut_end: u64,
+    pub reserved_sol: u64,
+    pub excess_sol: u64,             // program_sol_vault lamports not backing a reservation
+    pub next_chain_id: u32,
+    pub users_in_profit: usize,      // Users whose net_with_donut is positive
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    pub config: Config,
+    pub summary: Summary,
+    pub users: Vec<UserReport>,
+}
+
+impl Report {
+    pub fn new(simulation: &Simulation) -> Self {
+        let users: Vec<UserReport> = simulation.users.iter().enumerate().map(|(index, user)| {
+            let sol_deposited = user.deposit - user.retained;
+            let donut_value_sol = simulation.donut_value_in_sol(user.donut_received);
+            let net_sol = user.sol_received as i128 - sol_deposited as i128;
+
+            UserReport {
+                user: index,
+                referrer: user.referrer,
+                depth: user.upline.len(),
+                registered_at: user.registered_at,
+                referrals: user.referrals,
+                matrices_completed: user.matrices_completed,
+                sol_deposited,
+                sol_received: user.sol_received,
+                donut_received: user.donut_received,
+                donut_value_sol,
+                reserved_sol: user.node.reserved_sol,
+                reserved_tokens: user.node.reserved_tokens,
+                net_sol,
+                net_with_donut: net_sol + donut_value_sol as i128,
+            }
+        }).collect();
+
+        let totals = simulation.totals.clone();
+        let reserved_sol = simulation.state.total_reserved_sol;
+
+        let summary = Summary {
+            mint_limit_rejection_rate: if totals.attempted == 0 {
+                0.0
+            } else {
+                totals.mint_limit_rejections as f64 / totals.attempted as f64
+            },
+            pool_sol_start: simulation.config.pool_sol,
+            pool_sol_end: simulation.pool_sol,
+            pool_donut_start: simulation.config.pool_donut,
+            pool_donut_end: simulation.pool_donut,
+            reserved_sol,
+            excess_sol: totals.sol_vault.saturating_sub(reserved_sol),
+            next_chain_id: simulation.state.next_chain_id,
+            users_in_profit: users.iter().filter(|user| user.net_with_donut > 0).count(),
+            totals,
+        };
+
+        Self {
+            config: simulation.config.clone(),
+            summary,
+            users,
+        }
+    }
+
+    pub fn write_json(&self, writer: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(writer, self).map_err(io::Error::from)
+    }
+
+    // One row per user
+    pub fn write_users_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "user,referrer,depth,registered_at,referrals,matrices_completed,sol_deposited,sol_received,\
+             donut_received,donut_value_sol,reserved_sol,reserved_tokens,net_sol,net_with_donut"
+        )?;
+
+        for user in &self.users {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                user.user,
+                user.referrer.map(|referrer| referrer.to_string()).unwrap_or_default(),
+                user.depth,
+                user.registered_at,
+                user.referrals,
+                user.matrices_completed,
+                user.sol_deposited,
+                user.sol_received,
+                user.donut_received,
+                user.donut_value_sol,
+                user.reserved_sol,
+                user.reserved_tokens,
+                user.net_sol,
+                user.net_with_donut,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // One `metric,value` row per summary field
+    pub fn write_summary_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "metric,value")?;
+
+        let summary = serde_json::to_value(&self.summary).map_err(io::Error::from)?;
+        if let serde_json::Value::Object(fields) = summary {
+            for (metric, value) in fields {
+                writeln!(writer, "{},{}", metric, value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
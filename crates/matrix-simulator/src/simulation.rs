@@ -0,0 +1,432 @@
+// Simulation state: users, pool, oracle, program vaults and counters
+use anchor_lang::prelude::*;
+use matrix_system::matrix::{self, Effect, MatrixNode, Target};
+use matrix_system::{
+    calculate_minimum_sol_deposit, calculate_sol_for_usd_value, calculate_treasury_fee,
+    calculate_usd_value, check_mint_limit, get_donut_tokens_amount, meteora, record_price_observation,
+    ErrorCode, ExpiryPolicy, MintBudget, PoolReserves, PriceObservation, ProgramState, ReferralChain,
+    Slot1Policy, DEFAULT_BUYBACK_SLIPPAGE_BPS, MAX_PRICE_OBSERVATIONS, MAX_UPLINE_DEPTH, MIN_TWAP_OBSERVATIONS,
+    STATE_LAYOUT_VERSION,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+
+use crate::topology::{registration_offset, Growth, ReferrerPicker, Topology};
+
+// Unix time of the first registration
+pub const START_TIME: i64 = 1_700_000_000;
+
+// Decimals of Chainlink USD prices
+pub const PRICE_DECIMALS: u32 = 8;
+
+pub const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+// Parameters of a simulation run
+#[derive(Clone, Debug, Serialize)]
+pub struct Config {
+    pub registrations: usize,        // Referred registrations to attempt
+    pub roots: usize,                // Users registered without referrer first
+    pub topology: Topology,
+    pub branching: usize,            // Referrals per user for the tree topology
+    pub growth: Growth,
+    pub days: f64,                   // Length of the simulated period
+    pub growth_rate: f64,            // Daily growth rate for exponential growth
+    pub seed: u64,
+    pub deposit_usd: f64,            // Deposit of each registration, raised to the minimum if below
+    pub sol_price_start: f64,        // Oracle SOL/USD price at the start
+    pub sol_price_end: f64,          // Oracle SOL/USD price at the end (linear in between)
+    pub pool_sol: u64,               // Initial pool SOL reserve (lamports)
+    pub pool_donut: u64,             // Initial pool DONUT reserve (base units)
+    pub trade_fee_bps: u64,          // Pool trade fee
+    pub twap_window: u32,
+    pub epoch_duration: i64,         // Mint budget window
+    pub max_tokens_per_epoch: u64,
+    pub max_tokens_per_usd: u64,
+    pub treasury_fee_bps: u16,
+    #[serde(serialize_with = "serialize_debug")]
+    pub slot1_policy: Slot1Policy,
+}
+
+// Program enums derive only Borsh, so report them by name
+fn serialize_debug<T: std::fmt::Debug, S: serde::Serializer>(value: &T, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("{:?}", value))
+}
+
+// One simulated user
+#[derive(Clone, Debug)]
+pub struct User {
+    pub node: MatrixNode,
+    pub referrer: Option<usize>,
+    pub upline: Vec<usize>,          // Stored upline, oldest first (UserAccount.upline.upline)
+    pub registered_at: i64,
+    pub deposit: u64,                // Lamports deposited, including the treasury fee
+    pub treasury_fee: u64,
+    pub retained: u64,               // Deposit left in the user's WSOL account
+    pub sol_received: u64,           // Slot-3 SOL payouts
+    pub donut_received: u64,         // Slot-3 DONUT payouts
+    pub referrals: u32,
+    pub matrices_completed: u32,
+}
+
+// Running totals of a simulation
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Totals {
+    pub attempted: usize,
+    pub registered: usize,
+    pub mint_limit_rejections: usize,  // Registrations reverted by check_mint_limit
+    pub deposits: u64,
+    pub treasury_fees: u64,
+    pub pool_deposits: u64,            // Slot-1 SOL added to the pool
+    pub buyback_sol: u64,              // Slot-1 SOL swapped for DONUT
+    pub donut_minted: u64,
+    pub donut_paid: u64,
+    pub donut_burned: u64,
+    pub donut_to_treasury: u64,
+    pub sol_paid: u64,
+    pub sol_vault: u64,                // program_sol_vault lamports above rent
+    pub token_vault: u64,              // program_token_vault DONUT
+    pub retained: u64,                 // Deposits left in users' WSOL accounts
+    pub matrices_completed: u64,
+}
+
+pub struct Simulation {
+    pub config: Config,
+    pub state: ProgramState,
+    pub mint_budget: MintBudget,
+    pub users: Vec<User>,
+    pub pool_sol: u64,
+    pub pool_donut: u64,
+    pub totals: Totals,
+    picker: ReferrerPicker,
+    rng: StdRng,
+}
+
+impl Simulation {
+    pub fn new(config: Config) -> Self {
+        let mut state = ProgramState {
+            owner: Pubkey::default(),
+            multisig_treasury: Pubkey::default(),
+            next_upline_id: 1,
+            next_chain_id: 1,
+            twap_window: config.twap_window,
+            observation_index: 0,
+            observation_count: 0,
+            price_observations: [PriceObservation::default(); MAX_PRICE_OBSERVATIONS],
+            slot1_policy: config.slot1_policy,
+            buyback_slippage_bps: DEFAULT_BUYBACK_SLIPPAGE_BPS,
+            treasury_fee_bps: config.treasury_fee_bps,
+            total_reserved_sol: 0,
+            vesting_duration: 0,
+            staking_fee_bps: 0,
+            matrix_expiry: 0,
+            expiry_policy: ExpiryPolicy::default(),
+            layout_version: STATE_LAYOUT_VERSION,
+            reserved_sol_reconciled: true,
+        };
+
+        // Mints need a TWAP, so the pool is sampled with record_price before registrations open
+        let reserves = PoolReserves {
+            token_a: config.pool_donut,
+            token_b: config.pool_sol,
+            trade_fee_numerator: config.trade_fee_bps,
+            trade_fee_denominator: 10_000,
+        };
+        let sample_interval = (config.twap_window as i64 / MAX_PRICE_OBSERVATIONS as i64).max(1);
+        for sample in (1..=MIN_TWAP_OBSERVATIONS as i64).rev() {
+            record_price_observation(&mut state, &reserves, START_TIME - sample * sample_interval);
+        }
+
+        let mint_budget = MintBudget {
+            epoch_duration: config.epoch_duration,
+            max_tokens_per_epoch: config.max_tokens_per_epoch,
+            max_tokens_per_usd: config.max_tokens_per_usd,
+            epoch_start: START_TIME,
+            minted_in_epoch: 0,
+        };
+
+        Self {
+            picker: ReferrerPicker::new(config.topology, config.branching),
+            rng: StdRng::seed_from_u64(config.seed),
+            pool_sol: config.pool_sol,
+            pool_donut: config.pool_donut,
+            config,
+            state,
+            mint_budget,
+            users: Vec::new(),
+            totals: Totals::default(),
+        }
+    }
+
+    // Run the roots and every referred registration
+    pub fn run(&mut self) -> Result<()> {
+        let total = self.config.roots + self.config.registrations;
+
+        for index in 0..total {
+            let now = START_TIME + registration_offset(
+                self.config.growth,
+                index,
+                total,
+                self.config.days,
+                self.config.growth_rate,
+            );
+
+            if index < self.config.roots || self.users.is_empty() {
+                self.register_root(now);
+            } else {
+                let referrer = self.picker.pick(self.users.len(), &mut self.rng);
+                self.register(referrer, now)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Oracle SOL/USD price at `now`, with PRICE_DECIMALS decimals
+    pub fn sol_price(&self, now: i64) -> i128 {
+        let period = (self.config.days * 86_400.0).max(1.0);
+        let progress = ((now - START_TIME) as f64 / period).clamp(0.0, 1.0);
+        let price = self.config.sol_price_start + (self.config.sol_price_end - self.config.sol_price_start) * progress;
+
+        (price * 10f64.powi(PRICE_DECIMALS as i32)) as i128
+    }
+
+    // Current pool reserves in the form the program reads them
+    pub fn pool_reserves(&self) -> PoolReserves {
+        PoolReserves {
+            token_a: self.pool_donut,
+            token_b: self.pool_sol,
+            trade_fee_numerator: self.config.trade_fee_bps,
+            trade_fee_denominator: 10_000,
+        }
+    }
+
+    // Lamports deposited by a registration at `price`: the configured USD value, at least the minimum
+    pub fn deposit_amount(&self, price: i128) -> Result<u64> {
+        let minimum = calculate_minimum_sol_deposit(price, PRICE_DECIMALS)?;
+        let usd_value = (self.config.deposit_usd * 1_00000000.0) as u64;
+        let deposit = calculate_sol_for_usd_value(usd_value, price, PRICE_DECIMALS).unwrap_or(minimum);
+
+        Ok(deposit.max(minimum))
+    }
+
+    // DONUT value in lamports at the current pool ratio
+    pub fn donut_value_in_sol(&self, donut: u64) -> u64 {
+        if self.pool_donut == 0 {
+            return 0;
+        }
+
+        ((donut as u128) * (self.pool_sol as u128) / (self.pool_donut as u128)) as u64
+    }
+
+    fn new_user(&mut self, referrer: Option<usize>, upline: Vec<usize>, now: i64) -> usize {
+        let index = self.users.len();
+
+        let chain_id = self.state.next_chain_id;
+        self.state.next_chain_id += 1;
+        self.state.next_upline_id += 1;
+
+        self.users.push(User {
+            node: MatrixNode {
+                key: Pubkey::new_from_array(user_key_bytes(index)),
+                chain: ReferralChain {
+                    id: chain_id,
+                    ..ReferralChain::default()
+                },
+                reserved_sol: 0,
+                reserved_tokens: 0,
+                expires_at: 0,
+            },
+            referrer,
+            upline,
+            registered_at: now,
+            deposit: 0,
+            treasury_fee: 0,
+            retained: 0,
+            sol_received: 0,
+            donut_received: 0,
+            referrals: 0,
+            matrices_completed: 0,
+        });
+
+        self.picker.add_user(index);
+        index
+    }
+
+    // register_without_referrer: the deposit goes straight to the pool
+    fn register_root(&mut self, now: i64) {
+        let reserves = self.pool_reserves();
+        record_price_observation(&mut self.state, &reserves, now);
+
+        let deposit = self.deposit_amount(self.sol_price(now)).unwrap_or(0);
+        let user = self.new_user(None, Vec::new(), now);
+
+        self.users[user].deposit = deposit;
+        self.pool_sol += deposit;
+        self.totals.pool_deposits += deposit;
+        self.totals.deposits += deposit;
+        self.totals.attempted += 1;
+        self.totals.registered += 1;
+    }
+
+    // register_with_sol_deposit under `referrer`
+    // A mint over the budget reverts the registration, which is counted and skipped
+    fn register(&mut self, referrer: usize, now: i64) -> Result<()> {
+        self.totals.attempted += 1;
+
+        let reserves = self.pool_reserves();
+        record_price_observation(&mut self.state, &reserves, now);
+
+        let price = self.sol_price(now);
+        let deposit = self.deposit_amount(price)?;
+        let treasury_fee = calculate_treasury_fee(deposit, self.state.treasury_fee_bps)?;
+        let slot_deposit = deposit - treasury_fee;
+
+        // The referrer's uplines, closest first, as passed in remaining_accounts
+        let upline_indices: Vec<usize> = self.users[referrer].upline.iter().rev().copied().collect();
+
+        let mut referrer_node = self.users[referrer].node.clone();
+        let mut upline_nodes: Vec<MatrixNode> = upline_indices.iter().map(|&index| self.users[index].node.clone()).collect();
+        let mut mint_budget = self.mint_budget.clone();
+
+        // The new user's chain ID is taken before the matrix runs, as in the handler
+        let next_chain_id = self.state.next_chain_id + 1;
+        let user_key = Pubkey::new_from_array(user_key_bytes(self.users.len()));
+
+        let state = &self.state;
+        let result = matrix::process_deposit(
+            user_key,
+            &mut referrer_node,
+            &mut upline_nodes,
+            slot_deposit,
+            next_chain_id,
+            0,
+            |sol_amount| {
+                let token_amount = get_donut_tokens_amount(Some(&reserves), state, sol_amount, now)?;
+                let deposit_usd_value = calculate_usd_value(sol_amount, price, PRICE_DECIMALS)
+                    .ok_or(error!(ErrorCode::PriceFeedReadFailed))?;
+                check_mint_limit(&mut mint_budget, token_amount, deposit_usd_value, now)?;
+
+                Ok(token_amount)
+            },
+        );
+
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            Err(error) if is_error(&error, ErrorCode::MintBudgetExceeded) => {
+                self.totals.mint_limit_rejections += 1;
+                return Ok(());
+            },
+            Err(error) => return Err(error),
+        };
+
+        // Commit the registration
+        let mut upline = self.users[referrer].upline.clone();
+        if upline.len() >= MAX_UPLINE_DEPTH {
+            upline.drain(..upline.len() - (MAX_UPLINE_DEPTH - 1));
+        }
+        upline.push(referrer);
+
+        let user = self.new_user(Some(referrer), upline, now);
+        self.state.next_chain_id = outcome.next_chain_id;
+        self.mint_budget = mint_budget;
+
+        self.users[referrer].node = referrer_node;
+        self.users[referrer].referrals += 1;
+        for (offset, node) in upline_nodes.into_iter().enumerate().take(outcome.uplines_updated) {
+            self.users[upline_indices[offset]].node = node;
+        }
+        self.picker.add_referral(referrer);
+
+        self.users[user].deposit = deposit;
+        self.users[user].treasury_fee = treasury_fee;
+        self.totals.deposits += deposit;
+        self.totals.treasury_fees += treasury_fee;
+        self.totals.registered += 1;
+
+        let mut spent = 0;
+        for effect in outcome.effects {
+            let owner = |target: Target| match target {
+                Target::Referrer => referrer,
+                Target::Upline(offset) => upline_indices[offset],
+            };
+
+            match effect {
+                Effect::DepositToPool { amount } => {
+                    spent += amount;
+                    self.process_slot1(amount);
+                },
+                Effect::ReserveSol { amount, previous, .. } => {
+                    spent += amount;
+                    self.totals.sol_vault += amount;
+                    self.state.total_reserved_sol = self.state.total_reserved_sol - previous + amount;
+                },
+                Effect::MintTokens { amount, .. } => {
+                    self.totals.donut_minted += amount;
+                    self.totals.token_vault += amount;
+                },
+                Effect::PaySol { target, amount } => {
+                    self.totals.sol_vault -= amount;
+                    self.totals.sol_paid += amount;
+                    self.state.total_reserved_sol -= amount;
+                    self.users[owner(target)].sol_received += amount;
+                },
+                Effect::PayTokens { target, amount } => {
+                    self.totals.token_vault -= amount;
+                    self.totals.donut_paid += amount;
+                    self.users[owner(target)].donut_received += amount;
+                },
+                Effect::ResetChain { target, .. } => {
+                    self.totals.matrices_completed += 1;
+                    self.users[owner(target)].matrices_completed += 1;
+                },
+                Effect::CloseWsol | Effect::SlotFilled { .. } => {},
+            }
+        }
+
+        // A completed root matrix with no uplines leaves the deposit in the user's WSOL account
+        self.users[user].retained = slot_deposit - spent;
+        self.totals.retained += slot_deposit - spent;
+
+        Ok(())
+    }
+
+    // Slot-1 SOL according to the policy
+    fn process_slot1(&mut self, amount: u64) {
+        if self.state.slot1_policy == Slot1Policy::DepositLiquidity {
+            self.pool_sol += amount;
+            self.totals.pool_deposits += amount;
+            return;
+        }
+
+        let donut_out = meteora::swap_output(
+            self.pool_sol,
+            self.pool_donut,
+            amount,
+            self.config.trade_fee_bps,
+            10_000,
+        ).unwrap_or(0);
+
+        self.pool_sol += amount;
+        self.pool_donut -= donut_out;
+        self.totals.buyback_sol += amount;
+
+        if self.state.slot1_policy == Slot1Policy::BuybackAndBurn {
+            self.totals.donut_burned += donut_out;
+        } else {
+            self.totals.donut_to_treasury += donut_out;
+        }
+    }
+}
+
+// Deterministic stand-in for a user account PDA
+fn user_key_bytes(index: usize) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&(index as u64 + 1).to_le_bytes());
+    bytes
+}
+
+fn is_error(error: &anchor_lang::error::Error, expected: ErrorCode) -> bool {
+    matches!(error, anchor_lang::error::Error::AnchorError(anchor_error)
+        if anchor_error.error_code_number == u32::from(expected))
+}
@@ -0,0 +1,80 @@
+// Referral topologies and growth curves that drive a simulation
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::Serialize;
+
+// How each new user picks a referrer among the registered users
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Topology {
+    Line,          // Each user refers the next one
+    Tree,          // Users fill the tree breadth-first, `branching` referrals each
+    Random,        // Uniformly random registered referrer
+    Preferential,  // Referrers weighted by 1 + the referrals they already made
+}
+
+// Shape of the cumulative registration count over the simulated period
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Growth {
+    Constant,      // Same number of registrations every day
+    Linear,        // Daily registrations grow linearly from zero
+    Exponential,   // Daily registrations grow by `growth_rate` per day
+}
+
+// Picks referrers for a topology
+pub struct ReferrerPicker {
+    topology: Topology,
+    branching: usize,
+    joined: usize,         // Referred users registered so far (tree position)
+    tickets: Vec<usize>,   // Preferential attachment: one entry per user plus one per referral
+}
+
+impl ReferrerPicker {
+    pub fn new(topology: Topology, branching: usize) -> Self {
+        Self {
+            topology,
+            branching: branching.max(1),
+            joined: 0,
+            tickets: Vec::new(),
+        }
+    }
+
+    // Record a registered user (index into the registered users)
+    pub fn add_user(&mut self, user: usize) {
+        self.tickets.push(user);
+    }
+
+    // Record a successful referral by `referrer`
+    pub fn add_referral(&mut self, referrer: usize) {
+        self.joined += 1;
+        self.tickets.push(referrer);
+    }
+
+    // Referrer for the next user, among `registered` users
+    pub fn pick(&self, registered: usize, rng: &mut StdRng) -> usize {
+        match self.topology {
+            Topology::Line => registered - 1,
+            Topology::Tree => std::cmp::min(self.joined / self.branching, registered - 1),
+            Topology::Random => rng.gen_range(0..registered),
+            Topology::Preferential => self.tickets[rng.gen_range(0..self.tickets.len())],
+        }
+    }
+}
+
+// Seconds after the start at which registration `index` of `count` happens
+pub fn registration_offset(growth: Growth, index: usize, count: usize, days: f64, growth_rate: f64) -> i64 {
+    let fraction = (index + 1) as f64 / count.max(1) as f64;
+
+    let day = match growth {
+        Growth::Constant => days * fraction,
+        Growth::Linear => days * fraction.sqrt(),
+        Growth::Exponential if growth_rate > 0.0 => {
+            // Cumulative count proportional to e^(rate * t) - 1
+            (1.0 + fraction * ((growth_rate * days).exp() - 1.0)).ln() / growth_rate
+        },
+        Growth::Exponential => days * fraction,
+    };
+
+    (day * 86_400.0) as i64
+}
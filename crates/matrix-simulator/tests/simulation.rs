@@ -0,0 +1,103 @@
+// simulation: conservation of SOL and DONUT across topologies and slot-1 policies
+use matrix_simulator::report::Report;
+use matrix_simulator::topology::{Growth, Topology};
+use matrix_simulator::{silence_program_logs, Config, Simulation};
+use matrix_system::{Slot1Policy, DEFAULT_TWAP_WINDOW};
+
+fn config(topology: Topology, slot1_policy: Slot1Policy) -> Config {
+    Config {
+        registrations: 500,
+        roots: 2,
+        topology,
+        branching: 3,
+        growth: Growth::Exponential,
+        days: 10.0,
+        growth_rate: 0.2,
+        seed: 7,
+        deposit_usd: 12.0,
+        sol_price_start: 100.0,
+        sol_price_end: 60.0,
+        pool_sol: 1_000_000_000_000,
+        pool_donut: 1_000_000_000_000_000,
+        trade_fee_bps: 25,
+        twap_window: DEFAULT_TWAP_WINDOW,
+        epoch_duration: 86_400,
+        max_tokens_per_epoch: 1_000_000_000_000_000,
+        max_tokens_per_usd: 100_000_000_000,
+        treasury_fee_bps: 100,
+        slot1_policy,
+    }
+}
+
+fn run(config: Config) -> Simulation {
+    silence_program_logs();
+    let mut simulation = Simulation::new(config);
+    simulation.run().unwrap();
+    simulation
+}
+
+#[test]
+fn every_deposited_lamport_is_accounted_for() {
+    for topology in [Topology::Line, Topology::Tree, Topology::Random, Topology::Preferential] {
+        for policy in [Slot1Policy::DepositLiquidity, Slot1Policy::BuybackAndBurn, Slot1Policy::BuybackToTreasury] {
+            let totals = run(config(topology, policy)).totals;
+
+            assert_eq!(
+                totals.deposits,
+                totals.treasury_fees + totals.pool_deposits + totals.buyback_sol
+                    + totals.sol_paid + totals.sol_vault + totals.retained,
+                "{:?} / {:?}", topology, policy
+            );
+        }
+    }
+}
+
+#[test]
+fn minted_donut_is_paid_or_held_in_the_vault() {
+    for topology in [Topology::Line, Topology::Tree, Topology::Random, Topology::Preferential] {
+        let totals = run(config(topology, Slot1Policy::DepositLiquidity)).totals;
+        assert_eq!(totals.donut_minted, totals.donut_paid + totals.token_vault, "{:?}", topology);
+    }
+}
+
+#[test]
+fn vault_matches_outstanding_reservations() {
+    let simulation = run(config(Topology::Random, Slot1Policy::DepositLiquidity));
+
+    let reserved: u64 = simulation.users.iter().map(|user| user.node.reserved_sol).sum();
+    assert_eq!(simulation.state.total_reserved_sol, reserved);
+    assert_eq!(simulation.totals.sol_vault, reserved);
+}
+
+#[test]
+fn user_payouts_add_up_to_totals() {
+    let simulation = run(config(Topology::Preferential, Slot1Policy::BuybackAndBurn));
+    let report = Report::new(&simulation);
+
+    let sol_received: u64 = report.users.iter().map(|user| user.sol_received).sum();
+    let donut_received: u64 = report.users.iter().map(|user| user.donut_received).sum();
+    assert_eq!(sol_received, simulation.totals.sol_paid);
+    assert_eq!(donut_received, simulation.totals.donut_paid);
+}
+
+#[test]
+fn tight_mint_budget_rejects_registrations() {
+    let mut tight = config(Topology::Random, Slot1Policy::DepositLiquidity);
+    tight.max_tokens_per_epoch = 500_000_000_000;
+
+    let totals = run(tight).totals;
+    assert!(totals.mint_limit_rejections > 0);
+    assert_eq!(totals.attempted, totals.registered + totals.mint_limit_rejections);
+}
+
+#[test]
+fn same_seed_gives_same_report() {
+    let first = Report::new(&run(config(Topology::Random, Slot1Policy::DepositLiquidity)));
+    let second = Report::new(&run(config(Topology::Random, Slot1Policy::DepositLiquidity)));
+
+    let mut first_json = Vec::new();
+    let mut second_json = Vec::new();
+    first.write_json(&mut first_json).unwrap();
+    second.write_json(&mut second_json).unwrap();
+    assert_eq!(first_json, second_json);
+}
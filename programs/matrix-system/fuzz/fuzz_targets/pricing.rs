@@ -0,0 +1,122 @@
+// Prices deposits against arbitrary pool reserves, TWAP samples, oracle answers and mint budgets
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use matrix_system::{
+    calculate_minimum_sol_deposit, calculate_sol_for_usd_value, calculate_twap_price, calculate_usd_value,
+    check_mint_limit, get_donut_tokens_amount, record_price_observation, ExpiryPolicy, MintBudget, PoolReserves,
+    ProgramState, Slot1Policy, STATE_LAYOUT_VERSION,
+};
+
+#[derive(Arbitrary, Debug)]
+struct Sample {
+    token_a: u64,
+    token_b: u64,
+    elapsed: u16,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    token_a: u64,
+    token_b: u64,
+    trade_fee_numerator: u64,
+    trade_fee_denominator: u64,
+    twap_window: u32,
+    start: u32,
+    samples: Vec<Sample>,
+    sol_amount: u64,
+    larger_sol_amount: u64,
+    oracle_answer: i128,
+    oracle_decimals: u32,
+    epoch_duration: i64,
+    max_tokens_per_epoch: u64,
+    max_tokens_per_usd: u64,
+    epoch_start: i64,
+    minted_in_epoch: u64,
+    budget_now: i64,
+}
+
+fn reserves(token_a: u64, token_b: u64, trade_fee_numerator: u64, trade_fee_denominator: u64) -> PoolReserves {
+    PoolReserves {
+        token_a,
+        token_b,
+        trade_fee_numerator,
+        trade_fee_denominator,
+    }
+}
+
+fn program_state(twap_window: u32) -> ProgramState {
+    ProgramState {
+        owner: Default::default(),
+        multisig_treasury: Default::default(),
+        next_upline_id: 1,
+        next_chain_id: 1,
+        twap_window,
+        observation_index: 0,
+        observation_count: 0,
+        price_observations: Default::default(),
+        slot1_policy: Slot1Policy::DepositLiquidity,
+        buyback_slippage_bps: 0,
+        treasury_fee_bps: 0,
+        total_reserved_sol: 0,
+        vesting_duration: 0,
+        staking_fee_bps: 0,
+        matrix_expiry: 0,
+        expiry_policy: ExpiryPolicy::ReleaseToOwner,
+        layout_version: STATE_LAYOUT_VERSION,
+        reserved_sol_reconciled: true,
+    }
+}
+
+fuzz_target!(|input: Input| {
+    // TWAP samples at clock-sysvar timestamps
+    let mut state = program_state(input.twap_window);
+    let mut now = i64::from(input.start);
+    for sample in input.samples.iter().take(64) {
+        now += i64::from(sample.elapsed);
+        record_price_observation(&mut state, &reserves(sample.token_a, sample.token_b, 25, 10_000), now);
+    }
+
+    // Mints are priced at the TWAP only; the spot reserves just supply the trade fee
+    let pool = reserves(
+        input.token_a,
+        input.token_b,
+        input.trade_fee_numerator,
+        input.trade_fee_denominator,
+    );
+    let smaller = input.sol_amount.min(input.larger_sol_amount);
+    let larger = input.sol_amount.max(input.larger_sol_amount);
+
+    let at_smaller = get_donut_tokens_amount(Some(&pool), &state, smaller, now);
+    let at_larger = get_donut_tokens_amount(Some(&pool), &state, larger, now);
+
+    if calculate_twap_price(&state, now).is_none() {
+        assert!(at_smaller.is_err() && at_larger.is_err());
+    }
+    if let (Ok(at_smaller), Ok(at_larger)) = (&at_smaller, &at_larger) {
+        assert!(*at_smaller > 0 && at_smaller <= at_larger);
+    }
+
+    let other_pool = reserves(input.token_b, input.token_a, input.trade_fee_numerator, input.trade_fee_denominator);
+    assert_eq!(get_donut_tokens_amount(Some(&other_pool), &state, larger, now).ok(), at_larger.ok());
+    assert!(get_donut_tokens_amount(None, &state, larger, now).is_err());
+
+    // Oracle answers, including negative and absurdly scaled ones
+    let minimum = calculate_minimum_sol_deposit(input.oracle_answer, input.oracle_decimals).unwrap();
+    let _ = calculate_usd_value(minimum, input.oracle_answer, input.oracle_decimals);
+    let _ = calculate_sol_for_usd_value(input.sol_amount, input.oracle_answer, input.oracle_decimals);
+
+    // Mint budget: a successful mint never takes the window over its budget
+    let deposit_usd_value = calculate_usd_value(input.sol_amount, input.oracle_answer, input.oracle_decimals).unwrap_or(0);
+    let mut budget = MintBudget {
+        epoch_duration: input.epoch_duration,
+        max_tokens_per_epoch: input.max_tokens_per_epoch,
+        max_tokens_per_usd: input.max_tokens_per_usd,
+        epoch_start: input.epoch_start,
+        minted_in_epoch: input.minted_in_epoch,
+    };
+    if check_mint_limit(&mut budget, input.larger_sol_amount, deposit_usd_value, input.budget_now).is_ok() {
+        assert!(budget.minted_in_epoch <= budget.max_tokens_per_epoch);
+    }
+});
@@ -0,0 +1,18 @@
+// Decodes arbitrary upline account data the way the matrix recursion does
+#![no_main]
+
+use anchor_lang::AccountSerialize;
+use libfuzzer_sys::fuzz_target;
+use matrix_system::decode_user_account_data;
+
+fuzz_target!(|data: &[u8]| {
+    let account = match decode_user_account_data(data) {
+        Ok(account) => account,
+        Err(_) => return,
+    };
+
+    // Whatever decodes must serialize back to the bytes it was read from
+    let mut encoded = Vec::new();
+    account.try_serialize(&mut encoded).unwrap();
+    assert_eq!(encoded[8..], data[8..encoded.len()]);
+});
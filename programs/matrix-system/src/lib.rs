@@ -1,12 +1,18 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::{self, clock::Clock};
 use anchor_spl::token::{self, Token, TokenAccount};
-use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_2022::spl_token_2022;
+use anchor_spl::token_interface::{self, TokenInterface};
+use anchor_spl::associated_token::{self, AssociatedToken};
 use chainlink_solana as chainlink;
-use solana_program::program_pack::Pack;
 #[cfg(not(feature = "no-entrypoint"))]
 use {solana_security_txt::security_txt};
 
+pub mod meteora;
+pub mod matrix;
+
+use matrix::{Effect, MatrixNode, Target};
+
 
 declare_id!("2wFmCLVQ8pSF2aKu43gLv2vzasUHhtmAA9HffBDXcRfF");
 
@@ -26,19 +32,64 @@ security_txt! {
 }
 
 // Minimum deposit amount in USD (10 dollars in base units - 8 decimals)
-const MINIMUM_USD_DEPOSIT: u64 = 10_00000000; // 10 USD with 8 decimals (Chainlink format)
+pub const MINIMUM_USD_DEPOSIT: u64 = 10_00000000; // 10 USD with 8 decimals (Chainlink format)
 
 // Maximum price feed staleness (24 hours in seconds)
-const MAX_PRICE_FEED_AGE: i64 = 86400;
+pub const MAX_PRICE_FEED_AGE: i64 = 86400;
 
 // Default SOL price in case of stale feed ($100 USD per SOL)
-const DEFAULT_SOL_PRICE: i128 = 100_00000000; // $100 with 8 decimals
+pub const DEFAULT_SOL_PRICE: i128 = 100_00000000; // $100 with 8 decimals
 
 // Maximum number of upline accounts that can be processed in a single transaction
-const MAX_UPLINE_DEPTH: usize = 6;
+pub const MAX_UPLINE_DEPTH: usize = 6;
+
+// Number of Vault A accounts in the remaining_accounts (LP, LP mint, token vault, vault)
+pub const VAULT_A_ACCOUNTS_COUNT: usize = 4;
+
+// Number of pool price observations kept in the program state ring buffer
+pub const MAX_PRICE_OBSERVATIONS: usize = 16;
+
+// Observations needed before the TWAP is used (a quarter of the ring buffer)
+pub const MIN_TWAP_OBSERVATIONS: usize = 4;
+
+// Default window for the time-weighted pool price (30 minutes in seconds)
+pub const DEFAULT_TWAP_WINDOW: u32 = 1800;
+
+// Maximum configurable TWAP window (7 days in seconds)
+pub const MAX_TWAP_WINDOW: u32 = 604800;
+
+// Scale of the DONUT/SOL pool ratio stored in price observations
+pub const PRICE_SCALE: u128 = 1_000_000_000_000;
+
+// Denominator for values expressed in basis points
+pub const BASIS_POINTS_DENOMINATOR: u128 = 10_000;
+
+// Maximum slippage that can be configured for token deposit swaps (10%)
+pub const MAX_SWAP_SLIPPAGE_BPS: u16 = 1_000;
+
+// Default slippage allowed when slot-1 SOL buys DONUT (1%)
+pub const DEFAULT_BUYBACK_SLIPPAGE_BPS: u16 = 100;
+
+// Hard cap on the treasury fee taken from each deposit (5%)
+pub const MAX_TREASURY_FEE_BPS: u16 = 500;
+
+// Longest vesting duration that can be configured for matrix rewards (1 year)
+pub const MAX_VESTING_DURATION: i64 = 365 * 24 * 60 * 60;
+
+// Payouts a vesting account tracks separately before the earliest-ending ones are joined
+pub const MAX_VESTING_TRANCHES: usize = 8;
+
+// Hard cap on the share of slot-3 SOL payouts sent to stakers (10%)
+pub const MAX_STAKING_FEE_BPS: u16 = 1_000;
+
+// Fixed-point scale of the staking reward per staked token
+pub const REWARD_PER_SHARE_SCALE: u128 = 1_000_000_000_000;
 
-// Number of Vault A accounts in the remaining_accounts
-const VAULT_A_ACCOUNTS_COUNT: usize = 3;
+// Longest matrix expiry that can be configured (1 year)
+pub const MAX_MATRIX_EXPIRY: i64 = 365 * 24 * 60 * 60;
+
+// Program state layout written by initialize and migrate_state
+pub const STATE_LAYOUT_VERSION: u8 = 1;
 
 // Constants for strict address verification
 pub mod verified_addresses {
@@ -51,7 +102,16 @@ pub mod verified_addresses {
     
     // Meteora pool addresses
     pub static POOL_ADDRESS: Pubkey = solana_program::pubkey!("BEuzx33ecm4rtgjtB2bShqGco4zMkdr6ioyzPh6vY9ot");
+    pub static B_VAULT: Pubkey = solana_program::pubkey!("FERjPVNEa7Udq8CEv68h6tPL46Tq7ieE49HrE2wea3XT");
     pub static B_VAULT_LP: Pubkey = solana_program::pubkey!("8mNjx5Aww9DX33uFxZwqb7m2vhsavrxyzkME3hE63sT2");
+    pub static B_VAULT_LP_MINT: Pubkey = solana_program::pubkey!("BvoAjwEDhpLzs3jtu4H72j96ShKT5rvZE9RP1vgpfSM");
+    pub static B_TOKEN_VAULT: Pubkey = solana_program::pubkey!("HZeLxbZ9uHtSpwZC3LBr4Nubd14iHwz7bRSghRZf5VCG");
+    
+    // Meteora dynamic vault program
+    pub static VAULT_PROGRAM: Pubkey = solana_program::pubkey!("24Uqj9JCLxUeoC3hGfh5W3s9FM9uCHDS2SG3LYwBpyTi");
+    
+    // Meteora dynamic AMM program (token deposit swaps)
+    pub static AMM_PROGRAM: Pubkey = solana_program::pubkey!("Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB");
     
     // Token and oracle addresses
     pub static TOKEN_MINT: Pubkey = solana_program::pubkey!("3dCXCZd3cbKHT7jQSLzRNJQYu1zEzaD8FHi4MWHLX4DZ");
@@ -79,11 +139,312 @@ pub struct ProgramState {
     pub multisig_treasury: Pubkey,
     pub next_upline_id: u32,
     pub next_chain_id: u32,
-    pub last_mint_amount: u64,
+    pub twap_window: u32,                // Seconds averaged by the TWAP used for minting
+    pub observation_index: u8,           // Index of the most recent observation
+    pub observation_count: u8,           // Number of valid observations in the buffer
+    pub price_observations: [PriceObservation; MAX_PRICE_OBSERVATIONS],
+    pub slot1_policy: Slot1Policy,       // What happens to slot-1 SOL
+    pub buyback_slippage_bps: u16,       // Slippage allowed when slot-1 SOL buys DONUT
+    pub treasury_fee_bps: u16,           // Share of each deposit sent to the treasury
+    pub total_reserved_sol: u64,         // Outstanding slot-2 SOL reservations held in program_sol_vault
+    pub vesting_duration: i64,           // Seconds over which slot-3 DONUT vests (0 pays immediately)
+    pub staking_fee_bps: u16,            // Share of slot-3 SOL payouts sent to DONUT stakers
+    pub matrix_expiry: i64,              // Seconds a matrix has to fill after its first slot (0 never expires)
+    pub expiry_policy: ExpiryPolicy,     // What happens to the reservations of an expired matrix
+    pub layout_version: u8,              // STATE_LAYOUT_VERSION of this account
+    pub reserved_sol_reconciled: bool,   // total_reserved_sol covers every reservation (false after migrate_state)
 }
 
 impl ProgramState {
-    pub const SIZE: usize = 32 + 32 + 4 + 4 + 8; // owner + multisig_treasury + next_upline_id + next_chain_id + last_mint_amount
+    pub const SIZE: usize = 32 + 32 + 4 + 4 + // owner + multisig_treasury + next_upline_id + next_chain_id
+                           4 + 1 + 1 + // twap_window + observation_index + observation_count
+                           (MAX_PRICE_OBSERVATIONS * PriceObservation::SIZE) + // price_observations
+                           1 + 2 + // slot1_policy + buyback_slippage_bps
+                           2 + // treasury_fee_bps
+                           8 + // total_reserved_sol
+                           8 + // vesting_duration
+                           2 + // staking_fee_bps
+                           8 + 1 + // matrix_expiry + expiry_policy
+                           1 + 1; // layout_version + reserved_sol_reconciled
+
+    // Function to build the state written by initialize
+    pub fn new(owner: Pubkey, multisig_treasury: Pubkey) -> Self {
+        Self {
+            owner,
+            multisig_treasury,
+            next_upline_id: 1,
+            next_chain_id: 1,
+            twap_window: DEFAULT_TWAP_WINDOW,
+            observation_index: 0,
+            observation_count: 0,
+            price_observations: [PriceObservation::default(); MAX_PRICE_OBSERVATIONS],
+            slot1_policy: Slot1Policy::DepositLiquidity,
+            buyback_slippage_bps: DEFAULT_BUYBACK_SLIPPAGE_BPS,
+            treasury_fee_bps: 0,
+            total_reserved_sol: 0,
+            vesting_duration: 0,
+            staking_fee_bps: 0,
+            matrix_expiry: 0,
+            expiry_policy: ExpiryPolicy::ReleaseToOwner,
+            layout_version: STATE_LAYOUT_VERSION,
+            reserved_sol_reconciled: true,
+        }
+    }
+}
+
+// Program state as deployed before the layout was versioned, upgraded by migrate_state
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyProgramState {
+    pub owner: Pubkey,
+    pub multisig_treasury: Pubkey,
+    pub next_upline_id: u32,
+    pub next_chain_id: u32,
+    pub last_mint_amount: u64,
+}
+
+impl LegacyProgramState {
+    pub const SIZE: usize = 32 + 32 + 4 + 4 + 8;
+}
+
+// Destination of the SOL deposited in slot 1
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Slot1Policy {
+    #[default]
+    DepositLiquidity,    // Add the SOL to Meteora vault B
+    BuybackAndBurn,      // Swap the SOL for DONUT and burn it
+    BuybackToTreasury,   // Swap the SOL for DONUT and send it to the treasury
+}
+
+// Destination of the reserved SOL and DONUT of an expired matrix
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum ExpiryPolicy {
+    #[default]
+    ReleaseToOwner,      // Pay the reservations to the matrix owner
+    ReturnToPool,        // Deposit the SOL to Meteora vault B and burn the DONUT
+    MoveToTreasury,      // Send the SOL and DONUT to the treasury
+}
+
+// Rolling mint budget configuration and counters
+#[account]
+pub struct MintBudget {
+    pub epoch_duration: i64,        // Length of a budget window in seconds
+    pub max_tokens_per_epoch: u64,  // Maximum DONUT minted per window
+    pub max_tokens_per_usd: u64,    // Maximum DONUT minted per 1 USD of deposit
+    pub epoch_start: i64,           // Start of the current window
+    pub minted_in_epoch: u64,       // DONUT minted in the current window
+}
+
+impl MintBudget {
+    pub const SIZE: usize = 8 + 8 + 8 + 8 + 8;
+}
+
+// SPL token accepted for registration deposits
+#[account]
+pub struct AcceptedToken {
+    pub mint: Pubkey,            // Token mint
+    pub price_feed: Pubkey,      // Chainlink TOKEN/USD feed
+    pub swap_pool: Pubkey,       // Meteora TOKEN/SOL pool used to convert deposits
+    pub max_slippage_bps: u16,   // Maximum slippage against the oracle value
+    pub enabled: bool,
+}
+
+impl AcceptedToken {
+    pub const SIZE: usize = 32 + 32 + 32 + 2 + 1;
+}
+
+// DONUT staking pool, PDA seeds [b"staking_pool"]
+// Staked DONUT is held by the staking_vault_authority ATA; SOL rewards by the staking_reward_vault PDA
+#[account]
+#[derive(Default)]
+pub struct StakingPool {
+    pub total_staked: u64,          // DONUT currently staked
+    pub reward_per_share: u128,     // Lamports earned per staked token, scaled by REWARD_PER_SHARE_SCALE
+    pub total_rewards: u64,         // Lamports distributed to stakers since creation
+}
+
+impl StakingPool {
+    pub const SIZE: usize = 8 + 16 + 8;
+
+    // Spread a SOL reward over the currently staked DONUT
+    pub fn distribute(&mut self, reward: u64) -> Result<()> {
+        if self.total_staked == 0 {
+            return Ok(());
+        }
+
+        let increment = (reward as u128)
+            .checked_mul(REWARD_PER_SHARE_SCALE)
+            .ok_or(error!(ErrorCode::StakingOverflow))?
+            / self.total_staked as u128;
+
+        self.reward_per_share = self.reward_per_share
+            .checked_add(increment)
+            .ok_or(error!(ErrorCode::StakingOverflow))?;
+        self.total_rewards = self.total_rewards.saturating_add(reward);
+
+        Ok(())
+    }
+}
+
+// A wallet's DONUT stake, PDA seeds [b"user_stake", wallet]
+#[account]
+#[derive(Default)]
+pub struct UserStake {
+    pub owner_wallet: Pubkey,       // Wallet that owns the stake
+    pub amount: u64,                // DONUT staked
+    pub reward_debt: u128,          // amount * reward_per_share at the last settlement
+    pub pending_rewards: u64,       // Lamports earned but not yet claimed
+}
+
+impl UserStake {
+    pub const SIZE: usize = 32 + 8 + 16 + 8;
+
+    // Move rewards earned since the last settlement into pending_rewards
+    pub fn settle(&mut self, reward_per_share: u128) -> Result<()> {
+        let accumulated = (self.amount as u128)
+            .checked_mul(reward_per_share)
+            .ok_or(error!(ErrorCode::StakingOverflow))?
+            / REWARD_PER_SHARE_SCALE;
+
+        let earned = u64::try_from(accumulated.saturating_sub(self.reward_debt))
+            .map_err(|_| error!(ErrorCode::StakingOverflow))?;
+
+        self.pending_rewards = self.pending_rewards
+            .checked_add(earned)
+            .ok_or(error!(ErrorCode::StakingOverflow))?;
+        self.reward_debt = accumulated;
+
+        Ok(())
+    }
+
+    // Reset the reward debt after the staked amount changes
+    pub fn reset_reward_debt(&mut self, reward_per_share: u128) -> Result<()> {
+        self.reward_debt = (self.amount as u128)
+            .checked_mul(reward_per_share)
+            .ok_or(error!(ErrorCode::StakingOverflow))?
+            / REWARD_PER_SHARE_SCALE;
+
+        Ok(())
+    }
+}
+
+// One payout unlocking linearly until end_time
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug)]
+pub struct VestingTranche {
+    pub amount: u64,                // DONUT still locked at start_time (0 marks a free tranche)
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+impl VestingTranche {
+    pub const SIZE: usize = 8 + 8 + 8;
+
+    // Part of the tranche unlocked at `now`
+    pub fn vested_amount(&self, now: i64) -> u64 {
+        if now >= self.end_time {
+            return self.amount;
+        }
+        if now <= self.start_time {
+            return 0;
+        }
+
+        let elapsed = (now - self.start_time) as u128;
+        let duration = (self.end_time - self.start_time) as u128;
+
+        (self.amount as u128 * elapsed / duration) as u64
+    }
+}
+
+// Linear vesting of the DONUT paid out to a wallet, PDA seeds [b"vesting", wallet]
+// Each payout vests over its own period; later payouts don't move the end of earlier ones
+#[account]
+#[derive(Default)]
+pub struct VestingAccount {
+    pub owner_wallet: Pubkey,       // Wallet that can claim
+    pub total_amount: u64,          // DONUT credited since the account was created
+    pub unlocked_amount: u64,       // DONUT unlocked by tranches up to the last payout
+    pub claimed_amount: u64,        // DONUT already claimed
+    pub tranches: [VestingTranche; MAX_VESTING_TRANCHES],
+}
+
+impl VestingAccount {
+    pub const SIZE: usize = 32 + 8 + 8 + 8 + (MAX_VESTING_TRANCHES * VestingTranche::SIZE);
+
+    // DONUT unlocked at `now`
+    pub fn vested_amount(&self, now: i64) -> u64 {
+        self.tranches
+            .iter()
+            .fold(self.unlocked_amount, |vested, tranche| vested.saturating_add(tranche.vested_amount(now)))
+    }
+
+    // DONUT that can be claimed at `now`
+    pub fn claimable_amount(&self, now: i64) -> u64 {
+        self.vested_amount(now).saturating_sub(self.claimed_amount)
+    }
+
+    // Credit a payout vesting from `now` over `duration`
+    pub fn add_tokens(&mut self, amount: u64, now: i64, duration: i64) -> Result<()> {
+        self.total_amount = self.total_amount
+            .checked_add(amount)
+            .ok_or(error!(ErrorCode::VestingOverflow))?;
+
+        // Move what each tranche has unlocked so far out of it; the rest keeps its end time
+        for tranche in self.tranches.iter_mut().filter(|tranche| tranche.amount > 0) {
+            let vested = tranche.vested_amount(now);
+            self.unlocked_amount = self.unlocked_amount
+                .checked_add(vested)
+                .ok_or(error!(ErrorCode::VestingOverflow))?;
+
+            if vested == tranche.amount {
+                *tranche = VestingTranche::default();
+            } else {
+                tranche.amount -= vested;
+                tranche.start_time = tranche.start_time.max(now);
+            }
+        }
+
+        if duration <= 0 {
+            self.unlocked_amount = self.unlocked_amount
+                .checked_add(amount)
+                .ok_or(error!(ErrorCode::VestingOverflow))?;
+            return Ok(());
+        }
+
+        let tranche = VestingTranche {
+            amount,
+            start_time: now,
+            end_time: now.checked_add(duration).ok_or(error!(ErrorCode::VestingOverflow))?,
+        };
+
+        if let Some(free) = self.tranches.iter_mut().find(|tranche| tranche.amount == 0) {
+            *free = tranche;
+            return Ok(());
+        }
+
+        // All tranches are in use: the one ending first joins the next to end, which it
+        // finishes with, and the payout takes its place
+        let mut by_end: Vec<usize> = (0..MAX_VESTING_TRANCHES).collect();
+        by_end.sort_by_key(|&index| self.tranches[index].end_time);
+        let (first, next) = (by_end[0], by_end[1]);
+
+        self.tranches[next].amount = self.tranches[next].amount
+            .checked_add(self.tranches[first].amount)
+            .ok_or(error!(ErrorCode::VestingOverflow))?;
+        self.tranches[first] = tranche;
+
+        Ok(())
+    }
+}
+
+// Pool price sample used to build the time-weighted average price
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PriceObservation {
+    pub timestamp: i64,          // Unix time of the sample
+    pub price: u128,             // DONUT per SOL pool ratio, scaled by PRICE_SCALE
+    pub cumulative_price: u128,  // Sum of price * seconds up to timestamp (wrapping)
+}
+
+impl PriceObservation {
+    pub const SIZE: usize = 8 + 16 + 16;
 }
 
 // Structure to store complete information for each upline
@@ -102,7 +463,7 @@ pub struct ReferralUpline {
 }
 
 // Referral matrix structure
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, Debug, PartialEq, Eq)]
 pub struct ReferralChain {
     pub id: u32,
     pub slots: [Option<Pubkey>; 3],
@@ -120,6 +481,10 @@ pub struct UserAccount {
     pub chain: ReferralChain,
     pub reserved_sol: u64,       // SOL reserved from the second slot
     pub reserved_tokens: u64,    // Tokens reserved from the second slot
+    // Deadline for filling the current matrix (0 = no deadline)
+    // Kept last so accounts created before it was added still deserialize; migrate_user_account
+    // reallocates them and clears the stale bytes it reads from
+    pub matrix_expires_at: i64,
 }
 
 impl UserAccount {
@@ -129,7 +494,11 @@ impl UserAccount {
                            4 + 1 + 4 + (MAX_UPLINE_DEPTH * (32 + 32)) + // ReferralUpline
                            4 + (3 * (1 + 32)) + 1 + // ReferralChain
                            8 + // reserved_sol
-                           8;  // reserved_tokens
+                           8 + // reserved_tokens
+                           8;  // matrix_expires_at
+
+    // Size of accounts created before matrix_expires_at was added
+    pub const LEGACY_SIZE: usize = Self::SIZE - 8;
 }
 
 // Error codes
@@ -248,6 +617,144 @@ pub enum ErrorCode {
     
     #[msg("Invalid price feed")]
     InvalidPriceFeed,
+
+    #[msg("Invalid vault B LP mint address")]
+    InvalidVaultBLpMintAddress,
+
+    #[msg("Invalid token B vault address")]
+    InvalidTokenBVaultAddress,
+
+    #[msg("Invalid TWAP window")]
+    InvalidTwapWindow,
+
+    #[msg("Mint budget exceeded")]
+    MintBudgetExceeded,
+
+    #[msg("Invalid mint budget configuration")]
+    InvalidMintBudgetConfig,
+
+    #[msg("Invalid token program for the token mint")]
+    InvalidTokenProgram,
+
+    #[msg("Token is not accepted for deposits")]
+    TokenNotAccepted,
+
+    #[msg("Invalid AMM program")]
+    InvalidAmmProgram,
+
+    #[msg("Invalid slippage configuration")]
+    InvalidSlippage,
+
+    #[msg("Failed to swap deposit token")]
+    TokenSwapFailed,
+
+    #[msg("Token account is not the canonical ATA")]
+    TokenAccountNotCanonical,
+
+    #[msg("Failed to create token account")]
+    TokenAccountCreationFailed,
+
+    #[msg("Wallet does not match the account owner")]
+    WalletMismatch,
+
+    #[msg("Invalid vault program")]
+    InvalidVaultProgram,
+
+    #[msg("Invalid vault B address")]
+    InvalidVaultBAddress,
+
+    #[msg("Missing accounts required by the slot-1 buyback")]
+    MissingBuybackAccounts,
+
+    #[msg("Invalid pool protocol fee account")]
+    InvalidProtocolFeeAddress,
+
+    #[msg("Failed to burn tokens")]
+    TokenBurnFailed,
+
+    #[msg("Invalid treasury fee")]
+    InvalidTreasuryFee,
+
+    #[msg("Missing treasury accounts required by the fee")]
+    MissingTreasuryAccounts,
+
+    #[msg("Invalid treasury address")]
+    InvalidTreasuryAddress,
+
+    #[msg("Failed to pay the treasury fee")]
+    TreasuryFeeFailed,
+
+    #[msg("Program SOL vault holds less than the outstanding reservations")]
+    ReservedSolInvariantViolated,
+
+    #[msg("Amount exceeds the unallocated SOL in the program vault")]
+    InsufficientExcessSol,
+
+    #[msg("Invalid vesting duration")]
+    InvalidVestingDuration,
+
+    #[msg("Invalid vesting account")]
+    InvalidVestingAccount,
+
+    #[msg("Missing vesting account")]
+    MissingVestingAccount,
+
+    #[msg("Vesting amount overflow")]
+    VestingOverflow,
+
+    #[msg("No vested tokens to claim")]
+    NothingToClaim,
+
+    #[msg("Invalid staking fee")]
+    InvalidStakingFee,
+
+    #[msg("Missing staking accounts required by the staking fee")]
+    MissingStakingAccounts,
+
+    #[msg("Invalid stake amount")]
+    InvalidStakeAmount,
+
+    #[msg("Staking amount overflow")]
+    StakingOverflow,
+
+    #[msg("Failed to pay staking rewards")]
+    StakingRewardPaymentFailed,
+
+    #[msg("Invalid matrix expiry")]
+    InvalidMatrixExpiry,
+
+    #[msg("Matrix has not expired")]
+    MatrixNotExpired,
+
+    #[msg("Missing accounts required by the expiry policy")]
+    MissingExpiryAccounts,
+
+    #[msg("Pool reserves unavailable")]
+    PoolReservesUnavailable,
+
+    #[msg("Not enough price observations for the TWAP")]
+    TwapUnavailable,
+
+    #[msg("Deposit is worth no DONUT at the TWAP price")]
+    InvalidDonutAmount,
+
+    #[msg("Program state already uses the current layout")]
+    StateAlreadyMigrated,
+
+    #[msg("Reserved SOL total must be reconciled after the state migration")]
+    ReservedSolNotReconciled,
+
+    #[msg("Reserved SOL total is already reconciled")]
+    ReservedSolAlreadyReconciled,
+
+    #[msg("User account must be migrated to the current layout")]
+    UserAccountNotMigrated,
+
+    #[msg("User account already uses the current layout")]
+    UserAccountAlreadyMigrated,
+
+    #[msg("Upline account passed more than once")]
+    DuplicateUplineAccount,
 }
 
 // Event structure for slot filling
@@ -259,6 +766,88 @@ pub struct SlotFilled {
     pub owner: Pubkey,    // Owner of the matrix
 }
 
+// Event structure for slot-1 buybacks
+#[event]
+pub struct SlotOneBuyback {
+    pub sol_amount: u64,      // SOL swapped
+    pub donut_amount: u64,    // DONUT received from the pool
+    pub burned: bool,         // Burned (true) or sent to the treasury (false)
+}
+
+// Event structure for treasury fees
+#[event]
+pub struct TreasuryFeeCollected {
+    pub user: Pubkey,           // Registering wallet
+    pub deposit_amount: u64,    // Deposit before the fee
+    pub fee_amount: u64,        // Lamports sent to the treasury
+    pub fee_bps: u16,           // Fee rate applied
+}
+
+// Event structure for treasury sweeps of unallocated SOL
+#[event]
+pub struct ExcessSolSwept {
+    pub amount: u64,              // Lamports sent to the treasury
+    pub total_reserved_sol: u64,  // Outstanding reservations left in the vault
+}
+
+// Event structure for the reserved SOL total set after a state migration
+#[event]
+pub struct ReservedSolReconciled {
+    pub total_reserved_sol: u64,  // Outstanding reservations counted off-chain
+    pub vault_balance: u64,       // Program vault lamports at the time
+}
+
+// Event structure for DONUT credited to a vesting account
+#[event]
+pub struct TokensVested {
+    pub wallet: Pubkey,       // Wallet receiving the rewards
+    pub amount: u64,          // DONUT credited
+    pub start_time: i64,      // Start of the payout's schedule
+    pub duration: i64,        // Length of the schedule in seconds
+}
+
+// Event structure for SOL sent to stakers from a slot-3 payout
+#[event]
+pub struct StakingRewardsDistributed {
+    pub amount: u64,              // Lamports moved to the staking reward vault
+    pub total_staked: u64,        // DONUT staked at distribution time
+    pub reward_per_share: u128,   // Updated reward per staked token (scaled)
+}
+
+// Event structure for stake changes
+#[event]
+pub struct StakeUpdated {
+    pub wallet: Pubkey,           // Staking wallet
+    pub amount: u64,              // DONUT staked by the wallet after the change
+    pub total_staked: u64,        // DONUT staked in the pool after the change
+}
+
+// Event structure for staking reward claims
+#[event]
+pub struct StakingRewardsClaimed {
+    pub wallet: Pubkey,           // Claiming wallet
+    pub amount: u64,              // Lamports paid
+}
+
+// Event structure for expired matrices
+#[event]
+pub struct MatrixExpired {
+    pub owner: Pubkey,            // Owner of the matrix (user account)
+    pub chain_id: u32,            // ID of the expired matrix
+    pub new_chain_id: u32,        // ID of the reset matrix
+    pub policy: ExpiryPolicy,     // Policy applied to the reservations
+    pub sol_amount: u64,          // Reserved SOL released
+    pub token_amount: u64,        // Reserved DONUT released
+}
+
+// Event structure for vested DONUT claims
+#[event]
+pub struct VestedTokensClaimed {
+    pub wallet: Pubkey,       // Wallet claiming
+    pub amount: u64,          // DONUT transferred
+    pub claimed_amount: u64,  // Total claimed so far
+}
+
 // Decimal handling for price display
 #[derive(Default)]
 pub struct Decimal {
@@ -295,6 +884,25 @@ fn force_memory_cleanup() {
     // The vector will be automatically freed when it goes out of scope
 }
 
+// Function to log the heap taken so far, read by the compute budget suite (heap-profile builds only)
+// The default bump allocator keeps its position in the first word of the heap and allocates downwards
+#[cfg(all(feature = "heap-profile", target_os = "solana"))]
+fn log_heap_usage() {
+    use solana_program::entrypoint::{HEAP_LENGTH, HEAP_START_ADDRESS};
+
+    // SAFETY: on SBF the heap region starts at HEAP_START_ADDRESS and is mapped for the whole
+    // program run, so its first word is always readable and aligned for usize; the read only
+    // observes the allocator's position and never writes to it
+    let position = unsafe { *(HEAP_START_ADDRESS as *const usize) };
+    let heap_end = HEAP_START_ADDRESS as usize + HEAP_LENGTH;
+    let used = if position == 0 { 0 } else { heap_end - position };
+
+    msg!("Heap used: {} of {} bytes", used, HEAP_LENGTH);
+}
+
+#[cfg(not(all(feature = "heap-profile", target_os = "solana")))]
+fn log_heap_usage() {}
+
 // Function to get SOL/USD price from Chainlink feed
 fn get_sol_usd_price<'info>(
     chainlink_feed: &AccountInfo<'info>,
@@ -321,23 +929,26 @@ fn get_sol_usd_price<'info>(
 }
 
 
-// Function to calculate minimum SOL deposit based on USD price
-fn calculate_minimum_sol_deposit<'info>(
+// Function to get the SOL/USD price to use, falling back to the default price on a stale feed
+fn get_effective_sol_usd_price<'info>(
     chainlink_feed: &AccountInfo<'info>, 
     chainlink_program: &AccountInfo<'info>
-) -> Result<u64> {
+) -> Result<(i128, u32)> {
     let (price, decimals, current_timestamp, feed_timestamp) = get_sol_usd_price(chainlink_feed, chainlink_program)?;
     
     // Check if price feed is too old (24 hours)
     let age = current_timestamp - feed_timestamp;
     
-    let sol_price_per_unit = if age > MAX_PRICE_FEED_AGE {
-        // Use default price of $100 per SOL
-        DEFAULT_SOL_PRICE
-    } else {
-        price
-    };
-    
+    if age > MAX_PRICE_FEED_AGE {
+        // Use default price of $100 per SOL (8 decimals)
+        return Ok((DEFAULT_SOL_PRICE, 8));
+    }
+
+    Ok((price, decimals))
+}
+
+// Function to calculate minimum SOL deposit based on USD price
+pub fn calculate_minimum_sol_deposit(sol_price_per_unit: i128, decimals: u32) -> Result<u64> {
     // Convert price to SOL per unit using dynamic decimals
     let price_f64 = sol_price_per_unit as f64 / 10f64.powf(decimals as f64);
     
@@ -353,331 +964,410 @@ fn calculate_minimum_sol_deposit<'info>(
     Ok(minimum_lamports)
 }
 
-// Function to check and adjust the mint value based on history
-fn check_mint_limit(program_state: &mut ProgramState, proposed_mint_value: u64) -> Result<u64> {
-    // If it's the first mint (last_mint_amount = 0), allow any value
-    if program_state.last_mint_amount == 0 {
-        msg!("First mint: establishing base value for limiter: {}", proposed_mint_value);
-        
-        // Update the last value and allow the proposed mint
-        program_state.last_mint_amount = proposed_mint_value;
-        return Ok(proposed_mint_value);
+// Function to calculate the USD value (8 decimals) of a token amount
+fn calculate_token_usd_value(
+    amount: u64,
+    token_decimals: u32,
+    price_per_unit: i128,
+    price_decimals: u32,
+) -> Option<u64> {
+    if price_per_unit <= 0 {
+        return None;
     }
-    
-    // Calculate the limit (3x the last value)
-    // Use of saturating_mul to safely avoid overflow
-    let current_limit = program_state.last_mint_amount.saturating_mul(3);
-    
-    // Check if the proposed value exceeds the limit
-    if proposed_mint_value > current_limit {
+
+    // amount * price / 10^(token_decimals + price_decimals) is whole USD, scale to 8 decimals
+    let numerator = (amount as u128)
+        .checked_mul(price_per_unit as u128)?
+        .checked_mul(1_00000000)?;
+    let denominator = 10u128.checked_pow(token_decimals.checked_add(price_decimals)?)?;
+
+    u64::try_from(numerator.checked_div(denominator)?).ok()
+}
+
+// Function to calculate the USD value (8 decimals) of a SOL amount in lamports
+pub fn calculate_usd_value(sol_amount: u64, sol_price_per_unit: i128, decimals: u32) -> Option<u64> {
+    calculate_token_usd_value(sol_amount, 9, sol_price_per_unit, decimals)
+}
+
+// Function to calculate the lamports worth a USD value (8 decimals)
+pub fn calculate_sol_for_usd_value(usd_value: u64, sol_price_per_unit: i128, decimals: u32) -> Option<u64> {
+    if sol_price_per_unit <= 0 {
+        return None;
+    }
+
+    // usd * 10^9 * 10^decimals / (price * 10^8)
+    let numerator = (usd_value as u128)
+        .checked_mul(1_000_000_000)?
+        .checked_mul(10u128.checked_pow(decimals)?)?;
+    let denominator = (sol_price_per_unit as u128).checked_mul(1_00000000)?;
+
+    u64::try_from(numerator.checked_div(denominator)?).ok()
+}
+
+// Function to check a proposed mint against the rolling mint budget
+// Fails with MintBudgetExceeded instead of substituting another amount
+pub fn check_mint_limit(
+    mint_budget: &mut MintBudget,
+    proposed_mint_value: u64,
+    deposit_usd_value: u64,
+    now: i64,
+) -> Result<()> {
+    // Start a new budget window once the current one has elapsed
+    if now >= mint_budget.epoch_start.saturating_add(mint_budget.epoch_duration) {
+        mint_budget.epoch_start = now;
+        mint_budget.minted_in_epoch = 0;
+    }
+
+    // Limit for a single mint, derived from the USD value of the deposit (8 decimals)
+    let max_single_mint = (deposit_usd_value as u128)
+        .saturating_mul(mint_budget.max_tokens_per_usd as u128)
+        / 1_00000000u128;
+
+    if proposed_mint_value as u128 > max_single_mint {
         msg!(
-            "Mint adjustment: {} exceeds limit of {} (3x last mint). Using previous value: {}",
+            "Mint of {} exceeds the per-mint limit of {} for a deposit worth {} USD (8 decimals)",
             proposed_mint_value,
-            current_limit,
-            program_state.last_mint_amount
+            max_single_mint,
+            deposit_usd_value
         );
-        
-        // If it exceeds the limit, we use the last known value
-        // We don't update last_mint_amount, as we're reusing the same value
-        return Ok(program_state.last_mint_amount);
+        return Err(error!(ErrorCode::MintBudgetExceeded));
     }
-    
-    // For values within the limit, update the last value for the next check
-    program_state.last_mint_amount = proposed_mint_value;
-    
-    // Return the original proposed value (it's within the limit)
-    Ok(proposed_mint_value)
+
+    // Limit for the whole window
+    let minted_after = mint_budget.minted_in_epoch.saturating_add(proposed_mint_value);
+
+    if minted_after > mint_budget.max_tokens_per_epoch {
+        msg!(
+            "Mint of {} exceeds the window budget: {} of {} already minted",
+            proposed_mint_value,
+            mint_budget.minted_in_epoch,
+            mint_budget.max_tokens_per_epoch
+        );
+        return Err(error!(ErrorCode::MintBudgetExceeded));
+    }
+
+    mint_budget.minted_in_epoch = minted_after;
+
+    Ok(())
 }
 
-/// Uses Meteora pool data to derive the real-time exchange rate
-/// Ensures precise calculations at any scale, from 0.000000001 to millions of tokens
-/// Calculate DONUT tokens equivalent to a SOL amount using simplified calculation with high precision
-fn get_donut_tokens_amount<'info>(
+// Pool reserves and trade fee read from the Meteora accounts
+pub struct PoolReserves {
+    pub token_a: u64,                // DONUT owned by the pool
+    pub token_b: u64,                // SOL owned by the pool
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+}
+
+/// Uses Meteora pool data to read the pool's real-time reserves
+/// Returns None when any account can't be read or holds zero values
+fn read_pool_reserves<'info>(
+    pool: &AccountInfo<'info>,
+    a_vault: &AccountInfo<'info>,
+    b_vault: &AccountInfo<'info>,
     a_vault_lp: &AccountInfo<'info>,
     b_vault_lp: &AccountInfo<'info>,
     a_vault_lp_mint: &AccountInfo<'info>,
     b_vault_lp_mint: &AccountInfo<'info>,
-    a_token_vault: &AccountInfo<'info>,
-    b_token_vault: &AccountInfo<'info>,
-    sol_amount: u64,
-) -> Result<u64> {
-    // Constants for calculations
-    const METEORA_FEE: i128 = 1800; // 18%
-    const FEE_DENOMINATOR: i128 = 10000; // Base for percentages
-    const PRECISION_FACTOR: i128 = 1_000_000_000; // For precision in divisions
-    
-    // Log the input parameter
-    msg!("get_donut_tokens_amount called with sol_amount: {}", sol_amount);
-    
-    // 1. Read LP token values using deserialization
-    let a_vault_lp_amount: u64;
-    let b_vault_lp_amount: u64;
-    
-    {
-        // Deserialize the LP token accounts
-        let a_vault_lp_data = match spl_token::state::Account::unpack(&a_vault_lp.try_borrow_data()?) {
-            Ok(data) => data,
-            Err(_) => {
-                msg!("Error reading LP data");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        };
-            
-        let b_vault_lp_data = match spl_token::state::Account::unpack(&b_vault_lp.try_borrow_data()?) {
-            Ok(data) => data,
-            Err(_) => {
-                msg!("Error reading LP data");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
+) -> Result<Option<PoolReserves>> {
+    // 1. Read the pool account and its trade fee
+    let pool_fees = {
+        let pool_data = match meteora::Pool::from_account_info(pool) {
+            Some(data) => data,
+            None => {
+                msg!("Error reading pool data");
+                return Ok(None);
             }
         };
-        
-        a_vault_lp_amount = a_vault_lp_data.amount;
-        b_vault_lp_amount = b_vault_lp_data.amount;
-        msg!("LP amounts - A: {}, B: {}", a_vault_lp_amount, b_vault_lp_amount);
-    }
-    
-    force_memory_cleanup();
-    
-    // 2. Read total LP token supplies
-    let a_vault_lp_supply: u64;
-    let b_vault_lp_supply: u64;
-    
-    {
-        let a_vault_lp_mint_data = match spl_token::state::Mint::unpack(&a_vault_lp_mint.try_borrow_data()?) {
-            Ok(data) => data,
-            Err(_) => {
-                msg!("Error reading LP mint data");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        };
-            
-        let b_vault_lp_mint_data = match spl_token::state::Mint::unpack(&b_vault_lp_mint.try_borrow_data()?) {
-            Ok(data) => data,
-            Err(_) => {
-                msg!("Error reading LP mint data");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        };
-        
-        a_vault_lp_supply = a_vault_lp_mint_data.supply;
-        b_vault_lp_supply = b_vault_lp_mint_data.supply;
-        msg!("LP supplies - A: {}, B: {}", a_vault_lp_supply, b_vault_lp_supply);
-    }
-    
+
+        // The vaults and LP accounts must be the ones the pool itself holds
+        if pool_data.a_vault != a_vault.key() {
+            return Err(error!(ErrorCode::InvalidVaultAddress));
+        }
+        if pool_data.b_vault != b_vault.key() {
+            return Err(error!(ErrorCode::InvalidVaultBAddress));
+        }
+        if pool_data.a_vault_lp != a_vault_lp.key() {
+            return Err(error!(ErrorCode::InvalidVaultALpAddress));
+        }
+        if pool_data.b_vault_lp != b_vault_lp.key() {
+            return Err(error!(ErrorCode::InvalidVaultAddress));
+        }
+
+        pool_data.fees
+    };
+
     force_memory_cleanup();
-    
-    // 3. Read total token values in the vaults
-    let total_token_a_amount: u64;
-    let total_token_b_amount: u64;
-    
-    {
-        let a_token_vault_data = match spl_token::state::Account::unpack(&a_token_vault.try_borrow_data()?) {
-            Ok(data) => data,
-            Err(_) => {
-                msg!("Error reading token vault data");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        };
-            
-        let b_token_vault_data = match spl_token::state::Account::unpack(&b_token_vault.try_borrow_data()?) {
-            Ok(data) => data,
-            Err(_) => {
-                msg!("Error reading token vault data");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        };
-        
-        total_token_a_amount = a_token_vault_data.amount;
-        total_token_b_amount = b_token_vault_data.amount;
-        msg!("Total token amounts - A: {}, B: {}", total_token_a_amount, total_token_b_amount);
+
+    // A zero denominator or a fee of 100% or more can't be used for pricing
+    if !pool_fees.is_valid() {
+        msg!("Error reading pool fee data");
+        return Ok(None);
     }
-    
-    force_memory_cleanup();
-    
-    // 4. Check for zero values to avoid division by zero
-    if a_vault_lp_supply == 0 || b_vault_lp_supply == 0 || total_token_a_amount == 0 || total_token_b_amount == 0 {
-        msg!("Zero values detected, using fallback");
-        //return Ok(100 * 1_000_000_000);
-        return Ok(100);
-    }
-    
-    // 5. Convert to i128 for safe calculations
-    let a_lp_amount_big = a_vault_lp_amount as i128;
-    let a_lp_supply_big = a_vault_lp_supply as i128;
-    let b_lp_amount_big = b_vault_lp_amount as i128;
-    let b_lp_supply_big = b_vault_lp_supply as i128;
-    let token_a_big = total_token_a_amount as i128;
-    let token_b_big = total_token_b_amount as i128;
-    let sol_amount_big = sol_amount as i128;
-    
-    // 6. Calculate token quantities in the pool with safe operations
-    let pool_token_a = match token_a_big.checked_mul(a_lp_amount_big) {
-        Some(num) => match num.checked_div(a_lp_supply_big) {
-            Some(result) => result,
-            None => {
-                msg!("Division by zero in pool_token_a calculation");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        },
+
+    msg!("Pool trade fee: {}/{}", pool_fees.trade_fee_numerator, pool_fees.trade_fee_denominator);
+
+    // 2. Read the pool's LP positions in both vaults
+    let a_position = match meteora::LpPosition::from_accounts(a_vault_lp, a_vault_lp_mint) {
+        Some(position) => position,
         None => {
-            msg!("Overflow in pool_token_a calculation");
-            //return Ok(100 * 1_000_000_000);
-            return Ok(100);
+            msg!("Error reading LP data");
+            return Ok(None);
         }
     };
-    
-    let pool_token_b = match token_b_big.checked_mul(b_lp_amount_big) {
-        Some(num) => match num.checked_div(b_lp_supply_big) {
-            Some(result) => result,
-            None => {
-                msg!("Division by zero in pool_token_b calculation");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        },
+
+    let b_position = match meteora::LpPosition::from_accounts(b_vault_lp, b_vault_lp_mint) {
+        Some(position) => position,
         None => {
-            msg!("Overflow in pool_token_b calculation");
-            //return Ok(100 * 1_000_000_000);
-            return Ok(100);
+            msg!("Error reading LP data");
+            return Ok(None);
         }
     };
-    
-    msg!("Pool tokens - A: {}, B: {}", pool_token_a, pool_token_b);
-    
-    // 7. Check for zero values
-    if pool_token_a == 0 || pool_token_b == 0 {
-        msg!("Zero pool tokens, using fallback");
-        //return Ok(100 * 1_000_000_000);
-        return Ok(100);
-    }
-    
-    // 8. Calculate the basic ratio with overflow checks
-    let basic_ratio = match pool_token_a.checked_mul(PRECISION_FACTOR) {
-        Some(num) => match num.checked_div(pool_token_b) {
-            Some(result) => result,
-            None => {
-                msg!("Division by zero in basic_ratio calculation");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        },
-        None => {
-            msg!("Overflow in basic_ratio calculation");
-            //return Ok(100 * 1_000_000_000);
-            return Ok(100);
+
+    msg!("LP amounts - A: {}, B: {}", a_position.amount, b_position.amount);
+    msg!("LP supplies - A: {}, B: {}", a_position.supply, b_position.supply);
+
+    // 3. Read both vaults; their totals include tokens lent to strategies
+    let (a_vault_data, b_vault_data) = match (
+        meteora::Vault::from_account_info(a_vault),
+        meteora::Vault::from_account_info(b_vault),
+    ) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            msg!("Error reading vault data");
+            return Ok(None);
         }
     };
-    
-    msg!("Basic ratio without fees (scaled): {}", basic_ratio);
-    
-    // 9. Calculate the fee multiplier with overflow checks
-    let fee_multiplier = match FEE_DENOMINATOR.checked_mul(PRECISION_FACTOR) {
-        Some(num) => {
-            let denominator = match FEE_DENOMINATOR.checked_sub(METEORA_FEE) {
-                Some(val) => val,
-                None => {
-                    msg!("Underflow in fee denominator calculation");
-                    //return Ok(100 * 1_000_000_000);
-                    return Ok(100);
-                }
-            };
-            
-            match num.checked_div(denominator) {
-                Some(result) => result,
-                None => {
-                    msg!("Division by zero in fee_multiplier calculation");
-                    //return Ok(100 * 1_000_000_000);
-                    return Ok(100);
-                }
-            }
-        },
-        None => {
-            msg!("Overflow in fee_multiplier calculation");
-            //return Ok(100 * 1_000_000_000);
-            return Ok(100);
-        }
+
+    msg!("Vault total amounts - A: {}, B: {}", a_vault_data.total_amount, b_vault_data.total_amount);
+
+    force_memory_cleanup();
+
+    // 4. Calculate token quantities in the pool, excluding the vaults' locked profit
+    let current_time = match u64::try_from(Clock::get()?.unix_timestamp) {
+        Ok(time) => time,
+        Err(_) => return Ok(None),
     };
-    
-    msg!("Fee multiplier (scaled): {}", fee_multiplier);
-    
-    // 10. Apply the fee multiplier with overflow checks
-    let fee_adjusted_ratio = match basic_ratio.checked_mul(fee_multiplier) {
-        Some(num) => match num.checked_div(PRECISION_FACTOR) {
-            Some(result) => result,
-            None => {
-                msg!("Division by zero in fee_adjusted_ratio calculation");
-                //return Ok(100 * 1_000_000_000);
-                return Ok(100);
-            }
-        },
+
+    let pool_token_a = match a_vault_data.get_amount_by_share(current_time, a_position.amount, a_position.supply) {
+        Some(amount) => amount,
         None => {
-            msg!("Overflow in fee_adjusted_ratio calculation");
-            //return Ok(100 * 1_000_000_000);
-            return Ok(100);
+            msg!("Invalid values in pool_token_a calculation");
+            return Ok(None);
         }
     };
-    
-    msg!("Fee adjusted ratio (scaled): {}", fee_adjusted_ratio);
-    
-    // 11. Calculate tokens with overflow checks
-    let donut_tokens_scaled = match sol_amount_big.checked_mul(fee_adjusted_ratio) {
-        Some(result) => result,
+
+    let pool_token_b = match b_vault_data.get_amount_by_share(current_time, b_position.amount, b_position.supply) {
+        Some(amount) => amount,
         None => {
-            msg!("Overflow in donut_tokens_scaled calculation");
-            //return Ok(100 * 1_000_000_000);
-            return Ok(100);
+            msg!("Invalid values in pool_token_b calculation");
+            return Ok(None);
         }
     };
-    
-    msg!("Donut tokens scaled: {}", donut_tokens_scaled);
-    
-    //12. Remove precision factor with overflow checks
-    let donut_tokens_big = match donut_tokens_scaled.checked_div(PRECISION_FACTOR) {
-        Some(result) => result,
+
+    msg!("Pool tokens - A: {}, B: {}", pool_token_a, pool_token_b);
+
+    // 5. Check for zero values
+    if pool_token_a == 0 || pool_token_b == 0 {
+        msg!("Zero pool tokens");
+        return Ok(None);
+    }
+
+    Ok(Some(PoolReserves {
+        token_a: pool_token_a,
+        token_b: pool_token_b,
+        trade_fee_numerator: pool_fees.trade_fee_numerator,
+        trade_fee_denominator: pool_fees.trade_fee_denominator,
+    }))
+}
+
+// DONUT/SOL pool ratio scaled by PRICE_SCALE
+pub fn calculate_pool_price(reserves: &PoolReserves) -> Option<u128> {
+    if reserves.token_b == 0 {
+        return None;
+    }
+
+    (reserves.token_a as u128)
+        .checked_mul(PRICE_SCALE)?
+        .checked_div(reserves.token_b as u128)
+}
+
+// Adds a pool price sample to the ring buffer in the program state.
+// Only one sample is kept per window / MAX_PRICE_OBSERVATIONS seconds, so the buffer
+// always spans the whole TWAP window and same-block samples can't overwrite history.
+pub fn record_price_observation(state: &mut ProgramState, reserves: &PoolReserves, now: i64) -> bool {
+    let price = match calculate_pool_price(reserves) {
+        Some(price) => price,
+        None => return false,
+    };
+
+    if state.observation_count == 0 {
+        state.price_observations[0] = PriceObservation {
+            timestamp: now,
+            price,
+            cumulative_price: 0,
+        };
+        state.observation_index = 0;
+        state.observation_count = 1;
+        return true;
+    }
+
+    let last = state.price_observations[state.observation_index as usize];
+    let elapsed = now.saturating_sub(last.timestamp);
+    let min_interval = (state.twap_window as i64) / (MAX_PRICE_OBSERVATIONS as i64);
+
+    if elapsed <= 0 || elapsed < min_interval {
+        return false;
+    }
+
+    // The previous price held for the whole elapsed period
+    let cumulative_price = last.cumulative_price
+        .wrapping_add(last.price.wrapping_mul(elapsed as u128));
+
+    let next_index = (state.observation_index as usize + 1) % MAX_PRICE_OBSERVATIONS;
+    state.price_observations[next_index] = PriceObservation {
+        timestamp: now,
+        price,
+        cumulative_price,
+    };
+    state.observation_index = next_index as u8;
+
+    if (state.observation_count as usize) < MAX_PRICE_OBSERVATIONS {
+        state.observation_count += 1;
+    }
+
+    true
+}
+
+// Time-weighted average of the pool price over the configured window ending at `now`.
+// If the buffer doesn't reach back a full window, the average covers what is available.
+// None until the buffer holds MIN_TWAP_OBSERVATIONS samples.
+pub fn calculate_twap_price(state: &ProgramState, now: i64) -> Option<u128> {
+    if (state.observation_count as usize) < MIN_TWAP_OBSERVATIONS || state.twap_window == 0 {
+        return None;
+    }
+
+    let newest = state.price_observations[state.observation_index as usize];
+    let elapsed_since_newest = now.saturating_sub(newest.timestamp).max(0);
+    let cumulative_now = newest.cumulative_price
+        .wrapping_add(newest.price.wrapping_mul(elapsed_since_newest as u128));
+
+    let window_start = now.saturating_sub(state.twap_window as i64);
+    let mut oldest = newest;
+
+    // Walk from newest to oldest looking for the sample active at window_start
+    for offset in 0..state.observation_count as usize {
+        let idx = (state.observation_index as usize + MAX_PRICE_OBSERVATIONS - offset) % MAX_PRICE_OBSERVATIONS;
+        let observation = state.price_observations[idx];
+
+        if observation.timestamp <= window_start {
+            // Its price held until the next sample, so the cumulative at window_start is exact
+            let cumulative_start = observation.cumulative_price.wrapping_add(
+                observation.price.wrapping_mul((window_start - observation.timestamp) as u128)
+            );
+            return Some(cumulative_now.wrapping_sub(cumulative_start) / state.twap_window as u128);
+        }
+
+        oldest = observation;
+    }
+
+    let elapsed = now.saturating_sub(oldest.timestamp);
+    if elapsed <= 0 {
+        return Some(newest.price);
+    }
+
+    Some(cumulative_now.wrapping_sub(oldest.cumulative_price) / elapsed as u128)
+}
+
+// DONUT value of a SOL amount at a given pool price, after the trade fee
+fn calculate_price_output(
+    price: u128,
+    amount_in: u64,
+    trade_fee_numerator: u64,
+    trade_fee_denominator: u64,
+) -> Option<u64> {
+    if trade_fee_denominator == 0 || trade_fee_numerator >= trade_fee_denominator {
+        return None;
+    }
+
+    let amount_in = amount_in as u128;
+    let trade_fee = amount_in
+        .checked_mul(trade_fee_numerator as u128)?
+        .checked_div(trade_fee_denominator as u128)?;
+    let amount_in_after_fee = amount_in.checked_sub(trade_fee)?;
+
+    let amount_out = amount_in_after_fee
+        .checked_mul(price)?
+        .checked_div(PRICE_SCALE)?;
+
+    u64::try_from(amount_out).ok()
+}
+
+/// Calculate DONUT tokens equivalent to a SOL amount at the time-weighted pool price
+/// The spot reserves only supply the trade fee, so a manipulated spot price can't move the mint
+pub fn get_donut_tokens_amount(
+    reserves: Option<&PoolReserves>,
+    state: &ProgramState,
+    sol_amount: u64,
+    now: i64,
+) -> Result<u64> {
+    // Log the input parameter
+    msg!("get_donut_tokens_amount called with sol_amount: {}", sol_amount);
+
+    let reserves = reserves.ok_or(error!(ErrorCode::PoolReservesUnavailable))?;
+
+    // 1. Time-weighted DONUT/SOL price, once the buffer holds enough samples
+    let twap_price = match calculate_twap_price(state, now) {
+        Some(twap_price) => twap_price,
         None => {
-            msg!("Division by zero in donut_tokens_big calculation");
-            //return Ok(100 * 1_000_000_000);
-            return Ok(100);
+            msg!("Price observations: {}, required: {}", state.observation_count, MIN_TWAP_OBSERVATIONS);
+            return Err(error!(ErrorCode::TwapUnavailable));
         }
     };
 
-    msg!("donut_tokens_big (i128): {}", donut_tokens_big);
-    
-    //13. Check for overflow to u64
-    if donut_tokens_big > i128::from(u64::MAX) {
-        msg!("donut_tokens_big exceeds u64::MAX");
-        //return Ok(100 * 1_000_000_000);
-        return Ok(100);
-    }
+    msg!("TWAP price (scaled): {}", twap_price);
 
-    //14. Convert to u64
-    let donut_tokens = donut_tokens_big as u64;
+    // 2. SOL (token B) goes in, DONUT (token A) comes out, after the pool's trade fee
+    let donut_tokens = calculate_price_output(
+        twap_price,
+        sol_amount,
+        reserves.trade_fee_numerator,
+        reserves.trade_fee_denominator,
+    ).ok_or(error!(ErrorCode::InvalidDonutAmount))?;
 
     msg!("Final donut_tokens (u64): {}", donut_tokens);
-    
-    // 15. Validate that we have a non-zero value
+
+    // 3. Validate that we have a non-zero value
     if donut_tokens == 0 {
-        // If the original calculation was positive but truncated to zero
-        if donut_tokens_big > 0 {
-            msg!("Small positive value truncated to zero, returning minimum value");
-            return Ok(1); // 0.000000001 DONUT (smallest possible value)
-        }
-        
-        msg!("donut_tokens is zero, using fallback");
-        //return Ok(100 * 1_000_000_000);
-        return Ok(100);
+        return Err(error!(ErrorCode::InvalidDonutAmount));
     }
-    
+
     Ok(donut_tokens)
 }
 
+// Minimum DONUT accepted when slot-1 SOL is swapped: the output at the TWAP price, less the
+// configured slippage. None until the TWAP is available, as the current reserves can be skewed
+pub fn calculate_buyback_minimum_out(
+    reserves: &PoolReserves,
+    state: &ProgramState,
+    sol_amount: u64,
+    now: i64,
+) -> Option<u64> {
+    let twap_price = calculate_twap_price(state, now)?;
+    let expected_out = calculate_price_output(
+        twap_price,
+        sol_amount,
+        reserves.trade_fee_numerator,
+        reserves.trade_fee_denominator,
+    )?;
+
+    let minimum_out = (expected_out as u128)
+        .checked_mul(BASIS_POINTS_DENOMINATOR - state.buyback_slippage_bps as u128)?
+        .checked_div(BASIS_POINTS_DENOMINATOR)?;
+
+    u64::try_from(minimum_out).ok()
+}
+
 // Function to strictly verify an address
 fn verify_address_strict(provided: &Pubkey, expected: &Pubkey, error_code: ErrorCode) -> Result<()> {
     if provided != expected {
@@ -686,15 +1376,57 @@ fn verify_address_strict(provided: &Pubkey, expected: &Pubkey, error_code: Error
     Ok(())
 }
 
-// Verify vault A addresses
+// Verify vault A addresses read for pricing
 fn verify_vault_a_addresses<'info>(
     a_vault_lp: &Pubkey,
-    a_vault_lp_mint: &Pubkey,
-    a_token_vault: &Pubkey
+    a_vault_lp_mint: &Pubkey
 ) -> Result<()> {
     verify_address_strict(a_vault_lp, &verified_addresses::A_VAULT_LP, ErrorCode::InvalidVaultALpAddress)?;
     verify_address_strict(a_vault_lp_mint, &verified_addresses::A_VAULT_LP_MINT, ErrorCode::InvalidVaultALpMintAddress)?;
-    verify_address_strict(a_token_vault, &verified_addresses::A_TOKEN_VAULT, ErrorCode::InvalidTokenAVaultAddress)?;
+    
+    Ok(())
+}
+
+// Verify vault B addresses read for pricing
+fn verify_vault_b_addresses(
+    b_vault: &Pubkey,
+    b_vault_lp: &Pubkey,
+    b_vault_lp_mint: &Pubkey
+) -> Result<()> {
+    verify_address_strict(b_vault, &verified_addresses::B_VAULT, ErrorCode::InvalidVaultBAddress)?;
+    verify_address_strict(b_vault_lp, &verified_addresses::B_VAULT_LP, ErrorCode::InvalidVaultAddress)?;
+    verify_address_strict(b_vault_lp_mint, &verified_addresses::B_VAULT_LP_MINT, ErrorCode::InvalidVaultBLpMintAddress)?;
+    
+    Ok(())
+}
+
+// Function to verify that a token account is the canonical ATA of a wallet
+fn verify_canonical_ata(
+    token_account: &Pubkey,
+    wallet: &Pubkey,
+    token_mint: &Pubkey,
+    token_program: &Pubkey
+) -> Result<()> {
+    let expected = associated_token::get_associated_token_address_with_program_id(
+        wallet,
+        token_mint,
+        token_program,
+    );
+
+    verify_address_strict(token_account, &expected, ErrorCode::TokenAccountNotCanonical)
+}
+
+// Verify the vault B accounts and vault program used for deposit CPIs
+fn verify_vault_b_deposit_accounts(
+    b_vault: &Pubkey,
+    b_token_vault: &Pubkey,
+    b_vault_lp_mint: &Pubkey,
+    b_vault_lp: &Pubkey,
+    vault_program: &Pubkey
+) -> Result<()> {
+    verify_vault_b_addresses(b_vault, b_vault_lp, b_vault_lp_mint)?;
+    verify_address_strict(b_token_vault, &verified_addresses::B_TOKEN_VAULT, ErrorCode::InvalidTokenBVaultAddress)?;
+    verify_address_strict(vault_program, &verified_addresses::VAULT_PROGRAM, ErrorCode::InvalidVaultProgram)?;
     
     Ok(())
 }
@@ -703,13 +1435,16 @@ fn verify_vault_a_addresses<'info>(
 fn verify_ata_strict<'info>(
     token_account: &AccountInfo<'info>,
     owner: &Pubkey,
-    expected_mint: &Pubkey
+    expected_mint: &Pubkey,
+    token_program: &Pubkey
 ) -> Result<()> {
-    if token_account.owner != &spl_token::id() {
+    verify_canonical_ata(token_account.key, owner, expected_mint, token_program)?;
+
+    if token_account.owner != token_program {
         return Err(error!(ErrorCode::InvalidTokenAccount));
     }
     
-    match TokenAccount::try_deserialize(&mut &token_account.data.borrow()[..]) {
+    match token_interface::TokenAccount::try_deserialize(&mut &token_account.data.borrow()[..]) {
         Ok(token_data) => {
             if token_data.owner != *owner {
                 return Err(error!(ErrorCode::InvalidWalletForATA));
@@ -769,13 +1504,14 @@ fn verify_wallet_is_system_account<'info>(wallet: &AccountInfo<'info>) -> Result
 fn verify_token_account<'info>(
     token_account: &AccountInfo<'info>,
     wallet: &Pubkey,
-    token_mint: &Pubkey
+    token_mint: &Pubkey,
+    token_program: &Pubkey
 ) -> Result<()> {
-    if token_account.owner != &spl_token::id() {
+    if token_account.owner != token_program {
         return Err(error!(ErrorCode::TokenAccountInvalid));
     }
     
-    let token_data = match TokenAccount::try_deserialize(&mut &token_account.data.borrow()[..]) {
+    let token_data = match token_interface::TokenAccount::try_deserialize(&mut &token_account.data.borrow()[..]) {
         Ok(data) => data,
         Err(_) => {
             return Err(error!(ErrorCode::TokenAccountInvalid));
@@ -793,6 +1529,69 @@ fn verify_token_account<'info>(
     Ok(())
 }
 
+// Check if a program is one of the supported token programs
+fn is_supported_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == spl_token_2022::id()
+}
+
+// Verify that the token program passed for DONUT is the one that owns the mint
+fn verify_token_program_for_mint<'info>(
+    token_mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>
+) -> Result<()> {
+    if !is_supported_token_program(token_program.key) {
+        return Err(error!(ErrorCode::InvalidTokenProgram));
+    }
+
+    if token_mint.owner != token_program.key {
+        return Err(error!(ErrorCode::InvalidTokenProgram));
+    }
+
+    Ok(())
+}
+
+// Read the decimals of a mint owned by either token program
+fn get_mint_decimals<'info>(token_mint: &AccountInfo<'info>) -> Result<u8> {
+    let mint = token_interface::Mint::try_deserialize(&mut &token_mint.data.borrow()[..])
+        .map_err(|_| error!(ErrorCode::InvalidTokenMintAddress))?;
+
+    Ok(mint.decimals)
+}
+
+// Function to create a wallet's ATA for the token mint if it doesn't exist yet
+// The registrant pays the rent; an existing account is left untouched
+fn ensure_token_account_exists<'info>(
+    payer: &AccountInfo<'info>,
+    token_account: &AccountInfo<'info>,
+    wallet: &AccountInfo<'info>,
+    token_mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    associated_token_program: &AccountInfo<'info>,
+) -> Result<()> {
+    verify_canonical_ata(token_account.key, wallet.key, token_mint.key, token_program.key)?;
+
+    if !token_account.data_is_empty() {
+        return Ok(());
+    }
+
+    msg!("Creating token account {} for wallet {}", token_account.key, wallet.key);
+
+    associated_token::create_idempotent(CpiContext::new(
+        associated_token_program.clone(),
+        associated_token::Create {
+            payer: payer.clone(),
+            associated_token: token_account.clone(),
+            authority: wallet.clone(),
+            mint: token_mint.clone(),
+            system_program: system_program.clone(),
+            token_program: token_program.clone(),
+        },
+    )).map_err(|_| error!(ErrorCode::TokenAccountCreationFailed))?;
+
+    Ok(())
+}
+
 // Function to process deposit to the liquidity pool
 fn process_deposit_to_pool<'info>(
     user: &AccountInfo<'info>,
@@ -804,7 +1603,27 @@ fn process_deposit_to_pool<'info>(
     vault_program: &UncheckedAccount<'info>,
     token_program: &Program<'info, Token>,
     amount: u64,
+    signer_seeds: &[&[&[u8]]],
 ) -> Result<()> {
+    // Never hand the user's signature to anything but the Meteora vault program
+    verify_address_strict(&vault_program.key(), &verified_addresses::VAULT_PROGRAM, ErrorCode::InvalidVaultProgram)?;
+
+    let deposit_ix = meteora::deposit(
+        &meteora::VaultUserAccounts {
+            vault: b_vault.key(),
+            token_vault: b_token_vault.key(),
+            lp_mint: b_vault_lp_mint.key(),
+            user_token: user_source_token.key(),
+            user_lp: b_vault_lp.key(),
+            user: user.key(),
+            token_program: token_program.key(),
+        },
+        meteora::DepositArgs {
+            token_amount: amount,
+            minimum_lp_token_amount: 0,
+        },
+    );
+
     let deposit_accounts = [
         b_vault.to_account_info(),
         b_token_vault.clone(),
@@ -813,92 +1632,410 @@ fn process_deposit_to_pool<'info>(
         b_vault_lp.clone(),
         user.clone(),
         token_program.to_account_info(),
+        vault_program.to_account_info(),
     ];
 
-    let mut deposit_data = Vec::with_capacity(24);
-    deposit_data.extend_from_slice(&[242, 35, 198, 137, 82, 225, 242, 182]); // Deposit sighash
-    deposit_data.extend_from_slice(&amount.to_le_bytes());
-    deposit_data.extend_from_slice(&0u64.to_le_bytes()); // minimum_lp_token_amount = 0
-
-    solana_program::program::invoke(
-        &solana_program::instruction::Instruction {
-            program_id: vault_program.key(),
-            accounts: deposit_accounts.iter().enumerate().map(|(i, a)| {
-                if i == 5 {
-                    solana_program::instruction::AccountMeta::new_readonly(a.key(), true)
-                } else if i < 5 {
-                    solana_program::instruction::AccountMeta::new(a.key(), false)
-                } else {
-                    solana_program::instruction::AccountMeta::new_readonly(a.key(), false)
-                }
-            }).collect::<Vec<solana_program::instruction::AccountMeta>>(),
-            data: deposit_data,
-        },
+    solana_program::program::invoke_signed(
+        &deposit_ix,
         &deposit_accounts,
+        signer_seeds,
     ).map_err(|_| error!(ErrorCode::DepositToPoolFailed))?;
     
     Ok(())
 }
 
-// Function to reserve SOL for the referrer
-fn process_reserve_sol<'info>(
-    from: &AccountInfo<'info>,
-    to: &AccountInfo<'info>,
-    amount: u64,
+// Function to swap a deposit token for WSOL through a Meteora pool
+fn process_token_swap<'info>(
+    accounts: &RegisterWithTokenDeposit<'info>,
+    in_amount: u64,
+    minimum_out_amount: u64,
 ) -> Result<()> {
-    let ix = solana_program::system_instruction::transfer(
-        &from.key(),
-        &to.key(),
-        amount
+    let swap_ix = meteora::swap(
+        &meteora::SwapAccounts {
+            pool: accounts.swap_pool.key(),
+            user_source_token: accounts.user_source_token.key(),
+            user_destination_token: accounts.registration.user_wsol_account.key(),
+            a_vault: accounts.swap_a_vault.key(),
+            b_vault: accounts.swap_b_vault.key(),
+            a_token_vault: accounts.swap_a_token_vault.key(),
+            b_token_vault: accounts.swap_b_token_vault.key(),
+            a_vault_lp_mint: accounts.swap_a_vault_lp_mint.key(),
+            b_vault_lp_mint: accounts.swap_b_vault_lp_mint.key(),
+            a_vault_lp: accounts.swap_a_vault_lp.key(),
+            b_vault_lp: accounts.swap_b_vault_lp.key(),
+            protocol_token_fee: accounts.swap_protocol_token_fee.key(),
+            user: accounts.registration.user_wallet.key(),
+            vault_program: accounts.registration.vault_program.key(),
+            token_program: accounts.registration.token_program.key(),
+        },
+        meteora::SwapArgs {
+            in_amount,
+            minimum_out_amount,
+        },
     );
-    
+
+    let swap_accounts = [
+        accounts.swap_pool.to_account_info(),
+        accounts.user_source_token.to_account_info(),
+        accounts.registration.user_wsol_account.to_account_info(),
+        accounts.swap_a_vault.to_account_info(),
+        accounts.swap_b_vault.to_account_info(),
+        accounts.swap_a_token_vault.to_account_info(),
+        accounts.swap_b_token_vault.to_account_info(),
+        accounts.swap_a_vault_lp_mint.to_account_info(),
+        accounts.swap_b_vault_lp_mint.to_account_info(),
+        accounts.swap_a_vault_lp.to_account_info(),
+        accounts.swap_b_vault_lp.to_account_info(),
+        accounts.swap_protocol_token_fee.to_account_info(),
+        accounts.registration.user_wallet.to_account_info(),
+        accounts.registration.vault_program.to_account_info(),
+        accounts.registration.token_program.to_account_info(),
+        accounts.amm_program.to_account_info(),
+    ];
+
     solana_program::program::invoke(
-        &ix,
-        &[from.clone(), to.clone()],
-    ).map_err(|_| error!(ErrorCode::SolReserveFailed))?;
-    
+        &swap_ix,
+        &swap_accounts,
+    ).map_err(|_| error!(ErrorCode::TokenSwapFailed))?;
+
     Ok(())
 }
 
-// Function process_pay_referrer with explicit lifetimes
-fn process_pay_referrer<'info>(
-    from: &AccountInfo<'info>,
-    to: &AccountInfo<'info>,
-    amount: u64,
-    signer_seeds: &[&[&[u8]]],
-) -> Result<()> {
-    verify_wallet_is_system_account(to)?;
-    
-    let ix = solana_program::system_instruction::transfer(
-        &from.key(),
-        &to.key(),
-        amount
-    );
-    
-    // Create a vector of AccountInfo to avoid lifetime problems
-    let mut accounts = Vec::with_capacity(2);
-    accounts.push(from.clone());
-    accounts.push(to.clone());
-    
-    solana_program::program::invoke_signed(
-        &ix,
-        &accounts,
-        signer_seeds,
-    ).map_err(|_| error!(ErrorCode::ReferrerPaymentFailed))?;
-    
-    Ok(())
+// Function to read the balance of a token account (SPL Token or Token-2022)
+fn read_token_amount<'info>(token_account: &AccountInfo<'info>) -> Result<u64> {
+    let token_data = token_interface::TokenAccount::try_deserialize(&mut &token_account.data.borrow()[..])
+        .map_err(|_| error!(ErrorCode::InvalidTokenAccount))?;
+
+    Ok(token_data.amount)
 }
 
-// Function to mint tokens for the program vault
-pub fn process_mint_tokens<'info>(
-    token_mint: &AccountInfo<'info>,
-    program_token_vault: &AccountInfo<'info>,
-    token_mint_authority: &AccountInfo<'info>,
-    token_program: &Program<'info, Token>,
+// Function to process the slot-1 SOL according to the configured policy
+// Either deposits it to vault B or swaps it for DONUT, which is then burned or sent to the treasury
+fn process_slot1_deposit<'info>(
+    accounts: &RegisterWithSolDeposit<'info>,
+    bumps: &RegisterWithSolDepositBumps,
+    remaining_accounts: &[AccountInfo<'info>],
+    pool_reserves: Option<&PoolReserves>,
     amount: u64,
-    mint_authority_seeds: &[&[&[u8]]],
+    now: i64,
 ) -> Result<()> {
-    let mint_instruction = spl_token::instruction::mint_to(
+    let policy = accounts.state.slot1_policy;
+
+    // Without readable reserves or a TWAP there is no price to bound the swap with
+    let minimum_out_amount = match (policy, pool_reserves) {
+        (Slot1Policy::DepositLiquidity, _) => None,
+        (_, None) => {
+            msg!("Pool reserves unavailable, depositing slot-1 SOL to the pool");
+            None
+        },
+        (_, Some(reserves)) => {
+            let minimum_out_amount = calculate_buyback_minimum_out(
+                reserves,
+                &accounts.state,
+                amount,
+                now,
+            );
+            if minimum_out_amount.is_none() {
+                msg!("TWAP unavailable, depositing slot-1 SOL to the pool");
+            }
+            minimum_out_amount
+        },
+    };
+
+    let minimum_out_amount = match minimum_out_amount {
+        Some(minimum_out_amount) => minimum_out_amount,
+        None => {
+            return process_deposit_to_pool(
+                &accounts.user_wallet.to_account_info(),
+                &accounts.user_wsol_account.to_account_info(),
+                &accounts.b_vault_lp.to_account_info(),
+                &accounts.b_vault,
+                &accounts.b_token_vault.to_account_info(),
+                &accounts.b_vault_lp_mint.to_account_info(),
+                &accounts.vault_program,
+                &accounts.token_program,
+                amount,
+                &[]
+            );
+        }
+    };
+
+    let burn = policy == Slot1Policy::BuybackAndBurn;
+
+    // Buyback accounts are only passed when the policy needs them
+    let (protocol_token_b_fee, amm_program) = match (
+        accounts.protocol_token_b_fee.as_ref(),
+        accounts.amm_program.as_ref(),
+    ) {
+        (Some(protocol_token_b_fee), Some(amm_program)) => (protocol_token_b_fee, amm_program),
+        _ => return Err(error!(ErrorCode::MissingBuybackAccounts)),
+    };
+
+    // Vault A is passed with the other vault A accounts in remaining_accounts
+    let a_vault = &remaining_accounts[3];
+
+    verify_address_strict(&amm_program.key(), &verified_addresses::AMM_PROGRAM, ErrorCode::InvalidAmmProgram)?;
+
+    // Vault A and the protocol fee account aren't fixed addresses, so check them against the pool
+    {
+        let pool_data = meteora::Pool::from_account_info(&accounts.pool.to_account_info())
+            .ok_or(error!(ErrorCode::InvalidPoolAddress))?;
+
+        verify_address_strict(&a_vault.key(), &pool_data.a_vault, ErrorCode::InvalidVaultAddress)?;
+        verify_address_strict(&protocol_token_b_fee.key(), &pool_data.protocol_token_b_fee, ErrorCode::InvalidProtocolFeeAddress)?;
+    }
+
+    force_memory_cleanup();
+
+    // DONUT goes to the program vault for burning, or straight to the treasury ATA
+    let destination = if burn {
+        accounts.program_token_vault.to_account_info()
+    } else {
+        match accounts.treasury_token_account.as_ref() {
+            Some(treasury_token_account) => treasury_token_account.to_account_info(),
+            None => return Err(error!(ErrorCode::MissingBuybackAccounts)),
+        }
+    };
+
+    let destination_owner = if burn {
+        accounts.vault_authority.key()
+    } else {
+        accounts.state.multisig_treasury
+    };
+
+    verify_ata_strict(
+        &destination,
+        &destination_owner,
+        &accounts.token_mint.key(),
+        &accounts.donut_token_program.key()
+    )?;
+
+    let balance_before = read_token_amount(&destination)?;
+
+    let swap_ix = meteora::swap(
+        &meteora::SwapAccounts {
+            pool: accounts.pool.key(),
+            user_source_token: accounts.user_wsol_account.key(),
+            user_destination_token: destination.key(),
+            a_vault: a_vault.key(),
+            b_vault: accounts.b_vault.key(),
+            a_token_vault: remaining_accounts[2].key(),
+            b_token_vault: accounts.b_token_vault.key(),
+            a_vault_lp_mint: remaining_accounts[1].key(),
+            b_vault_lp_mint: accounts.b_vault_lp_mint.key(),
+            a_vault_lp: remaining_accounts[0].key(),
+            b_vault_lp: accounts.b_vault_lp.key(),
+            protocol_token_fee: protocol_token_b_fee.key(),
+            user: accounts.user_wallet.key(),
+            vault_program: accounts.vault_program.key(),
+            token_program: accounts.token_program.key(),
+        },
+        meteora::SwapArgs {
+            in_amount: amount,
+            minimum_out_amount,
+        },
+    );
+
+    let swap_accounts = [
+        accounts.pool.to_account_info(),
+        accounts.user_wsol_account.to_account_info(),
+        destination.clone(),
+        a_vault.clone(),
+        accounts.b_vault.to_account_info(),
+        remaining_accounts[2].clone(),
+        accounts.b_token_vault.to_account_info(),
+        remaining_accounts[1].clone(),
+        accounts.b_vault_lp_mint.to_account_info(),
+        remaining_accounts[0].clone(),
+        accounts.b_vault_lp.to_account_info(),
+        protocol_token_b_fee.to_account_info(),
+        accounts.user_wallet.to_account_info(),
+        accounts.vault_program.to_account_info(),
+        accounts.token_program.to_account_info(),
+        amm_program.to_account_info(),
+    ];
+
+    solana_program::program::invoke(
+        &swap_ix,
+        &swap_accounts,
+    ).map_err(|_| error!(ErrorCode::TokenSwapFailed))?;
+
+    let donut_amount = read_token_amount(&destination)?
+        .checked_sub(balance_before)
+        .ok_or(error!(ErrorCode::TokenSwapFailed))?;
+
+    msg!("Slot-1 buyback: {} lamports for {} DONUT", amount, donut_amount);
+
+    if burn && donut_amount > 0 {
+        process_burn_tokens(
+            &accounts.program_token_vault.to_account_info(),
+            &accounts.token_mint.to_account_info(),
+            &accounts.vault_authority.to_account_info(),
+            &accounts.donut_token_program.to_account_info(),
+            donut_amount,
+            &[&[
+                b"token_vault_authority".as_ref(),
+                &[bumps.vault_authority]
+            ]],
+        )?;
+    }
+
+    emit!(SlotOneBuyback {
+        sol_amount: amount,
+        donut_amount,
+        burned: burn,
+    });
+
+    Ok(())
+}
+
+// Function to track a slot-2 SOL reservation that replaces `previous_reservation`
+// A replaced reservation is no longer owed, so its lamports become unallocated
+fn track_sol_reservation(state: &mut ProgramState, previous_reservation: u64, amount: u64) -> Result<()> {
+    state.total_reserved_sol = state.total_reserved_sol
+        .saturating_sub(previous_reservation)
+        .checked_add(amount)
+        .ok_or(error!(ErrorCode::ReservedSolInvariantViolated))?;
+
+    Ok(())
+}
+
+// Function to release a SOL reservation once it has been paid out
+// Saturates so reservations made before the total was tracked can still be paid
+fn release_sol_reservation(state: &mut ProgramState, amount: u64) {
+    state.total_reserved_sol = state.total_reserved_sol.saturating_sub(amount);
+}
+
+// Lamports in the program SOL vault above the outstanding reservations and the vault's rent-exempt minimum
+fn calculate_excess_sol(vault_lamports: u64, total_reserved_sol: u64, rent_exempt_minimum: u64) -> u64 {
+    vault_lamports
+        .saturating_sub(total_reserved_sol)
+        .saturating_sub(rent_exempt_minimum)
+}
+
+// Function to check that the program SOL vault covers every outstanding reservation
+fn verify_reserved_sol_invariant<'info>(state: &ProgramState, program_sol_vault: &AccountInfo<'info>) -> Result<()> {
+    if program_sol_vault.lamports() < state.total_reserved_sol {
+        msg!("Vault balance: {}, reserved: {}", program_sol_vault.lamports(), state.total_reserved_sol);
+        return Err(error!(ErrorCode::ReservedSolInvariantViolated));
+    }
+
+    Ok(())
+}
+
+// Function to send the stakers' share of a slot-3 SOL payout to the staking reward vault
+// Returns the lamports taken; nothing is taken while no DONUT is staked
+fn process_staking_fee<'info>(
+    accounts: &mut RegisterWithSolDeposit<'info>,
+    bumps: &RegisterWithSolDepositBumps,
+    payout_amount: u64,
+) -> Result<u64> {
+    let fee_bps = accounts.state.staking_fee_bps;
+    if fee_bps == 0 || payout_amount == 0 {
+        return Ok(0);
+    }
+
+    let (staking_pool, staking_reward_vault) = match (
+        accounts.staking_pool.as_mut(),
+        accounts.staking_reward_vault.as_ref(),
+    ) {
+        (Some(staking_pool), Some(staking_reward_vault)) => (staking_pool, staking_reward_vault),
+        _ => return Err(error!(ErrorCode::MissingStakingAccounts)),
+    };
+
+    if staking_pool.total_staked == 0 {
+        return Ok(0);
+    }
+
+    let fee_amount = u64::try_from(
+        (payout_amount as u128) * (fee_bps as u128) / BASIS_POINTS_DENOMINATOR
+    ).map_err(|_| error!(ErrorCode::InvalidStakingFee))?;
+
+    if fee_amount == 0 {
+        return Ok(0);
+    }
+
+    process_pay_referrer(
+        &accounts.program_sol_vault.to_account_info(),
+        &staking_reward_vault.to_account_info(),
+        fee_amount,
+        &[&[
+            b"program_sol_vault".as_ref(),
+            &[bumps.program_sol_vault]
+        ]],
+    )?;
+
+    staking_pool.distribute(fee_amount)?;
+
+    emit!(StakingRewardsDistributed {
+        amount: fee_amount,
+        total_staked: staking_pool.total_staked,
+        reward_per_share: staking_pool.reward_per_share,
+    });
+
+    Ok(fee_amount)
+}
+
+// Function to reserve SOL for the referrer
+fn process_reserve_sol<'info>(
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let ix = solana_program::system_instruction::transfer(
+        &from.key(),
+        &to.key(),
+        amount
+    );
+    
+    solana_program::program::invoke(
+        &ix,
+        &[from.clone(), to.clone()],
+    ).map_err(|_| error!(ErrorCode::SolReserveFailed))?;
+    
+    Ok(())
+}
+
+// Function process_pay_referrer with explicit lifetimes
+fn process_pay_referrer<'info>(
+    from: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    verify_wallet_is_system_account(to)?;
+    
+    let ix = solana_program::system_instruction::transfer(
+        &from.key(),
+        &to.key(),
+        amount
+    );
+    
+    // Create a vector of AccountInfo to avoid lifetime problems
+    let mut accounts = Vec::with_capacity(2);
+    accounts.push(from.clone());
+    accounts.push(to.clone());
+    
+    solana_program::program::invoke_signed(
+        &ix,
+        &accounts,
+        signer_seeds,
+    ).map_err(|_| error!(ErrorCode::ReferrerPaymentFailed))?;
+    
+    Ok(())
+}
+
+// Function to mint tokens for the program vault
+pub fn process_mint_tokens<'info>(
+    token_mint: &AccountInfo<'info>,
+    program_token_vault: &AccountInfo<'info>,
+    token_mint_authority: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    amount: u64,
+    mint_authority_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    // Builds the instruction for either SPL Token or Token-2022
+    let mint_instruction = spl_token_2022::instruction::mint_to(
         &token_program.key(),
         &token_mint.key(),
         &program_token_vault.key(),
@@ -912,7 +2049,7 @@ pub fn process_mint_tokens<'info>(
     mint_accounts.push(token_mint.clone());
     mint_accounts.push(program_token_vault.clone());
     mint_accounts.push(token_mint_authority.clone());
-    mint_accounts.push(token_program.to_account_info());
+    mint_accounts.push(token_program.clone());
     
     solana_program::program::invoke_signed(
         &mint_instruction,
@@ -924,33 +2061,40 @@ pub fn process_mint_tokens<'info>(
 }
 
 // Function to transfer tokens from vault to user
+// Uses transfer_checked so mints with Token-2022 transfer fees are supported
 pub fn process_transfer_tokens<'info>(
     program_token_vault: &AccountInfo<'info>,
     user_token_account: &AccountInfo<'info>,
+    token_mint: &AccountInfo<'info>,
     vault_authority: &AccountInfo<'info>,
-    token_program: &Program<'info, Token>,
+    token_program: &AccountInfo<'info>,
     amount: u64,
     authority_seeds: &[&[&[u8]]],
 ) -> Result<()> {
-    if user_token_account.owner != &spl_token::id() {
+    if user_token_account.owner != token_program.key {
         return Err(error!(ErrorCode::TokenAccountInvalid));
     }
+
+    let decimals = get_mint_decimals(token_mint)?;
     
-    let transfer_instruction = spl_token::instruction::transfer(
+    let transfer_instruction = spl_token_2022::instruction::transfer_checked(
         &token_program.key(),
         &program_token_vault.key(),
+        &token_mint.key(),
         &user_token_account.key(),
         &vault_authority.key(),
         &[],
-        amount
+        amount,
+        decimals
     ).map_err(|_| error!(ErrorCode::TokenTransferFailed))?;
     
     // Use Vec instead of fixed array to avoid lifetime problems
-    let mut transfer_accounts = Vec::with_capacity(4);
+    let mut transfer_accounts = Vec::with_capacity(5);
     transfer_accounts.push(program_token_vault.clone());
+    transfer_accounts.push(token_mint.clone());
     transfer_accounts.push(user_token_account.clone());
     transfer_accounts.push(vault_authority.clone());
-    transfer_accounts.push(token_program.to_account_info());
+    transfer_accounts.push(token_program.clone());
     
     solana_program::program::invoke_signed(
         &transfer_instruction,
@@ -961,41 +2105,266 @@ pub fn process_transfer_tokens<'info>(
     Ok(())
 }
 
-/// Process the direct referrer's matrix when a new user registers
-/// Returns (bool, Pubkey) where:
-/// - bool: indicates if the matrix was completed
-/// - Pubkey: referrer key for use in recursion
-fn process_referrer_chain<'info>(
-   user_key: &Pubkey,
-   referrer: &mut Account<'_, UserAccount>,
-   next_chain_id: u32,
-) -> Result<(bool, Pubkey)> {
-   let slot_idx = referrer.chain.filled_slots as usize;
-   if slot_idx >= 3 {
-       return Ok((false, referrer.key())); 
-   }
+// Function to burn tokens held by the program vault
+pub fn process_burn_tokens<'info>(
+    program_token_vault: &AccountInfo<'info>,
+    token_mint: &AccountInfo<'info>,
+    vault_authority: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    amount: u64,
+    authority_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let burn_instruction = spl_token_2022::instruction::burn(
+        &token_program.key(),
+        &program_token_vault.key(),
+        &token_mint.key(),
+        &vault_authority.key(),
+        &[],
+        amount
+    ).map_err(|_| error!(ErrorCode::TokenBurnFailed))?;
+
+    // Use Vec instead of fixed array to avoid lifetime problems
+    let burn_accounts = vec![
+        program_token_vault.clone(),
+        token_mint.clone(),
+        vault_authority.clone(),
+        token_program.clone(),
+    ];
+
+    solana_program::program::invoke_signed(
+        &burn_instruction,
+        &burn_accounts,
+        authority_seeds,
+    ).map_err(|_| error!(ErrorCode::TokenBurnFailed))?;
+
+    Ok(())
+}
+
+// Function to credit paid-out tokens to a wallet's vesting account, creating it if needed
+// The tokens stay in the program vault until they are claimed
+fn process_vest_tokens<'info>(
+    payer: &AccountInfo<'info>,
+    vesting_account: &AccountInfo<'info>,
+    wallet: &Pubkey,
+    system_program: &AccountInfo<'info>,
+    amount: u64,
+    duration: i64,
+    now: i64,
+) -> Result<()> {
+    let (expected_vesting, vesting_bump) = Pubkey::find_program_address(
+        &[b"vesting", wallet.as_ref()],
+        &crate::ID
+    );
+    verify_address_strict(vesting_account.key, &expected_vesting, ErrorCode::InvalidVestingAccount)?;
+
+    let vesting_seeds: &[&[u8]] = &[b"vesting", wallet.as_ref(), &[vesting_bump]];
+
+    let mut vesting = if vesting_account.data_is_empty() {
+        let space = 8 + VestingAccount::SIZE;
+        let required_lamports = Rent::get()?.minimum_balance(space);
+        let current_lamports = vesting_account.lamports();
+
+        if current_lamports == 0 {
+            solana_program::program::invoke_signed(
+                &solana_program::system_instruction::create_account(
+                    payer.key,
+                    vesting_account.key,
+                    required_lamports,
+                    space as u64,
+                    &crate::ID
+                ),
+                &[payer.clone(), vesting_account.clone(), system_program.clone()],
+                &[vesting_seeds],
+            )?;
+        } else {
+            // Someone sent lamports to the address first, so create_account would fail
+            if current_lamports < required_lamports {
+                solana_program::program::invoke(
+                    &solana_program::system_instruction::transfer(
+                        payer.key,
+                        vesting_account.key,
+                        required_lamports - current_lamports
+                    ),
+                    &[payer.clone(), vesting_account.clone(), system_program.clone()],
+                )?;
+            }
+
+            solana_program::program::invoke_signed(
+                &solana_program::system_instruction::allocate(vesting_account.key, space as u64),
+                &[vesting_account.clone(), system_program.clone()],
+                &[vesting_seeds],
+            )?;
+
+            solana_program::program::invoke_signed(
+                &solana_program::system_instruction::assign(vesting_account.key, &crate::ID),
+                &[vesting_account.clone(), system_program.clone()],
+                &[vesting_seeds],
+            )?;
+        }
+
+        VestingAccount {
+            owner_wallet: *wallet,
+            ..VestingAccount::default()
+        }
+    } else {
+        if vesting_account.owner != &crate::ID {
+            return Err(error!(ErrorCode::InvalidVestingAccount));
+        }
+
+        let vesting = VestingAccount::try_deserialize(&mut &vesting_account.data.borrow()[..])
+            .map_err(|_| error!(ErrorCode::InvalidVestingAccount))?;
+
+        if vesting.owner_wallet != *wallet {
+            return Err(error!(ErrorCode::InvalidVestingAccount));
+        }
+
+        vesting
+    };
+
+    vesting.add_tokens(amount, now, duration)?;
+
+    {
+        let mut data = vesting_account.try_borrow_mut_data()?;
+        vesting.try_serialize(&mut &mut data[..])?;
+    }
+
+    emit!(TokensVested {
+        wallet: *wallet,
+        amount,
+        start_time: now,
+        duration,
+    });
+
+    Ok(())
+}
+
+// Deadline for a matrix whose first slot fills at `now` (0 when expiry is disabled)
+fn calculate_matrix_expires_at(state: &ProgramState, now: i64) -> i64 {
+    if state.matrix_expiry <= 0 {
+        return 0;
+    }
+
+    now.saturating_add(state.matrix_expiry)
+}
+
+// Function to decode the data of an upline user account, skipping its 8-byte discriminator
+// Trailing bytes are ignored, as the account is allocated for a full upline
+pub fn decode_user_account_data(data: &[u8]) -> Result<UserAccount> {
+    if data.len() <= 8 {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+
+    let mut account_slice = &data[8..];
+    Ok(UserAccount::deserialize(&mut account_slice)?)
+}
+
+// Function to load the user accounts of the upline trios (PDA, wallet, ATA) for the matrix recursion
+// The trios must follow the referrer's upline, closest first; at most MAX_UPLINE_DEPTH are read
+fn load_upline_accounts<'info>(
+    upline_accounts: &[AccountInfo<'info>],
+    referrer: &Pubkey,
+    upline: &[UplineEntry],
+) -> Result<Vec<UserAccount>> {
+    if !upline_accounts.chunks_exact(3).remainder().is_empty() {
+        return Err(error!(ErrorCode::MissingUplineAccount));
+    }
+
+    let trio_count = std::cmp::min(upline.len(), MAX_UPLINE_DEPTH);
+    if upline_accounts.len() / 3 < trio_count {
+        return Err(error!(ErrorCode::MissingUplineAccount));
+    }
+
+    // Each matrix is snapshotted once: a repeated account would be written back twice
+    let upline_keys: Vec<Pubkey> = upline_accounts
+        .chunks_exact(3)
+        .take(trio_count)
+        .map(|trio| trio[0].key())
+        .collect();
+    for (index, key) in upline_keys.iter().enumerate() {
+        if key == referrer || upline_keys[..index].contains(key) {
+            return Err(error!(ErrorCode::DuplicateUplineAccount));
+        }
+    }
+
+    let mut uplines = Vec::with_capacity(trio_count);
+
+    for (index, trio) in upline_accounts.chunks_exact(3).take(trio_count).enumerate() {
+        let upline_info = &trio[0];   // Account PDA
+        let upline_wallet = &trio[1]; // Wallet
+
+        if upline_wallet.owner != &solana_program::system_program::ID {
+            return Err(error!(ErrorCode::PaymentWalletInvalid));
+        }
+
+        // Check program ownership first before trying to deserialize
+        if !upline_info.owner.eq(&crate::ID) {
+            return Err(error!(ErrorCode::InvalidSlotOwner));
+        }
+
+        let upline_account_data = {
+            // Limited scope for data borrowing
+            let data = upline_info.try_borrow_data()?;
+            decode_user_account_data(&data)?
+        };
+
+        if !upline_account_data.is_registered {
+            return Err(error!(ErrorCode::SlotNotRegistered));
+        }
+
+        // Verify the wallet is the upline's owner wallet
+        if upline_wallet.key() != upline_account_data.owner_wallet {
+            return Err(error!(ErrorCode::WalletMismatch));
+        }
+
+        // Verify the account is the referrer's upline at this depth
+        if upline_info.key() != upline[upline.len() - 1 - index].pda {
+            return Err(error!(ErrorCode::InvalidUpline));
+        }
+
+        uplines.push(upline_account_data);
+    }
+
+    Ok(uplines)
+}
 
-   referrer.chain.slots[slot_idx] = Some(*user_key);
+// Function to resolve the user account and wallet of a matrix effect target
+fn matrix_target_accounts<'info>(
+    accounts: &RegisterWithSolDeposit<'info>,
+    upline_accounts: &[AccountInfo<'info>],
+    target: Target,
+) -> (Pubkey, AccountInfo<'info>) {
+    match target {
+        Target::Referrer => (accounts.referrer.key(), accounts.referrer_wallet.to_account_info()),
+        Target::Upline(index) => (upline_accounts[index * 3].key(), upline_accounts[index * 3 + 1].clone()),
+    }
+}
 
-   // Emit slot filled event
-   emit!(SlotFilled {
-       slot_idx: slot_idx as u8,
-       chain_id: referrer.chain.id,
-       user: *user_key,
-       owner: referrer.key(),
-   });
+// Function to close the user's WSOL account, returning its lamports to the user's wallet
+fn close_user_wsol_account<'info>(accounts: &RegisterWithSolDeposit<'info>) -> Result<()> {
+    if accounts.user_wsol_account.to_account_info().data_len() == 0 {
+        return Ok(());
+    }
 
-   referrer.chain.filled_slots += 1;
+    let close_ix = spl_token::instruction::close_account(
+        &token::ID,
+        &accounts.user_wsol_account.key(),
+        &accounts.user_wallet.key(),
+        &accounts.user_wallet.key(),
+        &[]
+    )?;
 
-   if referrer.chain.filled_slots == 3 {
-       referrer.chain.id = next_chain_id;
-       referrer.chain.slots = [None, None, None];
-       referrer.chain.filled_slots = 0;
+    let close_accounts = [
+        accounts.user_wsol_account.to_account_info(),
+        accounts.user_wallet.to_account_info(),
+        accounts.user_wallet.to_account_info(),
+    ];
 
-       return Ok((true, referrer.key()));
-   }
+    solana_program::program::invoke(
+        &close_ix,
+        &close_accounts,
+    ).map_err(|_| error!(ErrorCode::UnwrapSolFailed))?;
 
-   Ok((false, referrer.key()))
+    Ok(())
 }
 
 // Accounts for initialize instruction
@@ -1006,19 +2375,40 @@ pub struct Initialize<'info> {
         payer = owner,
         space = 8 + ProgramState::SIZE
     )]
-    pub state: Account<'info, ProgramState>,
+    pub state: Box<Account<'info, ProgramState>>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for migrate_state instruction
+#[derive(Accounts)]
+pub struct MigrateState<'info> {
+    /// CHECK: Legacy layout that Account<ProgramState> cannot read, verified in the instruction
+    #[account(mut)]
+    pub state: UncheckedAccount<'info>,
     #[account(mut)]
     pub owner: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
+// Accounts for migrate_user_account instruction
+#[derive(Accounts)]
+pub struct MigrateUserAccount<'info> {
+    #[account(mut)]
+    pub user: Account<'info, UserAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
 // Accounts for registration without referrer with deposit
 // Accounts for registration without referrer with deposit
 #[derive(Accounts)]
 #[instruction(deposit_amount: u64)]
 pub struct RegisterWithoutReferrerDeposit<'info> {
     #[account(mut)]
-    pub state: Account<'info, ProgramState>,
+    pub state: Box<Account<'info, ProgramState>>,
 
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -1035,37 +2425,37 @@ pub struct RegisterWithoutReferrerDeposit<'info> {
     )]
     pub user: Account<'info, UserAccount>,
 
-/// CHECK: User token account for Wrapped SOL, verified in the instruction code
+    /// CHECK: User's WSOL ATA, verified in the instruction code (canonical address, owner and mint)
     #[account(mut)]
     pub user_source_token: UncheckedAccount<'info>,
     
     // WSOL mint
-    /// CHECK: This is the fixed WSOL mint address
+    /// CHECK: This is the fixed WSOL mint address, verified in the instruction code
     pub wsol_mint: AccountInfo<'info>,
 
     // Deposit Accounts (same logic as Slot 1)
-    /// CHECK: Pool account (PDA)
+    /// CHECK: Pool account, verified against the fixed address
     #[account(mut)]
     pub pool: UncheckedAccount<'info>,
 
     // Existing accounts for vault B (SOL)
-    /// CHECK: Vault account for token B (SOL)
+    /// CHECK: Vault account for token B (SOL), verified against the fixed address
     #[account(mut)]
     pub b_vault: UncheckedAccount<'info>,
 
-    /// CHECK: Token vault account for token B (SOL)
+    /// CHECK: Token vault account for token B (SOL), verified against the fixed address
     #[account(mut)]
     pub b_token_vault: UncheckedAccount<'info>,
 
-    /// CHECK: LP token mint for vault B
+    /// CHECK: LP token mint for vault B, verified against the fixed address
     #[account(mut)]
     pub b_vault_lp_mint: UncheckedAccount<'info>,
 
-    /// CHECK: LP token account for vault B
+    /// CHECK: LP token account for vault B, verified against the fixed address
     #[account(mut)]
     pub b_vault_lp: UncheckedAccount<'info>,
 
-    /// CHECK: Vault program
+    /// CHECK: Meteora vault program, verified against the fixed address
     pub vault_program: UncheckedAccount<'info>,
 
     // TOKEN MINT - Added for base user
@@ -1079,134 +2469,2171 @@ pub struct RegisterWithoutReferrerDeposit<'info> {
     pub rent: Sysvar<'info, Rent>,
 }
 
-// Structure for registration with SOL in a single transaction
-// Now includes Chainlink accounts and remaining_accounts
+// Accounts for the permissionless price sampling instruction
 #[derive(Accounts)]
-#[instruction(deposit_amount: u64)]
-pub struct RegisterWithSolDeposit<'info> {
+pub struct RecordPrice<'info> {
     #[account(mut)]
-    pub state: Account<'info, ProgramState>,
+    pub state: Box<Account<'info, ProgramState>>,
 
-    #[account(mut)]
-    pub user_wallet: Signer<'info>,
+    /// CHECK: Meteora pool account, verified against the fixed address
+    pub pool: UncheckedAccount<'info>,
 
-    // Reference accounts
-    #[account(mut)]
-    pub referrer: Account<'info, UserAccount>,
-    
+    /// CHECK: LP token account for vault A, verified against the fixed address
+    pub a_vault_lp: UncheckedAccount<'info>,
+
+    /// CHECK: LP token mint for vault A, verified against the fixed address
+    pub a_vault_lp_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Meteora vault for token A, verified against the pool
+    pub a_vault: UncheckedAccount<'info>,
+
+    /// CHECK: LP token account for vault B, verified against the fixed address
+    pub b_vault_lp: UncheckedAccount<'info>,
+
+    /// CHECK: LP token mint for vault B, verified against the fixed address
+    pub b_vault_lp_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Meteora vault for token B, verified against the fixed address
+    pub b_vault: UncheckedAccount<'info>,
+}
+
+// Accounts for owner-only configuration updates
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
     #[account(mut)]
-    pub referrer_wallet: SystemAccount<'info>,
+    pub state: Box<Account<'info, ProgramState>>,
+
+    pub owner: Signer<'info>,
+}
+
+// Accounts for sweeping unallocated SOL from the program vault (multisig treasury only)
+#[derive(Accounts)]
+pub struct SweepExcessSol<'info> {
+    pub state: Box<Account<'info, ProgramState>>,
+
+    #[account(
+        mut,
+        seeds = [b"program_sol_vault"],
+        bump
+    )]
+    pub program_sol_vault: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub treasury: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for reconciling the reserved SOL total after a migration (owner only)
+#[derive(Accounts)]
+pub struct ReconcileReservedSol<'info> {
+    #[account(mut)]
+    pub state: Box<Account<'info, ProgramState>>,
+
+    #[account(
+        seeds = [b"program_sol_vault"],
+        bump
+    )]
+    pub program_sol_vault: SystemAccount<'info>,
+
+    pub owner: Signer<'info>,
+}
+
+// Accounts for claiming vested DONUT
+#[derive(Accounts)]
+pub struct ClaimVested<'info> {
+    #[account(
+        mut,
+        seeds = [b"vesting", user_wallet.key().as_ref()],
+        bump,
+        constraint = vesting_account.owner_wallet == user_wallet.key() @ ErrorCode::InvalidVestingAccount
+    )]
+    pub vesting_account: Account<'info, VestingAccount>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+
+    /// CHECK: DONUT mint, verified against the fixed address
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Program token vault holding vested tokens, verified as the vault authority's ATA
+    #[account(mut)]
+    pub program_token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: User's ATA to receive tokens, created if missing and verified
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Token vault authority
+    #[account(
+        seeds = [b"token_vault_authority"],
+        bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    // Token program that owns the DONUT mint (SPL Token or Token-2022)
+    pub donut_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for expiring a matrix (anyone can call)
+// Policy-specific accounts are optional and checked against the configured policy
+#[derive(Accounts)]
+pub struct ExpireMatrix<'info> {
+    #[account(mut)]
+    pub state: Box<Account<'info, ProgramState>>,
+
+    // Matrix owner
+    #[account(mut)]
+    pub user: Box<Account<'info, UserAccount>>,
+
+    #[account(mut)]
+    pub owner_wallet: SystemAccount<'info>,
+
+    // Pays for any account created on the way
+    #[account(mut)]
+    pub caller: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"program_sol_vault"],
+        bump
+    )]
+    pub program_sol_vault: SystemAccount<'info>,
+
+    /// CHECK: DONUT mint, verified against the fixed address
+    #[account(mut)]
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Program token vault, verified as the vault authority's ATA
+    #[account(mut)]
+    pub program_token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Token vault authority
+    #[account(
+        seeds = [b"token_vault_authority"],
+        bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    // ReleaseToOwner
+    /// CHECK: Owner's DONUT ATA, created if missing and verified
+    #[account(mut)]
+    pub owner_token_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Owner's vesting PDA when vesting is enabled, verified before use
+    #[account(mut)]
+    pub owner_vesting: Option<UncheckedAccount<'info>>,
+
+    // MoveToTreasury
+    #[account(mut)]
+    pub treasury_wallet: Option<SystemAccount<'info>>,
+
+    /// CHECK: Treasury DONUT ATA, verified before use
+    #[account(mut)]
+    pub treasury_token_account: Option<UncheckedAccount<'info>>,
+
+    // ReturnToPool
+    /// CHECK: WSOL ATA of the program SOL vault, created if missing and verified
+    #[account(mut)]
+    pub vault_wsol_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: WSOL mint, verified against the fixed address
+    pub wsol_mint: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Vault account for token B (SOL), verified against the fixed address
+    #[account(mut)]
+    pub b_vault: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Token vault account for token B (SOL), verified against the fixed address
+    #[account(mut)]
+    pub b_token_vault: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: LP token mint for vault B, verified against the fixed address
+    #[account(mut)]
+    pub b_vault_lp_mint: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: LP token account for vault B, verified against the fixed address
+    #[account(mut)]
+    pub b_vault_lp: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Meteora vault program, verified against the fixed address
+    pub vault_program: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    // Token program that owns the DONUT mint (SPL Token or Token-2022)
+    pub donut_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for creating the staking pool (owner only)
+#[derive(Accounts)]
+pub struct InitializeStakingPool<'info> {
+    #[account(mut)]
+    pub state: Box<Account<'info, ProgramState>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + StakingPool::SIZE,
+        seeds = [b"staking_pool"],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    // SOL rewards waiting to be claimed
+    #[account(
+        mut,
+        seeds = [b"staking_reward_vault"],
+        bump
+    )]
+    pub staking_reward_vault: SystemAccount<'info>,
+
+    /// CHECK: DONUT mint, verified against the fixed address
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Staking vault (ATA of the staking vault authority), created if missing and verified
+    #[account(mut)]
+    pub staking_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Staking vault authority
+    #[account(
+        seeds = [b"staking_vault_authority"],
+        bump
+    )]
+    pub staking_vault_authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    // Token program that owns the DONUT mint (SPL Token or Token-2022)
+    pub donut_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for updating the staking fee (owner only)
+#[derive(Accounts)]
+pub struct UpdateStakingFee<'info> {
+    #[account(mut)]
+    pub state: Box<Account<'info, ProgramState>>,
+
+    // Must exist before registrations can route fees to it
+    #[account(
+        seeds = [b"staking_pool"],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    pub owner: Signer<'info>,
+}
+
+// Accounts for staking DONUT
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(
+        mut,
+        seeds = [b"staking_pool"],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        init_if_needed,
+        payer = user_wallet,
+        space = 8 + UserStake::SIZE,
+        seeds = [b"user_stake", user_wallet.key().as_ref()],
+        bump
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+
+    /// CHECK: DONUT mint, verified against the fixed address
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: User's DONUT ATA, verified before the transfer
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Staking vault, verified as the staking vault authority's ATA
+    #[account(mut)]
+    pub staking_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Staking vault authority
+    #[account(
+        seeds = [b"staking_vault_authority"],
+        bump
+    )]
+    pub staking_vault_authority: UncheckedAccount<'info>,
+
+    // Token program that owns the DONUT mint (SPL Token or Token-2022)
+    pub donut_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for unstaking DONUT
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    #[account(
+        mut,
+        seeds = [b"staking_pool"],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", user_wallet.key().as_ref()],
+        bump,
+        constraint = user_stake.owner_wallet == user_wallet.key() @ ErrorCode::NotAuthorized
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+
+    /// CHECK: DONUT mint, verified against the fixed address
+    pub token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: User's DONUT ATA, created if missing and verified
+    #[account(mut)]
+    pub user_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Staking vault, verified as the staking vault authority's ATA
+    #[account(mut)]
+    pub staking_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Staking vault authority
+    #[account(
+        seeds = [b"staking_vault_authority"],
+        bump
+    )]
+    pub staking_vault_authority: UncheckedAccount<'info>,
+
+    // Token program that owns the DONUT mint (SPL Token or Token-2022)
+    pub donut_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for claiming staking rewards
+#[derive(Accounts)]
+pub struct ClaimStakingRewards<'info> {
+    #[account(
+        seeds = [b"staking_pool"],
+        bump
+    )]
+    pub staking_pool: Account<'info, StakingPool>,
+
+    #[account(
+        mut,
+        seeds = [b"user_stake", user_wallet.key().as_ref()],
+        bump,
+        constraint = user_stake.owner_wallet == user_wallet.key() @ ErrorCode::NotAuthorized
+    )]
+    pub user_stake: Account<'info, UserStake>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"staking_reward_vault"],
+        bump
+    )]
+    pub staking_reward_vault: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for creating the mint budget (owner only)
+#[derive(Accounts)]
+pub struct InitializeMintBudget<'info> {
+    pub state: Box<Account<'info, ProgramState>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + MintBudget::SIZE,
+        seeds = [b"mint_budget"],
+        bump
+    )]
+    pub mint_budget: Account<'info, MintBudget>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Accounts for updating the mint budget (owner only)
+#[derive(Accounts)]
+pub struct UpdateMintBudget<'info> {
+    pub state: Box<Account<'info, ProgramState>>,
+
+    #[account(
+        mut,
+        seeds = [b"mint_budget"],
+        bump
+    )]
+    pub mint_budget: Account<'info, MintBudget>,
+
+    pub owner: Signer<'info>,
+}
+
+// Structure for registration with SOL in a single transaction
+// Now includes Chainlink accounts and remaining_accounts
+#[derive(Accounts)]
+#[instruction(deposit_amount: u64)]
+pub struct RegisterWithSolDeposit<'info> {
+    #[account(mut)]
+    pub state: Box<Account<'info, ProgramState>>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+
+    // Reference accounts
+    #[account(mut)]
+    pub referrer: Account<'info, UserAccount>,
+    
+    #[account(mut)]
+    pub referrer_wallet: SystemAccount<'info>,
+
+    // User account
+    #[account(
+        init,
+        payer = user_wallet,
+        space = 8 + UserAccount::SIZE,
+        seeds = [b"user_account", user_wallet.key().as_ref()],
+        bump
+    )]
+    pub user: Account<'info, UserAccount>,
+
+    // New WSOL ATA account
+    #[account(
+        init,
+        payer = user_wallet,
+        associated_token::mint = wsol_mint,
+        associated_token::authority = user_wallet
+    )]
+    pub user_wsol_account: Account<'info, TokenAccount>,
+    
+    // WSOL mint
+    /// CHECK: This is the fixed WSOL mint address
+    pub wsol_mint: AccountInfo<'info>,
+
+    // Deposit Accounts (Slot 1 and 3)
+    /// CHECK: Pool account (PDA)
+    #[account(mut)]
+    pub pool: UncheckedAccount<'info>,
+
+    // Existing accounts for vault B (SOL)
+    /// CHECK: Vault account for token B (SOL), verified against the fixed address
+    #[account(mut)]
+    pub b_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Token vault account for token B (SOL), verified against the fixed address
+    #[account(mut)]
+    pub b_token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: LP token mint for vault B, verified against the fixed address
+    #[account(mut)]
+    pub b_vault_lp_mint: UncheckedAccount<'info>,
+
+    /// CHECK: LP token account for vault B, verified against the fixed address
+    #[account(mut)]
+    pub b_vault_lp: UncheckedAccount<'info>,
+
+    /// CHECK: Meteora vault program, verified against the fixed address
+    pub vault_program: UncheckedAccount<'info>,
+
+    // Buyback accounts (Slot 1, only required when the slot-1 policy buys DONUT)
+    /// CHECK: Pool protocol fee account for SOL, verified against the pool
+    #[account(mut)]
+    pub protocol_token_b_fee: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Treasury DONUT ATA, verified when bought tokens go to the treasury
+    #[account(mut)]
+    pub treasury_token_account: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: Meteora AMM program, verified against the fixed address
+    pub amm_program: Option<UncheckedAccount<'info>>,
+
+    // Treasury fee accounts (only required when the treasury fee is enabled)
+    #[account(mut)]
+    pub treasury_wallet: Option<SystemAccount<'info>>,
+
+    /// CHECK: Treasury WSOL ATA for fees on token deposits, verified before use
+    #[account(mut)]
+    pub treasury_wsol_account: Option<UncheckedAccount<'info>>,
+
+    // Staking accounts (only required when the staking fee is enabled)
+    #[account(
+        mut,
+        seeds = [b"staking_pool"],
+        bump
+    )]
+    pub staking_pool: Option<Box<Account<'info, StakingPool>>>,
+
+    #[account(
+        mut,
+        seeds = [b"staking_reward_vault"],
+        bump
+    )]
+    pub staking_reward_vault: Option<SystemAccount<'info>>,
+
+    // Accounts for SOL reserve (Slot 2)
+    #[account(
+        mut,
+        seeds = [b"program_sol_vault"],
+        bump
+    )]
+    pub program_sol_vault: SystemAccount<'info>,
+
+    // Rolling mint budget (Slot 2)
+    #[account(
+        mut,
+        seeds = [b"mint_budget"],
+        bump
+    )]
+    pub mint_budget: Account<'info, MintBudget>,
+    
+    // ACCOUNTS FOR TOKENS (Slot 2 and 3)
+    /// CHECK: Token mint for minting new tokens
+    #[account(mut)]
+    pub token_mint: UncheckedAccount<'info>,
+    
+    /// CHECK: Program token vault to store reserved tokens
+    #[account(mut)]
+    pub program_token_vault: UncheckedAccount<'info>,
+    
+    /// CHECK: Referrer's ATA to receive tokens
+    #[account(mut)]
+    pub referrer_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Referrer's vesting PDA, required on slot 3 when vesting is enabled and verified before use
+    #[account(mut)]
+    pub referrer_vesting: Option<UncheckedAccount<'info>>,
+    
+    // Authority to mint tokens (program PDA)
+    /// CHECK: Mint authority PDA
+    #[account(
+        seeds = [b"token_mint_authority"],
+        bump
+    )]
+    pub token_mint_authority: UncheckedAccount<'info>,
+    
+    // Vault authority for token transfers
+    /// CHECK: Token vault authority
+    #[account(
+        seeds = [b"token_vault_authority"],
+        bump
+    )]
+    pub vault_authority: UncheckedAccount<'info>,
+
+    // Required programs
+    pub token_program: Program<'info, Token>,
+    // Token program that owns the DONUT mint (SPL Token or Token-2022)
+    pub donut_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Accounts for adding or updating an accepted deposit token (owner only)
+#[derive(Accounts)]
+pub struct SetAcceptedToken<'info> {
+    pub state: Box<Account<'info, ProgramState>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + AcceptedToken::SIZE,
+        seeds = [b"accepted_token", token_mint.key().as_ref()],
+        bump
+    )]
+    pub accepted_token: Account<'info, AcceptedToken>,
+
+    /// CHECK: Mint of the accepted token, only used as a seed
+    pub token_mint: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// Structure for registration with an SPL token deposit
+// Reuses every account of the SOL registration plus the swap accounts
+#[derive(Accounts)]
+pub struct RegisterWithTokenDeposit<'info> {
+    pub registration: RegisterWithSolDeposit<'info>,
+
+    #[account(
+        seeds = [b"accepted_token", source_mint.key().as_ref()],
+        bump
+    )]
+    pub accepted_token: Account<'info, AcceptedToken>,
+
+    /// CHECK: Mint of the deposited token, bound to accepted_token by its seeds
+    pub source_mint: UncheckedAccount<'info>,
+
+    /// CHECK: User's token account for the deposited token, verified in the instruction code
+    #[account(mut)]
+    pub user_source_token: UncheckedAccount<'info>,
+
+    /// CHECK: Chainlink TOKEN/USD feed, verified against accepted_token
+    pub token_price_feed: UncheckedAccount<'info>,
+
+    // Swap accounts (Meteora TOKEN/SOL pool)
+    /// CHECK: Swap pool, verified against accepted_token
+    #[account(mut)]
+    pub swap_pool: UncheckedAccount<'info>,
+
+    /// CHECK: Vault A of the swap pool, validated by the AMM program
+    #[account(mut)]
+    pub swap_a_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Vault B of the swap pool, validated by the AMM program
+    #[account(mut)]
+    pub swap_b_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Token vault A of the swap pool, validated by the AMM program
+    #[account(mut)]
+    pub swap_a_token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Token vault B of the swap pool, validated by the AMM program
+    #[account(mut)]
+    pub swap_b_token_vault: UncheckedAccount<'info>,
+
+    /// CHECK: LP mint of vault A, validated by the AMM program
+    #[account(mut)]
+    pub swap_a_vault_lp_mint: UncheckedAccount<'info>,
+
+    /// CHECK: LP mint of vault B, validated by the AMM program
+    #[account(mut)]
+    pub swap_b_vault_lp_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Pool LP account in vault A, validated by the AMM program
+    #[account(mut)]
+    pub swap_a_vault_lp: UncheckedAccount<'info>,
+
+    /// CHECK: Pool LP account in vault B, validated by the AMM program
+    #[account(mut)]
+    pub swap_b_vault_lp: UncheckedAccount<'info>,
+
+    /// CHECK: Protocol fee account for the source token, validated by the AMM program
+    #[account(mut)]
+    pub swap_protocol_token_fee: UncheckedAccount<'info>,
+
+    /// CHECK: Meteora AMM program, verified against the fixed address
+    pub amm_program: UncheckedAccount<'info>,
+}
+
+// Function to calculate the treasury fee on a deposit
+pub fn calculate_treasury_fee(deposit_amount: u64, fee_bps: u16) -> Result<u64> {
+    let fee_amount = (deposit_amount as u128)
+        .checked_mul(fee_bps as u128)
+        .and_then(|amount| amount.checked_div(BASIS_POINTS_DENOMINATOR))
+        .ok_or(error!(ErrorCode::InvalidTreasuryFee))?;
+
+    u64::try_from(fee_amount).map_err(|_| error!(ErrorCode::InvalidTreasuryFee))
+}
+
+// Function to send the treasury fee from a deposit
+// Returns the deposit left for the slot logic
+fn process_treasury_fee<'info>(
+    accounts: &RegisterWithSolDeposit<'info>,
+    deposit_amount: u64,
+    deposit_source: DepositSource,
+) -> Result<u64> {
+    let fee_bps = accounts.state.treasury_fee_bps;
+    let fee_amount = calculate_treasury_fee(deposit_amount, fee_bps)?;
+
+    if fee_amount == 0 {
+        return Ok(deposit_amount);
+    }
+
+    match deposit_source {
+        // Lamports go straight from the user's wallet to the treasury
+        DepositSource::Sol => {
+            let treasury_wallet = accounts.treasury_wallet.as_ref()
+                .ok_or(error!(ErrorCode::MissingTreasuryAccounts))?;
+
+            verify_address_strict(&treasury_wallet.key(), &accounts.state.multisig_treasury, ErrorCode::InvalidTreasuryAddress)?;
+
+            let fee_ix = solana_program::system_instruction::transfer(
+                &accounts.user_wallet.key(),
+                &treasury_wallet.key(),
+                fee_amount
+            );
+
+            solana_program::program::invoke(
+                &fee_ix,
+                &[accounts.user_wallet.to_account_info(), treasury_wallet.to_account_info()],
+            ).map_err(|_| error!(ErrorCode::TreasuryFeeFailed))?;
+        },
+        // Swapped deposits are already WSOL, so the fee goes to the treasury's WSOL ATA
+        DepositSource::SwappedToken => {
+            let treasury_wsol_account = accounts.treasury_wsol_account.as_ref()
+                .ok_or(error!(ErrorCode::MissingTreasuryAccounts))?;
+
+            verify_ata_strict(
+                &treasury_wsol_account.to_account_info(),
+                &accounts.state.multisig_treasury,
+                &verified_addresses::WSOL_MINT,
+                &spl_token::id()
+            )?;
+
+            let fee_ix = spl_token::instruction::transfer(
+                &token::ID,
+                &accounts.user_wsol_account.key(),
+                &treasury_wsol_account.key(),
+                &accounts.user_wallet.key(),
+                &[],
+                fee_amount
+            ).map_err(|_| error!(ErrorCode::TreasuryFeeFailed))?;
+
+            solana_program::program::invoke(
+                &fee_ix,
+                &[
+                    accounts.user_wsol_account.to_account_info(),
+                    treasury_wsol_account.to_account_info(),
+                    accounts.user_wallet.to_account_info(),
+                    accounts.token_program.to_account_info(),
+                ],
+            ).map_err(|_| error!(ErrorCode::TreasuryFeeFailed))?;
+        },
+    }
+
+    msg!("Treasury fee: {} of {} lamports ({} bps)", fee_amount, deposit_amount, fee_bps);
+
+    emit!(TreasuryFeeCollected {
+        user: accounts.user_wallet.key(),
+        deposit_amount,
+        fee_amount,
+        fee_bps,
+    });
+
+    Ok(deposit_amount - fee_amount)
+}
+
+// Where the SOL for a registration comes from
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DepositSource {
+    Sol,           // Lamports from the user's wallet, wrapped here
+    SwappedToken,  // WSOL already received from a token swap
+}
+
+/// Process a registration once the deposit is known
+/// Shared by the SOL and token deposit instructions
+fn process_registration<'info>(
+    accounts: &mut RegisterWithSolDeposit<'info>,
+    bumps: &RegisterWithSolDepositBumps,
+    remaining_accounts: &[AccountInfo<'info>],
+    deposit_amount: u64,
+    deposit_source: DepositSource,
+) -> Result<()> {
+    // Check if referrer is registered
+    if !accounts.referrer.is_registered {
+        return Err(error!(ErrorCode::ReferrerNotRegistered));
+    }
+
+    // Check if we have vault A accounts in remaining_accounts
+    if remaining_accounts.len() < VAULT_A_ACCOUNTS_COUNT + 2 { // +2 for Chainlink accounts
+        return Err(error!(ErrorCode::MissingVaultAAccounts));
+    }
+
+    // Extract vault A accounts from the beginning of remaining_accounts
+    let a_vault_lp = &remaining_accounts[0];
+    let a_vault_lp_mint = &remaining_accounts[1];
+    let a_token_vault = &remaining_accounts[2];
+    let a_vault = &remaining_accounts[3];
+
+    // Verify Vault A addresses (vault A itself is checked against the pool when it is read)
+    verify_vault_a_addresses(&a_vault_lp.key(), &a_vault_lp_mint.key())?;
+    verify_address_strict(&a_token_vault.key(), &verified_addresses::A_TOKEN_VAULT, ErrorCode::InvalidTokenAVaultAddress)?;
+
+    // Extract Chainlink accounts from remaining_accounts
+    let chainlink_feed = &remaining_accounts[VAULT_A_ACCOUNTS_COUNT];
+    let chainlink_program = &remaining_accounts[VAULT_A_ACCOUNTS_COUNT + 1];
+
+    // STRICT VERIFICATION OF ALL ADDRESSES
+    verify_all_fixed_addresses(
+        &accounts.pool.key(),
+        &accounts.b_vault_lp.key(),
+        &accounts.token_mint.key(),
+        &accounts.wsol_mint.key(),
+    )?;
+
+    // Verify vault B accounts and the vault program (pricing and deposit CPIs)
+    verify_vault_b_deposit_accounts(
+        &accounts.b_vault.key(),
+        &accounts.b_token_vault.key(),
+        &accounts.b_vault_lp_mint.key(),
+        &accounts.b_vault_lp.key(),
+        &accounts.vault_program.key(),
+    )?;
+
+    // Verify Chainlink addresses
+    verify_chainlink_addresses(
+        &chainlink_program.key(),
+        &chainlink_feed.key(),
+    )?;
+
+    // Sample the pool price on every registration
+    let now = Clock::get()?.unix_timestamp;
+    let pool_reserves = read_pool_reserves(
+        &accounts.pool.to_account_info(),
+        a_vault,
+        &accounts.b_vault.to_account_info(),
+        a_vault_lp,
+        &accounts.b_vault_lp.to_account_info(),
+        a_vault_lp_mint,
+        &accounts.b_vault_lp_mint.to_account_info(),
+    )?;
+
+    if let Some(reserves) = &pool_reserves {
+        record_price_observation(&mut accounts.state, reserves, now);
+    }
+
+    force_memory_cleanup();
+
+    // Get SOL price from Chainlink feed
+    let (sol_price, sol_price_decimals) = get_effective_sol_usd_price(
+        chainlink_feed,
+        chainlink_program,
+    )?;
+
+    // Token deposits were already valued against MINIMUM_USD_DEPOSIT with the token's own feed
+    if deposit_source == DepositSource::Sol {
+        // Get minimum deposit amount from the SOL price
+        let minimum_deposit = calculate_minimum_sol_deposit(sol_price, sol_price_decimals)?;
+
+        // Verify deposit amount meets the minimum requirement
+        if deposit_amount < minimum_deposit {
+            msg!("Deposit amount: {}, minimum required: {}", deposit_amount, minimum_deposit);
+            return Err(error!(ErrorCode::InsufficientDeposit));
+        }
+    }
+
+    // Verify the DONUT token program owns the token mint
+    verify_token_program_for_mint(
+        &accounts.token_mint.to_account_info(),
+        &accounts.donut_token_program.to_account_info()
+    )?;
+
+    // Verify the referrer wallet is the referrer's owner wallet
+    if accounts.referrer_wallet.key() != accounts.referrer.owner_wallet {
+        return Err(error!(ErrorCode::WalletMismatch));
+    }
+
+    // Verify referrer's ATA address (the account is created on payout if missing)
+    verify_canonical_ata(
+        &accounts.referrer_token_account.key(),
+        &accounts.referrer_wallet.key(),
+        &accounts.token_mint.key(),
+        &accounts.donut_token_program.key()
+    )?;
+    
+    // Skim the treasury fee before the slot logic; the minimum deposit applies to the full amount
+    let deposit_amount = process_treasury_fee(accounts, deposit_amount, deposit_source)?;
+
+    // Swapped token deposits already arrive as WSOL in the user's WSOL account
+    if deposit_source == DepositSource::Sol {
+        // 1. Transfer SOL to WSOL (wrap)
+        let transfer_ix = solana_program::system_instruction::transfer(
+            &accounts.user_wallet.key(),
+            &accounts.user_wsol_account.key(),
+            deposit_amount
+        );
+        
+        let wrap_accounts = [
+            accounts.user_wallet.to_account_info(),
+            accounts.user_wsol_account.to_account_info(),
+        ];
+        
+        solana_program::program::invoke(
+            &transfer_ix,
+            &wrap_accounts,
+        ).map_err(|_| error!(ErrorCode::WrapSolFailed))?;
+        
+        // 2. Sync the WSOL account
+        let sync_native_ix = spl_token::instruction::sync_native(
+            &token::ID,
+            &accounts.user_wsol_account.key(),
+        )?;
+        
+        let sync_accounts = [accounts.user_wsol_account.to_account_info()];
+        
+        solana_program::program::invoke(
+            &sync_native_ix,
+            &sync_accounts,
+        ).map_err(|_| error!(ErrorCode::WrapSolFailed))?;
+    }
+        
+    // 3. Create the new UplineEntry structure for the referrer
+    let referrer_entry = UplineEntry {
+        pda: accounts.referrer.key(),
+        wallet: accounts.referrer_wallet.key(),
+    };
+    
+    // 4. Create the user's upline by copying the referrer's upline and adding the referrer
+    let mut new_upline = Vec::new();
+    
+    // OPTIMIZATION - Try to reserve exact capacity to avoid reallocations
+    if accounts.referrer.upline.upline.len() >= MAX_UPLINE_DEPTH {
+        // If already at depth limit, reserve space for MAX_UPLINE_DEPTH entries only
+        new_upline.try_reserve(MAX_UPLINE_DEPTH).ok();
+        
+        // Copy only the most recent entries
+        let start_idx = accounts.referrer.upline.upline.len() - (MAX_UPLINE_DEPTH - 1);
+        new_upline.extend_from_slice(&accounts.referrer.upline.upline[start_idx..]);
+    } else {
+        // If space is available, reserve space for all existing entries plus the new one
+        new_upline.try_reserve(accounts.referrer.upline.upline.len() + 1).ok();
+        
+        // Copy all existing entries
+        new_upline.extend_from_slice(&accounts.referrer.upline.upline);
+    }
+    
+    // Add the current referrer
+    new_upline.push(referrer_entry);
+    
+    // OPTIMIZATION - Reduce capacity to current size
+    new_upline.shrink_to_fit();
+
+    // 5. Get upline ID from global counter
+    let (upline_id, chain_id) = {
+        let state = &mut accounts.state;
+        let upline_id = state.next_upline_id;
+        let chain_id = state.next_chain_id;
+
+        state.next_upline_id += 1; // Increment for next user
+        state.next_chain_id += 1;
+
+        (upline_id, chain_id)
+    };
+
+    // 6. Create new user data
+    let user = &mut accounts.user;
+
+    user.is_registered = true;
+    user.referrer = Some(accounts.referrer.key());
+    user.owner_wallet = accounts.user_wallet.key();
+    user.upline = ReferralUpline {
+        id: upline_id,
+        depth: accounts.referrer.upline.depth + 1,
+        upline: new_upline,
+    };
+    user.chain = ReferralChain {
+        id: chain_id,
+        slots: [None, None, None],
+        filled_slots: 0,
+    };
+    user.matrix_expires_at = 0;
+    
+    // Initialize user financial data
+    user.reserved_sol = 0;
+    user.reserved_tokens = 0;
+
+    // ===== FINANCIAL LOGIC =====
+    // Snapshot the referrer's matrix
+    let mut referrer_node = MatrixNode::from_account(accounts.referrer.key(), &accounts.referrer);
+
+    // Upline trios (PDA, wallet, ATA) follow the vault A and Chainlink accounts
+    // They are only read when the user completes the referrer's matrix
+    let upline_accounts = &remaining_accounts[VAULT_A_ACCOUNTS_COUNT + 2..];
+    let mut upline_data = if referrer_node.chain.filled_slots == 2 {
+        load_upline_accounts(upline_accounts, &accounts.referrer.key(), &accounts.referrer.upline.upline)?
+    } else {
+        Vec::new()
+    };
+
+    let mut upline_nodes: Vec<MatrixNode> = upline_data
+        .iter()
+        .enumerate()
+        .map(|(index, data)| MatrixNode::from_account(upline_accounts[index * 3].key(), data))
+        .collect();
+
+    force_memory_cleanup();
+
+    // Run the deposit through the matrix; slot-2 mints are priced and checked against the mint budget
+    let matrix_expires_at = calculate_matrix_expires_at(&accounts.state, now);
+    let outcome = matrix::process_deposit(
+        accounts.user_wallet.key(),
+        &mut referrer_node,
+        &mut upline_nodes,
+        deposit_amount,
+        accounts.state.next_chain_id,
+        matrix_expires_at,
+        |sol_amount| {
+            let token_amount = get_donut_tokens_amount(
+                pool_reserves.as_ref(),
+                &accounts.state,
+                sol_amount,
+                now
+            )?;
+
+            let deposit_usd_value = calculate_usd_value(sol_amount, sol_price, sol_price_decimals)
+                .ok_or(error!(ErrorCode::PriceFeedReadFailed))?;
+            check_mint_limit(&mut accounts.mint_budget, token_amount, deposit_usd_value, now)?;
+
+            Ok(token_amount)
+        },
+    )?;
+
+    accounts.state.next_chain_id = outcome.next_chain_id;
+
+    // Execute the effects in order
+    for effect in outcome.effects {
+        match effect {
+            Effect::DepositToPool { amount } => {
+                // Send the SOL to the slot-1 destination using the created WSOL account
+                process_slot1_deposit(
+                    accounts,
+                    bumps,
+                    remaining_accounts,
+                    pool_reserves.as_ref(),
+                    amount,
+                    now
+                )?;
+            },
+            Effect::CloseWsol => {
+                close_user_wsol_account(accounts)?;
+            },
+            Effect::ReserveSol { amount, previous, .. } => {
+                process_reserve_sol(
+                    &accounts.user_wallet.to_account_info(),
+                    &accounts.program_sol_vault.to_account_info(),
+                    amount
+                )?;
+
+                track_sol_reservation(&mut accounts.state, previous, amount)?;
+            },
+            Effect::MintTokens { amount, .. } => {
+                // Mint tokens for the program vault
+                process_mint_tokens(
+                    &accounts.token_mint.to_account_info(),
+                    &accounts.program_token_vault.to_account_info(),
+                    &accounts.token_mint_authority.to_account_info(),
+                    &accounts.donut_token_program.to_account_info(),
+                    amount,
+                    &[&[
+                        b"token_mint_authority".as_ref(),
+                        &[bumps.token_mint_authority]
+                    ]],
+                )?;
+
+                force_memory_cleanup();
+            },
+            Effect::PaySol { target, amount } => {
+                let (_, wallet) = matrix_target_accounts(accounts, upline_accounts, target);
+
+                // Stakers receive their share of the payout first
+                let staking_fee = process_staking_fee(accounts, bumps, amount)?;
+
+                process_pay_referrer(
+                    &accounts.program_sol_vault.to_account_info(),
+                    &wallet,
+                    amount - staking_fee,
+                    &[&[
+                        b"program_sol_vault".as_ref(),
+                        &[bumps.program_sol_vault]
+                    ]],
+                )?;
+
+                release_sol_reservation(&mut accounts.state, amount);
+            },
+            Effect::PayTokens { target, amount } => {
+                let (_, wallet) = matrix_target_accounts(accounts, upline_accounts, target);
+
+                // Credit the tokens to the wallet's vesting account, or pay them now
+                if accounts.state.vesting_duration > 0 {
+                    if amount > 0 {
+                        let vesting_account = match target {
+                            Target::Referrer => accounts.referrer_vesting.as_ref()
+                                .ok_or(error!(ErrorCode::MissingVestingAccount))?
+                                .to_account_info(),
+                            Target::Upline(index) => upline_accounts[index * 3 + 2].clone(),
+                        };
+
+                        process_vest_tokens(
+                            &accounts.user_wallet.to_account_info(),
+                            &vesting_account,
+                            &wallet.key(),
+                            &accounts.system_program.to_account_info(),
+                            amount,
+                            accounts.state.vesting_duration,
+                            now,
+                        )?;
+                    }
+                } else {
+                    let token_account = match target {
+                        Target::Referrer => accounts.referrer_token_account.to_account_info(),
+                        Target::Upline(index) => upline_accounts[index * 3 + 2].clone(),
+                    };
+
+                    // Create the wallet's ATA if missing, then verify it
+                    ensure_token_account_exists(
+                        &accounts.user_wallet.to_account_info(),
+                        &token_account,
+                        &wallet,
+                        &accounts.token_mint.to_account_info(),
+                        &accounts.donut_token_program.to_account_info(),
+                        &accounts.system_program.to_account_info(),
+                        &accounts.associated_token_program.to_account_info(),
+                    )?;
+
+                    verify_ata_strict(
+                        &token_account,
+                        &wallet.key(),
+                        &accounts.token_mint.key(),
+                        &accounts.donut_token_program.key()
+                    )?;
+
+                    // Transfer the reserved tokens using vault_authority
+                    if amount > 0 {
+                        process_transfer_tokens(
+                            &accounts.program_token_vault.to_account_info(),
+                            &token_account,
+                            &accounts.token_mint.to_account_info(),
+                            &accounts.vault_authority.to_account_info(),
+                            &accounts.donut_token_program.to_account_info(),
+                            amount,
+                            &[&[
+                                b"token_vault_authority".as_ref(),
+                                &[bumps.vault_authority]
+                            ]],
+                        )?;
+
+                        force_memory_cleanup();
+                    }
+                }
+            },
+            Effect::SlotFilled { target, slot_idx, chain_id, user } => {
+                let (owner, _) = matrix_target_accounts(accounts, upline_accounts, target);
+
+                emit!(SlotFilled {
+                    slot_idx,
+                    chain_id,
+                    user,
+                    owner,
+                });
+            },
+            // Already applied to the snapshot by the matrix engine
+            Effect::ResetChain { .. } => {},
+        }
+    }
+
+    // Save the matrices back to the accounts
+    referrer_node.apply_to(&mut accounts.referrer);
+
+    for (index, node) in upline_nodes.iter().enumerate().take(outcome.uplines_updated) {
+        let data = &mut upline_data[index];
+        node.apply_to(data);
+
+        let mut account_data = upline_accounts[index * 3].try_borrow_mut_data()?;
+        let mut write_data = &mut account_data[8..];
+        data.serialize(&mut write_data)?;
+    }
+
+    force_memory_cleanup();
+
+    // The vault must still cover every outstanding reservation
+    verify_reserved_sol_invariant(&accounts.state, &accounts.program_sol_vault.to_account_info())?;
+
+    log_heap_usage();
+
+    Ok(())
+}
+
+#[program]
+pub mod referral_system {
+    use super::*;
+
+    // Initialize program state
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+
+        if ctx.accounts.owner.key() != admin_addresses::AUTHORIZED_INITIALIZER {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        ctx.accounts.state.set_inner(ProgramState::new(
+            ctx.accounts.owner.key(),
+            admin_addresses::MULTISIG_TREASURY,
+        ));
+        
+        Ok(())
+    }
+
+    // Upgrade a legacy program state account to the current layout (owner only)
+    pub fn migrate_state(ctx: Context<MigrateState>) -> Result<()> {
+        let state_info = ctx.accounts.state.to_account_info();
+        if state_info.owner != &crate::ID {
+            return Err(error!(ErrorCode::InvalidStateAccount));
+        }
+
+        let legacy = {
+            let data = state_info.try_borrow_data()?;
+            if data.len() < 8 || data[..8] != <ProgramState as anchor_lang::Discriminator>::DISCRIMINATOR {
+                return Err(error!(ErrorCode::InvalidStateAccount));
+            }
+            if data.len() == 8 + ProgramState::SIZE {
+                return Err(error!(ErrorCode::StateAlreadyMigrated));
+            }
+            if data.len() != 8 + LegacyProgramState::SIZE {
+                return Err(error!(ErrorCode::InvalidStateSize));
+            }
+            LegacyProgramState::deserialize(&mut &data[8..])?
+        };
+
+        if ctx.accounts.owner.key() != legacy.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        // The owner pays the rent of the larger account
+        let space = 8 + ProgramState::SIZE;
+        let rent_shortfall = Rent::get()?.minimum_balance(space).saturating_sub(state_info.lamports());
+        if rent_shortfall > 0 {
+            solana_program::program::invoke(
+                &solana_program::system_instruction::transfer(
+                    &ctx.accounts.owner.key(),
+                    &state_info.key(),
+                    rent_shortfall,
+                ),
+                &[
+                    ctx.accounts.owner.to_account_info(),
+                    state_info.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+        state_info.realloc(space, true)?;
+
+        // Keep the identity and counters, start everything added since from the initialize defaults
+        // Reservations made before the upgrade aren't counted yet, so sweeps wait for reconcile_reserved_sol
+        let state = ProgramState {
+            next_upline_id: legacy.next_upline_id,
+            next_chain_id: legacy.next_chain_id,
+            reserved_sol_reconciled: false,
+            ..ProgramState::new(legacy.owner, legacy.multisig_treasury)
+        };
+        let mut data = state_info.try_borrow_mut_data()?;
+        state.try_serialize(&mut &mut data[..])?;
+
+        msg!("Program state migrated to layout version {}", STATE_LAYOUT_VERSION);
+        Ok(())
+    }
+
+    // Grow a user account created before matrix expiry to the current layout (anyone can call)
+    pub fn migrate_user_account(ctx: Context<MigrateUserAccount>) -> Result<()> {
+        let user_info = ctx.accounts.user.to_account_info();
+        if user_info.data_len() != 8 + UserAccount::LEGACY_SIZE {
+            return Err(error!(ErrorCode::UserAccountAlreadyMigrated));
+        }
+
+        // The payer covers the rent of the larger account
+        let space = 8 + UserAccount::SIZE;
+        let rent_shortfall = Rent::get()?.minimum_balance(space).saturating_sub(user_info.lamports());
+        if rent_shortfall > 0 {
+            solana_program::program::invoke(
+                &solana_program::system_instruction::transfer(
+                    &ctx.accounts.payer.key(),
+                    &user_info.key(),
+                    rent_shortfall,
+                ),
+                &[
+                    ctx.accounts.payer.to_account_info(),
+                    user_info.clone(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+        user_info.realloc(space, true)?;
+
+        // The deadline was read from bytes left over from older serializations, so the current
+        // matrix runs without one; the next matrix gets the configured expiry
+        ctx.accounts.user.matrix_expires_at = 0;
+
+        msg!("User account migrated: {}", ctx.accounts.user.key());
+        Ok(())
+    }
+
+    // Record a pool price observation (anyone can call)
+    pub fn record_price(ctx: Context<RecordPrice>) -> Result<()> {
+        // STRICT VERIFICATION OF ALL ADDRESSES
+        verify_address_strict(&ctx.accounts.pool.key(), &verified_addresses::POOL_ADDRESS, ErrorCode::InvalidPoolAddress)?;
+
+        verify_vault_a_addresses(
+            &ctx.accounts.a_vault_lp.key(),
+            &ctx.accounts.a_vault_lp_mint.key()
+        )?;
+
+        verify_vault_b_addresses(
+            &ctx.accounts.b_vault.key(),
+            &ctx.accounts.b_vault_lp.key(),
+            &ctx.accounts.b_vault_lp_mint.key()
+        )?;
+
+        let reserves = read_pool_reserves(
+            &ctx.accounts.pool.to_account_info(),
+            &ctx.accounts.a_vault.to_account_info(),
+            &ctx.accounts.b_vault.to_account_info(),
+            &ctx.accounts.a_vault_lp.to_account_info(),
+            &ctx.accounts.b_vault_lp.to_account_info(),
+            &ctx.accounts.a_vault_lp_mint.to_account_info(),
+            &ctx.accounts.b_vault_lp_mint.to_account_info(),
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+
+        match reserves {
+            Some(reserves) => {
+                if record_price_observation(&mut ctx.accounts.state, &reserves, now) {
+                    msg!("Price observation recorded at {}", now);
+                } else {
+                    msg!("Price observation skipped, last sample is too recent");
+                }
+            },
+            None => msg!("Pool reserves unavailable, no observation recorded"),
+        }
+
+        Ok(())
+    }
+
+    // Create the rolling mint budget (owner only)
+    pub fn initialize_mint_budget(
+        ctx: Context<InitializeMintBudget>,
+        epoch_duration: i64,
+        max_tokens_per_epoch: u64,
+        max_tokens_per_usd: u64,
+    ) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if epoch_duration <= 0 || max_tokens_per_epoch == 0 || max_tokens_per_usd == 0 {
+            return Err(error!(ErrorCode::InvalidMintBudgetConfig));
+        }
+
+        let mint_budget = &mut ctx.accounts.mint_budget;
+        mint_budget.epoch_duration = epoch_duration;
+        mint_budget.max_tokens_per_epoch = max_tokens_per_epoch;
+        mint_budget.max_tokens_per_usd = max_tokens_per_usd;
+        mint_budget.epoch_start = Clock::get()?.unix_timestamp;
+        mint_budget.minted_in_epoch = 0;
+
+        Ok(())
+    }
+
+    // Update the rolling mint budget limits (owner only)
+    pub fn update_mint_budget(
+        ctx: Context<UpdateMintBudget>,
+        epoch_duration: i64,
+        max_tokens_per_epoch: u64,
+        max_tokens_per_usd: u64,
+    ) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if epoch_duration <= 0 || max_tokens_per_epoch == 0 || max_tokens_per_usd == 0 {
+            return Err(error!(ErrorCode::InvalidMintBudgetConfig));
+        }
+
+        // Counters are kept so the current window isn't reset by a config change
+        let mint_budget = &mut ctx.accounts.mint_budget;
+        mint_budget.epoch_duration = epoch_duration;
+        mint_budget.max_tokens_per_epoch = max_tokens_per_epoch;
+        mint_budget.max_tokens_per_usd = max_tokens_per_usd;
+
+        Ok(())
+    }
+
+    // Add or update an SPL token accepted for registration deposits (owner only)
+    pub fn set_accepted_token(
+        ctx: Context<SetAcceptedToken>,
+        price_feed: Pubkey,
+        swap_pool: Pubkey,
+        max_slippage_bps: u16,
+        enabled: bool,
+    ) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if max_slippage_bps > MAX_SWAP_SLIPPAGE_BPS {
+            return Err(error!(ErrorCode::InvalidSlippage));
+        }
+
+        // Only SPL Token mints can be swapped through the Meteora pools
+        if ctx.accounts.token_mint.owner != &spl_token::id() {
+            return Err(error!(ErrorCode::InvalidTokenMintAddress));
+        }
+
+        let accepted_token = &mut ctx.accounts.accepted_token;
+        accepted_token.mint = ctx.accounts.token_mint.key();
+        accepted_token.price_feed = price_feed;
+        accepted_token.swap_pool = swap_pool;
+        accepted_token.max_slippage_bps = max_slippage_bps;
+        accepted_token.enabled = enabled;
+
+        Ok(())
+    }
+
+    // Update the TWAP window used for mint pricing (owner only)
+    pub fn set_twap_window(ctx: Context<UpdateConfig>, twap_window: u32) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if twap_window == 0 || twap_window > MAX_TWAP_WINDOW {
+            return Err(error!(ErrorCode::InvalidTwapWindow));
+        }
+
+        ctx.accounts.state.twap_window = twap_window;
+
+        Ok(())
+    }
+
+    // Set the vesting duration for slot-3 DONUT; 0 pays tokens immediately (owner only)
+    pub fn set_vesting_duration(ctx: Context<UpdateConfig>, vesting_duration: i64) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if !(0..=MAX_VESTING_DURATION).contains(&vesting_duration) {
+            return Err(error!(ErrorCode::InvalidVestingDuration));
+        }
+
+        ctx.accounts.state.vesting_duration = vesting_duration;
+
+        Ok(())
+    }
+
+    // Claim the unlocked part of the caller's vested DONUT
+    pub fn claim_vested(ctx: Context<ClaimVested>) -> Result<()> {
+        verify_address_strict(&ctx.accounts.token_mint.key(), &verified_addresses::TOKEN_MINT, ErrorCode::InvalidTokenMintAddress)?;
+
+        verify_token_program_for_mint(
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info()
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.program_token_vault.to_account_info(),
+            &ctx.accounts.vault_authority.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let claimable = ctx.accounts.vesting_account.claimable_amount(now);
+
+        if claimable == 0 {
+            return Err(error!(ErrorCode::NothingToClaim));
+        }
+
+        // Create the user's ATA if missing, then verify it
+        ensure_token_account_exists(
+            &ctx.accounts.user_wallet.to_account_info(),
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.user_wallet.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.associated_token_program.to_account_info(),
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.user_wallet.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        process_transfer_tokens(
+            &ctx.accounts.program_token_vault.to_account_info(),
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.vault_authority.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info(),
+            claimable,
+            &[&[
+                b"token_vault_authority".as_ref(),
+                &[ctx.bumps.vault_authority]
+            ]],
+        )?;
+
+        let vesting = &mut ctx.accounts.vesting_account;
+        vesting.claimed_amount = vesting.claimed_amount
+            .checked_add(claimable)
+            .ok_or(error!(ErrorCode::VestingOverflow))?;
+
+        emit!(VestedTokensClaimed {
+            wallet: ctx.accounts.user_wallet.key(),
+            amount: claimable,
+            claimed_amount: vesting.claimed_amount,
+        });
+
+        Ok(())
+    }
+
+    // Configure matrix expiry and the policy for expired reservations; 0 disables expiry (owner only)
+    pub fn set_matrix_expiry(
+        ctx: Context<UpdateConfig>,
+        matrix_expiry: i64,
+        expiry_policy: ExpiryPolicy,
+    ) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if !(0..=MAX_MATRIX_EXPIRY).contains(&matrix_expiry) {
+            return Err(error!(ErrorCode::InvalidMatrixExpiry));
+        }
+
+        let state = &mut ctx.accounts.state;
+        state.matrix_expiry = matrix_expiry;
+        state.expiry_policy = expiry_policy;
+
+        msg!("Matrix expiry: {} seconds, policy: {:?}", matrix_expiry, expiry_policy);
+
+        Ok(())
+    }
+
+    // Release the reservations of a matrix past its deadline and start a new one (anyone can call)
+    pub fn expire_matrix(ctx: Context<ExpireMatrix>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        // A legacy account's deadline is read from stale bytes until it is migrated
+        if ctx.accounts.user.to_account_info().data_len() != 8 + UserAccount::SIZE {
+            return Err(error!(ErrorCode::UserAccountNotMigrated));
+        }
+
+        let expires_at = ctx.accounts.user.matrix_expires_at;
+        if expires_at == 0 || now < expires_at || ctx.accounts.user.chain.filled_slots == 0 {
+            return Err(error!(ErrorCode::MatrixNotExpired));
+        }
+
+        if ctx.accounts.owner_wallet.key() != ctx.accounts.user.owner_wallet {
+            return Err(error!(ErrorCode::WalletMismatch));
+        }
+
+        verify_address_strict(&ctx.accounts.token_mint.key(), &verified_addresses::TOKEN_MINT, ErrorCode::InvalidTokenMintAddress)?;
+
+        verify_token_program_for_mint(
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info()
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.program_token_vault.to_account_info(),
+            &ctx.accounts.vault_authority.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        let policy = ctx.accounts.state.expiry_policy;
+        let reserved_sol = ctx.accounts.user.reserved_sol;
+        let reserved_tokens = ctx.accounts.user.reserved_tokens;
+
+        let sol_vault_seeds: &[&[&[u8]]] = &[&[
+            b"program_sol_vault".as_ref(),
+            &[ctx.bumps.program_sol_vault]
+        ]];
+        let token_vault_seeds: &[&[&[u8]]] = &[&[
+            b"token_vault_authority".as_ref(),
+            &[ctx.bumps.vault_authority]
+        ]];
+
+        match policy {
+            ExpiryPolicy::ReleaseToOwner => {
+                if reserved_sol > 0 {
+                    process_pay_referrer(
+                        &ctx.accounts.program_sol_vault.to_account_info(),
+                        &ctx.accounts.owner_wallet.to_account_info(),
+                        reserved_sol,
+                        sol_vault_seeds,
+                    )?;
+                }
+
+                if reserved_tokens > 0 {
+                    // Released tokens follow the same vesting rule as slot-3 payouts
+                    if ctx.accounts.state.vesting_duration > 0 {
+                        let owner_vesting = ctx.accounts.owner_vesting.as_ref()
+                            .ok_or(error!(ErrorCode::MissingExpiryAccounts))?;
+
+                        process_vest_tokens(
+                            &ctx.accounts.caller.to_account_info(),
+                            &owner_vesting.to_account_info(),
+                            &ctx.accounts.owner_wallet.key(),
+                            &ctx.accounts.system_program.to_account_info(),
+                            reserved_tokens,
+                            ctx.accounts.state.vesting_duration,
+                            now,
+                        )?;
+                    } else {
+                        let owner_token_account = ctx.accounts.owner_token_account.as_ref()
+                            .ok_or(error!(ErrorCode::MissingExpiryAccounts))?;
+
+                        ensure_token_account_exists(
+                            &ctx.accounts.caller.to_account_info(),
+                            &owner_token_account.to_account_info(),
+                            &ctx.accounts.owner_wallet.to_account_info(),
+                            &ctx.accounts.token_mint.to_account_info(),
+                            &ctx.accounts.donut_token_program.to_account_info(),
+                            &ctx.accounts.system_program.to_account_info(),
+                            &ctx.accounts.associated_token_program.to_account_info(),
+                        )?;
+
+                        verify_ata_strict(
+                            &owner_token_account.to_account_info(),
+                            &ctx.accounts.owner_wallet.key(),
+                            &ctx.accounts.token_mint.key(),
+                            &ctx.accounts.donut_token_program.key()
+                        )?;
+
+                        process_transfer_tokens(
+                            &ctx.accounts.program_token_vault.to_account_info(),
+                            &owner_token_account.to_account_info(),
+                            &ctx.accounts.token_mint.to_account_info(),
+                            &ctx.accounts.vault_authority.to_account_info(),
+                            &ctx.accounts.donut_token_program.to_account_info(),
+                            reserved_tokens,
+                            token_vault_seeds,
+                        )?;
+                    }
+                }
+            },
+            ExpiryPolicy::MoveToTreasury => {
+                let (treasury_wallet, treasury_token_account) = match (
+                    ctx.accounts.treasury_wallet.as_ref(),
+                    ctx.accounts.treasury_token_account.as_ref(),
+                ) {
+                    (Some(treasury_wallet), Some(treasury_token_account)) => (treasury_wallet, treasury_token_account),
+                    _ => return Err(error!(ErrorCode::MissingExpiryAccounts)),
+                };
+
+                verify_address_strict(&treasury_wallet.key(), &ctx.accounts.state.multisig_treasury, ErrorCode::InvalidTreasuryAddress)?;
+
+                if reserved_sol > 0 {
+                    process_pay_referrer(
+                        &ctx.accounts.program_sol_vault.to_account_info(),
+                        &treasury_wallet.to_account_info(),
+                        reserved_sol,
+                        sol_vault_seeds,
+                    )?;
+                }
+
+                if reserved_tokens > 0 {
+                    verify_ata_strict(
+                        &treasury_token_account.to_account_info(),
+                        &ctx.accounts.state.multisig_treasury,
+                        &ctx.accounts.token_mint.key(),
+                        &ctx.accounts.donut_token_program.key()
+                    )?;
+
+                    process_transfer_tokens(
+                        &ctx.accounts.program_token_vault.to_account_info(),
+                        &treasury_token_account.to_account_info(),
+                        &ctx.accounts.token_mint.to_account_info(),
+                        &ctx.accounts.vault_authority.to_account_info(),
+                        &ctx.accounts.donut_token_program.to_account_info(),
+                        reserved_tokens,
+                        token_vault_seeds,
+                    )?;
+                }
+            },
+            ExpiryPolicy::ReturnToPool => {
+                if reserved_sol > 0 {
+                    let (vault_wsol_account, wsol_mint, b_vault, b_token_vault, b_vault_lp_mint, b_vault_lp, vault_program) = match (
+                        ctx.accounts.vault_wsol_account.as_ref(),
+                        ctx.accounts.wsol_mint.as_ref(),
+                        ctx.accounts.b_vault.as_ref(),
+                        ctx.accounts.b_token_vault.as_ref(),
+                        ctx.accounts.b_vault_lp_mint.as_ref(),
+                        ctx.accounts.b_vault_lp.as_ref(),
+                        ctx.accounts.vault_program.as_ref(),
+                    ) {
+                        (Some(a), Some(b), Some(c), Some(d), Some(e), Some(f), Some(g)) => (a, b, c, d, e, f, g),
+                        _ => return Err(error!(ErrorCode::MissingExpiryAccounts)),
+                    };
+
+                    verify_address_strict(&wsol_mint.key(), &verified_addresses::WSOL_MINT, ErrorCode::InvalidTokenMintAddress)?;
+
+                    verify_vault_b_deposit_accounts(
+                        &b_vault.key(),
+                        &b_token_vault.key(),
+                        &b_vault_lp_mint.key(),
+                        &b_vault_lp.key(),
+                        &vault_program.key(),
+                    )?;
+
+                    // The program SOL vault wraps the SOL in its own WSOL ATA and deposits it
+                    ensure_token_account_exists(
+                        &ctx.accounts.caller.to_account_info(),
+                        &vault_wsol_account.to_account_info(),
+                        &ctx.accounts.program_sol_vault.to_account_info(),
+                        &wsol_mint.to_account_info(),
+                        &ctx.accounts.token_program.to_account_info(),
+                        &ctx.accounts.system_program.to_account_info(),
+                        &ctx.accounts.associated_token_program.to_account_info(),
+                    )?;
+
+                    verify_ata_strict(
+                        &vault_wsol_account.to_account_info(),
+                        &ctx.accounts.program_sol_vault.key(),
+                        &verified_addresses::WSOL_MINT,
+                        &spl_token::id()
+                    )?;
+
+                    // Plain transfer: the WSOL account is a token account, not a wallet
+                    solana_program::program::invoke_signed(
+                        &solana_program::system_instruction::transfer(
+                            &ctx.accounts.program_sol_vault.key(),
+                            &vault_wsol_account.key(),
+                            reserved_sol,
+                        ),
+                        &[
+                            ctx.accounts.program_sol_vault.to_account_info(),
+                            vault_wsol_account.to_account_info(),
+                            ctx.accounts.system_program.to_account_info(),
+                        ],
+                        sol_vault_seeds,
+                    ).map_err(|_| error!(ErrorCode::WrapSolFailed))?;
+
+                    let sync_native_ix = spl_token::instruction::sync_native(
+                        &token::ID,
+                        &vault_wsol_account.key(),
+                    )?;
+
+                    solana_program::program::invoke(
+                        &sync_native_ix,
+                        &[vault_wsol_account.to_account_info()],
+                    ).map_err(|_| error!(ErrorCode::WrapSolFailed))?;
+
+                    process_deposit_to_pool(
+                        &ctx.accounts.program_sol_vault.to_account_info(),
+                        &vault_wsol_account.to_account_info(),
+                        &b_vault_lp.to_account_info(),
+                        b_vault,
+                        &b_token_vault.to_account_info(),
+                        &b_vault_lp_mint.to_account_info(),
+                        vault_program,
+                        &ctx.accounts.token_program,
+                        reserved_sol,
+                        sol_vault_seeds,
+                    )?;
+                }
+
+                // The DONUT was minted against the returned SOL, so it is burned
+                if reserved_tokens > 0 {
+                    process_burn_tokens(
+                        &ctx.accounts.program_token_vault.to_account_info(),
+                        &ctx.accounts.token_mint.to_account_info(),
+                        &ctx.accounts.vault_authority.to_account_info(),
+                        &ctx.accounts.donut_token_program.to_account_info(),
+                        reserved_tokens,
+                        token_vault_seeds,
+                    )?;
+                }
+            },
+        }
+
+        release_sol_reservation(&mut ctx.accounts.state, reserved_sol);
+
+        // Reset the matrix with a new ID
+        let chain_id = ctx.accounts.user.chain.id;
+        let new_chain_id = ctx.accounts.state.next_chain_id;
+        ctx.accounts.state.next_chain_id += 1;
+
+        let user = &mut ctx.accounts.user;
+        user.reserved_sol = 0;
+        user.reserved_tokens = 0;
+        user.matrix_expires_at = 0;
+        matrix::reset_chain(&mut user.chain, new_chain_id);
+
+        verify_reserved_sol_invariant(&ctx.accounts.state, &ctx.accounts.program_sol_vault.to_account_info())?;
+
+        emit!(MatrixExpired {
+            owner: ctx.accounts.user.key(),
+            chain_id,
+            new_chain_id,
+            policy,
+            sol_amount: reserved_sol,
+            token_amount: reserved_tokens,
+        });
+
+        Ok(())
+    }
+
+    // Create the DONUT staking pool and its vaults (owner only)
+    pub fn initialize_staking_pool(ctx: Context<InitializeStakingPool>, staking_fee_bps: u16) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if staking_fee_bps > MAX_STAKING_FEE_BPS {
+            return Err(error!(ErrorCode::InvalidStakingFee));
+        }
+
+        verify_address_strict(&ctx.accounts.token_mint.key(), &verified_addresses::TOKEN_MINT, ErrorCode::InvalidTokenMintAddress)?;
+
+        verify_token_program_for_mint(
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info()
+        )?;
+
+        // Create the staking vault owned by the staking vault authority
+        ensure_token_account_exists(
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.staking_vault.to_account_info(),
+            &ctx.accounts.staking_vault_authority.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.associated_token_program.to_account_info(),
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.staking_vault.to_account_info(),
+            &ctx.accounts.staking_vault_authority.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        // Keep the reward vault rent-exempt so claims can always empty the rewards
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(0);
+        let reward_vault_lamports = ctx.accounts.staking_reward_vault.lamports();
+        if reward_vault_lamports < rent_exempt_minimum {
+            process_reserve_sol(
+                &ctx.accounts.owner.to_account_info(),
+                &ctx.accounts.staking_reward_vault.to_account_info(),
+                rent_exempt_minimum - reward_vault_lamports
+            )?;
+        }
+
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.total_staked = 0;
+        staking_pool.reward_per_share = 0;
+        staking_pool.total_rewards = 0;
+
+        ctx.accounts.state.staking_fee_bps = staking_fee_bps;
+
+        Ok(())
+    }
+
+    // Update the share of slot-3 SOL payouts sent to stakers (owner only)
+    pub fn set_staking_fee(ctx: Context<UpdateStakingFee>, staking_fee_bps: u16) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if staking_fee_bps > MAX_STAKING_FEE_BPS {
+            return Err(error!(ErrorCode::InvalidStakingFee));
+        }
+
+        ctx.accounts.state.staking_fee_bps = staking_fee_bps;
+
+        Ok(())
+    }
+
+    // Stake DONUT to earn a share of the staking fee
+    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Err(error!(ErrorCode::InvalidStakeAmount));
+        }
+
+        verify_address_strict(&ctx.accounts.token_mint.key(), &verified_addresses::TOKEN_MINT, ErrorCode::InvalidTokenMintAddress)?;
+
+        verify_token_program_for_mint(
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info()
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.user_wallet.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.staking_vault.to_account_info(),
+            &ctx.accounts.staking_vault_authority.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        let reward_per_share = ctx.accounts.staking_pool.reward_per_share;
+        let user_stake = &mut ctx.accounts.user_stake;
+
+        // A new stake account starts at the current reward per share
+        if user_stake.owner_wallet == Pubkey::default() {
+            user_stake.owner_wallet = ctx.accounts.user_wallet.key();
+        } else if user_stake.owner_wallet != ctx.accounts.user_wallet.key() {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        user_stake.settle(reward_per_share)?;
+
+        // Credit what the vault actually received (Token-2022 transfer fees)
+        let vault_balance_before = read_token_amount(&ctx.accounts.staking_vault.to_account_info())?;
+
+        process_transfer_tokens(
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.staking_vault.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.user_wallet.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info(),
+            amount,
+            &[],
+        )?;
+
+        let received = read_token_amount(&ctx.accounts.staking_vault.to_account_info())?
+            .checked_sub(vault_balance_before)
+            .ok_or(error!(ErrorCode::TokenTransferFailed))?;
+
+        let user_stake = &mut ctx.accounts.user_stake;
+        user_stake.amount = user_stake.amount
+            .checked_add(received)
+            .ok_or(error!(ErrorCode::StakingOverflow))?;
+        user_stake.reset_reward_debt(reward_per_share)?;
+
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.total_staked = staking_pool.total_staked
+            .checked_add(received)
+            .ok_or(error!(ErrorCode::StakingOverflow))?;
+
+        emit!(StakeUpdated {
+            wallet: ctx.accounts.user_wallet.key(),
+            amount: ctx.accounts.user_stake.amount,
+            total_staked: staking_pool.total_staked,
+        });
+
+        Ok(())
+    }
+
+    // Withdraw staked DONUT; earned rewards stay claimable
+    pub fn unstake(ctx: Context<Unstake>, amount: u64) -> Result<()> {
+        if amount == 0 || amount > ctx.accounts.user_stake.amount {
+            return Err(error!(ErrorCode::InvalidStakeAmount));
+        }
+
+        verify_address_strict(&ctx.accounts.token_mint.key(), &verified_addresses::TOKEN_MINT, ErrorCode::InvalidTokenMintAddress)?;
+
+        verify_token_program_for_mint(
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info()
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.staking_vault.to_account_info(),
+            &ctx.accounts.staking_vault_authority.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        // Create the user's ATA if missing, then verify it
+        ensure_token_account_exists(
+            &ctx.accounts.user_wallet.to_account_info(),
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.user_wallet.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.associated_token_program.to_account_info(),
+        )?;
+
+        verify_ata_strict(
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.user_wallet.key(),
+            &ctx.accounts.token_mint.key(),
+            &ctx.accounts.donut_token_program.key()
+        )?;
+
+        let reward_per_share = ctx.accounts.staking_pool.reward_per_share;
+        let user_stake = &mut ctx.accounts.user_stake;
+        user_stake.settle(reward_per_share)?;
+        user_stake.amount -= amount;
+        user_stake.reset_reward_debt(reward_per_share)?;
+
+        let staking_pool = &mut ctx.accounts.staking_pool;
+        staking_pool.total_staked = staking_pool.total_staked.saturating_sub(amount);
+
+        process_transfer_tokens(
+            &ctx.accounts.staking_vault.to_account_info(),
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.staking_vault_authority.to_account_info(),
+            &ctx.accounts.donut_token_program.to_account_info(),
+            amount,
+            &[&[
+                b"staking_vault_authority".as_ref(),
+                &[ctx.bumps.staking_vault_authority]
+            ]],
+        )?;
+
+        emit!(StakeUpdated {
+            wallet: ctx.accounts.user_wallet.key(),
+            amount: ctx.accounts.user_stake.amount,
+            total_staked: ctx.accounts.staking_pool.total_staked,
+        });
+
+        Ok(())
+    }
+
+    // Claim the SOL earned by the caller's stake
+    pub fn claim(ctx: Context<ClaimStakingRewards>) -> Result<()> {
+        let reward_per_share = ctx.accounts.staking_pool.reward_per_share;
+        let user_stake = &mut ctx.accounts.user_stake;
+        user_stake.settle(reward_per_share)?;
+
+        let amount = user_stake.pending_rewards;
+        if amount == 0 {
+            return Err(error!(ErrorCode::NothingToClaim));
+        }
 
-    // User account
-    #[account(
-        init,
-        payer = user_wallet,
-        space = 8 + UserAccount::SIZE,
-        seeds = [b"user_account", user_wallet.key().as_ref()],
-        bump
-    )]
-    pub user: Account<'info, UserAccount>,
+        user_stake.pending_rewards = 0;
 
-    // New WSOL ATA account
-    #[account(
-        init,
-        payer = user_wallet,
-        associated_token::mint = wsol_mint,
-        associated_token::authority = user_wallet
-    )]
-    pub user_wsol_account: Account<'info, TokenAccount>,
-    
-    // WSOL mint
-    /// CHECK: This is the fixed WSOL mint address
-    pub wsol_mint: AccountInfo<'info>,
+        let ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.staking_reward_vault.key(),
+            &ctx.accounts.user_wallet.key(),
+            amount
+        );
 
-    // Deposit Accounts (Slot 1 and 3)
-    /// CHECK: Pool account (PDA)
-    #[account(mut)]
-    pub pool: UncheckedAccount<'info>,
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.staking_reward_vault.to_account_info(),
+                ctx.accounts.user_wallet.to_account_info(),
+            ],
+            &[&[
+                b"staking_reward_vault".as_ref(),
+                &[ctx.bumps.staking_reward_vault]
+            ]],
+        ).map_err(|_| error!(ErrorCode::StakingRewardPaymentFailed))?;
+
+        emit!(StakingRewardsClaimed {
+            wallet: ctx.accounts.user_wallet.key(),
+            amount,
+        });
 
-    // Existing accounts for vault B (SOL)
-    /// CHECK: Vault account for token B (SOL)
-    #[account(mut)]
-    pub b_vault: UncheckedAccount<'info>,
+        Ok(())
+    }
 
-    /// CHECK: Token vault account for token B (SOL)
-    #[account(mut)]
-    pub b_token_vault: UncheckedAccount<'info>,
+    // Set the treasury fee taken from each deposit (multisig treasury only)
+    pub fn set_treasury_fee(ctx: Context<UpdateConfig>, treasury_fee_bps: u16) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.multisig_treasury {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
 
-    /// CHECK: LP token mint for vault B
-    #[account(mut)]
-    pub b_vault_lp_mint: UncheckedAccount<'info>,
+        if treasury_fee_bps > MAX_TREASURY_FEE_BPS {
+            return Err(error!(ErrorCode::InvalidTreasuryFee));
+        }
 
-    /// CHECK: LP token account for vault B
-    #[account(mut)]
-    pub b_vault_lp: UncheckedAccount<'info>,
+        ctx.accounts.state.treasury_fee_bps = treasury_fee_bps;
 
-    /// CHECK: Vault program
-    pub vault_program: UncheckedAccount<'info>,
+        msg!("Treasury fee: {} bps", treasury_fee_bps);
 
-    // Accounts for SOL reserve (Slot 2)
-    #[account(
-        mut,
-        seeds = [b"program_sol_vault"],
-        bump
-    )]
-    pub program_sol_vault: SystemAccount<'info>,
-    
-    // ACCOUNTS FOR TOKENS (Slot 2 and 3)
-    /// CHECK: Token mint for minting new tokens
-    #[account(mut)]
-    pub token_mint: UncheckedAccount<'info>,
-    
-    /// CHECK: Program token vault to store reserved tokens
-    #[account(mut)]
-    pub program_token_vault: UncheckedAccount<'info>,
-    
-    /// CHECK: Referrer's ATA to receive tokens
-    #[account(mut)]
-    pub referrer_token_account: UncheckedAccount<'info>,
-    
-    // Authority to mint tokens (program PDA)
-    /// CHECK: Mint authority PDA
-    #[account(
-        seeds = [b"token_mint_authority"],
-        bump
-    )]
-    pub token_mint_authority: UncheckedAccount<'info>,
-    
-    // Vault authority for token transfers
-    /// CHECK: Token vault authority
-    #[account(
-        seeds = [b"token_vault_authority"],
-        bump
-    )]
-    pub vault_authority: UncheckedAccount<'info>,
+        Ok(())
+    }
 
-    // Required programs
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub rent: Sysvar<'info, Rent>,
-}
+    // Withdraw SOL in the program vault that isn't owed to any reservation (multisig treasury only)
+    pub fn sweep_excess_sol(ctx: Context<SweepExcessSol>, amount: u64) -> Result<()> {
+        if ctx.accounts.treasury.key() != ctx.accounts.state.multisig_treasury {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
 
-#[program]
-pub mod referral_system {
-    use super::*;
+        // Until reconciled, the counter misses reservations made before the upgrade
+        if !ctx.accounts.state.reserved_sol_reconciled {
+            return Err(error!(ErrorCode::ReservedSolNotReconciled));
+        }
 
-    // Initialize program state
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        let vault = ctx.accounts.program_sol_vault.to_account_info();
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(0);
+        let excess_sol = calculate_excess_sol(
+            vault.lamports(),
+            ctx.accounts.state.total_reserved_sol,
+            rent_exempt_minimum,
+        );
 
-        if ctx.accounts.owner.key() != admin_addresses::AUTHORIZED_INITIALIZER {
+        msg!("Vault balance: {}, reserved: {}, excess: {}", vault.lamports(), ctx.accounts.state.total_reserved_sol, excess_sol);
+
+        if amount == 0 || amount > excess_sol {
+            return Err(error!(ErrorCode::InsufficientExcessSol));
+        }
+
+        process_pay_referrer(
+            &vault,
+            &ctx.accounts.treasury.to_account_info(),
+            amount,
+            &[&[
+                b"program_sol_vault".as_ref(),
+                &[ctx.bumps.program_sol_vault]
+            ]],
+        )?;
+
+        verify_reserved_sol_invariant(&ctx.accounts.state, &vault)?;
+
+        emit!(ExcessSolSwept {
+            amount,
+            total_reserved_sol: ctx.accounts.state.total_reserved_sol,
+        });
+
+        Ok(())
+    }
+
+    // Set the reserved SOL total once after a state migration (owner only)
+    // The total is the sum of every user's reserved_sol, read off-chain after the upgrade
+    pub fn reconcile_reserved_sol(ctx: Context<ReconcileReservedSol>, total_reserved_sol: u64) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.owner {
+            return Err(error!(ErrorCode::NotAuthorized));
+        }
+
+        if ctx.accounts.state.reserved_sol_reconciled {
+            return Err(error!(ErrorCode::ReservedSolAlreadyReconciled));
+        }
+
+        let vault = ctx.accounts.program_sol_vault.to_account_info();
+        ctx.accounts.state.total_reserved_sol = total_reserved_sol;
+        verify_reserved_sol_invariant(&ctx.accounts.state, &vault)?;
+        ctx.accounts.state.reserved_sol_reconciled = true;
+
+        msg!("Reserved SOL reconciled: {} of {} lamports in the vault", total_reserved_sol, vault.lamports());
+
+        emit!(ReservedSolReconciled {
+            total_reserved_sol,
+            vault_balance: vault.lamports(),
+        });
+
+        Ok(())
+    }
+
+    // Choose what happens to slot-1 SOL (multisig treasury only)
+    pub fn set_slot1_policy(
+        ctx: Context<UpdateConfig>,
+        policy: Slot1Policy,
+        buyback_slippage_bps: u16,
+    ) -> Result<()> {
+        if ctx.accounts.owner.key() != ctx.accounts.state.multisig_treasury {
             return Err(error!(ErrorCode::NotAuthorized));
         }
 
+        if buyback_slippage_bps > MAX_SWAP_SLIPPAGE_BPS {
+            return Err(error!(ErrorCode::InvalidSlippage));
+        }
+
         let state = &mut ctx.accounts.state;
-        state.owner = ctx.accounts.owner.key();
-        state.multisig_treasury = admin_addresses::MULTISIG_TREASURY;
-        state.next_upline_id = 1;
-        state.next_chain_id = 1;
-        state.last_mint_amount = 0;
-        
+        state.slot1_policy = policy;
+        state.buyback_slippage_bps = buyback_slippage_bps;
+
+        msg!("Slot-1 policy: {:?}, slippage: {} bps", policy, buyback_slippage_bps);
+
         Ok(())
     }
     
@@ -1222,7 +4649,24 @@ pub mod referral_system {
         &ctx.accounts.pool.key(),
         &ctx.accounts.b_vault_lp.key(),
         &ctx.accounts.token_mint.key(),
+        &ctx.accounts.wsol_mint.key(),
+    )?;
+
+    // Verify vault B accounts and the vault program before the deposit CPI
+    verify_vault_b_deposit_accounts(
+        &ctx.accounts.b_vault.key(),
+        &ctx.accounts.b_token_vault.key(),
+        &ctx.accounts.b_vault_lp_mint.key(),
+        &ctx.accounts.b_vault_lp.key(),
+        &ctx.accounts.vault_program.key(),
+    )?;
+
+    // Verify the source account is the user's WSOL ATA
+    verify_ata_strict(
+        &ctx.accounts.user_source_token.to_account_info(),
+        &ctx.accounts.user_wallet.key(),
         &verified_addresses::WSOL_MINT,
+        &spl_token::id()
     )?;
 
     // Use global upline ID
@@ -1250,6 +4694,7 @@ pub mod referral_system {
         slots: [None, None, None],
         filled_slots: 0,
     };
+    user.matrix_expires_at = 0;
     
     // Initialize financial data
     user.reserved_sol = 0;
@@ -1278,7 +4723,8 @@ pub mod referral_system {
         &ctx.accounts.b_vault_lp_mint.to_account_info(),
         &ctx.accounts.vault_program,
         &ctx.accounts.token_program,
-        deposit_amount
+        deposit_amount,
+        &[]
     )?;
 
     Ok(())
@@ -1286,667 +4732,99 @@ pub mod referral_system {
 
     // Register user with SOL in a single transaction - Modified to use remaining_accounts
     pub fn register_with_sol_deposit<'a, 'b, 'c, 'info>(ctx: Context<'a, 'b, 'c, 'info, RegisterWithSolDeposit<'info>>, deposit_amount: u64) -> Result<()> {
-        // Check if referrer is registered
-        if !ctx.accounts.referrer.is_registered {
-            return Err(error!(ErrorCode::ReferrerNotRegistered));
-        }
+        process_registration(
+            ctx.accounts,
+            &ctx.bumps,
+            ctx.remaining_accounts,
+            deposit_amount,
+            DepositSource::Sol,
+        )
+    }
 
-        // Check if we have vault A accounts in remaining_accounts
-        if ctx.remaining_accounts.len() < VAULT_A_ACCOUNTS_COUNT + 2 { // +2 for Chainlink accounts
-            return Err(error!(ErrorCode::MissingVaultAAccounts));
-        }
+    // Register user with an allowlisted SPL token, swapped to SOL through the token's configured pool
+    pub fn register_with_token_deposit<'a, 'b, 'c, 'info>(ctx: Context<'a, 'b, 'c, 'info, RegisterWithTokenDeposit<'info>>, token_amount: u64) -> Result<()> {
+        let accepted_token = &ctx.accounts.accepted_token;
 
-        // Extract vault A accounts from the beginning of remaining_accounts
-        let a_vault_lp = &ctx.remaining_accounts[0];
-        let a_vault_lp_mint = &ctx.remaining_accounts[1];
-        let a_token_vault = &ctx.remaining_accounts[2];
+        // Check if the token is accepted for deposits
+        if !accepted_token.enabled {
+            return Err(error!(ErrorCode::TokenNotAccepted));
+        }
 
-        // Verify Vault A addresses
-        verify_vault_a_addresses(
-            &a_vault_lp.key(),
-            &a_vault_lp_mint.key(),
-            &a_token_vault.key()
+        // STRICT VERIFICATION OF THE SWAP ACCOUNTS
+        verify_address_strict(&ctx.accounts.token_price_feed.key(), &accepted_token.price_feed, ErrorCode::InvalidPriceFeed)?;
+        verify_address_strict(&ctx.accounts.swap_pool.key(), &accepted_token.swap_pool, ErrorCode::InvalidPoolAddress)?;
+        verify_address_strict(&ctx.accounts.amm_program.key(), &verified_addresses::AMM_PROGRAM, ErrorCode::InvalidAmmProgram)?;
+
+        // Verify the user's source token account
+        verify_token_account(
+            &ctx.accounts.user_source_token.to_account_info(),
+            &ctx.accounts.registration.user_wallet.key(),
+            &ctx.accounts.source_mint.key(),
+            &spl_token::id()
         )?;
 
-        // Extract Chainlink accounts from remaining_accounts
-        let chainlink_feed = &ctx.remaining_accounts[3];
-        let chainlink_program = &ctx.remaining_accounts[4];
+        // Check if we have vault A and Chainlink accounts in remaining_accounts
+        if ctx.remaining_accounts.len() < VAULT_A_ACCOUNTS_COUNT + 2 {
+            return Err(error!(ErrorCode::MissingVaultAAccounts));
+        }
 
-        // STRICT VERIFICATION OF ALL ADDRESSES
-        verify_all_fixed_addresses(
-            &ctx.accounts.pool.key(),
-            &ctx.accounts.b_vault_lp.key(),
-            &ctx.accounts.token_mint.key(),
-            &ctx.accounts.wsol_mint.key(),
-        )?;
+        let chainlink_feed = &ctx.remaining_accounts[VAULT_A_ACCOUNTS_COUNT];
+        let chainlink_program = &ctx.remaining_accounts[VAULT_A_ACCOUNTS_COUNT + 1];
 
-        // Verify Chainlink addresses
         verify_chainlink_addresses(
             &chainlink_program.key(),
             &chainlink_feed.key(),
         )?;
 
-        // Get minimum deposit amount from Chainlink feed
-        let minimum_deposit = calculate_minimum_sol_deposit(
-            chainlink_feed,
+        // Value the deposit with the token's own feed (a stale feed is rejected)
+        let (token_price, token_price_decimals, current_timestamp, feed_timestamp) = get_sol_usd_price(
+            &ctx.accounts.token_price_feed.to_account_info(),
             chainlink_program,
         )?;
 
-        // Verify deposit amount meets the minimum requirement
-        if deposit_amount < minimum_deposit {
-            msg!("Deposit amount: {}, minimum required: {}", deposit_amount, minimum_deposit);
-            return Err(error!(ErrorCode::InsufficientDeposit));
-        }
-
-        // Verify referrer's ATA account
-        verify_ata_strict(
-            &ctx.accounts.referrer_token_account.to_account_info(),
-            &ctx.accounts.referrer_wallet.key(),
-            &ctx.accounts.token_mint.key()
-        )?;
-        
-        // 1. Transfer SOL to WSOL (wrap)
-        let transfer_ix = solana_program::system_instruction::transfer(
-            &ctx.accounts.user_wallet.key(),
-            &ctx.accounts.user_wsol_account.key(),
-            deposit_amount
-        );
-        
-        let wrap_accounts = [
-            ctx.accounts.user_wallet.to_account_info(),
-            ctx.accounts.user_wsol_account.to_account_info(),
-        ];
-        
-        solana_program::program::invoke(
-            &transfer_ix,
-            &wrap_accounts,
-        ).map_err(|_| error!(ErrorCode::WrapSolFailed))?;
-        
-        // 2. Sync the WSOL account
-        let sync_native_ix = spl_token::instruction::sync_native(
-            &token::ID,
-            &ctx.accounts.user_wsol_account.key(),
-        )?;
-        
-        let sync_accounts = [ctx.accounts.user_wsol_account.to_account_info()];
-        
-        solana_program::program::invoke(
-            &sync_native_ix,
-            &sync_accounts,
-        ).map_err(|_| error!(ErrorCode::WrapSolFailed))?;
-        
-        // 3. Create the new UplineEntry structure for the referrer
-        let referrer_entry = UplineEntry {
-            pda: ctx.accounts.referrer.key(),
-            wallet: ctx.accounts.referrer_wallet.key(),
-        };
-        
-        // 4. Create the user's upline by copying the referrer's upline and adding the referrer
-        let mut new_upline = Vec::new();
-        
-        // OPTIMIZATION - Try to reserve exact capacity to avoid reallocations
-        if ctx.accounts.referrer.upline.upline.len() >= MAX_UPLINE_DEPTH {
-            // If already at depth limit, reserve space for MAX_UPLINE_DEPTH entries only
-            new_upline.try_reserve(MAX_UPLINE_DEPTH).ok();
-            
-            // Copy only the most recent entries
-            let start_idx = ctx.accounts.referrer.upline.upline.len() - (MAX_UPLINE_DEPTH - 1);
-            new_upline.extend_from_slice(&ctx.accounts.referrer.upline.upline[start_idx..]);
-        } else {
-            // If space is available, reserve space for all existing entries plus the new one
-            new_upline.try_reserve(ctx.accounts.referrer.upline.upline.len() + 1).ok();
-            
-            // Copy all existing entries
-            new_upline.extend_from_slice(&ctx.accounts.referrer.upline.upline);
+        if current_timestamp - feed_timestamp > MAX_PRICE_FEED_AGE {
+            return Err(error!(ErrorCode::PriceFeedTooOld));
         }
-        
-        // Add the current referrer
-        new_upline.push(referrer_entry);
-        
-        // OPTIMIZATION - Reduce capacity to current size
-        new_upline.shrink_to_fit();
-
-        // 5. Get upline ID from global counter
-        let state = &mut ctx.accounts.state;
-        let upline_id = state.next_upline_id;
-        let chain_id = state.next_chain_id;
-
-        state.next_upline_id += 1; // Increment for next user
-        state.next_chain_id += 1;
-
-        // 6. Create new user data
-        let user = &mut ctx.accounts.user;
 
-        user.is_registered = true;
-        user.referrer = Some(ctx.accounts.referrer.key());
-        user.owner_wallet = ctx.accounts.user_wallet.key();
-        user.upline = ReferralUpline {
-            id: upline_id,
-            depth: ctx.accounts.referrer.upline.depth + 1,
-            upline: new_upline,
-        };
-        user.chain = ReferralChain {
-            id: chain_id,
-            slots: [None, None, None],
-            filled_slots: 0,
-        };
-        
-        // Initialize user financial data
-        user.reserved_sol = 0;
-        user.reserved_tokens = 0;
+        let token_decimals = get_mint_decimals(&ctx.accounts.source_mint.to_account_info())?;
+        let deposit_usd_value = calculate_token_usd_value(token_amount, token_decimals as u32, token_price, token_price_decimals)
+            .ok_or(error!(ErrorCode::PriceFeedReadFailed))?;
 
-        // ===== FINANCIAL LOGIC =====
-        // Determine which slot we're filling in the referrer's matrix
-        let slot_idx = ctx.accounts.referrer.chain.filled_slots as usize;
-
-        // LOGIC FOR SLOT 1: Deposit to liquidity pool
-        if slot_idx == 0 {
-            // Transfer SOL to the liquidity pool using the created WSOL account
-            process_deposit_to_pool(
-                &ctx.accounts.user_wallet.to_account_info(),
-                &ctx.accounts.user_wsol_account.to_account_info(),
-                &ctx.accounts.b_vault_lp.to_account_info(),
-                &ctx.accounts.b_vault,
-                &ctx.accounts.b_token_vault.to_account_info(),
-                &ctx.accounts.b_vault_lp_mint.to_account_info(),
-                &ctx.accounts.vault_program,
-                &ctx.accounts.token_program,
-                deposit_amount
-            )?;
-        } 
-        // LOGIC FOR SLOT 2: Reserve SOL value and mint tokens
-        else if slot_idx == 1 {
-            // Closing the WSOL account transfers the lamports back to the owner
-            let close_ix = spl_token::instruction::close_account(
-                &token::ID,
-                &ctx.accounts.user_wsol_account.key(),
-                &ctx.accounts.user_wallet.key(),
-                &ctx.accounts.user_wallet.key(),
-                &[]
-            )?;
-            
-            let close_accounts = [
-                ctx.accounts.user_wsol_account.to_account_info(),
-                ctx.accounts.user_wallet.to_account_info(),
-                ctx.accounts.user_wallet.to_account_info(),
-            ];
-            
-            solana_program::program::invoke(
-                &close_ix,
-                &close_accounts,
-            ).map_err(|_| error!(ErrorCode::UnwrapSolFailed))?;
-            
-            // Now transfer SOL to reserve
-            process_reserve_sol(
-                &ctx.accounts.user_wallet.to_account_info(),
-                &ctx.accounts.program_sol_vault.to_account_info(),
-                deposit_amount
-            )?;
-            
-            // Update reserved value for the referrer
-            ctx.accounts.referrer.reserved_sol = deposit_amount;
-            
-            // Calculate tokens based on pool value
-            let token_amount = get_donut_tokens_amount(
-                a_vault_lp,
-                &ctx.accounts.b_vault_lp.to_account_info(),
-                a_vault_lp_mint,
-                &ctx.accounts.b_vault_lp_mint.to_account_info(),
-                a_token_vault,
-                &ctx.accounts.b_token_vault.to_account_info(),
-                deposit_amount
-            )?;
-            
-            // Check and adjust the value according to the limit
-            let adjusted_token_amount = check_mint_limit(state, token_amount)?;
+        // Verify deposit value meets the minimum requirement
+        if deposit_usd_value < MINIMUM_USD_DEPOSIT {
+            msg!("Deposit value: {}, minimum required: {}", deposit_usd_value, MINIMUM_USD_DEPOSIT);
+            return Err(error!(ErrorCode::InsufficientDeposit));
+        }
 
-            // Force cleanup after calculation
-            force_memory_cleanup();
-            
-            // Mint tokens for the program vault
-            process_mint_tokens(
-                &ctx.accounts.token_mint.to_account_info(),
-                &ctx.accounts.program_token_vault.to_account_info(),
-                &ctx.accounts.token_mint_authority.to_account_info(),
-                &ctx.accounts.token_program,
-                adjusted_token_amount,
-                &[&[
-                    b"token_mint_authority".as_ref(),
-                    &[ctx.bumps.token_mint_authority]
-                ]],
-            )?;
+        // Minimum SOL out from the oracle value and the token's slippage limit. The default SOL
+        // price would set a meaningless minimum, so a stale SOL feed is rejected as well
+        let (sol_price, sol_price_decimals, current_timestamp, feed_timestamp) = get_sol_usd_price(chainlink_feed, chainlink_program)?;
 
-            // Add cleanup:
-            force_memory_cleanup();
-            
-            // Update reserved tokens value for the referrer
-            ctx.accounts.referrer.reserved_tokens = adjusted_token_amount;
-        }
-        // LOGIC FOR SLOT 3: Pay referrer (SOL and tokens) and start recursion
-        else if slot_idx == 2 {
-            // 1. Transfer the reserved SOL value to the referrer
-            if ctx.accounts.referrer.reserved_sol > 0 {
-                // Verify that referrer_wallet is a system account
-                verify_wallet_is_system_account(&ctx.accounts.referrer_wallet.to_account_info())?;
-                
-                process_pay_referrer(
-                    &ctx.accounts.program_sol_vault.to_account_info(),
-                    &ctx.accounts.referrer_wallet.to_account_info(),
-                    ctx.accounts.referrer.reserved_sol,
-                    &[&[
-                        b"program_sol_vault".as_ref(),
-                        &[ctx.bumps.program_sol_vault]
-                    ]],
-                )?;
-                
-                // Zero out the reserved SOL value after payment
-                ctx.accounts.referrer.reserved_sol = 0;
-            }
-            
-            // Verify the token account is valid
-            verify_token_account(
-                &ctx.accounts.referrer_token_account.to_account_info(),
-                &ctx.accounts.referrer_wallet.key(),
-                &ctx.accounts.token_mint.key()
-            )?;
-            
-            // Transfer the reserved tokens to the referrer using vault_authority
-            if ctx.accounts.referrer.reserved_tokens > 0 {
-                process_transfer_tokens(
-                    &ctx.accounts.program_token_vault.to_account_info(),
-                    &ctx.accounts.referrer_token_account.to_account_info(),
-                    &ctx.accounts.vault_authority.to_account_info(),
-                    &ctx.accounts.token_program,
-                    ctx.accounts.referrer.reserved_tokens,
-                    &[&[
-                        b"token_vault_authority".as_ref(),
-                        &[ctx.bumps.vault_authority]
-                    ]],
-                )?;
-                // Add cleanup:
-                force_memory_cleanup();
-                
-                // Zero out the reserved tokens value after payment
-                ctx.accounts.referrer.reserved_tokens = 0;
-            }
+        if current_timestamp - feed_timestamp > MAX_PRICE_FEED_AGE {
+            return Err(error!(ErrorCode::PriceFeedTooOld));
         }
-        
-        // Process the referrer's matrix
-        let (chain_completed, upline_pubkey) = process_referrer_chain(
-            &ctx.accounts.user_wallet.key(),
-            &mut ctx.accounts.referrer,
-            state.next_chain_id,
-        )?;
 
-        // Add cleanup:
-        force_memory_cleanup();
-        
-        // If the matrix was completed, increment the global ID for the next one
-        if chain_completed {
-            state.next_chain_id += 1;
-        }
-
-        // If the referrer's matrix was completed, process recursion
-        if chain_completed && slot_idx == 2 {
-            let mut current_user_pubkey = upline_pubkey;
-            let mut current_deposit = deposit_amount;
-            let mut wsol_closed = false;
-
-            // Calculate remaining accounts offset - skip the 3 vault A accounts and 2 Chainlink accounts
-            let upline_start_idx = VAULT_A_ACCOUNTS_COUNT + 2;
-
-            // Check if we have upline accounts to process (besides the 3 vault A accounts and 2 Chainlink accounts)
-            if ctx.remaining_accounts.len() > upline_start_idx && current_deposit > 0 {
-                let upline_accounts = &ctx.remaining_accounts[upline_start_idx..];
-                
-                // OPTIMIZATION - Check if remaining upline accounts are multiples of 3
-                if upline_accounts.len() % 3 != 0 {
-                    return Err(error!(ErrorCode::MissingUplineAccount));
-                }
-                
-                // Calculate number of trios (PDA, wallet, ATA)
-                let trio_count = upline_accounts.len() / 3;
-                
-                // OPTIMIZATION - Process in smaller batches to save memory
-                const BATCH_SIZE: usize = 1; 
-                
-                // Calculate number of batches (division with rounding up)
-                let batch_count = (trio_count + BATCH_SIZE - 1) / BATCH_SIZE;
-                
-                // Process each batch
-                for batch_idx in 0..batch_count {
-                    // Calculate batch range
-                    let start_trio = batch_idx * BATCH_SIZE;
-                    let end_trio = std::cmp::min(start_trio + BATCH_SIZE, trio_count);
-                    
-                    // Iterate through trios in current batch
-                    for trio_index in start_trio..end_trio {
-                        // Check maximum depth and if deposit is remaining
-                        if trio_index >= MAX_UPLINE_DEPTH || current_deposit == 0 {
-                            break;
-                        }
-
-                        // Calculate base index for each trio
-                        let base_idx = trio_index * 3;
-                        
-                        // Get current upline information
-                        let upline_info = &upline_accounts[base_idx];       // Account PDA
-                        let upline_wallet = &upline_accounts[base_idx + 1]; // Wallet 
-                        let upline_token = &upline_accounts[base_idx + 2];  // ATA for tokens
-                        
-                        // OPTIMIZATION - Basic validations before processing the account
-                        if upline_wallet.owner != &solana_program::system_program::ID {
-                            return Err(error!(ErrorCode::PaymentWalletInvalid));
-                        }
-                        
-                        // Check program ownership first before trying to deserialize
-                        if !upline_info.owner.eq(&crate::ID) {
-                            return Err(error!(ErrorCode::InvalidSlotOwner));
-                        }
-
-                        // STEP 1: Read and process data - Optimized for lower memory usage
-                        let mut upline_account_data;
-                        {
-                            // Limited scope for data borrowing
-                            let data = upline_info.try_borrow_data()?;
-                            if data.len() <= 8 {
-                                return Err(ProgramError::InvalidAccountData.into());
-                            }
-
-                            // Deserialize directly without clone
-                            let mut account_slice = &data[8..];
-                            upline_account_data = UserAccount::deserialize(&mut account_slice)?;
-
-                            // Verify registration immediately
-                            if !upline_account_data.is_registered {
-                                return Err(error!(ErrorCode::SlotNotRegistered));
-                            }
-                        }
+        let expected_lamports = calculate_sol_for_usd_value(deposit_usd_value, sol_price, sol_price_decimals)
+            .ok_or(error!(ErrorCode::PriceFeedReadFailed))?;
+        let minimum_out_amount = ((expected_lamports as u128)
+            * (BASIS_POINTS_DENOMINATOR - accepted_token.max_slippage_bps as u128)
+            / BASIS_POINTS_DENOMINATOR) as u64;
 
-                        force_memory_cleanup();
+        // Swap the token into the user's WSOL account
+        process_token_swap(ctx.accounts, token_amount, minimum_out_amount)?;
 
-                        // Continue processing with deserialized data
-                        let upline_slot_idx = upline_account_data.chain.filled_slots as usize;
-                        let upline_key = *upline_info.key;
-                        
-                        // Verify token account only if it's slot 3
-                        if upline_slot_idx == 2 {
-                            if upline_token.owner != &spl_token::id() {
-                                return Err(error!(ErrorCode::TokenAccountInvalid));
-                            }
-                            
-                            verify_token_account(
-                                upline_token,
-                                &upline_wallet.key(),
-                                &ctx.accounts.token_mint.key()
-                            )?;
-                        }
-                        
-                        // Add current user to the matrix
-                        upline_account_data.chain.slots[upline_slot_idx] = Some(current_user_pubkey);
-                        
-                        // Emit slot filled event in recursion
-                        emit!(SlotFilled {
-                            slot_idx: upline_slot_idx as u8,
-                            chain_id: upline_account_data.chain.id,
-                            user: current_user_pubkey,
-                            owner: upline_key,
-                        });
-                        
-                        // Increment filled slots count
-                        upline_account_data.chain.filled_slots += 1;
-                        
-                        // Apply specific financial logic for the deposit
-                        if upline_slot_idx == 0 {
-                            // SLOT 1: Deposit to pool
-                            // Use the WSOL account that was kept open
-                            process_deposit_to_pool(
-                                &ctx.accounts.user_wallet.to_account_info(),
-                                &ctx.accounts.user_wsol_account.to_account_info(),
-                                &ctx.accounts.b_vault_lp.to_account_info(),
-                                &ctx.accounts.b_vault,
-                                &ctx.accounts.b_token_vault.to_account_info(),
-                                &ctx.accounts.b_vault_lp_mint.to_account_info(),
-                                &ctx.accounts.vault_program,
-                                &ctx.accounts.token_program,
-                                current_deposit
-                            )?;
-                            
-                            // Deposit was used, doesn't continue in recursion
-                            current_deposit = 0;
-                        } 
-                        else if upline_slot_idx == 1 {
-                            // SLOT 2: Reserve for upline (SOL and tokens)
-                            // Close WSOL account if still open
-                            if !wsol_closed {
-                                let close_ix = spl_token::instruction::close_account(
-                                    &token::ID,
-                                    &ctx.accounts.user_wsol_account.key(),
-                                    &ctx.accounts.user_wallet.key(),
-                                    &ctx.accounts.user_wallet.key(),
-                                    &[]
-                                )?;
-                                
-                                let close_accounts = [
-                                    ctx.accounts.user_wsol_account.to_account_info(),
-                                    ctx.accounts.user_wallet.to_account_info(),
-                                    ctx.accounts.user_wallet.to_account_info(),
-                                ];
-                                
-                                solana_program::program::invoke(
-                                    &close_ix,
-                                    &close_accounts,
-                                ).map_err(|_| error!(ErrorCode::UnwrapSolFailed))?;
-                                
-                                wsol_closed = true;
-                            }
-                            
-                            // Now reserve the SOL
-                            process_reserve_sol(
-                                &ctx.accounts.user_wallet.to_account_info(),
-                                &ctx.accounts.program_sol_vault.to_account_info(),
-                                current_deposit
-                            )?;
-                            
-                            // Update the reserved SOL value for the upline
-                            upline_account_data.reserved_sol = current_deposit;
-                            
-                            // Calculate tokens based on pool value (using vault A accounts)
-                            let token_amount = get_donut_tokens_amount(
-                                a_vault_lp,
-                                &ctx.accounts.b_vault_lp.to_account_info(),
-                                a_vault_lp_mint,
-                                &ctx.accounts.b_vault_lp_mint.to_account_info(),
-                                a_token_vault,
-                                &ctx.accounts.b_token_vault.to_account_info(),
-                                current_deposit
-                            )?;
-                            
-                            // Check and adjust the value according to the limit
-                            let adjusted_token_amount = check_mint_limit(state, token_amount)?;
-
-                            // Force cleanup after calculation
-                            force_memory_cleanup();
-                            
-                            // Mint tokens for the program vault
-                            process_mint_tokens(
-                                &ctx.accounts.token_mint.to_account_info(),
-                                &ctx.accounts.program_token_vault.to_account_info(),
-                                &ctx.accounts.token_mint_authority.to_account_info(),
-                                &ctx.accounts.token_program,
-                                adjusted_token_amount,
-                                &[&[
-                                    b"token_mint_authority".as_ref(),
-                                    &[ctx.bumps.token_mint_authority]
-                                ]],
-                            )?;
-
-                            force_memory_cleanup();
-                            
-                            // Update the reserved tokens value for the upline
-                            upline_account_data.reserved_tokens = adjusted_token_amount;
-                            
-                            // Deposit was reserved, doesn't continue in recursion
-                            current_deposit = 0;
-                        }
-                        // SLOT 3: Pay reserved SOL and tokens to upline
-                        else if upline_slot_idx == 2 {
-                            // Pay reserved SOL
-                            if upline_account_data.reserved_sol > 0 {
-                                let reserved_sol = upline_account_data.reserved_sol;
-                                
-                                // Verify that wallet is a system account
-                                if upline_wallet.owner != &solana_program::system_program::ID {
-                                    return Err(error!(ErrorCode::PaymentWalletInvalid));
-                                }
-                                
-                                // Create the transfer instruction
-                                let ix = solana_program::system_instruction::transfer(
-                                    &ctx.accounts.program_sol_vault.key(),
-                                    &upline_wallet.key(),
-                                    reserved_sol
-                                );
-                                
-                                // Use Vec instead of array to avoid lifetime problems
-                                let mut accounts = Vec::with_capacity(2);
-                                accounts.push(ctx.accounts.program_sol_vault.to_account_info());
-                                accounts.push(upline_wallet.clone());
-                                
-                                // Invoke the instruction with signature
-                                solana_program::program::invoke_signed(
-                                    &ix,
-                                    &accounts,
-                                    &[&[
-                                        b"program_sol_vault".as_ref(),
-                                        &[ctx.bumps.program_sol_vault]
-                                    ]],
-                                ).map_err(|_| error!(ErrorCode::ReferrerPaymentFailed))?;
-                                
-                                // Zero out the reserved SOL value
-                                upline_account_data.reserved_sol = 0;
-                            }
-                            
-                            // Pay reserved tokens
-                            if upline_account_data.reserved_tokens > 0 {
-                                let reserved_tokens = upline_account_data.reserved_tokens;
-                                
-                                // Verify if the token account is valid
-                                if upline_token.owner != &spl_token::id() {
-                                    return Err(error!(ErrorCode::TokenAccountInvalid));
-                                }
-                                
-                                // Transfer tokens using function with individual parameters
-                                process_transfer_tokens(
-                                    &ctx.accounts.program_token_vault.to_account_info(),
-                                    upline_token,
-                                    &ctx.accounts.vault_authority.to_account_info(),
-                                    &ctx.accounts.token_program,
-                                    reserved_tokens,
-                                    &[&[
-                                        b"token_vault_authority".as_ref(),
-                                        &[ctx.bumps.vault_authority]
-                                    ]],
-                                )?;
-                                
-                                // Add cleanup:
-                                force_memory_cleanup();
-                                
-                                // Zero out the reserved tokens value after payment
-                                upline_account_data.reserved_tokens = 0;
-                            }
-                        }
-                        
-                        // Check if matrix is complete
-                        let chain_completed = upline_account_data.chain.filled_slots == 3;
-                        
-                        // Process matrix completion only if necessary
-                        if chain_completed {
-                            // Get new ID for the reset matrix
-                            let next_chain_id_value = state.next_chain_id;
-                            state.next_chain_id += 1;
-                            
-                            // Reset matrix with new ID
-                            upline_account_data.chain.id = next_chain_id_value;
-                            upline_account_data.chain.slots = [None, None, None];
-                            upline_account_data.chain.filled_slots = 0;
-                            
-                            // Update current user for recursion
-                            current_user_pubkey = upline_key;
-                        }
-                        
-                        // STEP 2: Save changes back to the account
-                        {
-                            // New scope for mutable borrowing
-                            let mut data = upline_info.try_borrow_mut_data()?;
-                            let mut write_data = &mut data[8..];
-                            upline_account_data.serialize(&mut write_data)?;
-                        }
-
-                        // Add cleanup:
-                        force_memory_cleanup();
-                        
-                        // If matrix was not completed, stop processing here
-                        if !chain_completed {
-                            break;
-                        }
-                        
-                        // Check maximum depth after processing
-                        if trio_index >= MAX_UPLINE_DEPTH - 1 {
-                            break;
-                        }
-                    }
-                    
-                    // Stop batch processing if no more deposits
-                    if current_deposit == 0 {
-                        break;
-                    }
-                }
+        ctx.accounts.registration.user_wsol_account.reload()?;
+        let deposit_amount = ctx.accounts.registration.user_wsol_account.amount;
+        msg!("Swapped {} tokens for {} lamports (minimum {})", token_amount, deposit_amount, minimum_out_amount);
 
-                // Handle any remaining deposit
-                if current_deposit > 0 {
-                    // Deposit to pool if WSOL is still open
-                    if !wsol_closed {
-                        process_deposit_to_pool(
-                            &ctx.accounts.user_wallet.to_account_info(),
-                            &ctx.accounts.user_wsol_account.to_account_info(),
-                            &ctx.accounts.b_vault_lp.to_account_info(),
-                            &ctx.accounts.b_vault,
-                            &ctx.accounts.b_token_vault.to_account_info(),
-                            &ctx.accounts.b_vault_lp_mint.to_account_info(),
-                            &ctx.accounts.vault_program,
-                            &ctx.accounts.token_program,
-                            current_deposit
-                        )?;
-                    }
-                }
-                
-                // Close WSOL account if still open
-                if !wsol_closed {
-                    let account_info = ctx.accounts.user_wsol_account.to_account_info();
-                    if account_info.data_len() > 0 {
-                        let close_ix = spl_token::instruction::close_account(
-                            &token::ID,
-                            &ctx.accounts.user_wsol_account.key(),
-                            &ctx.accounts.user_wallet.key(),
-                            &ctx.accounts.user_wallet.key(),
-                            &[]
-                        )?;
-                        
-                        let close_accounts = [
-                            ctx.accounts.user_wsol_account.to_account_info(),
-                            ctx.accounts.user_wallet.to_account_info(),
-                            ctx.accounts.user_wallet.to_account_info(),
-                        ];
-                        
-                        solana_program::program::invoke(
-                            &close_ix,
-                            &close_accounts,
-                        ).map_err(|_| error!(ErrorCode::UnwrapSolFailed))?;
-                    }
-                }
-            }
-        }
+        force_memory_cleanup();
 
-        Ok(())
+        process_registration(
+            &mut ctx.accounts.registration,
+            &ctx.bumps.registration,
+            ctx.remaining_accounts,
+            deposit_amount,
+            DepositSource::SwappedToken,
+        )
     }
-}
\ No newline at end of file
+}
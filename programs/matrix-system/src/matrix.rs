@@ -0,0 +1,260 @@
+// Pure state machine of the 3x1 matrix
+// Fills the referrer's matrix and recurses through the uplines on snapshots of the accounts,
+// returning the effects the registration executes: pool deposits, reservations, mints and payouts
+use anchor_lang::prelude::*;
+
+use crate::{ErrorCode, ReferralChain, UserAccount, MAX_UPLINE_DEPTH};
+
+// Number of slots in a matrix
+pub const MATRIX_SLOTS: u8 = 3;
+
+// Matrix owner an effect applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Referrer,       // The direct referrer
+    Upline(usize),  // Index into the upline snapshots, closest upline first
+}
+
+// Matrix fields of a user account
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatrixNode {
+    pub key: Pubkey,             // PDA of the user account
+    pub chain: ReferralChain,
+    pub reserved_sol: u64,       // SOL reserved from the second slot
+    pub reserved_tokens: u64,    // Tokens reserved from the second slot
+    pub expires_at: i64,         // Deadline for filling the matrix (0 = no deadline)
+}
+
+impl MatrixNode {
+    // Snapshot of a user account's matrix
+    pub fn from_account(key: Pubkey, account: &UserAccount) -> Self {
+        Self {
+            key,
+            chain: account.chain.clone(),
+            reserved_sol: account.reserved_sol,
+            reserved_tokens: account.reserved_tokens,
+            expires_at: account.matrix_expires_at,
+        }
+    }
+
+    // Write the snapshot back to the user account
+    pub fn apply_to(&self, account: &mut UserAccount) {
+        account.chain = self.chain.clone();
+        account.reserved_sol = self.reserved_sol;
+        account.reserved_tokens = self.reserved_tokens;
+        account.matrix_expires_at = self.expires_at;
+    }
+}
+
+// Action the registration must execute, in order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    // Send the SOL in the user's WSOL account to the slot-1 destination
+    DepositToPool { amount: u64 },
+    // Unwrap the user's WSOL account back to the user's wallet
+    CloseWsol,
+    // Move SOL from the user's wallet to the program SOL vault, replacing the `previous` reservation
+    ReserveSol { target: Target, amount: u64, previous: u64 },
+    // Mint DONUT to the program token vault for the target's reservation
+    MintTokens { target: Target, amount: u64 },
+    // Pay the target's reserved SOL to its wallet
+    PaySol { target: Target, amount: u64 },
+    // Pay or vest the target's reserved DONUT (also emitted for 0 so the payout account is prepared)
+    PayTokens { target: Target, amount: u64 },
+    // `user` filled a slot of the target's matrix
+    SlotFilled { target: Target, slot_idx: u8, chain_id: u32, user: Pubkey },
+    // The target's matrix completed and restarted with a new ID
+    ResetChain { target: Target, new_chain_id: u32 },
+}
+
+// Result of a deposit through the matrix
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatrixOutcome {
+    pub effects: Vec<Effect>,
+    pub next_chain_id: u32,      // Global chain counter after any resets
+    pub uplines_updated: usize,  // Leading upline snapshots that were modified
+}
+
+// Restart a matrix empty with a new ID
+pub fn reset_chain(chain: &mut ReferralChain, new_chain_id: u32) {
+    chain.id = new_chain_id;
+    chain.slots = [None, None, None];
+    chain.filled_slots = 0;
+}
+
+// Put `user` in the node's next free slot and return the slot index
+fn fill_slot(
+    node: &mut MatrixNode,
+    target: Target,
+    user: Pubkey,
+    expires_at: i64,
+    effects: &mut Vec<Effect>,
+) -> Result<u8> {
+    let slot_idx = node.chain.filled_slots;
+    if slot_idx >= MATRIX_SLOTS {
+        return Err(error!(ErrorCode::ChainFull));
+    }
+
+    node.chain.slots[slot_idx as usize] = Some(user);
+
+    // The deadline starts with the first slot
+    if slot_idx == 0 {
+        node.expires_at = expires_at;
+    }
+
+    node.chain.filled_slots += 1;
+
+    effects.push(Effect::SlotFilled {
+        target,
+        slot_idx,
+        chain_id: node.chain.id,
+        user,
+    });
+
+    Ok(slot_idx)
+}
+
+// Reserve `amount` of SOL for the node and mint its DONUT (slot 2)
+fn reserve(
+    node: &mut MatrixNode,
+    target: Target,
+    amount: u64,
+    token_amount: &mut impl FnMut(u64) -> Result<u64>,
+    effects: &mut Vec<Effect>,
+) -> Result<()> {
+    effects.push(Effect::ReserveSol {
+        target,
+        amount,
+        previous: node.reserved_sol,
+    });
+    node.reserved_sol = amount;
+
+    let tokens = token_amount(amount)?;
+    effects.push(Effect::MintTokens { target, amount: tokens });
+    node.reserved_tokens = tokens;
+
+    Ok(())
+}
+
+// Pay out the node's reservations (slot 3)
+fn pay_out(node: &mut MatrixNode, target: Target, effects: &mut Vec<Effect>) {
+    if node.reserved_sol > 0 {
+        effects.push(Effect::PaySol { target, amount: node.reserved_sol });
+        node.reserved_sol = 0;
+    }
+
+    effects.push(Effect::PayTokens { target, amount: node.reserved_tokens });
+    node.reserved_tokens = 0;
+}
+
+// Reset the node's matrix if it's full; returns whether it completed
+fn complete_if_full(
+    node: &mut MatrixNode,
+    target: Target,
+    next_chain_id: &mut u32,
+    effects: &mut Vec<Effect>,
+) -> bool {
+    if node.chain.filled_slots < MATRIX_SLOTS {
+        return false;
+    }
+
+    let new_chain_id = *next_chain_id;
+    *next_chain_id += 1;
+
+    reset_chain(&mut node.chain, new_chain_id);
+    node.expires_at = 0;
+    effects.push(Effect::ResetChain { target, new_chain_id });
+
+    true
+}
+
+/// Run a registration's deposit through the matrix
+/// - `user`: wallet of the registering user, stored in the referrer's slot
+/// - `uplines`: the referrer's uplines, closest first; only used when the referrer's matrix completes
+/// - `token_amount`: DONUT minted for a slot-2 reservation of the given SOL amount
+///
+/// The snapshots are updated in place; the caller writes them back and executes the effects
+pub fn process_deposit(
+    user: Pubkey,
+    referrer: &mut MatrixNode,
+    uplines: &mut [MatrixNode],
+    deposit: u64,
+    next_chain_id: u32,
+    expires_at: i64,
+    mut token_amount: impl FnMut(u64) -> Result<u64>,
+) -> Result<MatrixOutcome> {
+    let mut effects = Vec::new();
+    let mut next_chain_id = next_chain_id;
+    let mut wsol_open = true;
+
+    // Direct referrer: the user fills the next slot
+    let slot_idx = fill_slot(referrer, Target::Referrer, user, expires_at, &mut effects)?;
+
+    match slot_idx {
+        0 => effects.push(Effect::DepositToPool { amount: deposit }),
+        1 => {
+            effects.push(Effect::CloseWsol);
+            wsol_open = false;
+            reserve(referrer, Target::Referrer, deposit, &mut token_amount, &mut effects)?;
+        },
+        _ => pay_out(referrer, Target::Referrer, &mut effects),
+    }
+
+    let referrer_completed = complete_if_full(referrer, Target::Referrer, &mut next_chain_id, &mut effects);
+
+    // A completed matrix passes the deposit up: each upline's slot is filled by the matrix that completed below it
+    let mut uplines_updated = 0;
+    if referrer_completed && !uplines.is_empty() && deposit > 0 {
+        let mut current_user = referrer.key;
+        let mut current_deposit = deposit;
+
+        for (index, upline) in uplines.iter_mut().enumerate().take(MAX_UPLINE_DEPTH) {
+            if current_deposit == 0 {
+                break;
+            }
+
+            let target = Target::Upline(index);
+            uplines_updated = index + 1;
+
+            let upline_slot_idx = fill_slot(upline, target, current_user, expires_at, &mut effects)?;
+
+            match upline_slot_idx {
+                0 => {
+                    effects.push(Effect::DepositToPool { amount: current_deposit });
+                    current_deposit = 0;
+                },
+                1 => {
+                    if wsol_open {
+                        effects.push(Effect::CloseWsol);
+                        wsol_open = false;
+                    }
+
+                    reserve(upline, target, current_deposit, &mut token_amount, &mut effects)?;
+                    current_deposit = 0;
+                },
+                _ => pay_out(upline, target, &mut effects),
+            }
+
+            if !complete_if_full(upline, target, &mut next_chain_id, &mut effects) {
+                break;
+            }
+
+            current_user = upline.key;
+        }
+
+        // Every visited upline completed: the deposit goes to the slot-1 destination
+        if current_deposit > 0 && wsol_open {
+            effects.push(Effect::DepositToPool { amount: current_deposit });
+        }
+
+        if wsol_open {
+            effects.push(Effect::CloseWsol);
+        }
+    }
+
+    Ok(MatrixOutcome {
+        effects,
+        next_chain_id,
+        uplines_updated,
+    })
+}
@@ -0,0 +1,461 @@
+// Typed client for the Meteora dynamic vault and dynamic AMM programs
+// Account layouts, instruction builders and the vault/pool math used for pricing
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program_pack::Pack;
+
+use crate::verified_addresses;
+
+// Meteora dynamic vault program id
+pub fn vault_program_id() -> Pubkey {
+    verified_addresses::VAULT_PROGRAM
+}
+
+// Meteora dynamic AMM program id
+pub fn amm_program_id() -> Pubkey {
+    verified_addresses::AMM_PROGRAM
+}
+
+// Instruction discriminators: first 8 bytes of sha256("global:<instruction_name>")
+pub const DEPOSIT_DISCRIMINATOR: [u8; 8] = [242, 35, 198, 137, 82, 225, 242, 182];
+pub const WITHDRAW_DISCRIMINATOR: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
+pub const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+// Account discriminators: first 8 bytes of sha256("account:<AccountName>")
+pub const VAULT_ACCOUNT_DISCRIMINATOR: [u8; 8] = [211, 8, 232, 43, 2, 152, 117, 119];
+pub const POOL_ACCOUNT_DISCRIMINATOR: [u8; 8] = [241, 154, 109, 4, 17, 177, 109, 188];
+
+// Denominator of the vault's locked profit degradation rate
+pub const LOCKED_PROFIT_DEGRADATION_DENOMINATOR: u128 = 1_000_000_000_000;
+
+// Precision of the virtual prices returned by the helpers below
+pub const VIRTUAL_PRICE_PRECISION: u128 = 100_000_000;
+
+// Number of strategy slots in a vault
+pub const MAX_STRATEGY: usize = 30;
+
+// ===== ACCOUNTS =====
+
+// Deserialize an Anchor account from raw data, checking its discriminator
+fn deserialize_account<T: AnchorDeserialize>(data: &[u8], discriminator: &[u8; 8]) -> Option<T> {
+    if data.len() < 8 || data[..8] != discriminator[..] {
+        return None;
+    }
+
+    // Only the leading fields described by T are read
+    let mut slice = &data[8..];
+    T::deserialize(&mut slice).ok()
+}
+
+// PDA bumps stored in a vault
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct VaultBumps {
+    pub vault_bump: u8,
+    pub token_vault_bump: u8,
+}
+
+// Profit that is released linearly after each strategy report
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct LockedProfitTracker {
+    pub last_updated_locked_profit: u64,
+    pub last_report: u64,
+    pub locked_profit_degradation: u64,
+}
+
+impl LockedProfitTracker {
+    // Profit still locked at current_time
+    pub fn calculate_locked_profit(&self, current_time: u64) -> Option<u64> {
+        let duration = u128::from(current_time.checked_sub(self.last_report)?);
+        let locked_fund_ratio = duration.checked_mul(u128::from(self.locked_profit_degradation))?;
+
+        if locked_fund_ratio > LOCKED_PROFIT_DEGRADATION_DENOMINATOR {
+            return Some(0);
+        }
+
+        let locked_profit = u128::from(self.last_updated_locked_profit)
+            .checked_mul(LOCKED_PROFIT_DEGRADATION_DENOMINATOR - locked_fund_ratio)?
+            .checked_div(LOCKED_PROFIT_DEGRADATION_DENOMINATOR)?;
+
+        u64::try_from(locked_profit).ok()
+    }
+}
+
+// Meteora dynamic vault account
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct Vault {
+    pub enabled: u8,
+    pub bumps: VaultBumps,
+    pub total_amount: u64,            // Tokens in the vault, including those lent to strategies
+    pub token_vault: Pubkey,
+    pub fee_vault: Pubkey,
+    pub token_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub strategies: [Pubkey; MAX_STRATEGY],
+    pub base: Pubkey,
+    pub admin: Pubkey,
+    pub operator: Pubkey,
+    pub locked_profit_tracker: LockedProfitTracker,
+}
+
+impl Vault {
+    pub fn from_account_data(data: &[u8]) -> Option<Self> {
+        deserialize_account(data, &VAULT_ACCOUNT_DISCRIMINATOR)
+    }
+
+    // Read a vault, requiring it to be owned by the vault program
+    pub fn from_account_info(info: &AccountInfo) -> Option<Self> {
+        if info.owner != &vault_program_id() {
+            return None;
+        }
+
+        Self::from_account_data(&info.try_borrow_data().ok()?)
+    }
+
+    // Tokens that can be withdrawn at current_time (total minus locked profit)
+    pub fn get_unlocked_amount(&self, current_time: u64) -> Option<u64> {
+        self.total_amount.checked_sub(
+            self.locked_profit_tracker.calculate_locked_profit(current_time)?
+        )
+    }
+
+    // Tokens owned by `share` LP tokens out of `total_supply`
+    pub fn get_amount_by_share(&self, current_time: u64, share: u64, total_supply: u64) -> Option<u64> {
+        amount_by_share(self.get_unlocked_amount(current_time)?, share, total_supply)
+    }
+
+    // LP tokens to burn to withdraw `out_token` tokens
+    pub fn get_unmint_amount(&self, current_time: u64, out_token: u64, total_supply: u64) -> Option<u64> {
+        let total_amount = self.get_unlocked_amount(current_time)?;
+
+        let unmint_amount = u128::from(out_token)
+            .checked_mul(u128::from(total_supply))?
+            .checked_div(u128::from(total_amount))?;
+
+        u64::try_from(unmint_amount).ok()
+    }
+
+    // Value of one vault LP token, scaled by VIRTUAL_PRICE_PRECISION
+    pub fn virtual_price(&self, current_time: u64, lp_supply: u64) -> Option<u128> {
+        if lp_supply == 0 {
+            return None;
+        }
+
+        u128::from(self.get_unlocked_amount(current_time)?)
+            .checked_mul(VIRTUAL_PRICE_PRECISION)?
+            .checked_div(u128::from(lp_supply))
+    }
+}
+
+// Trade fees stored in a pool
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct PoolFees {
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub protocol_trade_fee_numerator: u64,
+    pub protocol_trade_fee_denominator: u64,
+}
+
+impl PoolFees {
+    // Checks that the trade fee is below 100% and has a non-zero denominator
+    pub fn is_valid(&self) -> bool {
+        self.trade_fee_denominator != 0 && self.trade_fee_numerator < self.trade_fee_denominator
+    }
+
+    // Trade fee charged on an input amount
+    pub fn trading_fee(&self, amount: u128) -> Option<u128> {
+        if self.trade_fee_denominator == 0 {
+            return None;
+        }
+
+        amount
+            .checked_mul(u128::from(self.trade_fee_numerator))?
+            .checked_div(u128::from(self.trade_fee_denominator))
+    }
+
+    // Part of the trade fee kept by the protocol
+    pub fn protocol_trading_fee(&self, trading_fee: u128) -> Option<u128> {
+        if self.protocol_trade_fee_denominator == 0 {
+            return Some(0);
+        }
+
+        trading_fee
+            .checked_mul(u128::from(self.protocol_trade_fee_numerator))?
+            .checked_div(u128::from(self.protocol_trade_fee_denominator))
+    }
+}
+
+// Meteora dynamic AMM pool account
+// Only the leading fields are described; the rest of the account isn't needed here
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct Pool {
+    pub lp_mint: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub a_vault: Pubkey,
+    pub b_vault: Pubkey,
+    pub a_vault_lp: Pubkey,          // Pool's LP token account in vault A
+    pub b_vault_lp: Pubkey,          // Pool's LP token account in vault B
+    pub a_vault_lp_bump: u8,
+    pub enabled: bool,
+    pub protocol_token_a_fee: Pubkey,
+    pub protocol_token_b_fee: Pubkey,
+    pub fee_last_updated_at: u64,
+    pub padding0: [u8; 24],
+    pub fees: PoolFees,
+}
+
+impl Pool {
+    pub fn from_account_data(data: &[u8]) -> Option<Self> {
+        deserialize_account(data, &POOL_ACCOUNT_DISCRIMINATOR)
+    }
+
+    // Read a pool, requiring it to be owned by the AMM program
+    pub fn from_account_info(info: &AccountInfo) -> Option<Self> {
+        if info.owner != &amm_program_id() {
+            return None;
+        }
+
+        Self::from_account_data(&info.try_borrow_data().ok()?)
+    }
+}
+
+// A pool's LP position in one vault: LP tokens held and the LP mint supply
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LpPosition {
+    pub amount: u64,
+    pub supply: u64,
+}
+
+impl LpPosition {
+    // Read the position from the pool's LP token account and the vault LP mint
+    pub fn from_accounts(lp_token_account: &AccountInfo, lp_mint: &AccountInfo) -> Option<Self> {
+        Some(LpPosition {
+            amount: token_account_amount(lp_token_account)?,
+            supply: mint_supply(lp_mint)?,
+        })
+    }
+
+    // Vault tokens owned by this position out of the vault's total
+    pub fn token_amount(&self, vault_total_amount: u64) -> Option<u64> {
+        amount_by_share(vault_total_amount, self.amount, self.supply)
+    }
+}
+
+// Amount held by an SPL token account
+pub fn token_account_amount(info: &AccountInfo) -> Option<u64> {
+    let data = info.try_borrow_data().ok()?;
+    spl_token::state::Account::unpack(&data).ok().map(|account| account.amount)
+}
+
+// Supply of an SPL token mint
+pub fn mint_supply(info: &AccountInfo) -> Option<u64> {
+    let data = info.try_borrow_data().ok()?;
+    spl_token::state::Mint::unpack(&data).ok().map(|mint| mint.supply)
+}
+
+// ===== MATH =====
+
+// Tokens owned by `share` LP tokens: total_amount * share / total_supply
+pub fn amount_by_share(total_amount: u64, share: u64, total_supply: u64) -> Option<u64> {
+    if total_supply == 0 {
+        return None;
+    }
+
+    let amount = u128::from(total_amount)
+        .checked_mul(u128::from(share))?
+        .checked_div(u128::from(total_supply))?;
+
+    u64::try_from(amount).ok()
+}
+
+// Output of a constant-product swap after the trade fee, computed in u128 fixed point.
+// The fee is taken from the input, then the destination reserve is reduced to
+// ceil(invariant / new_source_reserve), keeping the rounding in favour of the pool.
+pub fn swap_output(
+    source_reserve: u64,
+    destination_reserve: u64,
+    amount_in: u64,
+    trade_fee_numerator: u64,
+    trade_fee_denominator: u64,
+) -> Option<u64> {
+    if trade_fee_denominator == 0 || trade_fee_numerator >= trade_fee_denominator {
+        return None;
+    }
+
+    let amount_in = u128::from(amount_in);
+    let trade_fee = amount_in
+        .checked_mul(u128::from(trade_fee_numerator))?
+        .checked_div(u128::from(trade_fee_denominator))?;
+    let amount_in_after_fee = amount_in.checked_sub(trade_fee)?;
+
+    let source_reserve = u128::from(source_reserve);
+    let destination_reserve = u128::from(destination_reserve);
+
+    let invariant = source_reserve.checked_mul(destination_reserve)?;
+    let new_source_reserve = source_reserve.checked_add(amount_in_after_fee)?;
+    if new_source_reserve == 0 {
+        return None;
+    }
+
+    let new_destination_reserve = invariant
+        .checked_add(new_source_reserve - 1)?
+        .checked_div(new_source_reserve)?;
+
+    let amount_out = destination_reserve.checked_sub(new_destination_reserve)?;
+
+    u64::try_from(amount_out).ok()
+}
+
+// Integer square root (floor) using Newton's method
+pub fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+
+    let mut x = value;
+    let mut y = x / 2 + x % 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+// Virtual price of a constant-product pool LP token: sqrt(a * b) / lp_supply,
+// scaled by VIRTUAL_PRICE_PRECISION
+pub fn pool_virtual_price(token_a_amount: u64, token_b_amount: u64, lp_supply: u64) -> Option<u128> {
+    if lp_supply == 0 {
+        return None;
+    }
+
+    let invariant_d = integer_sqrt(u128::from(token_a_amount).checked_mul(u128::from(token_b_amount))?);
+
+    invariant_d
+        .checked_mul(VIRTUAL_PRICE_PRECISION)?
+        .checked_div(u128::from(lp_supply))
+}
+
+// ===== INSTRUCTIONS =====
+
+// Serialize instruction data as discriminator + borsh arguments
+fn instruction_data<T: AnchorSerialize>(discriminator: [u8; 8], args: &T) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + std::mem::size_of::<T>());
+    data.extend_from_slice(&discriminator);
+    // Serializing plain integer structs into a Vec can't fail
+    args.serialize(&mut data).unwrap();
+    data
+}
+
+// Accounts of the vault `deposit` and `withdraw` instructions, in program order
+pub struct VaultUserAccounts {
+    pub vault: Pubkey,          // writable
+    pub token_vault: Pubkey,    // writable
+    pub lp_mint: Pubkey,        // writable
+    pub user_token: Pubkey,     // writable
+    pub user_lp: Pubkey,        // writable
+    pub user: Pubkey,           // signer
+    pub token_program: Pubkey,
+}
+
+impl VaultUserAccounts {
+    pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.vault, false),
+            AccountMeta::new(self.token_vault, false),
+            AccountMeta::new(self.lp_mint, false),
+            AccountMeta::new(self.user_token, false),
+            AccountMeta::new(self.user_lp, false),
+            AccountMeta::new_readonly(self.user, true),
+            AccountMeta::new_readonly(self.token_program, false),
+        ]
+    }
+}
+
+// Arguments of the vault `deposit` instruction
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct DepositArgs {
+    pub token_amount: u64,
+    pub minimum_lp_token_amount: u64,
+}
+
+// Arguments of the vault `withdraw` instruction
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct WithdrawArgs {
+    pub unmint_amount: u64,
+    pub min_out_amount: u64,
+}
+
+// Build the vault `deposit` instruction
+pub fn deposit(accounts: &VaultUserAccounts, args: DepositArgs) -> Instruction {
+    Instruction {
+        program_id: vault_program_id(),
+        accounts: accounts.to_account_metas(),
+        data: instruction_data(DEPOSIT_DISCRIMINATOR, &args),
+    }
+}
+
+// Build the vault `withdraw` instruction
+pub fn withdraw(accounts: &VaultUserAccounts, args: WithdrawArgs) -> Instruction {
+    Instruction {
+        program_id: vault_program_id(),
+        accounts: accounts.to_account_metas(),
+        data: instruction_data(WITHDRAW_DISCRIMINATOR, &args),
+    }
+}
+
+// Accounts of the AMM `swap` instruction, in program order
+pub struct SwapAccounts {
+    pub pool: Pubkey,                    // writable
+    pub user_source_token: Pubkey,       // writable
+    pub user_destination_token: Pubkey,  // writable
+    pub a_vault: Pubkey,                 // writable
+    pub b_vault: Pubkey,                 // writable
+    pub a_token_vault: Pubkey,           // writable
+    pub b_token_vault: Pubkey,           // writable
+    pub a_vault_lp_mint: Pubkey,         // writable
+    pub b_vault_lp_mint: Pubkey,         // writable
+    pub a_vault_lp: Pubkey,              // writable
+    pub b_vault_lp: Pubkey,              // writable
+    pub protocol_token_fee: Pubkey,      // writable
+    pub user: Pubkey,                    // signer
+    pub vault_program: Pubkey,
+    pub token_program: Pubkey,
+}
+
+impl SwapAccounts {
+    pub fn to_account_metas(&self) -> Vec<AccountMeta> {
+        vec![
+            AccountMeta::new(self.pool, false),
+            AccountMeta::new(self.user_source_token, false),
+            AccountMeta::new(self.user_destination_token, false),
+            AccountMeta::new(self.a_vault, false),
+            AccountMeta::new(self.b_vault, false),
+            AccountMeta::new(self.a_token_vault, false),
+            AccountMeta::new(self.b_token_vault, false),
+            AccountMeta::new(self.a_vault_lp_mint, false),
+            AccountMeta::new(self.b_vault_lp_mint, false),
+            AccountMeta::new(self.a_vault_lp, false),
+            AccountMeta::new(self.b_vault_lp, false),
+            AccountMeta::new(self.protocol_token_fee, false),
+            AccountMeta::new_readonly(self.user, true),
+            AccountMeta::new_readonly(self.vault_program, false),
+            AccountMeta::new_readonly(self.token_program, false),
+        ]
+    }
+}
+
+// Arguments of the AMM `swap` instruction
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct SwapArgs {
+    pub in_amount: u64,
+    pub minimum_out_amount: u64,
+}
+
+// Build the AMM `swap` instruction
+pub fn swap(accounts: &SwapAccounts, args: SwapArgs) -> Instruction {
+    Instruction {
+        program_id: amm_program_id(),
+        accounts: accounts.to_account_metas(),
+        data: instruction_data(SWAP_DISCRIMINATOR, &args),
+    }
+}
@@ -0,0 +1,235 @@
+// buyback: slot-1 SOL swapped for DONUT through the pool under each Slot1Policy, and its configuration
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use anchor_lang::solana_program::program_option::COption;
+use common::*;
+use matrix_system::{accounts, instruction, verified_addresses, ErrorCode, ProgramState, Slot1Policy, MAX_SWAP_SLIPPAGE_BPS};
+use solana_sdk::{instruction::Instruction, signature::Signer};
+
+// Buyback under `policy`, with the mint supply covering the pool's DONUT so it can be burned
+async fn buyback_env(policy: Slot1Policy, buyback_slippage_bps: u16) -> TestEnv {
+    let mut env = TestEnv::new().await;
+    env.set_slot1_policy(policy, buyback_slippage_bps).await.unwrap();
+    env.set_account(
+        &verified_addresses::TOKEN_MINT,
+        mint_account(COption::Some(token_mint_authority()), POOL_DONUT_RESERVE),
+    );
+
+    let treasury = env.treasury.pubkey();
+    env.set_account(&donut_ata(&treasury), token_account(&verified_addresses::TOKEN_MINT, &treasury, 0));
+    env
+}
+
+// Slot-1 registration under the root carrying every buyback account (vault A is among the remaining accounts)
+async fn buyback_registration(env: &mut TestEnv, user: &Pubkey) -> Registration {
+    let root = env.root.pubkey();
+    let treasury = env.treasury.pubkey();
+    let mut registration = env.registration(user, &root, DEPOSIT).await;
+    registration.accounts.protocol_token_b_fee = Some(protocol_token_b_fee());
+    registration.accounts.treasury_token_account = Some(donut_ata(&treasury));
+    registration.accounts.amm_program = Some(verified_addresses::AMM_PROGRAM);
+    registration
+}
+
+// DONUT the mock pool pays for the deposit at its current balances
+async fn expected_buyback(env: &mut TestEnv) -> u64 {
+    let sol_reserve = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+    let donut_reserve = env.token_balance(&verified_addresses::A_TOKEN_VAULT).await;
+    spot_donut_output(sol_reserve, donut_reserve, DEPOSIT)
+}
+
+async fn assert_buyback_fails(
+    policy: Slot1Policy,
+    edit: impl FnOnce(&mut Registration),
+    error: ErrorCode,
+) {
+    let mut env = buyback_env(policy, MAX_SWAP_SLIPPAGE_BPS).await;
+    let user = env.create_wallet();
+    let mut registration = buyback_registration(&mut env, &user.pubkey()).await;
+    edit(&mut registration);
+
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, error);
+}
+
+#[tokio::test]
+async fn buyback_and_burn_burns_the_swapped_donut() {
+    let mut env = buyback_env(Slot1Policy::BuybackAndBurn, MAX_SWAP_SLIPPAGE_BPS).await;
+    let expected = expected_buyback(&mut env).await;
+    let sol_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let user = env.create_wallet();
+    let registration = buyback_registration(&mut env, &user.pubkey()).await;
+    let (_, logs) = env.send_profiled(&[registration.instruction()], &[&user]).await.unwrap();
+
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, sol_before + DEPOSIT);
+    assert_eq!(env.token_balance(&verified_addresses::A_TOKEN_VAULT).await, POOL_DONUT_RESERVE - expected);
+    assert_eq!(env.mint_supply(&verified_addresses::TOKEN_MINT).await, POOL_DONUT_RESERVE - expected);
+    assert_eq!(env.token_balance(&program_token_vault()).await, 0);
+    let treasury = env.treasury.pubkey();
+    assert_eq!(env.token_balance(&donut_ata(&treasury)).await, 0);
+
+    let message = format!("Slot-1 buyback: {} lamports for {} DONUT", DEPOSIT, expected);
+    assert!(logs.iter().any(|log| log.ends_with(&message)));
+}
+
+#[tokio::test]
+async fn buyback_to_treasury_sends_the_swapped_donut() {
+    let mut env = buyback_env(Slot1Policy::BuybackToTreasury, MAX_SWAP_SLIPPAGE_BPS).await;
+    let expected = expected_buyback(&mut env).await;
+
+    let user = env.create_wallet();
+    let registration = buyback_registration(&mut env, &user.pubkey()).await;
+    env.send_registration(&user, &registration).await.unwrap();
+
+    let treasury = env.treasury.pubkey();
+    assert_eq!(env.token_balance(&donut_ata(&treasury)).await, expected);
+    assert_eq!(env.mint_supply(&verified_addresses::TOKEN_MINT).await, POOL_DONUT_RESERVE);
+    let root = env.root.pubkey();
+    assert_eq!(env.user_account(&root).await.chain.filled_slots, 1);
+}
+
+#[tokio::test]
+async fn deposit_liquidity_ignores_the_buyback_accounts() {
+    let mut env = TestEnv::new().await;
+    let sol_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let user = env.create_wallet();
+    let registration = buyback_registration(&mut env, &user.pubkey()).await;
+    env.send_registration(&user, &registration).await.unwrap();
+
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, sol_before + DEPOSIT);
+    assert_eq!(env.token_balance(&verified_addresses::A_TOKEN_VAULT).await, POOL_DONUT_RESERVE);
+}
+
+#[tokio::test]
+async fn rejects_swap_below_the_slippage_minimum() {
+    // The minimum is the TWAP output, which the swap's own price impact already falls short of
+    let mut env = buyback_env(Slot1Policy::BuybackAndBurn, 0).await;
+    assert!(expected_buyback(&mut env).await < twap_donut_output(DEPOSIT));
+
+    let user = env.create_wallet();
+    let registration = buyback_registration(&mut env, &user.pubkey()).await;
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, ErrorCode::TokenSwapFailed);
+
+    // 0.2 SOL into 1,000 SOL moves the price by 2 basis points, well inside 10
+    env.set_slot1_policy(Slot1Policy::BuybackAndBurn, 10).await.unwrap();
+    let user = env.create_wallet();
+    let registration = buyback_registration(&mut env, &user.pubkey()).await;
+    env.send_registration(&user, &registration).await.unwrap();
+}
+
+#[tokio::test]
+async fn buyback_without_a_twap_deposits_to_the_pool() {
+    let mut env = buyback_env(Slot1Policy::BuybackAndBurn, MAX_SWAP_SLIPPAGE_BPS).await;
+    let state = unsampled_program_state(&env.owner.pubkey(), &env.treasury.pubkey());
+    let state = ProgramState { slot1_policy: Slot1Policy::BuybackAndBurn, ..state };
+    let address = env.state;
+    env.set_account(&address, anchor_account(&state, 8 + ProgramState::SIZE));
+
+    // Half the DONUT drained from the pool, so a swap at the spot price pays half the TWAP output
+    let donut_reserve = POOL_DONUT_RESERVE / 2;
+    env.set_account(
+        &verified_addresses::A_TOKEN_VAULT,
+        token_account(&verified_addresses::TOKEN_MINT, &mocks::swap_vault(&verified_addresses::TOKEN_MINT), donut_reserve),
+    );
+    env.set_account(
+        &mocks::swap_vault(&verified_addresses::TOKEN_MINT),
+        vault_account(
+            &verified_addresses::A_TOKEN_VAULT,
+            &verified_addresses::TOKEN_MINT,
+            &verified_addresses::A_VAULT_LP_MINT,
+            donut_reserve,
+        ),
+    );
+    let sol_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let user = env.create_wallet();
+    let registration = buyback_registration(&mut env, &user.pubkey()).await;
+    let (_, logs) = env.send_profiled(&[registration.instruction()], &[&user]).await.unwrap();
+
+    // The SOL goes to vault B as liquidity and no DONUT is swapped out or burned
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, sol_before + DEPOSIT);
+    assert_eq!(env.token_balance(&verified_addresses::A_TOKEN_VAULT).await, donut_reserve);
+    assert_eq!(env.mint_supply(&verified_addresses::TOKEN_MINT).await, POOL_DONUT_RESERVE);
+    assert!(logs.iter().any(|log| log.ends_with("TWAP unavailable, depositing slot-1 SOL to the pool")));
+}
+
+#[tokio::test]
+async fn rejects_missing_buyback_accounts() {
+    assert_buyback_fails(
+        Slot1Policy::BuybackAndBurn,
+        |r| r.accounts.protocol_token_b_fee = None,
+        ErrorCode::MissingBuybackAccounts,
+    ).await;
+    assert_buyback_fails(Slot1Policy::BuybackAndBurn, |r| r.accounts.amm_program = None, ErrorCode::MissingBuybackAccounts).await;
+    assert_buyback_fails(
+        Slot1Policy::BuybackToTreasury,
+        |r| r.accounts.treasury_token_account = None,
+        ErrorCode::MissingBuybackAccounts,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_buyback_accounts() {
+    assert_buyback_fails(
+        Slot1Policy::BuybackAndBurn,
+        |r| r.remaining_accounts[3] = AccountMeta::new(Pubkey::new_unique(), false),
+        ErrorCode::InvalidVaultAddress,
+    ).await;
+    assert_buyback_fails(
+        Slot1Policy::BuybackAndBurn,
+        |r| r.accounts.protocol_token_b_fee = Some(Pubkey::new_unique()),
+        ErrorCode::InvalidProtocolFeeAddress,
+    ).await;
+    assert_buyback_fails(
+        Slot1Policy::BuybackAndBurn,
+        |r| r.accounts.amm_program = Some(verified_addresses::VAULT_PROGRAM),
+        ErrorCode::InvalidAmmProgram,
+    ).await;
+    assert_buyback_fails(
+        Slot1Policy::BuybackToTreasury,
+        |r| r.accounts.treasury_token_account = Some(donut_ata(&Pubkey::new_unique())),
+        ErrorCode::TokenAccountNotCanonical,
+    ).await;
+}
+
+#[tokio::test]
+async fn policy_update_is_recorded() {
+    let mut env = TestEnv::new().await;
+    env.set_slot1_policy(Slot1Policy::BuybackToTreasury, MAX_SWAP_SLIPPAGE_BPS).await.unwrap();
+
+    let state = env.program_state().await;
+    assert_eq!(state.slot1_policy, Slot1Policy::BuybackToTreasury);
+    assert_eq!(state.buyback_slippage_bps, MAX_SWAP_SLIPPAGE_BPS);
+
+    let result = env.set_slot1_policy(Slot1Policy::BuybackAndBurn, MAX_SWAP_SLIPPAGE_BPS + 1).await;
+    assert_program_error(result, ErrorCode::InvalidSlippage);
+    assert_eq!(env.program_state().await.slot1_policy, Slot1Policy::BuybackToTreasury);
+}
+
+#[tokio::test]
+async fn rejects_policy_update_by_other_than_the_treasury() {
+    let mut env = TestEnv::new().await;
+    let owner = env.owner.insecure_clone();
+
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::UpdateConfig {
+            state: env.state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: instruction::SetSlot1Policy {
+            policy: Slot1Policy::BuybackAndBurn,
+            buyback_slippage_bps: 0,
+        }
+        .data(),
+    };
+
+    let result = env.send(&[ix], &[&owner]).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+    assert_eq!(env.program_state().await.slot1_policy, Slot1Policy::DepositLiquidity);
+}
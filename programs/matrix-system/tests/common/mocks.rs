@@ -0,0 +1,202 @@
+// Local stand-ins for the Meteora vault, Meteora AMM and Chainlink store programs
+// They are registered at the verified addresses, so the program under test runs unchanged
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed, set_return_data},
+    program_pack::Pack,
+};
+use matrix_system::meteora;
+
+// Trade fee charged by the mock AMM: 0.25%
+pub const SWAP_FEE_NUMERATOR: u64 = 25;
+pub const SWAP_FEE_DENOMINATOR: u64 = 10_000;
+
+// Error returned by the mock AMM when the output is below minimum_out_amount
+pub const SWAP_SLIPPAGE_ERROR: u32 = 6_000;
+
+// First 8 bytes of sha256("global:query"), sent by chainlink_solana before the query scope
+pub const CHAINLINK_QUERY_DISCRIMINATOR: [u8; 8] = [0x27, 0xfb, 0x82, 0x9f, 0x2e, 0x88, 0xa4, 0xa9];
+
+// Borsh tags of the chainlink_solana Query scopes answered by the mock
+const QUERY_DECIMALS: u8 = 1;
+const QUERY_LATEST_ROUND_DATA: u8 = 4;
+
+// Data stored in the mock SOL/USD feed account
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct MockFeed {
+    pub answer: i128,      // Price with `decimals` decimals
+    pub decimals: u8,
+    pub timestamp: u32,    // Unix time of the round
+}
+
+// Round returned by the Chainlink store, in the layout chainlink_solana deserializes
+#[derive(AnchorSerialize, AnchorDeserialize)]
+struct Round {
+    round_id: u32,
+    slot: u64,
+    timestamp: u32,
+    answer: i128,
+}
+
+// Meteora vault `deposit`: moves the tokens from the user into the vault's token account
+// and adds them to the vault's total_amount
+// LP tokens aren't minted, so the deposit accrues to the existing LP holders (the pool)
+pub fn process_vault_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.len() < 8 || data[..8] != meteora::DEPOSIT_DISCRIMINATOR {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let args = meteora::DepositArgs::try_from_slice(&data[8..])?;
+
+    let [vault, token_vault, _lp_mint, user_token, _user_lp, user, token_program] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !user.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let transfer_ix = spl_token::instruction::transfer(
+        token_program.key,
+        user_token.key,
+        token_vault.key,
+        user.key,
+        &[],
+        args.token_amount,
+    )?;
+
+    invoke(
+        &transfer_ix,
+        &[user_token.clone(), token_vault.clone(), user.clone(), token_program.clone()],
+    )?;
+
+    let mut vault_state = meteora::Vault::from_account_data(&vault.try_borrow_data()?)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    vault_state.total_amount = vault_state
+        .total_amount
+        .checked_add(args.token_amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    vault_state.serialize(&mut &mut vault.try_borrow_mut_data()?[8..])?;
+
+    Ok(())
+}
+
+// Vault of `mint` in the mock AMM, the authority of the pool token account holding it
+pub fn swap_vault(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"vault", mint.as_ref()], &matrix_system::verified_addresses::AMM_PROGRAM).0
+}
+
+fn token_state(info: &AccountInfo) -> std::result::Result<spl_token::state::Account, ProgramError> {
+    spl_token::state::Account::unpack(&info.try_borrow_data()?)
+}
+
+// Meteora AMM `swap`: constant-product swap between the pool's two token vaults
+// Each token vault is owned by the swap_vault PDA of its mint, which signs the payout
+// The vault accounts belong to the vault program, so their total_amount isn't updated here
+pub fn process_amm_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.len() < 8 || data[..8] != meteora::SWAP_DISCRIMINATOR {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let args = meteora::SwapArgs::try_from_slice(&data[8..])?;
+
+    let [_pool, user_source, user_destination, a_vault, b_vault, a_token_vault, b_token_vault, _a_lp_mint, _b_lp_mint, _a_lp, _b_lp, _protocol_fee, user, _vault_program, token_program] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !user.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let source_mint = token_state(user_source)?.mint;
+    let (in_token_vault, out_vault, out_token_vault) = if token_state(a_token_vault)?.mint == source_mint {
+        (a_token_vault, b_vault, b_token_vault)
+    } else {
+        (b_token_vault, a_vault, a_token_vault)
+    };
+
+    let out_mint = token_state(out_token_vault)?.mint;
+    let (vault_address, bump) = Pubkey::find_program_address(&[b"vault", out_mint.as_ref()], program_id);
+    if *out_vault.key != vault_address {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let out_amount = meteora::swap_output(
+        token_state(in_token_vault)?.amount,
+        token_state(out_token_vault)?.amount,
+        args.in_amount,
+        SWAP_FEE_NUMERATOR,
+        SWAP_FEE_DENOMINATOR,
+    )
+    .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    if out_amount < args.minimum_out_amount {
+        return Err(ProgramError::Custom(SWAP_SLIPPAGE_ERROR));
+    }
+
+    let transfer_in = spl_token::instruction::transfer(
+        token_program.key,
+        user_source.key,
+        in_token_vault.key,
+        user.key,
+        &[],
+        args.in_amount,
+    )?;
+    invoke(
+        &transfer_in,
+        &[user_source.clone(), in_token_vault.clone(), user.clone(), token_program.clone()],
+    )?;
+
+    let transfer_out = spl_token::instruction::transfer(
+        token_program.key,
+        out_token_vault.key,
+        user_destination.key,
+        out_vault.key,
+        &[],
+        out_amount,
+    )?;
+    invoke_signed(
+        &transfer_out,
+        &[out_token_vault.clone(), user_destination.clone(), out_vault.clone(), token_program.clone()],
+        &[&[b"vault", out_mint.as_ref(), &[bump]]],
+    )
+}
+
+// Chainlink store `query`: answers `decimals` and `latest_round_data` from the feed's MockFeed
+pub fn process_chainlink_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    if data.len() < 9 || data[..8] != CHAINLINK_QUERY_DISCRIMINATOR {
+        return Err(ProgramError::InvalidInstructionData);
+    }
+
+    let feed_info = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let feed = MockFeed::try_from_slice(&feed_info.try_borrow_data()?)?;
+
+    match data[8] {
+        QUERY_DECIMALS => set_return_data(&[feed.decimals]),
+        QUERY_LATEST_ROUND_DATA => {
+            let round = Round {
+                round_id: 1,
+                slot: 0,
+                timestamp: feed.timestamp,
+                answer: feed.answer,
+            };
+            set_return_data(&round.try_to_vec()?);
+        },
+        _ => return Err(ProgramError::InvalidInstructionData),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,996 @@
+// Shared harness for the program-test suites
+// Loads the program natively next to the Meteora vault, Meteora AMM and Chainlink stand-ins,
+// with every fixed address (pool, vaults, mints, feed) pre-seeded so registrations run fully offline
+#![allow(dead_code)]
+
+pub mod mocks;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program_option::COption,
+    program_pack::Pack,
+    system_instruction, sysvar,
+};
+use anchor_lang::{AccountDeserialize, AccountSerialize, InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::get_associated_token_address;
+use matrix_system::{
+    accounts, instruction, meteora, record_price_observation, verified_addresses, ErrorCode, ExpiryPolicy,
+    MintBudget, PoolReserves, ProgramState, Slot1Policy, UserAccount, MIN_TWAP_OBSERVATIONS, PRICE_SCALE,
+    STATE_LAYOUT_VERSION,
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    compute_budget::ComputeBudgetInstruction,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+use mocks::MockFeed;
+
+// Default registration deposit: 0.2 SOL, twice the minimum at the default $100 price
+pub const DEPOSIT: u64 = 200_000_000;
+
+// SOL/USD price written to the feed (8 decimals)
+pub const SOL_PRICE: i128 = 100_00000000;
+
+// Pool reserves: 1,000,000 DONUT against 1,000 SOL (both 9 decimals)
+pub const POOL_DONUT_RESERVE: u64 = 1_000_000_000_000_000;
+pub const POOL_SOL_RESERVE: u64 = 1_000_000_000_000;
+
+// Pool trade fee: 0.25%
+pub const TRADE_FEE_NUMERATOR: u64 = 25;
+pub const TRADE_FEE_DENOMINATOR: u64 = 10_000;
+
+// LP supply of each vault, all of it held by the pool
+pub const VAULT_LP_SUPPLY: u64 = 1_000_000_000;
+
+// Mint budget used by the suite, wide enough for every scenario
+pub const EPOCH_DURATION: i64 = 86_400;
+pub const MAX_TOKENS_PER_EPOCH: u64 = 1_000_000_000_000_000;
+pub const MAX_TOKENS_PER_USD: u64 = 100_000_000_000;
+
+// Lamports given to each test wallet
+pub const WALLET_LAMPORTS: u64 = 10_000_000_000;
+
+// Anchor's entry ties the account slice to the account lifetimes, which processor! can't express
+fn process_matrix_instruction<'a, 'b, 'c, 'd>(
+    program_id: &'a Pubkey,
+    accounts: &'b [AccountInfo<'c>],
+    data: &'d [u8],
+) -> ProgramResult {
+    let accounts: &'c [AccountInfo<'c>] = Box::leak(accounts.to_vec().into_boxed_slice());
+    matrix_system::entry(program_id, accounts, data)
+}
+
+// ===== PDAS =====
+
+pub fn user_pda(wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"user_account", wallet.as_ref()], &matrix_system::ID).0
+}
+
+pub fn program_sol_vault() -> Pubkey {
+    Pubkey::find_program_address(&[b"program_sol_vault"], &matrix_system::ID).0
+}
+
+pub fn mint_budget_pda() -> Pubkey {
+    Pubkey::find_program_address(&[b"mint_budget"], &matrix_system::ID).0
+}
+
+pub fn token_mint_authority() -> Pubkey {
+    Pubkey::find_program_address(&[b"token_mint_authority"], &matrix_system::ID).0
+}
+
+pub fn vault_authority() -> Pubkey {
+    Pubkey::find_program_address(&[b"token_vault_authority"], &matrix_system::ID).0
+}
+
+pub fn vesting_pda(wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"vesting", wallet.as_ref()], &matrix_system::ID).0
+}
+
+// DONUT ATA of a wallet
+pub fn donut_ata(wallet: &Pubkey) -> Pubkey {
+    get_associated_token_address(wallet, &verified_addresses::TOKEN_MINT)
+}
+
+// WSOL ATA of a wallet
+pub fn wsol_ata(wallet: &Pubkey) -> Pubkey {
+    get_associated_token_address(wallet, &verified_addresses::WSOL_MINT)
+}
+
+pub fn program_token_vault() -> Pubkey {
+    donut_ata(&vault_authority())
+}
+
+// ===== ACCOUNT BUILDERS =====
+
+fn rent_exempt(len: usize) -> u64 {
+    Rent::default().minimum_balance(len)
+}
+
+// Program-owned Anchor account with its discriminator
+pub fn anchor_account<T: AccountSerialize>(value: &T, space: usize) -> Account {
+    let mut data = Vec::with_capacity(space);
+    value.try_serialize(&mut data).unwrap();
+    data.resize(space, 0);
+
+    Account {
+        lamports: rent_exempt(space),
+        data,
+        owner: matrix_system::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+pub fn mint_account(mint_authority: COption<Pubkey>, supply: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        mint_authority,
+        supply,
+        decimals: 9,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    }
+    .pack_into_slice(&mut data);
+
+    Account {
+        lamports: rent_exempt(data.len()),
+        data,
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+pub fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }
+    .pack_into_slice(&mut data);
+
+    Account {
+        lamports: rent_exempt(data.len()),
+        data,
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// WSOL account whose amount is backed by lamports, as spl-token expects for native transfers
+pub fn native_token_account(owner: &Pubkey, amount: u64) -> Account {
+    let rent = rent_exempt(spl_token::state::Account::LEN);
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: verified_addresses::WSOL_MINT,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        is_native: COption::Some(rent),
+        ..Default::default()
+    }
+    .pack_into_slice(&mut data);
+
+    Account {
+        lamports: rent + amount,
+        data,
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+pub fn system_account(lamports: u64) -> Account {
+    Account {
+        lamports,
+        data: vec![],
+        owner: solana_sdk::system_program::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+pub fn feed_account(feed: MockFeed) -> Account {
+    Account {
+        lamports: rent_exempt(64),
+        data: feed.try_to_vec().unwrap(),
+        owner: verified_addresses::CHAINLINK_PROGRAM,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// Meteora vault holding `total_amount` tokens of `token_mint` in `token_vault`, with no locked profit
+pub fn vault_account(token_vault: &Pubkey, token_mint: &Pubkey, lp_mint: &Pubkey, total_amount: u64) -> Account {
+    let vault = meteora::Vault {
+        enabled: 1,
+        bumps: meteora::VaultBumps::default(),
+        total_amount,
+        token_vault: *token_vault,
+        fee_vault: Pubkey::new_unique(),
+        token_mint: *token_mint,
+        lp_mint: *lp_mint,
+        strategies: [Pubkey::default(); meteora::MAX_STRATEGY],
+        base: Pubkey::new_unique(),
+        admin: Pubkey::new_unique(),
+        operator: Pubkey::new_unique(),
+        locked_profit_tracker: meteora::LockedProfitTracker::default(),
+    };
+
+    let mut data = meteora::VAULT_ACCOUNT_DISCRIMINATOR.to_vec();
+    vault.serialize(&mut data).unwrap();
+
+    Account {
+        lamports: rent_exempt(data.len()),
+        data,
+        owner: verified_addresses::VAULT_PROGRAM,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// Protocol fee account of the pool for token B (WSOL)
+pub fn protocol_token_b_fee() -> Pubkey {
+    Pubkey::find_program_address(&[b"protocol_fee", verified_addresses::WSOL_MINT.as_ref()], &meteora::amm_program_id()).0
+}
+
+// Meteora pool holding the verified LP accounts and the suite's trade fee
+// Vault A is the mock AMM's DONUT vault, so slot-1 buybacks can swap against the pool
+pub fn pool_account() -> Account {
+    let pool = meteora::Pool {
+        lp_mint: Pubkey::new_unique(),
+        token_a_mint: verified_addresses::TOKEN_MINT,
+        token_b_mint: verified_addresses::WSOL_MINT,
+        a_vault: mocks::swap_vault(&verified_addresses::TOKEN_MINT),
+        b_vault: verified_addresses::B_VAULT,
+        a_vault_lp: verified_addresses::A_VAULT_LP,
+        b_vault_lp: verified_addresses::B_VAULT_LP,
+        a_vault_lp_bump: 0,
+        enabled: true,
+        protocol_token_a_fee: Pubkey::new_unique(),
+        protocol_token_b_fee: protocol_token_b_fee(),
+        fee_last_updated_at: 0,
+        padding0: [0; 24],
+        fees: meteora::PoolFees {
+            trade_fee_numerator: TRADE_FEE_NUMERATOR,
+            trade_fee_denominator: TRADE_FEE_DENOMINATOR,
+            protocol_trade_fee_numerator: 20,
+            protocol_trade_fee_denominator: 100,
+        },
+    };
+
+    let mut data = meteora::POOL_ACCOUNT_DISCRIMINATOR.to_vec();
+    data.extend(pool.try_to_vec().unwrap());
+
+    Account {
+        lamports: rent_exempt(data.len()),
+        data,
+        owner: meteora::amm_program_id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// TWAP window of the suite's program state
+pub const TWAP_WINDOW: u32 = 1800;
+
+// Reserves of the pre-seeded pool, as read for pricing
+pub fn pool_reserves() -> PoolReserves {
+    PoolReserves {
+        token_a: POOL_DONUT_RESERVE,
+        token_b: POOL_SOL_RESERVE,
+        trade_fee_numerator: TRADE_FEE_NUMERATOR,
+        trade_fee_denominator: TRADE_FEE_DENOMINATOR,
+    }
+}
+
+// Program state with the values `initialize` writes, and the pool sampled by record_price
+// The instruction itself only accepts AUTHORIZED_INITIALIZER, so the account is pre-seeded
+pub fn program_state(owner: &Pubkey, multisig_treasury: &Pubkey) -> ProgramState {
+    let mut state = unsampled_program_state(owner, multisig_treasury);
+    for sample in 0..MIN_TWAP_OBSERVATIONS as i64 {
+        record_price_observation(&mut state, &pool_reserves(), sample * TWAP_WINDOW as i64);
+    }
+    state
+}
+
+// Program state as `initialize` leaves it, before any price observation
+pub fn unsampled_program_state(owner: &Pubkey, multisig_treasury: &Pubkey) -> ProgramState {
+    ProgramState {
+        owner: *owner,
+        multisig_treasury: *multisig_treasury,
+        next_upline_id: 1,
+        next_chain_id: 1,
+        twap_window: TWAP_WINDOW,
+        observation_index: 0,
+        observation_count: 0,
+        price_observations: Default::default(),
+        slot1_policy: Slot1Policy::DepositLiquidity,
+        buyback_slippage_bps: 100,
+        treasury_fee_bps: 0,
+        total_reserved_sol: 0,
+        vesting_duration: 0,
+        staking_fee_bps: 0,
+        matrix_expiry: 0,
+        expiry_policy: ExpiryPolicy::ReleaseToOwner,
+        layout_version: STATE_LAYOUT_VERSION,
+        reserved_sol_reconciled: true,
+    }
+}
+
+// ===== ENVIRONMENT =====
+
+pub struct TestEnv {
+    pub context: ProgramTestContext,
+    pub state: Pubkey,
+    pub owner: Keypair,       // ProgramState.owner
+    pub treasury: Keypair,    // ProgramState.multisig_treasury
+    pub root: Keypair,        // Registered with register_without_referrer
+}
+
+// How the program under test is loaded
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ProgramBuild {
+    Native, // Compiled into the test binary; fast, but consumes no compute units
+    Sbf,    // matrix_system.so from SBF_OUT_DIR, as built by cargo test-sbf
+}
+
+// Program test with the program, both stand-ins and every fixed account
+pub fn program_test(build: ProgramBuild, state: &Pubkey, owner: &Pubkey, treasury: &Pubkey) -> ProgramTest {
+    let mut program_test = match build {
+        ProgramBuild::Native => ProgramTest::new(
+            "matrix_system",
+            matrix_system::ID,
+            processor!(process_matrix_instruction),
+        ),
+        ProgramBuild::Sbf => ProgramTest::new("matrix_system", matrix_system::ID, None),
+    };
+    program_test.prefer_bpf(build == ProgramBuild::Sbf);
+
+    program_test.add_program(
+        "meteora_vault",
+        verified_addresses::VAULT_PROGRAM,
+        processor!(mocks::process_vault_instruction),
+    );
+    program_test.add_program(
+        "meteora_amm",
+        verified_addresses::AMM_PROGRAM,
+        processor!(mocks::process_amm_instruction),
+    );
+    program_test.add_program(
+        "chainlink_store",
+        verified_addresses::CHAINLINK_PROGRAM,
+        processor!(mocks::process_chainlink_instruction),
+    );
+
+    program_test.add_account(
+        *state,
+        anchor_account(&program_state(owner, treasury), 8 + ProgramState::SIZE),
+    );
+
+    // Mints
+    program_test.add_account(
+        verified_addresses::TOKEN_MINT,
+        mint_account(COption::Some(token_mint_authority()), 0),
+    );
+    program_test.add_account(verified_addresses::WSOL_MINT, mint_account(COption::None, 0));
+    program_test.add_account(program_token_vault(), token_account(&verified_addresses::TOKEN_MINT, &vault_authority(), 0));
+
+    // Pool and vault A (DONUT)
+    program_test.add_account(verified_addresses::POOL_ADDRESS, pool_account());
+    program_test.add_account(
+        verified_addresses::A_VAULT_LP,
+        token_account(&verified_addresses::A_VAULT_LP_MINT, &verified_addresses::POOL_ADDRESS, VAULT_LP_SUPPLY),
+    );
+    program_test.add_account(
+        verified_addresses::A_VAULT_LP_MINT,
+        mint_account(COption::Some(Pubkey::new_unique()), VAULT_LP_SUPPLY),
+    );
+    program_test.add_account(
+        verified_addresses::A_TOKEN_VAULT,
+        token_account(&verified_addresses::TOKEN_MINT, &mocks::swap_vault(&verified_addresses::TOKEN_MINT), POOL_DONUT_RESERVE),
+    );
+    program_test.add_account(
+        mocks::swap_vault(&verified_addresses::TOKEN_MINT),
+        vault_account(
+            &verified_addresses::A_TOKEN_VAULT,
+            &verified_addresses::TOKEN_MINT,
+            &verified_addresses::A_VAULT_LP_MINT,
+            POOL_DONUT_RESERVE,
+        ),
+    );
+
+    // Vault B (SOL)
+    program_test.add_account(
+        verified_addresses::B_VAULT,
+        vault_account(
+            &verified_addresses::B_TOKEN_VAULT,
+            &verified_addresses::WSOL_MINT,
+            &verified_addresses::B_VAULT_LP_MINT,
+            POOL_SOL_RESERVE,
+        ),
+    );
+    program_test.add_account(
+        verified_addresses::B_TOKEN_VAULT,
+        native_token_account(&verified_addresses::B_VAULT, POOL_SOL_RESERVE),
+    );
+    program_test.add_account(
+        verified_addresses::B_VAULT_LP_MINT,
+        mint_account(COption::Some(verified_addresses::B_VAULT), VAULT_LP_SUPPLY),
+    );
+    program_test.add_account(
+        verified_addresses::B_VAULT_LP,
+        token_account(&verified_addresses::B_VAULT_LP_MINT, &verified_addresses::POOL_ADDRESS, VAULT_LP_SUPPLY),
+    );
+
+    program_test
+}
+
+impl TestEnv {
+    // Start the bank, write a fresh price, create the mint budget and register the root user
+    pub async fn new() -> Self {
+        Self::with_build(ProgramBuild::Native).await
+    }
+
+    pub async fn with_build(build: ProgramBuild) -> Self {
+        let state = Keypair::new();
+        let owner = Keypair::new();
+        let treasury = Keypair::new();
+        let root = Keypair::new();
+
+        let mut program_test = program_test(build, &state.pubkey(), &owner.pubkey(), &treasury.pubkey());
+        for wallet in [&owner, &treasury, &root] {
+            program_test.add_account(wallet.pubkey(), system_account(WALLET_LAMPORTS));
+        }
+
+        let context = program_test.start_with_context().await;
+
+        let mut env = TestEnv {
+            context,
+            state: state.pubkey(),
+            owner,
+            treasury,
+            root,
+        };
+
+        env.set_sol_price(SOL_PRICE, 0).await;
+        env.initialize_mint_budget(EPOCH_DURATION, MAX_TOKENS_PER_EPOCH, MAX_TOKENS_PER_USD)
+            .await
+            .unwrap();
+
+        let root = env.root.insecure_clone();
+        env.register_root(&root, DEPOSIT).await.unwrap();
+
+        env
+    }
+
+    // Transaction with a raised compute limit, paid by the context payer
+    async fn transaction(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> std::result::Result<Transaction, BanksClientError> {
+        let mut all_instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_400_000)];
+        all_instructions.extend_from_slice(instructions);
+
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let blockhash = self.context.banks_client.get_latest_blockhash().await?;
+        Ok(Transaction::new_signed_with_payer(
+            &all_instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        ))
+    }
+
+    pub async fn send(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> std::result::Result<(), BanksClientError> {
+        let transaction = self.transaction(instructions, signers).await?;
+        self.context.banks_client.process_transaction(transaction).await
+    }
+
+    // Send instructions and return the compute units consumed and the log messages
+    pub async fn send_profiled(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> std::result::Result<(u64, Vec<String>), BanksClientError> {
+        let transaction = self.transaction(instructions, signers).await?;
+        let processed = self.context.banks_client.process_transaction_with_metadata(transaction).await?;
+        processed.result?;
+
+        let metadata = processed.metadata.expect("bank returns metadata for processed transactions");
+        Ok((metadata.compute_units_consumed, metadata.log_messages))
+    }
+
+    pub async fn now(&mut self) -> i64 {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap().unix_timestamp
+    }
+
+    // Move the clock forward without producing blocks
+    pub async fn advance_clock(&mut self, seconds: i64) {
+        let mut clock = self.context.banks_client.get_sysvar::<Clock>().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub fn set_account(&mut self, address: &Pubkey, account: Account) {
+        self.context.set_account(address, &AccountSharedData::from(account));
+    }
+
+    // Write the SOL/USD feed, `age` seconds older than the current clock
+    pub async fn set_sol_price(&mut self, answer: i128, age: i64) {
+        let timestamp = (self.now().await - age) as u32;
+        self.set_account(
+            &verified_addresses::SOL_USD_FEED,
+            feed_account(MockFeed { answer, decimals: 8, timestamp }),
+        );
+    }
+
+    // New funded wallet
+    pub fn create_wallet(&mut self) -> Keypair {
+        let wallet = Keypair::new();
+        self.set_account(&wallet.pubkey(), system_account(WALLET_LAMPORTS));
+        wallet
+    }
+
+    // ===== READERS =====
+
+    pub async fn account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(*address).await.unwrap()
+    }
+
+    pub async fn lamports(&mut self, address: &Pubkey) -> u64 {
+        self.account(address).await.map(|account| account.lamports).unwrap_or(0)
+    }
+
+    pub async fn token_balance(&mut self, address: &Pubkey) -> u64 {
+        match self.account(address).await {
+            Some(account) => spl_token::state::Account::unpack(&account.data).unwrap().amount,
+            None => 0,
+        }
+    }
+
+    // Total amount recorded in a Meteora vault, which prices the pool
+    pub async fn vault_total(&mut self, vault: &Pubkey) -> u64 {
+        let account = self.account(vault).await.unwrap();
+        meteora::Vault::from_account_data(&account.data).unwrap().total_amount
+    }
+
+    pub async fn mint_supply(&mut self, mint: &Pubkey) -> u64 {
+        let account = self.account(mint).await.unwrap();
+        spl_token::state::Mint::unpack(&account.data).unwrap().supply
+    }
+
+    pub async fn anchor_data<T: AccountDeserialize>(&mut self, address: &Pubkey) -> T {
+        let account = self.account(address).await.expect("account not found");
+        T::try_deserialize(&mut &account.data[..]).unwrap()
+    }
+
+    pub async fn program_state(&mut self) -> ProgramState {
+        let state = self.state;
+        self.anchor_data(&state).await
+    }
+
+    pub async fn user_account(&mut self, wallet: &Pubkey) -> UserAccount {
+        self.anchor_data(&user_pda(wallet)).await
+    }
+
+    pub async fn mint_budget(&mut self) -> MintBudget {
+        self.anchor_data(&mint_budget_pda()).await
+    }
+
+    // ===== ADMIN INSTRUCTIONS =====
+
+    pub async fn initialize_mint_budget(
+        &mut self,
+        epoch_duration: i64,
+        max_tokens_per_epoch: u64,
+        max_tokens_per_usd: u64,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: accounts::InitializeMintBudget {
+                state: self.state,
+                mint_budget: mint_budget_pda(),
+                owner: self.owner.pubkey(),
+                system_program: solana_sdk::system_program::ID,
+            }
+            .to_account_metas(None),
+            data: instruction::InitializeMintBudget {
+                epoch_duration,
+                max_tokens_per_epoch,
+                max_tokens_per_usd,
+            }
+            .data(),
+        };
+
+        let owner = self.owner.insecure_clone();
+        self.send(&[ix], &[&owner]).await
+    }
+
+    pub async fn update_mint_budget(
+        &mut self,
+        epoch_duration: i64,
+        max_tokens_per_epoch: u64,
+        max_tokens_per_usd: u64,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: accounts::UpdateMintBudget {
+                state: self.state,
+                mint_budget: mint_budget_pda(),
+                owner: self.owner.pubkey(),
+            }
+            .to_account_metas(None),
+            data: instruction::UpdateMintBudget {
+                epoch_duration,
+                max_tokens_per_epoch,
+                max_tokens_per_usd,
+            }
+            .data(),
+        };
+
+        let owner = self.owner.insecure_clone();
+        self.send(&[ix], &[&owner]).await
+    }
+
+    fn update_config_accounts(&self, signer: &Pubkey) -> Vec<AccountMeta> {
+        accounts::UpdateConfig {
+            state: self.state,
+            owner: *signer,
+        }
+        .to_account_metas(None)
+    }
+
+    pub async fn set_vesting_duration(&mut self, vesting_duration: i64) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: self.update_config_accounts(&self.owner.pubkey()),
+            data: instruction::SetVestingDuration { vesting_duration }.data(),
+        };
+
+        let owner = self.owner.insecure_clone();
+        self.send(&[ix], &[&owner]).await
+    }
+
+    pub async fn set_matrix_expiry(&mut self, matrix_expiry: i64, expiry_policy: ExpiryPolicy) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: self.update_config_accounts(&self.owner.pubkey()),
+            data: instruction::SetMatrixExpiry { matrix_expiry, expiry_policy }.data(),
+        };
+
+        let owner = self.owner.insecure_clone();
+        self.send(&[ix], &[&owner]).await
+    }
+
+    pub async fn set_treasury_fee(&mut self, treasury_fee_bps: u16) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: self.update_config_accounts(&self.treasury.pubkey()),
+            data: instruction::SetTreasuryFee { treasury_fee_bps }.data(),
+        };
+
+        let treasury = self.treasury.insecure_clone();
+        self.send(&[ix], &[&treasury]).await
+    }
+
+    pub async fn set_slot1_policy(&mut self, policy: Slot1Policy, buyback_slippage_bps: u16) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: self.update_config_accounts(&self.treasury.pubkey()),
+            data: instruction::SetSlot1Policy { policy, buyback_slippage_bps }.data(),
+        };
+
+        let treasury = self.treasury.insecure_clone();
+        self.send(&[ix], &[&treasury]).await
+    }
+
+    // Sample the pool price with the permissionless record_price instruction
+    pub async fn record_price(&mut self) -> std::result::Result<(), BanksClientError> {
+        self.record_price_after(0).await
+    }
+
+    // Sample the pool price `seconds` after the current clock
+    pub async fn record_price_after(&mut self, seconds: i64) -> std::result::Result<(), BanksClientError> {
+        let accounts = self.record_price_accounts();
+        self.send_record_price(accounts, seconds).await
+    }
+
+    // Accounts of record_price
+    pub fn record_price_accounts(&self) -> accounts::RecordPrice {
+        accounts::RecordPrice {
+            state: self.state,
+            pool: verified_addresses::POOL_ADDRESS,
+            a_vault_lp: verified_addresses::A_VAULT_LP,
+            a_vault_lp_mint: verified_addresses::A_VAULT_LP_MINT,
+            a_vault: mocks::swap_vault(&verified_addresses::TOKEN_MINT),
+            b_vault_lp: verified_addresses::B_VAULT_LP,
+            b_vault_lp_mint: verified_addresses::B_VAULT_LP_MINT,
+            b_vault: verified_addresses::B_VAULT,
+        }
+    }
+
+    // Send record_price with `accounts`, `seconds` after the current clock
+    pub async fn send_record_price(
+        &mut self,
+        accounts: accounts::RecordPrice,
+        seconds: i64,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: accounts.to_account_metas(None),
+            data: instruction::RecordPrice {}.data(),
+        };
+
+        // A fresh blockhash keeps repeated samples from being deduplicated; the clock is moved
+        // after it, since the new bank recomputes the timestamp
+        self.context.get_new_latest_blockhash().await.unwrap();
+        self.advance_clock(seconds).await;
+        self.send(&[ix], &[]).await
+    }
+
+    // ===== MATRIX EXPIRY =====
+
+    // Accounts of expire_matrix for `wallet`'s matrix, without the policy accounts
+    pub fn expire_matrix_accounts(&self, wallet: &Pubkey, caller: &Pubkey) -> accounts::ExpireMatrix {
+        accounts::ExpireMatrix {
+            state: self.state,
+            user: user_pda(wallet),
+            owner_wallet: *wallet,
+            caller: *caller,
+            program_sol_vault: program_sol_vault(),
+            token_mint: verified_addresses::TOKEN_MINT,
+            program_token_vault: program_token_vault(),
+            vault_authority: vault_authority(),
+            owner_token_account: None,
+            owner_vesting: None,
+            treasury_wallet: None,
+            treasury_token_account: None,
+            vault_wsol_account: None,
+            wsol_mint: None,
+            b_vault: None,
+            b_token_vault: None,
+            b_vault_lp_mint: None,
+            b_vault_lp: None,
+            vault_program: None,
+            token_program: spl_token::ID,
+            donut_token_program: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: solana_sdk::system_program::ID,
+        }
+    }
+
+    pub async fn send_expire_matrix(
+        &mut self,
+        accounts: accounts::ExpireMatrix,
+        caller: &Keypair,
+    ) -> std::result::Result<(), BanksClientError> {
+        let ix = Instruction {
+            program_id: matrix_system::ID,
+            accounts: accounts.to_account_metas(None),
+            data: instruction::ExpireMatrix {}.data(),
+        };
+
+        self.send(&[ix], &[caller]).await
+    }
+
+    // ===== REGISTRATION =====
+
+    // Accounts of register_without_referrer for `user`, signed by the multisig treasury
+    pub fn root_registration(&self, user: &Pubkey) -> accounts::RegisterWithoutReferrerDeposit {
+        accounts::RegisterWithoutReferrerDeposit {
+            state: self.state,
+            owner: self.treasury.pubkey(),
+            user_wallet: *user,
+            user: user_pda(user),
+            user_source_token: wsol_ata(user),
+            wsol_mint: verified_addresses::WSOL_MINT,
+            pool: verified_addresses::POOL_ADDRESS,
+            b_vault: verified_addresses::B_VAULT,
+            b_token_vault: verified_addresses::B_TOKEN_VAULT,
+            b_vault_lp_mint: verified_addresses::B_VAULT_LP_MINT,
+            b_vault_lp: verified_addresses::B_VAULT_LP,
+            vault_program: verified_addresses::VAULT_PROGRAM,
+            token_mint: verified_addresses::TOKEN_MINT,
+            token_program: spl_token::ID,
+            system_program: solana_sdk::system_program::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            rent: sysvar::rent::ID,
+        }
+    }
+
+    // Fund the user's WSOL ATA and register it without a referrer
+    pub async fn send_root_registration(
+        &mut self,
+        user: &Keypair,
+        registration: accounts::RegisterWithoutReferrerDeposit,
+        deposit_amount: u64,
+    ) -> std::result::Result<(), BanksClientError> {
+        let instructions = [
+            create_ata_idempotent(&user.pubkey(), &user.pubkey(), &verified_addresses::WSOL_MINT),
+            system_instruction::transfer(&user.pubkey(), &wsol_ata(&user.pubkey()), deposit_amount),
+            Instruction {
+                program_id: matrix_system::ID,
+                accounts: registration.to_account_metas(None),
+                data: instruction::RegisterWithoutReferrer { deposit_amount }.data(),
+            },
+        ];
+
+        let treasury = self.treasury.insecure_clone();
+        self.send(&instructions, &[user, &treasury]).await
+    }
+
+    pub async fn register_root(&mut self, user: &Keypair, deposit_amount: u64) -> std::result::Result<(), BanksClientError> {
+        let registration = self.root_registration(&user.pubkey());
+        self.send_root_registration(user, registration, deposit_amount).await
+    }
+
+    // register_with_sol_deposit for `user` under `referrer_wallet`, with the upline trios
+    // passed whenever the referrer's matrix is about to complete
+    pub async fn registration(&mut self, user: &Pubkey, referrer_wallet: &Pubkey, deposit_amount: u64) -> Registration {
+        let referrer = self.user_account(referrer_wallet).await;
+        let state = self.program_state().await;
+        let vesting_enabled = state.vesting_duration > 0;
+
+        // The slot-1 buyback swaps through vault A, which must then be writable
+        let vault_a = |address| if state.slot1_policy == Slot1Policy::DepositLiquidity {
+            AccountMeta::new_readonly(address, false)
+        } else {
+            AccountMeta::new(address, false)
+        };
+
+        let mut remaining_accounts = vec![
+            vault_a(verified_addresses::A_VAULT_LP),
+            vault_a(verified_addresses::A_VAULT_LP_MINT),
+            vault_a(verified_addresses::A_TOKEN_VAULT),
+            vault_a(mocks::swap_vault(&verified_addresses::TOKEN_MINT)),
+            AccountMeta::new_readonly(verified_addresses::SOL_USD_FEED, false),
+            AccountMeta::new_readonly(verified_addresses::CHAINLINK_PROGRAM, false),
+        ];
+
+        if referrer.chain.filled_slots == 2 {
+            // Closest upline first
+            for entry in referrer.upline.upline.iter().rev() {
+                let token_account = if vesting_enabled {
+                    vesting_pda(&entry.wallet)
+                } else {
+                    donut_ata(&entry.wallet)
+                };
+
+                remaining_accounts.push(AccountMeta::new(entry.pda, false));
+                remaining_accounts.push(AccountMeta::new(entry.wallet, false));
+                remaining_accounts.push(AccountMeta::new(token_account, false));
+            }
+        }
+
+        Registration {
+            accounts: accounts::RegisterWithSolDeposit {
+                state: self.state,
+                user_wallet: *user,
+                referrer: user_pda(referrer_wallet),
+                referrer_wallet: *referrer_wallet,
+                user: user_pda(user),
+                user_wsol_account: wsol_ata(user),
+                wsol_mint: verified_addresses::WSOL_MINT,
+                pool: verified_addresses::POOL_ADDRESS,
+                b_vault: verified_addresses::B_VAULT,
+                b_token_vault: verified_addresses::B_TOKEN_VAULT,
+                b_vault_lp_mint: verified_addresses::B_VAULT_LP_MINT,
+                b_vault_lp: verified_addresses::B_VAULT_LP,
+                vault_program: verified_addresses::VAULT_PROGRAM,
+                protocol_token_b_fee: None,
+                treasury_token_account: None,
+                amm_program: None,
+                treasury_wallet: None,
+                treasury_wsol_account: None,
+                staking_pool: None,
+                staking_reward_vault: None,
+                program_sol_vault: program_sol_vault(),
+                mint_budget: mint_budget_pda(),
+                token_mint: verified_addresses::TOKEN_MINT,
+                program_token_vault: program_token_vault(),
+                referrer_token_account: donut_ata(referrer_wallet),
+                referrer_vesting: vesting_enabled.then(|| vesting_pda(referrer_wallet)),
+                token_mint_authority: token_mint_authority(),
+                vault_authority: vault_authority(),
+                token_program: spl_token::ID,
+                donut_token_program: spl_token::ID,
+                system_program: solana_sdk::system_program::ID,
+                associated_token_program: anchor_spl::associated_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            remaining_accounts,
+            deposit_amount,
+        }
+    }
+
+    pub async fn send_registration(&mut self, user: &Keypair, registration: &Registration) -> std::result::Result<(), BanksClientError> {
+        self.send(&[registration.instruction()], &[user]).await
+    }
+
+    pub async fn register(&mut self, user: &Keypair, referrer_wallet: &Pubkey, deposit_amount: u64) -> std::result::Result<(), BanksClientError> {
+        let registration = self.registration(&user.pubkey(), referrer_wallet, deposit_amount).await;
+        self.send_registration(user, &registration).await
+    }
+
+    // Register a new funded wallet under `referrer_wallet` with the default deposit
+    pub async fn register_new(&mut self, referrer_wallet: &Pubkey) -> Keypair {
+        let user = self.create_wallet();
+        self.register(&user, referrer_wallet, DEPOSIT).await.unwrap();
+        user
+    }
+}
+
+// Instruction and accounts of a register_with_sol_deposit call, editable before sending
+pub struct Registration {
+    pub accounts: accounts::RegisterWithSolDeposit,
+    pub remaining_accounts: Vec<AccountMeta>,
+    pub deposit_amount: u64,
+}
+
+impl Registration {
+    pub fn instruction(&self) -> Instruction {
+        let mut metas = self.accounts.to_account_metas(None);
+        metas.extend_from_slice(&self.remaining_accounts);
+
+        Instruction {
+            program_id: matrix_system::ID,
+            accounts: metas,
+            data: instruction::RegisterWithSolDeposit {
+                deposit_amount: self.deposit_amount,
+            }
+            .data(),
+        }
+    }
+}
+
+// Associated token program CreateIdempotent
+pub fn create_ata_idempotent(payer: &Pubkey, wallet: &Pubkey, mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: anchor_spl::associated_token::ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(get_associated_token_address(wallet, mint), false),
+            AccountMeta::new_readonly(*wallet, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(solana_sdk::system_program::ID, false),
+            AccountMeta::new_readonly(spl_token::ID, false),
+        ],
+        data: vec![1],
+    }
+}
+
+// Expected DONUT for a deposit at the pre-seeded pool ratio, the suite's TWAP price
+pub fn twap_donut_output(sol_amount: u64) -> u64 {
+    let price = POOL_DONUT_RESERVE as u128 * PRICE_SCALE / POOL_SOL_RESERVE as u128;
+    let amount_in = sol_amount as u128 - sol_amount as u128 * TRADE_FEE_NUMERATOR as u128 / TRADE_FEE_DENOMINATOR as u128;
+    (amount_in * price / PRICE_SCALE) as u64
+}
+
+// Expected DONUT for a deposit at the pool's spot price
+pub fn spot_donut_output(sol_reserve: u64, donut_reserve: u64, sol_amount: u64) -> u64 {
+    meteora::swap_output(sol_reserve, donut_reserve, sol_amount, TRADE_FEE_NUMERATOR, TRADE_FEE_DENOMINATOR).unwrap()
+}
+
+// Assert that a transaction failed with the given program error
+pub fn assert_program_error(result: std::result::Result<(), BanksClientError>, expected: ErrorCode) {
+    let expected_code = u32::from(expected);
+
+    match result {
+        Err(error) => match error.unwrap() {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                assert_eq!(code, expected_code, "expected {:?} ({}), got custom error {}", expected, expected_code, code);
+            },
+            other => panic!("expected {:?}, got {:?}", expected, other),
+        },
+        Ok(()) => panic!("expected {:?}, but the transaction succeeded", expected),
+    }
+}
@@ -0,0 +1,129 @@
+// compute_budget: compute units and heap of register_with_sol_deposit against the SBF build
+// cargo test-sbf --features heap-profile -- --test compute_budget --nocapture
+// Without heap-profile only the compute units are measured
+#![cfg(feature = "test-sbf")]
+mod common;
+
+use anchor_lang::prelude::*;
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+
+// Headroom below the 1,400,000 units a transaction can request
+const COMPUTE_UNIT_BUDGET: u64 = 1_200_000;
+
+// Headroom below the 32 KiB the default allocator can hand out
+const HEAP_BUDGET: u64 = 28 * 1024;
+
+const HEAP_LOG_PREFIX: &str = "Program log: Heap used: ";
+
+struct Profile {
+    scenario: String,
+    compute_units: u64,
+    heap_used: Option<u64>,
+}
+
+impl Profile {
+    fn within_budget(&self) -> bool {
+        self.compute_units <= COMPUTE_UNIT_BUDGET && self.heap_used.unwrap_or(0) <= HEAP_BUDGET
+    }
+}
+
+// Heap reported by the last registration in the logs (heap-profile builds only)
+fn heap_used(logs: &[String]) -> Option<u64> {
+    logs.iter().rev().find_map(|line| {
+        let used = line.strip_prefix(HEAP_LOG_PREFIX)?;
+        used.split(' ').next()?.parse().ok()
+    })
+}
+
+// Register a new wallet under `referrer_wallet` and measure the transaction
+async fn profile_registration(env: &mut TestEnv, referrer_wallet: &Pubkey, scenario: String) -> Profile {
+    let user = env.create_wallet();
+    let registration = env.registration(&user.pubkey(), referrer_wallet, DEPOSIT).await;
+    let (compute_units, logs) = env
+        .send_profiled(&[registration.instruction()], &[&user])
+        .await
+        .unwrap_or_else(|error| panic!("{}: registration failed: {:?}", scenario, error));
+
+    Profile {
+        scenario,
+        compute_units,
+        heap_used: heap_used(&logs),
+    }
+}
+
+// Line of `depth` users from the root with `x` under the last one, every matrix on the way
+// one registration short of completing (the root's has `root_filled` slots)
+// Returns the line from the root down and `x`
+async fn build_line(env: &mut TestEnv, depth: usize, root_filled: u8) -> (Vec<Keypair>, Keypair) {
+    let mut line = vec![env.root.insecure_clone()];
+    for _ in 1..depth {
+        let parent = line.last().unwrap().pubkey();
+        line.push(env.register_new(&parent).await);
+    }
+
+    let x = env.register_new(&line.last().unwrap().pubkey()).await;
+
+    for member in line.iter().skip(1) {
+        env.register_new(&member.pubkey()).await;
+    }
+    for _ in 1..root_filled {
+        env.register_new(&line[0].pubkey()).await;
+    }
+    for _ in 0..2 {
+        env.register_new(&x.pubkey()).await;
+    }
+
+    (line, x)
+}
+
+// Registration that takes slot `slot` of the root's matrix, without recursion
+async fn profile_direct_slot(slot: u8) -> Profile {
+    let mut env = TestEnv::with_build(ProgramBuild::Sbf).await;
+    let root = env.root.pubkey();
+    for _ in 1..slot {
+        env.register_new(&root).await;
+    }
+
+    profile_registration(&mut env, &root, format!("slot {}", slot)).await
+}
+
+// Registration that completes matrices `depth` levels up, ending in the root's slot 2,
+// or in a pool deposit when the root's matrix completes too
+async fn profile_recursion(depth: usize, root_filled: u8) -> Profile {
+    let mut env = TestEnv::with_build(ProgramBuild::Sbf).await;
+    let (_, x) = build_line(&mut env, depth, root_filled).await;
+
+    let scenario = if root_filled == 2 {
+        format!("recursion depth {}, root completes", depth)
+    } else {
+        format!("recursion depth {}", depth)
+    };
+    profile_registration(&mut env, &x.pubkey(), scenario).await
+}
+
+#[tokio::test]
+async fn registrations_stay_within_budget() {
+    let mut profiles = Vec::new();
+    for slot in 1..=3 {
+        profiles.push(profile_direct_slot(slot).await);
+    }
+    for depth in 1..=6 {
+        profiles.push(profile_recursion(depth, 1).await);
+    }
+    profiles.push(profile_recursion(6, 2).await);
+
+    println!("{:<36} {:>14} {:>12}", "scenario", "compute units", "heap bytes");
+    for profile in &profiles {
+        let heap = profile.heap_used.map_or("-".to_string(), |heap| heap.to_string());
+        println!("{:<36} {:>14} {:>12}", profile.scenario, profile.compute_units, heap);
+    }
+    println!("budget: {} compute units, {} heap bytes", COMPUTE_UNIT_BUDGET, HEAP_BUDGET);
+
+    let over_budget: Vec<_> = profiles
+        .iter()
+        .filter(|profile| !profile.within_budget())
+        .map(|profile| profile.scenario.as_str())
+        .collect();
+    assert!(over_budget.is_empty(), "over budget: {:?}", over_budget);
+}
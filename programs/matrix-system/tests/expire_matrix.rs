@@ -0,0 +1,92 @@
+// expire_matrix: reservations of a matrix past its deadline under the expiry policies
+mod common;
+
+use common::*;
+use matrix_system::{verified_addresses, ErrorCode, ExpiryPolicy};
+use solana_sdk::signature::Signer;
+
+// Seconds a matrix has to fill
+const MATRIX_EXPIRY: i64 = 3_600;
+
+// Root with a slot-2 reservation in a matrix that expires under `policy`
+// Returns the reserved SOL and DONUT
+async fn reserve_under(env: &mut TestEnv, policy: ExpiryPolicy) -> (u64, u64) {
+    env.set_matrix_expiry(MATRIX_EXPIRY, policy).await.unwrap();
+
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    let root_account = env.user_account(&root).await;
+    assert_eq!(root_account.matrix_expires_at, env.now().await + MATRIX_EXPIRY);
+    (root_account.reserved_sol, root_account.reserved_tokens)
+}
+
+#[tokio::test]
+async fn return_to_pool_deposits_the_sol_and_burns_the_donut() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let (reserved_sol, reserved_tokens) = reserve_under(&mut env, ExpiryPolicy::ReturnToPool).await;
+    let chain_id = env.user_account(&root).await.chain.id;
+
+    env.advance_clock(MATRIX_EXPIRY).await;
+
+    let vault_lamports = env.lamports(&program_sol_vault()).await;
+    let pool_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+    let vault_total_before = env.vault_total(&verified_addresses::B_VAULT).await;
+    let supply_before = env.mint_supply(&verified_addresses::TOKEN_MINT).await;
+
+    let caller = env.create_wallet();
+    let mut accounts = env.expire_matrix_accounts(&root, &caller.pubkey());
+    accounts.vault_wsol_account = Some(wsol_ata(&program_sol_vault()));
+    accounts.wsol_mint = Some(verified_addresses::WSOL_MINT);
+    accounts.b_vault = Some(verified_addresses::B_VAULT);
+    accounts.b_token_vault = Some(verified_addresses::B_TOKEN_VAULT);
+    accounts.b_vault_lp_mint = Some(verified_addresses::B_VAULT_LP_MINT);
+    accounts.b_vault_lp = Some(verified_addresses::B_VAULT_LP);
+    accounts.vault_program = Some(verified_addresses::VAULT_PROGRAM);
+    env.send_expire_matrix(accounts, &caller).await.unwrap();
+
+    // The SOL leaves the program vault for Meteora vault B, the DONUT minted against it is burned
+    assert_eq!(env.lamports(&program_sol_vault()).await, vault_lamports - reserved_sol);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, pool_before + reserved_sol);
+    assert_eq!(env.vault_total(&verified_addresses::B_VAULT).await, vault_total_before + reserved_sol);
+    assert_eq!(env.token_balance(&wsol_ata(&program_sol_vault())).await, 0);
+    assert_eq!(env.mint_supply(&verified_addresses::TOKEN_MINT).await, supply_before - reserved_tokens);
+    assert_eq!(env.token_balance(&program_token_vault()).await, 0);
+    assert_eq!(env.program_state().await.total_reserved_sol, 0);
+
+    let after = env.user_account(&root).await;
+    assert_eq!(after.reserved_sol, 0);
+    assert_eq!(after.reserved_tokens, 0);
+    assert_eq!(after.chain.filled_slots, 0);
+    assert_eq!(after.matrix_expires_at, 0);
+    assert_ne!(after.chain.id, chain_id);
+}
+
+#[tokio::test]
+async fn return_to_pool_requires_its_accounts() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    reserve_under(&mut env, ExpiryPolicy::ReturnToPool).await;
+    env.advance_clock(MATRIX_EXPIRY).await;
+
+    let caller = env.create_wallet();
+    let accounts = env.expire_matrix_accounts(&root, &caller.pubkey());
+    let result = env.send_expire_matrix(accounts, &caller).await;
+    assert_program_error(result, ErrorCode::MissingExpiryAccounts);
+}
+
+#[tokio::test]
+async fn matrix_cannot_expire_before_its_deadline() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let (reserved_sol, _) = reserve_under(&mut env, ExpiryPolicy::ReleaseToOwner).await;
+    env.advance_clock(MATRIX_EXPIRY - 1).await;
+
+    let caller = env.create_wallet();
+    let accounts = env.expire_matrix_accounts(&root, &caller.pubkey());
+    let result = env.send_expire_matrix(accounts, &caller).await;
+    assert_program_error(result, ErrorCode::MatrixNotExpired);
+    assert_eq!(env.user_account(&root).await.reserved_sol, reserved_sol);
+}
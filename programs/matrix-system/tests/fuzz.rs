@@ -0,0 +1,302 @@
+// fuzz: pricing, mint budget and upline account decoding over arbitrary inputs
+// The cargo-fuzz targets in fuzz/ drive the same functions with libFuzzer
+use anchor_lang::prelude::*;
+use matrix_system::meteora;
+use matrix_system::{
+    calculate_minimum_sol_deposit, calculate_sol_for_usd_value, calculate_twap_price, calculate_usd_value,
+    check_mint_limit, decode_user_account_data, get_donut_tokens_amount, record_price_observation, ExpiryPolicy,
+    MintBudget, PoolReserves, ProgramState, ReferralChain, ReferralUpline, Slot1Policy, UplineEntry, UserAccount,
+    MINIMUM_USD_DEPOSIT, STATE_LAYOUT_VERSION,
+};
+use proptest::prelude::*;
+
+// Unix timestamps up to 2^40 seconds, the range the clock sysvar can report
+const MAX_TIMESTAMP: i64 = 1 << 40;
+
+fn program_state(twap_window: u32) -> ProgramState {
+    ProgramState {
+        owner: Pubkey::default(),
+        multisig_treasury: Pubkey::default(),
+        next_upline_id: 1,
+        next_chain_id: 1,
+        twap_window,
+        observation_index: 0,
+        observation_count: 0,
+        price_observations: Default::default(),
+        slot1_policy: Slot1Policy::DepositLiquidity,
+        buyback_slippage_bps: 0,
+        treasury_fee_bps: 0,
+        total_reserved_sol: 0,
+        vesting_duration: 0,
+        staking_fee_bps: 0,
+        matrix_expiry: 0,
+        expiry_policy: ExpiryPolicy::ReleaseToOwner,
+        layout_version: STATE_LAYOUT_VERSION,
+        reserved_sol_reconciled: true,
+    }
+}
+
+fn reserves(token_a: u64, token_b: u64, (trade_fee_numerator, trade_fee_denominator): (u64, u64)) -> PoolReserves {
+    PoolReserves {
+        token_a,
+        token_b,
+        trade_fee_numerator,
+        trade_fee_denominator,
+    }
+}
+
+fn pubkey() -> impl Strategy<Value = Pubkey> {
+    any::<[u8; 32]>().prop_map(Pubkey::new_from_array)
+}
+
+// Trade fees including the invalid ones (zero denominator, fee of 100% or more)
+fn any_fee() -> impl Strategy<Value = (u64, u64)> {
+    prop_oneof![(any::<u64>(), any::<u64>()), (0u64..100, 100u64..=10_000)]
+}
+
+// Pool samples recorded into the TWAP buffer: window, first timestamp, (reserves, seconds since the previous sample)
+type Observations = (u32, i64, Vec<(u64, u64, i64)>);
+
+fn observations() -> impl Strategy<Value = Observations> {
+    (
+        0u32..=86_400,
+        0i64..MAX_TIMESTAMP,
+        prop::collection::vec((any::<u64>(), any::<u64>(), 0i64..100_000), 0..24),
+    )
+}
+
+// Program state holding the recorded samples, and the time of the last one
+fn state_with((twap_window, start, samples): Observations) -> (ProgramState, i64) {
+    let mut state = program_state(twap_window);
+    let mut now = start;
+    for (token_a, token_b, elapsed) in samples {
+        now += elapsed;
+        record_price_observation(&mut state, &reserves(token_a, token_b, (25, 10_000)), now);
+    }
+    (state, now)
+}
+
+// Serialized user account with an arbitrary upline, including ones longer than MAX_UPLINE_DEPTH
+fn user_account_data() -> impl Strategy<Value = Vec<u8>> {
+    (
+        any::<bool>(),
+        prop::option::of(pubkey()),
+        pubkey(),
+        (any::<u32>(), any::<u8>(), prop::collection::vec((pubkey(), pubkey()), 0..=8)),
+        (any::<u32>(), prop::array::uniform3(prop::option::of(pubkey())), any::<u8>()),
+        any::<u64>(),
+        any::<u64>(),
+        any::<i64>(),
+    )
+        .prop_map(
+            |(is_registered, referrer, owner_wallet, (id, depth, upline), (chain_id, slots, filled_slots), reserved_sol, reserved_tokens, matrix_expires_at)| {
+                let account = UserAccount {
+                    is_registered,
+                    referrer,
+                    owner_wallet,
+                    upline: ReferralUpline {
+                        id,
+                        depth,
+                        upline: upline.into_iter().map(|(pda, wallet)| UplineEntry { pda, wallet }).collect(),
+                    },
+                    chain: ReferralChain {
+                        id: chain_id,
+                        slots,
+                        filled_slots,
+                    },
+                    reserved_sol,
+                    reserved_tokens,
+                    matrix_expires_at,
+                };
+                account_data(&account)
+            },
+        )
+}
+
+fn account_data(account: &UserAccount) -> Vec<u8> {
+    let mut data = Vec::new();
+    account.try_serialize(&mut data).unwrap();
+    data
+}
+
+proptest! {
+    #[test]
+    fn minimum_deposit_never_panics(price in any::<i128>(), decimals in any::<u32>()) {
+        prop_assert!(calculate_minimum_sol_deposit(price, decimals).is_ok());
+    }
+
+    #[test]
+    fn minimum_deposit_falls_as_the_price_rises(
+        low in 1i128..=i64::MAX as i128,
+        high in 1i128..=i64::MAX as i128,
+        decimals in 0u32..=18,
+    ) {
+        let (low, high) = (low.min(high), low.max(high));
+        let at_low = calculate_minimum_sol_deposit(low, decimals).unwrap();
+        let at_high = calculate_minimum_sol_deposit(high, decimals).unwrap();
+
+        prop_assert!(at_low >= at_high);
+    }
+
+    #[test]
+    fn minimum_deposit_is_worth_the_minimum_usd(price in 1_00000000i128..100_000 * 1_00000000, decimals in 8u32..=10) {
+        let minimum = calculate_minimum_sol_deposit(price, decimals).unwrap();
+
+        // The float rounding can fall short by a lamport at most
+        let value = calculate_usd_value(minimum + 2, price, decimals).unwrap();
+        prop_assert!(value >= MINIMUM_USD_DEPOSIT);
+    }
+
+    #[test]
+    fn usd_conversions_never_panic(amount in any::<u64>(), price in any::<i128>(), decimals in any::<u32>()) {
+        let value = calculate_usd_value(amount, price, decimals);
+        let lamports = calculate_sol_for_usd_value(amount, price, decimals);
+
+        if price <= 0 {
+            prop_assert!(value.is_none() && lamports.is_none());
+        }
+    }
+
+    #[test]
+    fn mint_limit_never_panics_and_stays_within_budget(
+        epoch_duration in any::<i64>(),
+        max_tokens_per_epoch in any::<u64>(),
+        max_tokens_per_usd in any::<u64>(),
+        epoch_start in any::<i64>(),
+        minted_in_epoch in any::<u64>(),
+        proposed in any::<u64>(),
+        deposit_usd_value in any::<u64>(),
+        now in any::<i64>(),
+    ) {
+        let mut budget = MintBudget {
+            epoch_duration,
+            max_tokens_per_epoch,
+            max_tokens_per_usd,
+            epoch_start,
+            minted_in_epoch,
+        };
+        let window_elapsed = now >= epoch_start.saturating_add(epoch_duration);
+        let already_minted = if window_elapsed { 0 } else { minted_in_epoch };
+
+        let result = check_mint_limit(&mut budget, proposed, deposit_usd_value, now);
+
+        prop_assert_eq!(budget.epoch_start, if window_elapsed { now } else { epoch_start });
+        match result {
+            Ok(()) => {
+                let per_mint = deposit_usd_value as u128 * max_tokens_per_usd as u128 / 1_00000000;
+                prop_assert!(proposed as u128 <= per_mint);
+                prop_assert_eq!(already_minted.checked_add(proposed), Some(budget.minted_in_epoch));
+                prop_assert!(budget.minted_in_epoch <= max_tokens_per_epoch);
+            },
+            Err(_) => prop_assert_eq!(budget.minted_in_epoch, already_minted),
+        }
+    }
+
+    #[test]
+    fn smaller_mints_pass_where_larger_ones_do(
+        max_tokens_per_epoch in any::<u64>(),
+        max_tokens_per_usd in any::<u64>(),
+        minted_in_epoch in any::<u64>(),
+        proposed in any::<u64>(),
+        smaller in any::<u64>(),
+        deposit_usd_value in any::<u64>(),
+    ) {
+        let budget = MintBudget {
+            epoch_duration: 3_600,
+            max_tokens_per_epoch,
+            max_tokens_per_usd,
+            epoch_start: 0,
+            minted_in_epoch,
+        };
+        let smaller = smaller.min(proposed);
+
+        if check_mint_limit(&mut budget.clone(), proposed, deposit_usd_value, 1).is_ok() {
+            prop_assert!(check_mint_limit(&mut budget.clone(), smaller, deposit_usd_value, 1).is_ok());
+        }
+    }
+
+    #[test]
+    fn donut_amount_never_panics_and_needs_a_twap(
+        token_a in any::<u64>(),
+        token_b in any::<u64>(),
+        fee in any_fee(),
+        sol_amount in any::<u64>(),
+        observations in observations(),
+    ) {
+        let (state, now) = state_with(observations);
+        let reserves = reserves(token_a, token_b, fee);
+        let result = get_donut_tokens_amount(Some(&reserves), &state, sol_amount, now);
+
+        match (calculate_twap_price(&state, now), result) {
+            (None, result) => prop_assert!(result.is_err()),
+            (Some(_), Ok(amount)) => prop_assert!(amount > 0),
+            (Some(_), Err(_)) => {},
+        }
+
+        prop_assert!(get_donut_tokens_amount(None, &state, sol_amount, now).is_err());
+    }
+
+    #[test]
+    fn donut_amount_ignores_the_spot_reserves(
+        first in (any::<u64>(), any::<u64>()),
+        second in (any::<u64>(), any::<u64>()),
+        fee_numerator in 0u64..1_000,
+        sol_amount in any::<u64>(),
+        observations in observations(),
+    ) {
+        let (state, now) = state_with(observations);
+        let at_first = get_donut_tokens_amount(Some(&reserves(first.0, first.1, (fee_numerator, 10_000))), &state, sol_amount, now);
+        let at_second = get_donut_tokens_amount(Some(&reserves(second.0, second.1, (fee_numerator, 10_000))), &state, sol_amount, now);
+
+        prop_assert_eq!(at_first.ok(), at_second.ok());
+    }
+
+    #[test]
+    fn donut_amount_rises_with_the_deposit(
+        token_a in 1u64..=1 << 60,
+        token_b in 1u64..=1 << 60,
+        fee_numerator in 0u64..1_000,
+        first in any::<u64>(),
+        second in any::<u64>(),
+        observations in observations(),
+    ) {
+        let (state, now) = state_with(observations);
+        let reserves = reserves(token_a, token_b, (fee_numerator, 10_000));
+        let (smaller, larger) = (first.min(second), first.max(second));
+
+        let at_smaller = get_donut_tokens_amount(Some(&reserves), &state, smaller, now);
+        let at_larger = get_donut_tokens_amount(Some(&reserves), &state, larger, now);
+
+        if let (Ok(at_smaller), Ok(at_larger)) = (at_smaller, at_larger) {
+            prop_assert!(at_smaller <= at_larger);
+        }
+    }
+
+    #[test]
+    fn lp_share_amounts_never_panic(total in any::<u64>(), share in any::<u64>(), supply in any::<u64>()) {
+        if let Some(amount) = meteora::amount_by_share(total, share, supply) {
+            if share <= supply {
+                prop_assert!(amount <= total);
+            }
+        }
+    }
+
+    #[test]
+    fn arbitrary_account_data_never_panics(data in prop::collection::vec(any::<u8>(), 0..1_024)) {
+        let _ = decode_user_account_data(&data);
+    }
+
+    #[test]
+    fn user_accounts_round_trip(data in user_account_data(), padding in prop::collection::vec(any::<u8>(), 0..256)) {
+        // Upline accounts are allocated for a full upline, so shorter ones carry trailing bytes
+        let mut padded = data.clone();
+        padded.extend_from_slice(&padding);
+        let decoded = decode_user_account_data(&padded).unwrap();
+        prop_assert_eq!(account_data(&decoded), data.clone());
+
+        // Any truncation is rejected
+        for len in 0..data.len() {
+            prop_assert!(decode_user_account_data(&data[..len]).is_err());
+        }
+    }
+}
@@ -0,0 +1,567 @@
+// matrix: the pure slot, recursion and reset state machine
+use anchor_lang::prelude::*;
+use matrix_system::matrix::{self, Effect, MatrixNode, MatrixOutcome, Target, MATRIX_SLOTS};
+use matrix_system::{ErrorCode, ReferralChain, UserAccount};
+use proptest::prelude::*;
+
+const DEPOSIT: u64 = 200_000_000;
+const NEXT_CHAIN_ID: u32 = 100;
+const EXPIRES_AT: i64 = 1_700_000_000;
+
+// Tokens minted per lamport by the test pricing
+const TOKENS_PER_LAMPORT: u64 = 3;
+
+// Node whose matrix has `filled_slots` slots taken
+fn node(chain_id: u32, filled_slots: u8, reserved_sol: u64, reserved_tokens: u64) -> MatrixNode {
+    let mut slots = [None; 3];
+    for slot in slots.iter_mut().take(filled_slots as usize) {
+        *slot = Some(Pubkey::new_unique());
+    }
+
+    MatrixNode {
+        key: Pubkey::new_unique(),
+        chain: ReferralChain {
+            id: chain_id,
+            slots,
+            filled_slots,
+        },
+        reserved_sol,
+        reserved_tokens,
+        expires_at: if filled_slots > 0 { EXPIRES_AT - 1 } else { 0 },
+    }
+}
+
+// Node with a matrix in the state that matches its filled slots: slot 2 filled means SOL is reserved
+fn node_at(chain_id: u32, filled_slots: u8) -> MatrixNode {
+    if filled_slots >= 2 {
+        node(chain_id, filled_slots, DEPOSIT, DEPOSIT * TOKENS_PER_LAMPORT)
+    } else {
+        node(chain_id, filled_slots, 0, 0)
+    }
+}
+
+fn run(user: Pubkey, referrer: &mut MatrixNode, uplines: &mut [MatrixNode], deposit: u64) -> Result<MatrixOutcome> {
+    matrix::process_deposit(
+        user,
+        referrer,
+        uplines,
+        deposit,
+        NEXT_CHAIN_ID,
+        EXPIRES_AT,
+        |sol_amount| Ok(sol_amount * TOKENS_PER_LAMPORT),
+    )
+}
+
+fn assert_error(result: Result<MatrixOutcome>, expected: ErrorCode) {
+    match result {
+        Err(anchor_lang::error::Error::AnchorError(error)) => {
+            assert_eq!(error.error_code_number, u32::from(expected));
+        },
+        other => panic!("expected {:?}, got {:?}", u32::from(expected), other.map(|outcome| outcome.effects)),
+    }
+}
+
+// ===== DIRECT REFERRER =====
+
+#[test]
+fn slot1_deposits_to_pool() {
+    let user = Pubkey::new_unique();
+    let mut referrer = node_at(7, 0);
+
+    let outcome = run(user, &mut referrer, &mut [], DEPOSIT).unwrap();
+
+    assert_eq!(outcome.effects, vec![
+        Effect::SlotFilled { target: Target::Referrer, slot_idx: 0, chain_id: 7, user },
+        Effect::DepositToPool { amount: DEPOSIT },
+    ]);
+    assert_eq!(outcome.next_chain_id, NEXT_CHAIN_ID);
+    assert_eq!(outcome.uplines_updated, 0);
+
+    assert_eq!(referrer.chain.slots, [Some(user), None, None]);
+    assert_eq!(referrer.chain.filled_slots, 1);
+    assert_eq!(referrer.expires_at, EXPIRES_AT);
+}
+
+#[test]
+fn slot2_unwraps_reserves_and_mints() {
+    let user = Pubkey::new_unique();
+    let mut referrer = node_at(7, 1);
+    let first_slot = referrer.chain.slots[0];
+
+    let outcome = run(user, &mut referrer, &mut [], DEPOSIT).unwrap();
+
+    assert_eq!(outcome.effects, vec![
+        Effect::SlotFilled { target: Target::Referrer, slot_idx: 1, chain_id: 7, user },
+        Effect::CloseWsol,
+        Effect::ReserveSol { target: Target::Referrer, amount: DEPOSIT, previous: 0 },
+        Effect::MintTokens { target: Target::Referrer, amount: DEPOSIT * TOKENS_PER_LAMPORT },
+    ]);
+
+    assert_eq!(referrer.chain.slots, [first_slot, Some(user), None]);
+    assert_eq!(referrer.chain.filled_slots, 2);
+    assert_eq!(referrer.expires_at, EXPIRES_AT - 1, "only the first slot sets the deadline");
+    assert_eq!(referrer.reserved_sol, DEPOSIT);
+    assert_eq!(referrer.reserved_tokens, DEPOSIT * TOKENS_PER_LAMPORT);
+}
+
+#[test]
+fn slot2_replaces_a_previous_reservation() {
+    let mut referrer = node(7, 1, 5, 15);
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut [], DEPOSIT).unwrap();
+
+    assert!(outcome.effects.contains(&Effect::ReserveSol { target: Target::Referrer, amount: DEPOSIT, previous: 5 }));
+    assert_eq!(referrer.reserved_sol, DEPOSIT);
+}
+
+#[test]
+fn slot2_prices_the_mint_with_the_deposit() {
+    let mut referrer = node_at(7, 1);
+    let mut quoted = Vec::new();
+
+    matrix::process_deposit(
+        Pubkey::new_unique(),
+        &mut referrer,
+        &mut [],
+        DEPOSIT,
+        NEXT_CHAIN_ID,
+        EXPIRES_AT,
+        |sol_amount| {
+            quoted.push(sol_amount);
+            Ok(42)
+        },
+    ).unwrap();
+
+    assert_eq!(quoted, vec![DEPOSIT]);
+    assert_eq!(referrer.reserved_tokens, 42);
+}
+
+#[test]
+fn slot2_propagates_pricing_errors() {
+    let mut referrer = node_at(7, 1);
+
+    let result = matrix::process_deposit(
+        Pubkey::new_unique(),
+        &mut referrer,
+        &mut [],
+        DEPOSIT,
+        NEXT_CHAIN_ID,
+        EXPIRES_AT,
+        |_| Err(error!(ErrorCode::MintBudgetExceeded)),
+    );
+
+    assert_error(result, ErrorCode::MintBudgetExceeded);
+}
+
+#[test]
+fn slot3_pays_and_resets_without_uplines() {
+    let user = Pubkey::new_unique();
+    let mut referrer = node_at(7, 2);
+
+    let outcome = run(user, &mut referrer, &mut [], DEPOSIT).unwrap();
+
+    // Without uplines the deposit stays in the user's WSOL account
+    assert_eq!(outcome.effects, vec![
+        Effect::SlotFilled { target: Target::Referrer, slot_idx: 2, chain_id: 7, user },
+        Effect::PaySol { target: Target::Referrer, amount: DEPOSIT },
+        Effect::PayTokens { target: Target::Referrer, amount: DEPOSIT * TOKENS_PER_LAMPORT },
+        Effect::ResetChain { target: Target::Referrer, new_chain_id: NEXT_CHAIN_ID },
+    ]);
+    assert_eq!(outcome.next_chain_id, NEXT_CHAIN_ID + 1);
+
+    assert_eq!(referrer.chain, ReferralChain {
+        id: NEXT_CHAIN_ID,
+        slots: [None, None, None],
+        filled_slots: 0,
+    });
+    assert_eq!(referrer.reserved_sol, 0);
+    assert_eq!(referrer.reserved_tokens, 0);
+    assert_eq!(referrer.expires_at, 0);
+}
+
+#[test]
+fn slot3_without_reservations_still_prepares_the_token_payout() {
+    let mut referrer = node(7, 2, 0, 0);
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut [], DEPOSIT).unwrap();
+
+    assert!(!outcome.effects.iter().any(|effect| matches!(effect, Effect::PaySol { .. })));
+    assert!(outcome.effects.contains(&Effect::PayTokens { target: Target::Referrer, amount: 0 }));
+}
+
+#[test]
+fn rejects_full_referrer_matrix() {
+    let mut referrer = node(7, MATRIX_SLOTS, 0, 0);
+    assert_error(run(Pubkey::new_unique(), &mut referrer, &mut [], DEPOSIT), ErrorCode::ChainFull);
+}
+
+#[test]
+fn uplines_are_ignored_until_the_referrer_completes() {
+    for filled_slots in 0..2 {
+        let mut referrer = node_at(7, filled_slots);
+        let mut uplines = vec![node_at(8, 0)];
+        let before = uplines.clone();
+
+        let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, DEPOSIT).unwrap();
+
+        assert_eq!(outcome.uplines_updated, 0);
+        assert_eq!(uplines, before);
+        assert!(!outcome.effects.iter().any(|effect| matches!(
+            effect,
+            Effect::SlotFilled { target: Target::Upline(_), .. }
+        )));
+    }
+}
+
+// ===== RECURSION =====
+
+#[test]
+fn recursion_fills_upline_slot1_with_a_pool_deposit() {
+    let mut referrer = node_at(7, 2);
+    let mut uplines = vec![node_at(8, 0), node_at(9, 0)];
+    let referrer_key = referrer.key;
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, DEPOSIT).unwrap();
+
+    assert_eq!(outcome.effects[4..], [
+        Effect::SlotFilled { target: Target::Upline(0), slot_idx: 0, chain_id: 8, user: referrer_key },
+        Effect::DepositToPool { amount: DEPOSIT },
+        Effect::CloseWsol,
+    ]);
+    assert_eq!(outcome.uplines_updated, 1);
+    assert_eq!(uplines[0].chain.slots[0], Some(referrer_key));
+    assert_eq!(uplines[0].expires_at, EXPIRES_AT);
+    assert_eq!(uplines[1].chain.filled_slots, 0);
+}
+
+#[test]
+fn recursion_reserves_for_upline_slot2() {
+    let mut referrer = node_at(7, 2);
+    let mut uplines = vec![node_at(8, 1)];
+    let referrer_key = referrer.key;
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, DEPOSIT).unwrap();
+
+    // The WSOL account is unwrapped once, before the reservation, and not closed again
+    assert_eq!(outcome.effects[4..], [
+        Effect::SlotFilled { target: Target::Upline(0), slot_idx: 1, chain_id: 8, user: referrer_key },
+        Effect::CloseWsol,
+        Effect::ReserveSol { target: Target::Upline(0), amount: DEPOSIT, previous: 0 },
+        Effect::MintTokens { target: Target::Upline(0), amount: DEPOSIT * TOKENS_PER_LAMPORT },
+    ]);
+    assert_eq!(uplines[0].reserved_sol, DEPOSIT);
+    assert_eq!(uplines[0].reserved_tokens, DEPOSIT * TOKENS_PER_LAMPORT);
+}
+
+#[test]
+fn recursion_passes_completed_matrices_up() {
+    let mut referrer = node_at(7, 2);
+    let mut uplines = vec![node_at(8, 2), node_at(9, 2), node_at(10, 0)];
+    let keys: Vec<Pubkey> = uplines.iter().map(|upline| upline.key).collect();
+    let referrer_key = referrer.key;
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, DEPOSIT).unwrap();
+
+    // Each upline's slot holds the account whose matrix completed below it
+    let filled: Vec<(Target, Pubkey)> = outcome.effects.iter().filter_map(|effect| match effect {
+        Effect::SlotFilled { target: target @ Target::Upline(_), user, .. } => Some((*target, *user)),
+        _ => None,
+    }).collect();
+    assert_eq!(filled, vec![
+        (Target::Upline(0), referrer_key),
+        (Target::Upline(1), keys[0]),
+        (Target::Upline(2), keys[1]),
+    ]);
+
+    // The referrer and the two completed uplines get consecutive new chain IDs
+    let resets: Vec<Effect> = outcome.effects.iter().copied()
+        .filter(|effect| matches!(effect, Effect::ResetChain { .. }))
+        .collect();
+    assert_eq!(resets, vec![
+        Effect::ResetChain { target: Target::Referrer, new_chain_id: NEXT_CHAIN_ID },
+        Effect::ResetChain { target: Target::Upline(0), new_chain_id: NEXT_CHAIN_ID + 1 },
+        Effect::ResetChain { target: Target::Upline(1), new_chain_id: NEXT_CHAIN_ID + 2 },
+    ]);
+    assert_eq!(outcome.next_chain_id, NEXT_CHAIN_ID + 3);
+    assert_eq!(outcome.uplines_updated, 3);
+
+    assert!(outcome.effects.contains(&Effect::PaySol { target: Target::Upline(0), amount: DEPOSIT }));
+    assert!(outcome.effects.contains(&Effect::PaySol { target: Target::Upline(1), amount: DEPOSIT }));
+    assert_eq!(outcome.effects.last(), Some(&Effect::CloseWsol));
+    assert_eq!(uplines[2].chain.slots[0], Some(keys[1]));
+}
+
+#[test]
+fn recursion_deposits_leftover_when_every_upline_completes() {
+    let mut referrer = node_at(7, 2);
+    let mut uplines = vec![node_at(8, 2), node_at(9, 2)];
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, DEPOSIT).unwrap();
+
+    let tail = &outcome.effects[outcome.effects.len() - 2..];
+    assert_eq!(tail, [Effect::DepositToPool { amount: DEPOSIT }, Effect::CloseWsol]);
+    assert_eq!(outcome.uplines_updated, 2);
+}
+
+#[test]
+fn recursion_stops_at_max_upline_depth() {
+    let mut referrer = node_at(7, 2);
+    let mut uplines: Vec<MatrixNode> = (0..8).map(|index| node_at(8 + index, 2)).collect();
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, DEPOSIT).unwrap();
+
+    assert_eq!(outcome.uplines_updated, 6);
+    assert_eq!(outcome.next_chain_id, NEXT_CHAIN_ID + 7);
+    assert_eq!(uplines[6].chain.filled_slots, 2);
+    assert_eq!(uplines[7].chain.filled_slots, 2);
+    assert!(!outcome.effects.iter().any(|effect| matches!(
+        effect,
+        Effect::SlotFilled { target: Target::Upline(6), .. }
+    )));
+}
+
+#[test]
+fn rejects_full_upline_matrix() {
+    let mut referrer = node_at(7, 2);
+    let mut uplines = vec![node(8, MATRIX_SLOTS, 0, 0)];
+
+    assert_error(run(Pubkey::new_unique(), &mut referrer, &mut uplines, DEPOSIT), ErrorCode::ChainFull);
+}
+
+#[test]
+fn zero_deposit_does_not_recurse() {
+    let mut referrer = node_at(7, 2);
+    let mut uplines = vec![node_at(8, 0)];
+
+    let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, 0).unwrap();
+
+    assert_eq!(outcome.uplines_updated, 0);
+    assert_eq!(uplines[0].chain.filled_slots, 0);
+}
+
+// ===== ACCOUNT SNAPSHOTS =====
+
+#[test]
+fn reset_chain_clears_the_matrix() {
+    let mut chain = node_at(7, 2).chain;
+    matrix::reset_chain(&mut chain, 11);
+
+    assert_eq!(chain, ReferralChain { id: 11, slots: [None, None, None], filled_slots: 0 });
+}
+
+#[test]
+fn snapshot_round_trips_through_the_user_account() {
+    let source = node_at(7, 2);
+    let mut account = UserAccount {
+        is_registered: true,
+        owner_wallet: Pubkey::new_unique(),
+        ..UserAccount::default()
+    };
+
+    source.apply_to(&mut account);
+    assert_eq!(MatrixNode::from_account(source.key, &account), source);
+    assert!(account.is_registered, "only the matrix fields are written");
+}
+
+// ===== PROPERTIES =====
+
+// Referrer and up to 8 uplines in arbitrary valid matrix states
+fn matrices() -> impl Strategy<Value = (MatrixNode, Vec<MatrixNode>)> {
+    let matrix = (0u8..MATRIX_SLOTS, 0u64..1_000_000_000, 0u64..1_000_000_000_000)
+        .prop_map(|(filled_slots, reserved_sol, reserved_tokens)| {
+            node(u32::from(filled_slots) + 1, filled_slots, reserved_sol, reserved_tokens)
+        });
+
+    (matrix.clone(), prop::collection::vec(matrix, 0..9))
+}
+
+// SOL taken from the deposit by the effects (pool deposits and reservations)
+fn deposit_spent(effects: &[Effect]) -> u64 {
+    effects.iter().map(|effect| match effect {
+        Effect::DepositToPool { amount } | Effect::ReserveSol { amount, .. } => *amount,
+        _ => 0,
+    }).sum()
+}
+
+proptest! {
+    #[test]
+    fn deposit_is_spent_exactly_once(
+        (mut referrer, mut uplines) in matrices(),
+        deposit in 1u64..u64::MAX / 4,
+    ) {
+        let referrer_completes = referrer.chain.filled_slots == 2;
+        let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, deposit).unwrap();
+
+        // Only a completed root matrix (no uplines) leaves the deposit unspent
+        let expected = if referrer_completes && uplines.is_empty() { 0 } else { deposit };
+        prop_assert_eq!(deposit_spent(&outcome.effects), expected);
+    }
+
+    #[test]
+    fn slots_advance_and_reset_consistently(
+        (mut referrer, mut uplines) in matrices(),
+        deposit in 1u64..1_000_000_000,
+    ) {
+        let before_referrer = referrer.clone();
+        let before_uplines = uplines.clone();
+
+        let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, deposit).unwrap();
+
+        prop_assert!(outcome.uplines_updated <= 6);
+        prop_assert!(outcome.uplines_updated <= uplines.len());
+
+        let mut touched = vec![(before_referrer, referrer)];
+        touched.extend(before_uplines.iter().cloned().zip(uplines.iter().cloned()).take(outcome.uplines_updated));
+
+        let mut resets = 0;
+        for (before, after) in &touched {
+            prop_assert_eq!(after.chain.filled_slots, (before.chain.filled_slots + 1) % MATRIX_SLOTS);
+
+            if before.chain.filled_slots == 2 {
+                // Completed: new ID, empty matrix and reservations paid out
+                prop_assert_eq!(after.chain.id, NEXT_CHAIN_ID + resets);
+                prop_assert_eq!(after.chain.slots, [None, None, None]);
+                prop_assert_eq!(after.reserved_sol, 0);
+                prop_assert_eq!(after.reserved_tokens, 0);
+                resets += 1;
+            } else {
+                prop_assert_eq!(after.chain.id, before.chain.id);
+                prop_assert_eq!(
+                    after.chain.filled_slots as usize,
+                    after.chain.slots.iter().filter(|slot| slot.is_some()).count()
+                );
+            }
+        }
+
+        prop_assert_eq!(outcome.next_chain_id, NEXT_CHAIN_ID + resets);
+
+        // Uplines past the last visited one are untouched
+        prop_assert_eq!(&uplines[outcome.uplines_updated..], &before_uplines[outcome.uplines_updated..]);
+    }
+
+    #[test]
+    fn payouts_match_the_released_reservations(
+        (mut referrer, mut uplines) in matrices(),
+        deposit in 1u64..1_000_000_000,
+    ) {
+        let mut nodes = vec![referrer.clone()];
+        nodes.extend(uplines.iter().cloned());
+
+        let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, deposit).unwrap();
+
+        for effect in &outcome.effects {
+            match *effect {
+                Effect::PaySol { target, amount } => {
+                    let before = &nodes[target_index(target)];
+                    prop_assert_eq!(amount, before.reserved_sol);
+                    prop_assert!(amount > 0);
+                },
+                Effect::PayTokens { target, amount } => {
+                    prop_assert_eq!(amount, nodes[target_index(target)].reserved_tokens);
+                },
+                Effect::ReserveSol { target, previous, .. } => {
+                    prop_assert_eq!(previous, nodes[target_index(target)].reserved_sol);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    #[test]
+    fn wsol_is_unwrapped_at_most_once_and_last(
+        (mut referrer, mut uplines) in matrices(),
+        deposit in 1u64..1_000_000_000,
+    ) {
+        let outcome = run(Pubkey::new_unique(), &mut referrer, &mut uplines, deposit).unwrap();
+
+        let closes: Vec<usize> = outcome.effects.iter().enumerate()
+            .filter(|(_, effect)| matches!(effect, Effect::CloseWsol))
+            .map(|(index, _)| index)
+            .collect();
+        prop_assert!(closes.len() <= 1);
+
+        // Nothing is deposited from the WSOL account after it's closed
+        if let Some(&close) = closes.first() {
+            let deposits_after_close = outcome.effects[close..].iter()
+                .any(|effect| matches!(effect, Effect::DepositToPool { .. }));
+            prop_assert!(!deposits_after_close);
+        }
+
+        // Every mint follows the reservation it prices
+        for pair in outcome.effects.windows(2) {
+            if let Effect::MintTokens { target, .. } = pair[1] {
+                let follows_reservation = matches!(pair[0], Effect::ReserveSol { target: reserved, .. } if reserved == target);
+                prop_assert!(follows_reservation);
+            }
+        }
+    }
+
+    #[test]
+    fn registrations_conserve_sol(
+        referrers in prop::collection::vec(0usize..64, 1..200),
+        deposit in 1u64..1_000_000_000,
+    ) {
+        // A tree of 64 accounts where account i's uplines are its ancestors, closest first
+        let mut nodes: Vec<MatrixNode> = (0..64).map(|index| node(index + 1, 0, 0, 0)).collect();
+        let parent = |index: usize| if index == 0 { None } else { Some((index - 1) / 2) };
+
+        let mut next_chain_id = NEXT_CHAIN_ID;
+        let (mut pool, mut paid, mut left_in_wallets) = (0u64, 0u64, 0u64);
+
+        for &referrer_index in &referrers {
+            let mut path = Vec::new();
+            let mut current = parent(referrer_index);
+            while let Some(index) = current {
+                path.push(index);
+                current = parent(index);
+            }
+
+            let mut referrer = nodes[referrer_index].clone();
+            let mut uplines: Vec<MatrixNode> = path.iter().map(|&index| nodes[index].clone()).collect();
+
+            let outcome = matrix::process_deposit(
+                Pubkey::new_unique(),
+                &mut referrer,
+                &mut uplines,
+                deposit,
+                next_chain_id,
+                EXPIRES_AT,
+                |sol_amount| Ok(sol_amount * TOKENS_PER_LAMPORT),
+            ).unwrap();
+
+            next_chain_id = outcome.next_chain_id;
+            nodes[referrer_index] = referrer;
+            for (offset, upline) in uplines.into_iter().enumerate().take(outcome.uplines_updated) {
+                nodes[path[offset]] = upline;
+            }
+
+            let spent = deposit_spent(&outcome.effects);
+            left_in_wallets += deposit - spent;
+
+            for effect in &outcome.effects {
+                match *effect {
+                    Effect::DepositToPool { amount } => pool += amount,
+                    Effect::PaySol { amount, .. } => paid += amount,
+                    _ => {},
+                }
+            }
+        }
+
+        // Every lamport is in the pool, reserved, paid out or still with the registrant
+        let reserved: u64 = nodes.iter().map(|node| node.reserved_sol).sum();
+        prop_assert_eq!(pool + reserved + paid + left_in_wallets, deposit * referrers.len() as u64);
+
+        for node in &nodes {
+            prop_assert!(node.chain.filled_slots < MATRIX_SLOTS);
+            prop_assert_eq!(node.reserved_tokens, node.reserved_sol * TOKENS_PER_LAMPORT);
+        }
+    }
+}
+
+// Index of a target in [referrer, uplines...]
+fn target_index(target: Target) -> usize {
+    match target {
+        Target::Referrer => 0,
+        Target::Upline(index) => index + 1,
+    }
+}
@@ -0,0 +1,360 @@
+// meteora: constant-product swap output against an independent u128 reference, the vault share
+// and virtual-price math, and the layout of the typed vault deposit and withdraw instructions
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use matrix_system::meteora::{self, swap_output};
+use proptest::prelude::*;
+
+// Reference output: floor(destination * net_in / (source + net_in)), the fee taken from the input.
+// Equal to destination - ceil(source * destination / (source + net_in)), the pool-favouring rounding
+fn reference_output(source: u64, destination: u64, amount_in: u64, fee_numerator: u64, fee_denominator: u64) -> Option<u64> {
+    if fee_denominator == 0 || fee_numerator >= fee_denominator {
+        return None;
+    }
+
+    let fee = amount_in as u128 * fee_numerator as u128 / fee_denominator as u128;
+    let net_in = amount_in as u128 - fee;
+    let new_source = source as u128 + net_in;
+    if new_source == 0 {
+        return None;
+    }
+
+    Some((destination as u128 * net_in / new_source) as u64)
+}
+
+// Valid trade fees, from none up to 99.99%
+fn valid_fee() -> impl Strategy<Value = (u64, u64)> {
+    prop_oneof![
+        Just((0, 1)),
+        Just((25, 10_000)),
+        (1u64..=u64::MAX).prop_flat_map(|denominator| (0..denominator, Just(denominator))),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn output_matches_the_reference(
+        source in any::<u64>(),
+        destination in any::<u64>(),
+        amount_in in any::<u64>(),
+        (fee_numerator, fee_denominator) in valid_fee(),
+    ) {
+        prop_assert_eq!(
+            swap_output(source, destination, amount_in, fee_numerator, fee_denominator),
+            reference_output(source, destination, amount_in, fee_numerator, fee_denominator)
+        );
+    }
+
+    #[test]
+    fn output_keeps_the_invariant(
+        source in 1u64..,
+        destination in any::<u64>(),
+        amount_in in any::<u64>(),
+        (fee_numerator, fee_denominator) in valid_fee(),
+    ) {
+        let out = swap_output(source, destination, amount_in, fee_numerator, fee_denominator).unwrap();
+        let net_in = amount_in as u128 - amount_in as u128 * fee_numerator as u128 / fee_denominator as u128;
+
+        // The pool never ends with a smaller product, and never pays out its whole reserve
+        prop_assert!((source as u128 + net_in) * (destination - out) as u128 >= source as u128 * destination as u128);
+        prop_assert!(out < destination || destination == 0);
+    }
+
+    #[test]
+    fn output_rises_with_the_input(
+        source in any::<u64>(),
+        destination in any::<u64>(),
+        first in any::<u64>(),
+        second in any::<u64>(),
+        (fee_numerator, fee_denominator) in valid_fee(),
+    ) {
+        let (smaller, larger) = (first.min(second), first.max(second));
+        let at_smaller = swap_output(source, destination, smaller, fee_numerator, fee_denominator);
+        let at_larger = swap_output(source, destination, larger, fee_numerator, fee_denominator);
+
+        if let (Some(at_smaller), Some(at_larger)) = (at_smaller, at_larger) {
+            prop_assert!(at_smaller <= at_larger);
+        }
+    }
+
+    #[test]
+    fn invalid_fees_are_rejected(
+        source in any::<u64>(),
+        destination in any::<u64>(),
+        amount_in in any::<u64>(),
+        fee_denominator in any::<u64>(),
+        excess in any::<u64>(),
+    ) {
+        let fee_numerator = fee_denominator.saturating_add(excess);
+        prop_assert_eq!(swap_output(source, destination, amount_in, fee_numerator, fee_denominator), None);
+        prop_assert_eq!(swap_output(source, destination, amount_in, 0, 0), None);
+    }
+}
+
+#[test]
+fn maximum_reserves_and_input_do_not_overflow() {
+    let max = u64::MAX;
+
+    // source * destination and destination * net_in both reach (2^64 - 1)^2
+    assert_eq!(swap_output(max, max, max, 0, 1), reference_output(max, max, max, 0, 1));
+    assert_eq!(swap_output(max, max, max, 0, 1), Some(max / 2));
+    assert_eq!(swap_output(max, max, 1, 0, 1), Some(0));
+    assert_eq!(swap_output(1, max, max, 0, 1), Some(max - 1));
+    assert_eq!(swap_output(max, max, max, 9_999, 10_000), reference_output(max, max, max, 9_999, 10_000));
+}
+
+#[test]
+fn empty_reserves() {
+    // No DONUT in the pool: nothing can come out
+    assert_eq!(swap_output(1_000, 0, 1_000, 25, 10_000), Some(0));
+    assert_eq!(swap_output(0, 0, 1_000, 25, 10_000), Some(0));
+
+    // No SOL in the pool: any input buys the whole DONUT reserve
+    assert_eq!(swap_output(0, 1_000, 1_000, 25, 10_000), Some(1_000));
+
+    // Nothing in, nothing on the source side: the price is undefined
+    assert_eq!(swap_output(0, 1_000, 0, 25, 10_000), None);
+
+    // Outputs round down, in favour of the pool
+    assert_eq!(swap_output(1_000, 1_000, 3, 25, 100), Some(2));
+    assert_eq!(swap_output(1_000, 1_000, 1, 25, 100), Some(0));
+}
+
+#[test]
+fn known_outputs() {
+    // 1 SOL into a 1,000 SOL / 1,000,000 DONUT pool at 0.25%
+    let out = swap_output(1_000_000_000_000, 1_000_000_000_000_000, 1_000_000_000, 25, 10_000).unwrap();
+    assert_eq!(out, 996_505_985_279);
+
+    // Without a fee, 1 into 1 / 2 takes one token out
+    assert_eq!(swap_output(1, 2, 1, 0, 1), Some(1));
+}
+
+// First 8 bytes of sha256("<namespace>:<name>"), as Anchor derives them
+fn sighash(preimage: &str) -> [u8; 8] {
+    hash(preimage.as_bytes()).to_bytes()[..8].try_into().unwrap()
+}
+
+#[test]
+fn discriminators_match_the_anchor_sighash() {
+    assert_eq!(meteora::DEPOSIT_DISCRIMINATOR, sighash("global:deposit"));
+    assert_eq!(meteora::WITHDRAW_DISCRIMINATOR, sighash("global:withdraw"));
+    assert_eq!(meteora::SWAP_DISCRIMINATOR, sighash("global:swap"));
+    assert_eq!(meteora::VAULT_ACCOUNT_DISCRIMINATOR, sighash("account:Vault"));
+    assert_eq!(meteora::POOL_ACCOUNT_DISCRIMINATOR, sighash("account:Pool"));
+}
+
+#[test]
+fn deposit_instruction_layout() {
+    let accounts = meteora::VaultUserAccounts {
+        vault: Pubkey::new_unique(),
+        token_vault: Pubkey::new_unique(),
+        lp_mint: Pubkey::new_unique(),
+        user_token: Pubkey::new_unique(),
+        user_lp: Pubkey::new_unique(),
+        user: Pubkey::new_unique(),
+        token_program: anchor_spl::token::ID,
+    };
+    let ix = meteora::deposit(&accounts, meteora::DepositArgs { token_amount: 7, minimum_lp_token_amount: 3 });
+
+    assert_eq!(ix.program_id, matrix_system::verified_addresses::VAULT_PROGRAM);
+
+    let mut data = sighash("global:deposit").to_vec();
+    data.extend_from_slice(&7u64.to_le_bytes());
+    data.extend_from_slice(&3u64.to_le_bytes());
+    assert_eq!(ix.data, data);
+
+    // Only the user signs, and only the token program is read-only besides it
+    let keys = [
+        accounts.vault,
+        accounts.token_vault,
+        accounts.lp_mint,
+        accounts.user_token,
+        accounts.user_lp,
+        accounts.user,
+        accounts.token_program,
+    ];
+    assert_eq!(ix.accounts.len(), keys.len());
+    for (index, (meta, key)) in ix.accounts.iter().zip(keys).enumerate() {
+        assert_eq!(meta.pubkey, key);
+        assert_eq!(meta.is_signer, index == 5);
+        assert_eq!(meta.is_writable, index < 5);
+    }
+}
+
+// Vault holding `total_amount` tokens, `tracker` describing its locked profit
+fn vault(total_amount: u64, locked_profit_tracker: meteora::LockedProfitTracker) -> meteora::Vault {
+    meteora::Vault {
+        enabled: 1,
+        bumps: meteora::VaultBumps::default(),
+        total_amount,
+        token_vault: Pubkey::new_unique(),
+        fee_vault: Pubkey::new_unique(),
+        token_mint: Pubkey::new_unique(),
+        lp_mint: Pubkey::new_unique(),
+        strategies: [Pubkey::default(); meteora::MAX_STRATEGY],
+        base: Pubkey::new_unique(),
+        admin: Pubkey::new_unique(),
+        operator: Pubkey::new_unique(),
+        locked_profit_tracker,
+    }
+}
+
+// 1,000,000 of profit reported at t = 0, released over the 6 hours Meteora vaults use
+fn six_hour_tracker() -> meteora::LockedProfitTracker {
+    meteora::LockedProfitTracker {
+        last_updated_locked_profit: 1_000_000,
+        last_report: 0,
+        locked_profit_degradation: (meteora::LOCKED_PROFIT_DEGRADATION_DENOMINATOR / 21_600) as u64,
+    }
+}
+
+#[test]
+fn locked_profit_degrades_linearly() {
+    let tracker = six_hour_tracker();
+    assert_eq!(tracker.locked_profit_degradation, 46_296_296);
+
+    assert_eq!(tracker.calculate_locked_profit(0), Some(1_000_000));
+    assert_eq!(tracker.calculate_locked_profit(10_800), Some(500_000));
+    assert_eq!(tracker.calculate_locked_profit(21_600), Some(0));
+    assert_eq!(tracker.calculate_locked_profit(21_601), Some(0));
+
+    // A report in the future can't be priced
+    let reported_later = meteora::LockedProfitTracker { last_report: 100, ..tracker };
+    assert_eq!(reported_later.calculate_locked_profit(99), None);
+}
+
+#[test]
+fn amount_by_share_excludes_the_locked_profit() {
+    let vault = vault(10_000_000, six_hour_tracker());
+
+    assert_eq!(vault.get_unlocked_amount(0), Some(9_000_000));
+    assert_eq!(vault.get_unlocked_amount(10_800), Some(9_500_000));
+    assert_eq!(vault.get_amount_by_share(10_800, 250, 1_000), Some(2_375_000));
+    assert_eq!(vault.get_amount_by_share(21_600, 250, 1_000), Some(2_500_000));
+    assert_eq!(vault.get_amount_by_share(21_600, 1_000, 1_000), Some(10_000_000));
+
+    // No LP supply, or more locked profit than the vault holds
+    assert_eq!(vault.get_amount_by_share(21_600, 250, 0), None);
+    let overdrawn = meteora::Vault { total_amount: 999_999, ..vault };
+    assert_eq!(overdrawn.get_unlocked_amount(0), None);
+}
+
+#[test]
+fn vault_account_round_trips() {
+    let vault = vault(10_000_000, six_hour_tracker());
+    let mut data = meteora::VAULT_ACCOUNT_DISCRIMINATOR.to_vec();
+    vault.serialize(&mut data).unwrap();
+
+    let read = meteora::Vault::from_account_data(&data).unwrap();
+    assert_eq!(read.total_amount, vault.total_amount);
+    assert_eq!(read.token_vault, vault.token_vault);
+    assert_eq!(read.locked_profit_tracker.locked_profit_degradation, 46_296_296);
+
+    // A pool account isn't read as a vault
+    data[..8].copy_from_slice(&meteora::POOL_ACCOUNT_DISCRIMINATOR);
+    assert!(meteora::Vault::from_account_data(&data).is_none());
+}
+
+#[test]
+fn withdraw_instruction_layout() {
+    let accounts = meteora::VaultUserAccounts {
+        vault: Pubkey::new_unique(),
+        token_vault: Pubkey::new_unique(),
+        lp_mint: Pubkey::new_unique(),
+        user_token: Pubkey::new_unique(),
+        user_lp: Pubkey::new_unique(),
+        user: Pubkey::new_unique(),
+        token_program: anchor_spl::token::ID,
+    };
+    let ix = meteora::withdraw(&accounts, meteora::WithdrawArgs { unmint_amount: 11, min_out_amount: 5 });
+
+    assert_eq!(ix.program_id, matrix_system::verified_addresses::VAULT_PROGRAM);
+
+    let mut data = sighash("global:withdraw").to_vec();
+    data.extend_from_slice(&11u64.to_le_bytes());
+    data.extend_from_slice(&5u64.to_le_bytes());
+    assert_eq!(ix.data, data);
+
+    // Same accounts as deposit: the LP tokens are burned from user_lp, the tokens sent to user_token
+    let keys = [
+        accounts.vault,
+        accounts.token_vault,
+        accounts.lp_mint,
+        accounts.user_token,
+        accounts.user_lp,
+        accounts.user,
+        accounts.token_program,
+    ];
+    assert_eq!(ix.accounts.len(), keys.len());
+    for (index, (meta, key)) in ix.accounts.iter().zip(keys).enumerate() {
+        assert_eq!(meta.pubkey, key);
+        assert_eq!(meta.is_signer, index == 5);
+        assert_eq!(meta.is_writable, index < 5);
+    }
+}
+
+#[test]
+fn vault_virtual_price_and_unmint_amount() {
+    let vault = vault(10_000_000, six_hour_tracker());
+
+    // unlocked_amount * PRECISION / lp_supply, with half the profit still locked
+    assert_eq!(vault.virtual_price(10_800, 1_000), Some(9_500_000 * meteora::VIRTUAL_PRICE_PRECISION / 1_000));
+    assert_eq!(vault.virtual_price(21_600, 1_000), Some(10_000 * meteora::VIRTUAL_PRICE_PRECISION));
+    assert_eq!(vault.virtual_price(21_600, 0), None);
+
+    // out_token * lp_supply / unlocked_amount, the inverse of get_amount_by_share
+    assert_eq!(vault.get_unmint_amount(10_800, 2_375_000, 1_000), Some(250));
+    assert_eq!(vault.get_unmint_amount(21_600, 2_500_000, 1_000), Some(250));
+    assert_eq!(vault.get_unmint_amount(21_600, 2_499_999, 1_000), Some(249));
+
+    // Nothing unlocked yet to withdraw against
+    let all_locked = meteora::Vault { total_amount: 1_000_000, ..vault };
+    assert_eq!(all_locked.get_unmint_amount(0, 1, 1_000), None);
+}
+
+#[test]
+fn lp_position_owns_its_share_of_the_vault() {
+    let position = meteora::LpPosition { amount: 250, supply: 1_000 };
+    assert_eq!(position.token_amount(10_000_000), Some(2_500_000));
+    assert_eq!(meteora::LpPosition { amount: 0, supply: 1_000 }.token_amount(10_000_000), Some(0));
+    assert_eq!(meteora::LpPosition { amount: 250, supply: 0 }.token_amount(10_000_000), None);
+}
+
+#[test]
+fn integer_sqrt_known_values() {
+    assert_eq!(meteora::integer_sqrt(0), 0);
+    assert_eq!(meteora::integer_sqrt(1), 1);
+    assert_eq!(meteora::integer_sqrt(15), 3);
+    assert_eq!(meteora::integer_sqrt(16), 4);
+    assert_eq!(meteora::integer_sqrt(u128::from(u64::MAX) * u128::from(u64::MAX)), u128::from(u64::MAX));
+    assert_eq!(meteora::integer_sqrt(u128::MAX), u128::from(u64::MAX));
+}
+
+#[test]
+fn pool_virtual_price_is_the_invariant_per_lp_token() {
+    // sqrt(4e6 * 9e6) = 6e6 per 1,000 LP tokens
+    assert_eq!(
+        meteora::pool_virtual_price(4_000_000, 9_000_000, 1_000),
+        Some(6_000 * meteora::VIRTUAL_PRICE_PRECISION),
+    );
+    assert_eq!(meteora::pool_virtual_price(4_000_000, 9_000_000, 0), None);
+    assert_eq!(meteora::pool_virtual_price(0, 9_000_000, 1_000), Some(0));
+
+    // Swaps grow the invariant by their fees, so the virtual price never falls
+    let before = meteora::pool_virtual_price(1_000_000_000_000, 50_000_000_000, 1_000_000).unwrap();
+    let out = swap_output(1_000_000_000_000, 50_000_000_000, 1_000_000, 25, 10_000).unwrap();
+    let after = meteora::pool_virtual_price(1_000_001_000_000, 50_000_000_000 - out, 1_000_000).unwrap();
+    assert!(after >= before);
+}
+
+proptest! {
+    #[test]
+    fn integer_sqrt_is_the_floor_root(value in any::<u128>()) {
+        let root = meteora::integer_sqrt(value);
+        prop_assert!(root * root <= value);
+        if let Some(next) = (root + 1).checked_mul(root + 1) {
+            prop_assert!(next > value);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// migrate_state: upgrade of a program state written before the layout was versioned, and
+// migrate_user_account: user accounts allocated before matrix expiry
+mod common;
+
+use anchor_lang::{prelude::*, Discriminator, InstructionData};
+use common::*;
+use matrix_system::{
+    accounts, instruction, ErrorCode, ExpiryPolicy, LegacyProgramState, ProgramState, Slot1Policy, UserAccount,
+    DEFAULT_BUYBACK_SLIPPAGE_BPS, DEFAULT_TWAP_WINDOW, STATE_LAYOUT_VERSION,
+};
+use solana_program_test::BanksClientError;
+use solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+};
+
+// Program state bytes as the baseline program wrote them
+fn legacy_state_account(owner: &Pubkey, multisig_treasury: &Pubkey) -> Account {
+    let legacy = LegacyProgramState {
+        owner: *owner,
+        multisig_treasury: *multisig_treasury,
+        next_upline_id: 42,
+        next_chain_id: 17,
+        last_mint_amount: 123_456,
+    };
+    let mut data = ProgramState::DISCRIMINATOR.to_vec();
+    legacy.serialize(&mut data).unwrap();
+
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: matrix_system::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+// Replace the program state with its legacy layout
+fn set_legacy_state(env: &mut TestEnv) {
+    let account = legacy_state_account(&env.owner.pubkey(), &env.treasury.pubkey());
+    let address = env.state;
+    env.set_account(&address, account);
+}
+
+async fn migrate_state(env: &mut TestEnv, signer: &Keypair) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::MigrateState {
+            state: env.state,
+            owner: signer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::MigrateState {}.data(),
+    };
+
+    env.send(&[ix], &[signer]).await
+}
+
+#[tokio::test]
+async fn legacy_state_migrates_to_the_current_layout() {
+    let mut env = TestEnv::new().await;
+    set_legacy_state(&mut env);
+
+    // Instructions cannot read the legacy layout
+    assert!(env.record_price().await.is_err());
+
+    let owner = env.owner.insecure_clone();
+    migrate_state(&mut env, &owner).await.unwrap();
+
+    let state_address = env.state;
+    let account = env.account(&state_address).await.unwrap();
+    assert_eq!(account.data.len(), 8 + ProgramState::SIZE);
+    assert!(account.lamports >= Rent::default().minimum_balance(account.data.len()));
+
+    let state = env.program_state().await;
+    assert_eq!(state.owner, env.owner.pubkey());
+    assert_eq!(state.multisig_treasury, env.treasury.pubkey());
+    assert_eq!(state.next_upline_id, 42);
+    assert_eq!(state.next_chain_id, 17);
+    assert_eq!(state.twap_window, DEFAULT_TWAP_WINDOW);
+    assert_eq!(state.observation_count, 0);
+    assert_eq!(state.slot1_policy, Slot1Policy::DepositLiquidity);
+    assert_eq!(state.buyback_slippage_bps, DEFAULT_BUYBACK_SLIPPAGE_BPS);
+    assert_eq!(state.treasury_fee_bps, 0);
+    assert_eq!(state.total_reserved_sol, 0);
+    assert_eq!(state.vesting_duration, 0);
+    assert_eq!(state.matrix_expiry, 0);
+    assert_eq!(state.expiry_policy, ExpiryPolicy::ReleaseToOwner);
+    assert_eq!(state.layout_version, STATE_LAYOUT_VERSION);
+
+    // Reservations from before the upgrade aren't in total_reserved_sol yet
+    assert!(!state.reserved_sol_reconciled);
+
+    // The migrated state is usable again
+    env.record_price().await.unwrap();
+    assert_eq!(env.program_state().await.observation_count, 1);
+}
+
+#[tokio::test]
+async fn only_the_owner_can_migrate() {
+    let mut env = TestEnv::new().await;
+    set_legacy_state(&mut env);
+
+    let treasury = env.treasury.insecure_clone();
+    let result = migrate_state(&mut env, &treasury).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+
+    let state_address = env.state;
+    assert_eq!(env.account(&state_address).await.unwrap().data.len(), 8 + LegacyProgramState::SIZE);
+}
+
+#[tokio::test]
+async fn current_state_is_not_migrated_again() {
+    let mut env = TestEnv::new().await;
+    let before = env.program_state().await;
+
+    let owner = env.owner.insecure_clone();
+    let result = migrate_state(&mut env, &owner).await;
+    assert_program_error(result, ErrorCode::StateAlreadyMigrated);
+
+    let after = env.program_state().await;
+    assert_eq!(after.next_upline_id, before.next_upline_id);
+    assert_eq!(after.observation_count, before.observation_count);
+}
+
+// ===== USER ACCOUNTS =====
+
+// Shrink the wallet's user account to the size the program allocated before matrix expiry,
+// with the bytes past its data left over from an older, longer serialization
+async fn set_legacy_user_account(env: &mut TestEnv, wallet: &Pubkey) {
+    let address = user_pda(wallet);
+    let mut account = env.account(&address).await.unwrap();
+
+    let mut data = UserAccount::DISCRIMINATOR.to_vec();
+    env.user_account(wallet).await.serialize(&mut data).unwrap();
+    data.truncate(data.len() - 8);
+    data.resize(8 + UserAccount::LEGACY_SIZE, 0xff);
+
+    account.data = data;
+    account.lamports = Rent::default().minimum_balance(account.data.len());
+    env.set_account(&address, account);
+}
+
+async fn migrate_user_account(env: &mut TestEnv, wallet: &Pubkey) -> std::result::Result<(), BanksClientError> {
+    let payer = env.create_wallet();
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::MigrateUserAccount {
+            user: user_pda(wallet),
+            payer: payer.pubkey(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::MigrateUserAccount {}.data(),
+    };
+
+    env.send(&[ix], &[&payer]).await
+}
+
+#[tokio::test]
+async fn legacy_user_account_migrates_to_the_current_layout() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.set_matrix_expiry(60, ExpiryPolicy::ReleaseToOwner).await.unwrap();
+    env.register_new(&root).await;
+    let before = env.user_account(&root).await;
+
+    set_legacy_user_account(&mut env, &root).await;
+
+    // The stale bytes read as a deadline, which expire_matrix refuses to trust
+    assert_ne!(env.user_account(&root).await.matrix_expires_at, 0);
+    let caller = env.create_wallet();
+    let accounts = env.expire_matrix_accounts(&root, &caller.pubkey());
+    let result = env.send_expire_matrix(accounts, &caller).await;
+    assert_program_error(result, ErrorCode::UserAccountNotMigrated);
+
+    migrate_user_account(&mut env, &root).await.unwrap();
+
+    let account = env.account(&user_pda(&root)).await.unwrap();
+    assert_eq!(account.data.len(), 8 + UserAccount::SIZE);
+    assert!(account.lamports >= Rent::default().minimum_balance(account.data.len()));
+
+    let after = env.user_account(&root).await;
+    assert_eq!(after.owner_wallet, before.owner_wallet);
+    assert_eq!(after.chain, before.chain);
+    assert_eq!(after.reserved_sol, before.reserved_sol);
+    assert_eq!(after.matrix_expires_at, 0);
+
+    let result = migrate_user_account(&mut env, &root).await;
+    assert_program_error(result, ErrorCode::UserAccountAlreadyMigrated);
+}
+
+#[tokio::test]
+async fn legacy_user_account_still_registers() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    set_legacy_user_account(&mut env, &root).await;
+
+    // The account keeps its size; the serialized data fits in the unused tail
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    assert_eq!(env.account(&user_pda(&root)).await.unwrap().data.len(), 8 + UserAccount::LEGACY_SIZE);
+    assert_eq!(env.user_account(&root).await.chain.filled_slots, 0);
+}
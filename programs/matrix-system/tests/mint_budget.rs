@@ -0,0 +1,125 @@
+// mint_budget: the rolling window and per-deposit limits on slot-2 mints, and their configuration
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use common::*;
+use matrix_system::{accounts, instruction, ErrorCode};
+use solana_program_test::BanksClientError;
+use solana_sdk::{instruction::Instruction, signature::Signer};
+
+// One DONUT (9 decimals)
+const DONUT: u64 = 1_000_000_000;
+
+// Short window so tests can move past it
+const SHORT_EPOCH: i64 = 600;
+
+// Register two wallets under `referrer`, the second one taking slot 2 and minting
+async fn mint_under(env: &mut TestEnv, referrer: &Pubkey) -> std::result::Result<(), BanksClientError> {
+    env.register_new(referrer).await;
+    let user = env.create_wallet();
+    env.register(&user, referrer, DEPOSIT).await
+}
+
+#[tokio::test]
+async fn per_mint_limit_follows_the_deposit_value() {
+    // The deposit is worth 20 USD and mints 199.5 DONUT
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.update_mint_budget(EPOCH_DURATION, MAX_TOKENS_PER_EPOCH, 9 * DONUT).await.unwrap();
+    let result = mint_under(&mut env, &root).await;
+    assert_program_error(result, ErrorCode::MintBudgetExceeded);
+
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.update_mint_budget(EPOCH_DURATION, MAX_TOKENS_PER_EPOCH, 10 * DONUT).await.unwrap();
+    mint_under(&mut env, &root).await.unwrap();
+    assert_eq!(env.mint_budget().await.minted_in_epoch, twap_donut_output(DEPOSIT));
+}
+
+#[tokio::test]
+async fn window_budget_resets_after_the_epoch() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let referrer = env.register_new(&root).await.pubkey();
+
+    // Room for exactly one mint per window
+    let one_mint = twap_donut_output(DEPOSIT);
+    env.update_mint_budget(SHORT_EPOCH, one_mint, MAX_TOKENS_PER_USD).await.unwrap();
+
+    let user = env.create_wallet();
+    env.register(&user, &root, DEPOSIT).await.unwrap();
+    assert_eq!(env.mint_budget().await.minted_in_epoch, one_mint);
+
+    env.register_new(&referrer).await;
+    let user = env.create_wallet();
+    let result = env.register(&user, &referrer, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::MintBudgetExceeded);
+
+    // The next window starts at the first mint after the previous one ends. The TWAP has moved
+    // with the slot-1 deposits, so the new window only holds this mint
+    env.advance_clock(SHORT_EPOCH).await;
+    env.set_sol_price(SOL_PRICE, 0).await;
+    env.register(&user, &referrer, DEPOSIT).await.unwrap();
+
+    let mint_budget = env.mint_budget().await;
+    assert_eq!(mint_budget.minted_in_epoch, env.user_account(&referrer).await.reserved_tokens);
+    assert_eq!(mint_budget.epoch_start, env.now().await);
+}
+
+#[tokio::test]
+async fn budget_update_keeps_the_window_counters() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    mint_under(&mut env, &root).await.unwrap();
+    let before = env.mint_budget().await;
+
+    env.update_mint_budget(SHORT_EPOCH, 2 * MAX_TOKENS_PER_EPOCH, 2 * MAX_TOKENS_PER_USD).await.unwrap();
+
+    let after = env.mint_budget().await;
+    assert_eq!(after.epoch_duration, SHORT_EPOCH);
+    assert_eq!(after.max_tokens_per_epoch, 2 * MAX_TOKENS_PER_EPOCH);
+    assert_eq!(after.max_tokens_per_usd, 2 * MAX_TOKENS_PER_USD);
+    assert_eq!(after.epoch_start, before.epoch_start);
+    assert_eq!(after.minted_in_epoch, before.minted_in_epoch);
+}
+
+#[tokio::test]
+async fn rejects_invalid_budget_config() {
+    let mut env = TestEnv::new().await;
+
+    for (epoch_duration, max_tokens_per_epoch, max_tokens_per_usd) in [
+        (0, MAX_TOKENS_PER_EPOCH, MAX_TOKENS_PER_USD),
+        (-1, MAX_TOKENS_PER_EPOCH, MAX_TOKENS_PER_USD),
+        (EPOCH_DURATION, 0, MAX_TOKENS_PER_USD),
+        (EPOCH_DURATION, MAX_TOKENS_PER_EPOCH, 0),
+    ] {
+        let result = env.update_mint_budget(epoch_duration, max_tokens_per_epoch, max_tokens_per_usd).await;
+        assert_program_error(result, ErrorCode::InvalidMintBudgetConfig);
+    }
+}
+
+#[tokio::test]
+async fn rejects_budget_update_by_other_than_the_owner() {
+    let mut env = TestEnv::new().await;
+    let treasury = env.treasury.insecure_clone();
+
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::UpdateMintBudget {
+            state: env.state,
+            mint_budget: mint_budget_pda(),
+            owner: treasury.pubkey(),
+        }
+        .to_account_metas(None),
+        data: instruction::UpdateMintBudget {
+            epoch_duration: EPOCH_DURATION,
+            max_tokens_per_epoch: u64::MAX,
+            max_tokens_per_usd: u64::MAX,
+        }
+        .data(),
+    };
+
+    let result = env.send(&[ix], &[&treasury]).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+    assert_eq!(env.mint_budget().await.max_tokens_per_epoch, MAX_TOKENS_PER_EPOCH);
+}
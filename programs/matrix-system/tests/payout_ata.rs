@@ -0,0 +1,117 @@
+// payout_ata: DONUT ATAs created on payout at the registrant's expense, and forged accounts
+// at the canonical ATA address
+mod common;
+
+use anchor_lang::prelude::*;
+use common::*;
+use matrix_system::{verified_addresses, ErrorCode};
+use solana_sdk::{
+    program_pack::Pack,
+    rent::Rent,
+    signature::{Keypair, Signer},
+};
+
+// Line of `depth` users from the root with `x` under the last one; the next registration under
+// `x` pays out `x` and every user between it and the root
+// Returns the line from the root down and `x`
+async fn build_line(env: &mut TestEnv, depth: usize, root_filled: u8) -> (Vec<Keypair>, Keypair) {
+    let mut line = vec![env.root.insecure_clone()];
+    for _ in 1..depth {
+        let parent = line.last().unwrap().pubkey();
+        line.push(env.register_new(&parent).await);
+    }
+
+    let x = env.register_new(&line.last().unwrap().pubkey()).await;
+
+    for member in line.iter().skip(1) {
+        env.register_new(&member.pubkey()).await;
+    }
+    for _ in 1..root_filled {
+        env.register_new(&line[0].pubkey()).await;
+    }
+    for _ in 0..2 {
+        env.register_new(&x.pubkey()).await;
+    }
+
+    (line, x)
+}
+
+// Lamports a slot-3 registration under a fresh root costs the registrant
+async fn slot3_cost(root_has_ata: bool) -> u64 {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    if root_has_ata {
+        env.set_account(&donut_ata(&root), token_account(&verified_addresses::TOKEN_MINT, &root, 0));
+    }
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    let user = env.register_new(&root).await;
+    WALLET_LAMPORTS - env.lamports(&user.pubkey()).await
+}
+
+#[tokio::test]
+async fn registrant_pays_for_the_missing_referrer_ata() {
+    let ata_rent = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+    assert_eq!(slot3_cost(false).await, slot3_cost(true).await + ata_rent);
+}
+
+#[tokio::test]
+async fn existing_referrer_ata_is_credited_in_place() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let held = 5_000_000_000;
+    env.set_account(&donut_ata(&root), token_account(&verified_addresses::TOKEN_MINT, &root, held));
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+    let reserved_tokens = env.user_account(&root).await.reserved_tokens;
+
+    env.register_new(&root).await;
+
+    assert_eq!(env.token_balance(&donut_ata(&root)).await, held + reserved_tokens);
+}
+
+#[tokio::test]
+async fn upline_atas_are_created_during_the_recursion() {
+    let mut env = TestEnv::new().await;
+    let (line, x) = build_line(&mut env, 2, 1).await;
+    let upline = line[1].pubkey();
+    assert!(env.account(&donut_ata(&upline)).await.is_none());
+    let reserved_tokens = env.user_account(&upline).await.reserved_tokens;
+
+    env.register_new(&x.pubkey()).await;
+
+    assert_eq!(env.token_balance(&donut_ata(&upline)).await, reserved_tokens);
+}
+
+#[tokio::test]
+async fn rejects_forged_referrer_ata() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    // A token account at the canonical address, but owned by another wallet
+    let other = Pubkey::new_unique();
+    env.set_account(&donut_ata(&root), token_account(&verified_addresses::TOKEN_MINT, &other, 0));
+
+    let user = env.create_wallet();
+    let result = env.register(&user, &root, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::InvalidWalletForATA);
+}
+
+#[tokio::test]
+async fn rejects_forged_upline_ata() {
+    let mut env = TestEnv::new().await;
+    let (line, x) = build_line(&mut env, 2, 1).await;
+    let upline = line[1].pubkey();
+
+    // A DONUT-sized account at the canonical address that isn't a token account
+    let mut forged = token_account(&verified_addresses::TOKEN_MINT, &upline, 0);
+    forged.owner = matrix_system::ID;
+    env.set_account(&donut_ata(&upline), forged);
+
+    let user = env.create_wallet();
+    let result = env.register(&user, &x.pubkey(), DEPOSIT).await;
+    assert_program_error(result, ErrorCode::InvalidTokenAccount);
+}
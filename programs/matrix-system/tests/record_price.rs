@@ -0,0 +1,109 @@
+// record_price: permissionless pool sampling and the TWAP it provides to registrations
+mod common;
+
+use anchor_lang::AnchorSerialize;
+use common::mocks::swap_vault;
+use common::*;
+use matrix_system::{meteora, verified_addresses, ErrorCode, ProgramState, MAX_PRICE_OBSERVATIONS, MIN_TWAP_OBSERVATIONS, PRICE_SCALE};
+use solana_sdk::signature::Signer;
+
+#[tokio::test]
+async fn record_price_adds_an_observation() {
+    let mut env = TestEnv::new().await;
+    let count = env.program_state().await.observation_count;
+
+    env.record_price().await.unwrap();
+
+    let state = env.program_state().await;
+    let newest = state.price_observations[state.observation_index as usize];
+    let donut_reserve = env.vault_total(&swap_vault(&verified_addresses::TOKEN_MINT)).await;
+    let sol_reserve = env.vault_total(&verified_addresses::B_VAULT).await;
+    assert_eq!(state.observation_count, count + 1);
+    assert_eq!(newest.timestamp, env.now().await);
+    assert_eq!(newest.price, donut_reserve as u128 * PRICE_SCALE / sol_reserve as u128);
+}
+
+#[tokio::test]
+async fn record_price_reads_the_vault_total_net_of_locked_profit() {
+    let mut env = TestEnv::new().await;
+
+    // Half the SOL is lent to strategies, and a quarter of the vault is profit that is still locked
+    let sol_balance = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+    let mut account = env.account(&verified_addresses::B_VAULT).await.unwrap();
+    let mut vault = meteora::Vault::from_account_data(&account.data).unwrap();
+    vault.total_amount = 2 * sol_balance;
+    vault.locked_profit_tracker = meteora::LockedProfitTracker {
+        last_updated_locked_profit: sol_balance / 2,
+        last_report: 0,
+        locked_profit_degradation: 0,
+    };
+    vault.serialize(&mut &mut account.data[8..]).unwrap();
+    env.set_account(&verified_addresses::B_VAULT, account);
+
+    env.record_price().await.unwrap();
+
+    let state = env.program_state().await;
+    let newest = state.price_observations[state.observation_index as usize];
+    let sol_reserve = 2 * sol_balance - sol_balance / 2;
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, sol_balance);
+    assert_eq!(newest.price, POOL_DONUT_RESERVE as u128 * PRICE_SCALE / sol_reserve as u128);
+}
+
+#[tokio::test]
+async fn record_price_rejects_vaults_other_than_the_pool_vaults() {
+    let mut env = TestEnv::new().await;
+
+    let mut accounts = env.record_price_accounts();
+    accounts.a_vault = verified_addresses::B_VAULT;
+    let result = env.send_record_price(accounts, 0).await;
+    assert_program_error(result, ErrorCode::InvalidVaultAddress);
+
+    let mut accounts = env.record_price_accounts();
+    accounts.b_vault = swap_vault(&verified_addresses::TOKEN_MINT);
+    let result = env.send_record_price(accounts, 0).await;
+    assert_program_error(result, ErrorCode::InvalidVaultBAddress);
+}
+
+#[tokio::test]
+async fn record_price_skips_samples_within_the_interval() {
+    let mut env = TestEnv::new().await;
+    env.record_price().await.unwrap();
+    let count = env.program_state().await.observation_count;
+
+    env.record_price().await.unwrap();
+    assert_eq!(env.program_state().await.observation_count, count);
+
+    env.record_price_after(TWAP_WINDOW as i64 / MAX_PRICE_OBSERVATIONS as i64).await.unwrap();
+    assert_eq!(env.program_state().await.observation_count, count + 1);
+}
+
+// Replace the program state with one that has no price observations
+fn clear_observations(env: &mut TestEnv) {
+    let state = unsampled_program_state(&env.owner.pubkey(), &env.treasury.pubkey());
+    let address = env.state;
+    env.set_account(&address, anchor_account(&state, 8 + ProgramState::SIZE));
+}
+
+#[tokio::test]
+async fn slot2_mint_fails_until_the_twap_is_available() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    clear_observations(&mut env);
+
+    // Slot 1 mints nothing, so it still goes through (and records the first sample)
+    env.register_new(&root).await;
+    assert_eq!(env.program_state().await.observation_count, 1);
+
+    let user = env.create_wallet();
+    let result = env.register(&user, &root, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::TwapUnavailable);
+
+    // Samples spaced by the minimum interval make the TWAP available
+    for _ in 1..MIN_TWAP_OBSERVATIONS {
+        env.record_price_after(TWAP_WINDOW as i64 / MAX_PRICE_OBSERVATIONS as i64).await.unwrap();
+    }
+    assert_eq!(env.program_state().await.observation_count as usize, MIN_TWAP_OBSERVATIONS);
+
+    env.register(&user, &root, DEPOSIT).await.unwrap();
+    assert!(env.user_account(&root).await.reserved_tokens > 0);
+}
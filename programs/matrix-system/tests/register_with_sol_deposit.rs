@@ -0,0 +1,595 @@
+// register_with_sol_deposit: every slot path, the upline recursion at each depth and the error codes
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::AccountMeta;
+use common::*;
+use matrix_system::{verified_addresses, ErrorCode, UserAccount, VestingAccount};
+use solana_sdk::signature::{Keypair, Signer};
+
+// ===== DIRECT SLOTS =====
+
+#[tokio::test]
+async fn slot1_deposits_to_vault_b() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let user = env.register_new(&root).await;
+
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + DEPOSIT);
+    assert_eq!(env.token_balance(&wsol_ata(&user.pubkey())).await, 0);
+
+    let user_account = env.user_account(&user.pubkey()).await;
+    assert!(user_account.is_registered);
+    assert_eq!(user_account.referrer, Some(user_pda(&root)));
+    assert_eq!(user_account.owner_wallet, user.pubkey());
+    assert_eq!(user_account.upline.depth, 2);
+    assert_eq!(user_account.upline.upline.len(), 1);
+    assert_eq!(user_account.upline.upline[0].pda, user_pda(&root));
+    assert_eq!(user_account.upline.upline[0].wallet, root);
+    assert_eq!(user_account.chain.filled_slots, 0);
+
+    let root_account = env.user_account(&root).await;
+    assert_eq!(root_account.chain.filled_slots, 1);
+    assert_eq!(root_account.chain.slots[0], Some(user.pubkey()));
+    assert_eq!(root_account.reserved_sol, 0);
+}
+
+#[tokio::test]
+async fn slot2_reserves_sol_and_mints_tokens() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+
+    let supply_before = env.mint_supply(&verified_addresses::TOKEN_MINT).await;
+
+    let user = env.register_new(&root).await;
+
+    // Slot 2 closes the WSOL account and moves the SOL to the program vault
+    assert!(env.account(&wsol_ata(&user.pubkey())).await.is_none());
+    assert_eq!(env.lamports(&program_sol_vault()).await, DEPOSIT);
+    assert_eq!(env.program_state().await.total_reserved_sol, DEPOSIT);
+
+    let root_account = env.user_account(&root).await;
+    assert_eq!(root_account.chain.filled_slots, 2);
+    assert_eq!(root_account.chain.slots[1], Some(user.pubkey()));
+    assert_eq!(root_account.reserved_sol, DEPOSIT);
+
+    // Tokens are minted at the TWAP, whatever the spot reserves after the slot-1 deposit
+    let reserved_tokens = root_account.reserved_tokens;
+    assert_eq!(reserved_tokens, twap_donut_output(DEPOSIT));
+    assert_eq!(env.token_balance(&program_token_vault()).await, reserved_tokens);
+    assert_eq!(env.mint_supply(&verified_addresses::TOKEN_MINT).await, supply_before + reserved_tokens);
+    assert_eq!(env.mint_budget().await.minted_in_epoch, reserved_tokens);
+}
+
+#[tokio::test]
+async fn slot3_pays_referrer_and_resets_matrix() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    let before = env.user_account(&root).await;
+    let root_lamports = env.lamports(&root).await;
+    assert!(env.account(&donut_ata(&root)).await.is_none());
+
+    env.register_new(&root).await;
+
+    // Reserved SOL goes to the wallet; the missing ATA is created for the tokens
+    assert_eq!(env.lamports(&root).await, root_lamports + before.reserved_sol);
+    assert_eq!(env.token_balance(&donut_ata(&root)).await, before.reserved_tokens);
+    assert_eq!(env.token_balance(&program_token_vault()).await, 0);
+    assert_eq!(env.program_state().await.total_reserved_sol, 0);
+
+    let after = env.user_account(&root).await;
+    assert_eq!(after.reserved_sol, 0);
+    assert_eq!(after.reserved_tokens, 0);
+    assert_eq!(after.chain.filled_slots, 0);
+    assert_eq!(after.chain.slots, [None, None, None]);
+    assert_ne!(after.chain.id, before.chain.id);
+}
+
+#[tokio::test]
+async fn slot3_credits_vesting_account_when_vesting_is_enabled() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.set_vesting_duration(1_000).await.unwrap();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    let reserved_tokens = env.user_account(&root).await.reserved_tokens;
+
+    env.register_new(&root).await;
+
+    let vesting: VestingAccount = env.anchor_data(&vesting_pda(&root)).await;
+    assert_eq!(vesting.owner_wallet, root);
+    assert_eq!(vesting.total_amount, reserved_tokens);
+    assert_eq!(vesting.tranches[0].amount, reserved_tokens);
+    assert_eq!(vesting.tranches[0].end_time - vesting.tranches[0].start_time, 1_000);
+
+    // The tokens stay in the program vault until claimed
+    assert!(env.account(&donut_ata(&root)).await.is_none());
+    assert_eq!(env.token_balance(&program_token_vault()).await, reserved_tokens);
+}
+
+#[tokio::test]
+async fn treasury_fee_is_taken_before_the_slot_logic() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let treasury = env.treasury.pubkey();
+    env.set_treasury_fee(100).await.unwrap();
+
+    let treasury_lamports = env.lamports(&treasury).await;
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let user = env.create_wallet();
+    let mut registration = env.registration(&user.pubkey(), &root, DEPOSIT).await;
+    registration.accounts.treasury_wallet = Some(treasury);
+    env.send_registration(&user, &registration).await.unwrap();
+
+    let fee = DEPOSIT / 100;
+    assert_eq!(env.lamports(&treasury).await, treasury_lamports + fee);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + DEPOSIT - fee);
+}
+
+#[tokio::test]
+async fn stale_price_feed_falls_back_to_default_price() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+
+    // At $50 the minimum is 0.2 SOL, so 0.15 SOL is rejected while the feed is fresh
+    env.set_sol_price(50_00000000, 0).await;
+    let user = env.create_wallet();
+    let result = env.register(&user, &root, 150_000_000).await;
+    assert_program_error(result, ErrorCode::InsufficientDeposit);
+
+    // A feed older than a day is replaced by $100, where 0.15 SOL is enough
+    env.set_sol_price(50_00000000, 86_401).await;
+    env.register(&user, &root, 150_000_000).await.unwrap();
+}
+
+// ===== RECURSION =====
+
+// Build a line of `depth` users ending at the root, with `x` registered under the last of them
+// Every user between the root and `x` has two filled slots, the root has `root_filled` and `x`
+// has two, so the next registration under `x` completes matrices all the way up the line
+// Returns the line from the root down and `x`
+async fn build_line(env: &mut TestEnv, depth: usize, root_filled: u8) -> (Vec<Keypair>, Keypair) {
+    let mut line = vec![env.root.insecure_clone()];
+    for _ in 1..depth {
+        let parent = line.last().unwrap().pubkey();
+        line.push(env.register_new(&parent).await);
+    }
+
+    let x = env.register_new(&line.last().unwrap().pubkey()).await;
+
+    for member in line.iter().skip(1) {
+        env.register_new(&member.pubkey()).await;
+    }
+    for _ in 1..root_filled {
+        env.register_new(&line[0].pubkey()).await;
+    }
+    for _ in 0..2 {
+        env.register_new(&x.pubkey()).await;
+    }
+
+    (line, x)
+}
+
+// Complete the matrix of `x` and check the payouts at every level below the root,
+// which receives a slot-2 reservation at the end of the recursion
+async fn assert_recursion_to_depth(depth: usize) {
+    let mut env = TestEnv::new().await;
+    let (line, x) = build_line(&mut env, depth, 1).await;
+
+    let mut paid: Vec<(Pubkey, UserAccount, u64)> = Vec::new();
+    for member in line.iter().skip(1).chain(std::iter::once(&x)) {
+        let wallet = member.pubkey();
+        let account = env.user_account(&wallet).await;
+        assert_eq!(account.chain.filled_slots, 2);
+        let lamports = env.lamports(&wallet).await;
+        paid.push((wallet, account, lamports));
+    }
+
+    let user = env.register_new(&x.pubkey()).await;
+
+    for (wallet, before, lamports) in paid {
+        let after = env.user_account(&wallet).await;
+        assert_eq!(after.chain.filled_slots, 0, "depth {}: matrix of {} not reset", depth, wallet);
+        assert_ne!(after.chain.id, before.chain.id);
+        assert_eq!(after.reserved_sol, 0);
+        assert_eq!(after.reserved_tokens, 0);
+        assert_eq!(env.lamports(&wallet).await, lamports + before.reserved_sol);
+        assert_eq!(env.token_balance(&donut_ata(&wallet)).await, before.reserved_tokens);
+    }
+
+    // The last completed matrix fills the root's second slot
+    let completed_below_root = if depth == 1 { x.pubkey() } else { line[1].pubkey() };
+    let root_account = env.user_account(&line[0].pubkey()).await;
+    assert_eq!(root_account.chain.filled_slots, 2);
+    assert_eq!(root_account.chain.slots[1], Some(user_pda(&completed_below_root)));
+    assert_eq!(root_account.reserved_sol, DEPOSIT);
+    assert!(root_account.reserved_tokens > 0);
+
+    // Only the root's reservation is left in the vault
+    assert_eq!(env.program_state().await.total_reserved_sol, DEPOSIT);
+    assert!(env.account(&wsol_ata(&user.pubkey())).await.is_none());
+}
+
+#[tokio::test]
+async fn recursion_depth_1() {
+    assert_recursion_to_depth(1).await;
+}
+
+#[tokio::test]
+async fn recursion_depth_2() {
+    assert_recursion_to_depth(2).await;
+}
+
+#[tokio::test]
+async fn recursion_depth_3() {
+    assert_recursion_to_depth(3).await;
+}
+
+#[tokio::test]
+async fn recursion_depth_4() {
+    assert_recursion_to_depth(4).await;
+}
+
+#[tokio::test]
+async fn recursion_depth_5() {
+    assert_recursion_to_depth(5).await;
+}
+
+#[tokio::test]
+async fn recursion_depth_6() {
+    assert_recursion_to_depth(6).await;
+}
+
+#[tokio::test]
+async fn recursion_deposits_leftover_when_every_upline_completes() {
+    let mut env = TestEnv::new().await;
+    let (line, x) = build_line(&mut env, 2, 2).await;
+    let root = line[0].pubkey();
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let user = env.register_new(&x.pubkey()).await;
+
+    // The root's matrix completes too, so the deposit goes to the pool
+    assert_eq!(env.user_account(&root).await.chain.filled_slots, 0);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + DEPOSIT);
+    assert!(env.account(&wsol_ata(&user.pubkey())).await.is_none());
+    assert_eq!(env.program_state().await.total_reserved_sol, 0);
+}
+
+#[tokio::test]
+async fn recursion_fills_upline_slot1_with_a_pool_deposit() {
+    let mut env = TestEnv::new().await;
+    let (line, x) = build_line(&mut env, 1, 2).await;
+    let root = line[0].pubkey();
+
+    // Completes x and then the root, leaving both matrices empty
+    env.register_new(&x.pubkey()).await;
+    assert_eq!(env.user_account(&root).await.chain.filled_slots, 0);
+
+    env.register_new(&x.pubkey()).await;
+    env.register_new(&x.pubkey()).await;
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    env.register_new(&x.pubkey()).await;
+
+    let root_account = env.user_account(&root).await;
+    assert_eq!(root_account.chain.filled_slots, 1);
+    assert_eq!(root_account.chain.slots[0], Some(user_pda(&x.pubkey())));
+    assert_eq!(root_account.reserved_sol, 0);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + DEPOSIT);
+}
+
+#[tokio::test]
+async fn recursion_stops_at_max_upline_depth() {
+    let mut env = TestEnv::new().await;
+    let (line, x) = build_line(&mut env, 7, 1).await;
+    let root = line[0].pubkey();
+
+    // x only keeps the six closest uplines, so the root is never reached
+    assert_eq!(env.user_account(&x.pubkey()).await.upline.upline.len(), 6);
+    let root_before = env.user_account(&root).await;
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    env.register_new(&x.pubkey()).await;
+
+    let root_after = env.user_account(&root).await;
+    assert_eq!(root_after.chain.filled_slots, root_before.chain.filled_slots);
+    assert_eq!(env.user_account(&line[1].pubkey()).await.chain.filled_slots, 0);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + DEPOSIT);
+}
+
+// ===== ERRORS =====
+
+// Substitute one account of a first-slot registration and expect `error`
+async fn assert_substitution_fails(edit: impl FnOnce(&mut Registration), error: ErrorCode) {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let user = env.create_wallet();
+
+    let mut registration = env.registration(&user.pubkey(), &root, DEPOSIT).await;
+    edit(&mut registration);
+
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, error);
+}
+
+#[tokio::test]
+async fn rejects_missing_vault_a_accounts() {
+    assert_substitution_fails(|r| r.remaining_accounts.truncate(5), ErrorCode::MissingVaultAAccounts).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_vault_a_accounts() {
+    let fake = || AccountMeta::new_readonly(Pubkey::new_unique(), false);
+
+    assert_substitution_fails(|r| r.remaining_accounts[0] = fake(), ErrorCode::InvalidVaultALpAddress).await;
+    assert_substitution_fails(|r| r.remaining_accounts[1] = fake(), ErrorCode::InvalidVaultALpMintAddress).await;
+    assert_substitution_fails(|r| r.remaining_accounts[2] = fake(), ErrorCode::InvalidTokenAVaultAddress).await;
+
+    // Vault A isn't a fixed address; the pool names it
+    assert_substitution_fails(|r| r.remaining_accounts[3] = fake(), ErrorCode::InvalidVaultAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_chainlink_accounts() {
+    let fake = || AccountMeta::new_readonly(Pubkey::new_unique(), false);
+
+    assert_substitution_fails(|r| r.remaining_accounts[4] = fake(), ErrorCode::InvalidPriceFeed).await;
+    assert_substitution_fails(|r| r.remaining_accounts[5] = fake(), ErrorCode::InvalidChainlinkProgram).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_pool_and_vault_b_accounts() {
+    assert_substitution_fails(|r| r.accounts.pool = Pubkey::new_unique(), ErrorCode::InvalidPoolAddress).await;
+    assert_substitution_fails(|r| r.accounts.b_vault_lp = Pubkey::new_unique(), ErrorCode::InvalidVaultAddress).await;
+    assert_substitution_fails(|r| r.accounts.b_vault = Pubkey::new_unique(), ErrorCode::InvalidVaultBAddress).await;
+    assert_substitution_fails(|r| r.accounts.b_vault_lp_mint = Pubkey::new_unique(), ErrorCode::InvalidVaultBLpMintAddress).await;
+    assert_substitution_fails(|r| r.accounts.b_token_vault = Pubkey::new_unique(), ErrorCode::InvalidTokenBVaultAddress).await;
+    assert_substitution_fails(|r| r.accounts.vault_program = Pubkey::new_unique(), ErrorCode::InvalidVaultProgram).await;
+}
+
+#[tokio::test]
+async fn rejects_deployed_program_as_vault_program() {
+    // An executable program that would accept the CPI must still never receive the user's signature
+    assert_substitution_fails(
+        |r| r.accounts.vault_program = verified_addresses::AMM_PROGRAM,
+        ErrorCode::InvalidVaultProgram,
+    ).await;
+}
+
+#[tokio::test]
+async fn recursion_rejects_substituted_vault_b_accounts() {
+    // The pool deposit at the end of a recursion goes through the same checks as a slot-1 deposit
+    let mut env = TestEnv::new().await;
+    let (_, x) = build_line(&mut env, 2, 2).await;
+    let user = env.create_wallet();
+
+    let mut registration = env.registration(&user.pubkey(), &x.pubkey(), DEPOSIT).await;
+    registration.accounts.b_token_vault = Pubkey::new_unique();
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, ErrorCode::InvalidTokenBVaultAddress);
+
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+    env.register(&user, &x.pubkey(), DEPOSIT).await.unwrap();
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + DEPOSIT);
+}
+
+#[tokio::test]
+async fn rejects_substituted_token_accounts() {
+    assert_substitution_fails(|r| r.accounts.token_mint = Pubkey::new_unique(), ErrorCode::InvalidTokenMintAddress).await;
+    assert_substitution_fails(|r| r.accounts.referrer_token_account = Pubkey::new_unique(), ErrorCode::TokenAccountNotCanonical).await;
+    assert_substitution_fails(
+        |r| r.accounts.donut_token_program = anchor_spl::token_2022::ID,
+        ErrorCode::InvalidTokenProgram,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_insufficient_deposit() {
+    assert_substitution_fails(|r| r.deposit_amount = 99_000_000, ErrorCode::InsufficientDeposit).await;
+}
+
+#[tokio::test]
+async fn rejects_referrer_wallet_mismatch() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let user = env.create_wallet();
+    let other = env.create_wallet();
+
+    let mut registration = env.registration(&user.pubkey(), &root, DEPOSIT).await;
+    registration.accounts.referrer_wallet = other.pubkey();
+    registration.accounts.referrer_token_account = donut_ata(&other.pubkey());
+
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, ErrorCode::WalletMismatch);
+}
+
+#[tokio::test]
+async fn rejects_unregistered_referrer() {
+    let mut env = TestEnv::new().await;
+    let referrer = Pubkey::new_unique();
+    let unregistered = UserAccount {
+        owner_wallet: referrer,
+        ..Default::default()
+    };
+    env.set_account(&user_pda(&referrer), anchor_account(&unregistered, 8 + UserAccount::SIZE));
+
+    let user = env.create_wallet();
+    let result = env.register(&user, &referrer, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::ReferrerNotRegistered);
+}
+
+#[tokio::test]
+async fn rejects_mint_over_budget() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.update_mint_budget(EPOCH_DURATION, 1, MAX_TOKENS_PER_USD).await.unwrap();
+    env.register_new(&root).await;
+
+    let user = env.create_wallet();
+    let result = env.register(&user, &root, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::MintBudgetExceeded);
+}
+
+#[tokio::test]
+async fn rejects_missing_treasury_accounts() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.set_treasury_fee(100).await.unwrap();
+
+    let user = env.create_wallet();
+    let result = env.register(&user, &root, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::MissingTreasuryAccounts);
+
+    let mut registration = env.registration(&user.pubkey(), &root, DEPOSIT).await;
+    registration.accounts.treasury_wallet = Some(env.create_wallet().pubkey());
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, ErrorCode::InvalidTreasuryAddress);
+}
+
+#[tokio::test]
+async fn rejects_missing_vesting_account_on_slot3() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.set_vesting_duration(1_000).await.unwrap();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    let user = env.create_wallet();
+    let mut registration = env.registration(&user.pubkey(), &root, DEPOSIT).await;
+    registration.accounts.referrer_vesting = None;
+
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, ErrorCode::MissingVestingAccount);
+}
+
+// Substitute one upline account of a registration that completes a matrix below the root
+// The root has two filled slots, so its ATA is checked as well
+async fn assert_upline_substitution_fails(edit: impl FnOnce(&mut TestEnv, &mut Registration), error: ErrorCode) {
+    let mut env = TestEnv::new().await;
+    let (_, x) = build_line(&mut env, 1, 2).await;
+    let user = env.create_wallet();
+
+    let mut registration = env.registration(&user.pubkey(), &x.pubkey(), DEPOSIT).await;
+    assert_eq!(registration.remaining_accounts.len(), 9);
+    edit(&mut env, &mut registration);
+
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, error);
+}
+
+#[tokio::test]
+async fn rejects_incomplete_upline_trio() {
+    assert_upline_substitution_fails(|_, r| { r.remaining_accounts.pop(); }, ErrorCode::MissingUplineAccount).await;
+}
+
+#[tokio::test]
+async fn rejects_referrer_passed_as_its_own_upline() {
+    assert_upline_substitution_fails(
+        |_, r| {
+            let referrer_wallet = r.accounts.referrer_wallet;
+            r.remaining_accounts[6] = AccountMeta::new(user_pda(&referrer_wallet), false);
+            r.remaining_accounts[7] = AccountMeta::new(referrer_wallet, false);
+            r.remaining_accounts[8] = AccountMeta::new(donut_ata(&referrer_wallet), false);
+        },
+        ErrorCode::DuplicateUplineAccount,
+    ).await;
+}
+
+// Registration completing a matrix two levels below the root, with the two upline trios edited
+async fn assert_upline_trios_fail(edit: impl FnOnce(&mut Vec<AccountMeta>), error: ErrorCode) {
+    let mut env = TestEnv::new().await;
+    let (_, x) = build_line(&mut env, 2, 2).await;
+    let user = env.create_wallet();
+
+    let mut registration = env.registration(&user.pubkey(), &x.pubkey(), DEPOSIT).await;
+    assert_eq!(registration.remaining_accounts.len(), 12);
+    let mut trios = registration.remaining_accounts.split_off(6);
+    edit(&mut trios);
+    registration.remaining_accounts.extend(trios);
+
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, error);
+}
+
+#[tokio::test]
+async fn rejects_duplicate_upline_trio() {
+    assert_upline_trios_fail(
+        |trios| {
+            let closest = trios[..3].to_vec();
+            trios[3..].clone_from_slice(&closest);
+        },
+        ErrorCode::DuplicateUplineAccount,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_upline_trios_out_of_order() {
+    assert_upline_trios_fail(|trios| trios.rotate_left(3), ErrorCode::InvalidUpline).await;
+}
+
+#[tokio::test]
+async fn rejects_missing_upline_trio() {
+    assert_upline_trios_fail(|trios| trios.truncate(3), ErrorCode::MissingUplineAccount).await;
+}
+
+#[tokio::test]
+async fn rejects_upline_wallet_that_is_not_a_system_account() {
+    assert_upline_substitution_fails(
+        |_, r| r.remaining_accounts[7] = AccountMeta::new(program_token_vault(), false),
+        ErrorCode::PaymentWalletInvalid,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_upline_account_not_owned_by_program() {
+    assert_upline_substitution_fails(
+        |env, r| r.remaining_accounts[6] = AccountMeta::new(env.create_wallet().pubkey(), false),
+        ErrorCode::InvalidSlotOwner,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_unregistered_upline_account() {
+    assert_upline_substitution_fails(
+        |env, r| {
+            let address = Pubkey::new_unique();
+            env.set_account(&address, anchor_account(&UserAccount::default(), 8 + UserAccount::SIZE));
+            r.remaining_accounts[6] = AccountMeta::new(address, false);
+        },
+        ErrorCode::SlotNotRegistered,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_upline_wallet_mismatch() {
+    assert_upline_substitution_fails(
+        |env, r| r.remaining_accounts[7] = AccountMeta::new(env.create_wallet().pubkey(), false),
+        ErrorCode::WalletMismatch,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_non_canonical_upline_token_account() {
+    assert_upline_substitution_fails(
+        |_, r| r.remaining_accounts[8] = AccountMeta::new(Pubkey::new_unique(), false),
+        ErrorCode::TokenAccountNotCanonical,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_second_registration_of_the_same_wallet() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    let user = env.register_new(&root).await;
+
+    assert!(env.register(&user, &root, DEPOSIT).await.is_err());
+}
@@ -0,0 +1,400 @@
+// register_with_token_deposit: USDC deposits swapped to SOL through the mock AMM, the
+// accepted-token allowlist and the oracle checks
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use common::mocks::{swap_vault, MockFeed, SWAP_FEE_DENOMINATOR, SWAP_FEE_NUMERATOR};
+use common::*;
+use matrix_system::{accounts, instruction, meteora, verified_addresses, AcceptedToken, ErrorCode, MAX_SWAP_SLIPPAGE_BPS};
+use solana_program_test::BanksClientError;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    program_option::COption,
+    program_pack::Pack,
+    signature::{Keypair, Signer},
+};
+
+// USDC: 6 decimals at 1 USD (8 decimals)
+const USDC_DECIMALS: u8 = 6;
+const USDC_PRICE: i128 = 1_00000000;
+
+// 20 USDC, the value of the default SOL deposit
+const USDC_DEPOSIT: u64 = 20_000_000;
+
+// USDC/SOL pool: 100,000 USDC against 1,000 SOL, 100 USD per SOL
+const POOL_USDC_RESERVE: u64 = 100_000_000_000;
+const POOL_WSOL_RESERVE: u64 = 1_000_000_000_000;
+
+const SLIPPAGE_BPS: u16 = 100;
+
+// Fixed addresses of the USDC fixture
+struct Usdc {
+    mint: Pubkey,
+    feed: Pubkey,
+    pool: Pubkey,
+    a_token_vault: Pubkey,  // USDC side
+    b_token_vault: Pubkey,  // WSOL side
+}
+
+impl Usdc {
+    fn new() -> Self {
+        Self {
+            mint: Pubkey::new_unique(),
+            feed: Pubkey::new_unique(),
+            pool: Pubkey::new_unique(),
+            a_token_vault: Pubkey::new_unique(),
+            b_token_vault: Pubkey::new_unique(),
+        }
+    }
+
+    fn accepted_token(&self) -> Pubkey {
+        Pubkey::find_program_address(&[b"accepted_token", self.mint.as_ref()], &matrix_system::ID).0
+    }
+
+    fn user_token(&self, wallet: &Pubkey) -> Pubkey {
+        anchor_spl::associated_token::get_associated_token_address(wallet, &self.mint)
+    }
+}
+
+// USDC mint, feed and pool, with the token accepted at SLIPPAGE_BPS
+async fn usdc_env() -> (TestEnv, Usdc) {
+    let mut env = TestEnv::new().await;
+    let usdc = Usdc::new();
+
+    let mut mint = mint_account(COption::Some(Pubkey::new_unique()), POOL_USDC_RESERVE);
+    let mut mint_data = spl_token::state::Mint::unpack(&mint.data).unwrap();
+    mint_data.decimals = USDC_DECIMALS;
+    mint_data.pack_into_slice(&mut mint.data);
+    env.set_account(&usdc.mint, mint);
+
+    set_usdc_price(&mut env, &usdc, USDC_PRICE, 0).await;
+    set_usdc_pool(&mut env, &usdc, POOL_USDC_RESERVE, POOL_WSOL_RESERVE);
+
+    set_accepted_token(&mut env, &usdc, SLIPPAGE_BPS, true).await.unwrap();
+    (env, usdc)
+}
+
+async fn set_usdc_price(env: &mut TestEnv, usdc: &Usdc, answer: i128, age: i64) {
+    let timestamp = (env.now().await - age) as u32;
+    env.set_account(&usdc.feed, feed_account(MockFeed { answer, decimals: 8, timestamp }));
+}
+
+fn set_usdc_pool(env: &mut TestEnv, usdc: &Usdc, usdc_reserve: u64, wsol_reserve: u64) {
+    env.set_account(&usdc.a_token_vault, token_account(&usdc.mint, &swap_vault(&usdc.mint), usdc_reserve));
+    env.set_account(
+        &usdc.b_token_vault,
+        native_token_account(&swap_vault(&verified_addresses::WSOL_MINT), wsol_reserve),
+    );
+}
+
+async fn set_accepted_token(
+    env: &mut TestEnv,
+    usdc: &Usdc,
+    max_slippage_bps: u16,
+    enabled: bool,
+) -> std::result::Result<(), BanksClientError> {
+    let owner = env.owner.insecure_clone();
+    send_set_accepted_token(env, usdc, &owner, max_slippage_bps, enabled).await
+}
+
+async fn send_set_accepted_token(
+    env: &mut TestEnv,
+    usdc: &Usdc,
+    signer: &Keypair,
+    max_slippage_bps: u16,
+    enabled: bool,
+) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::SetAcceptedToken {
+            state: env.state,
+            accepted_token: usdc.accepted_token(),
+            token_mint: usdc.mint,
+            owner: signer.pubkey(),
+            system_program: solana_sdk::system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::SetAcceptedToken {
+            price_feed: usdc.feed,
+            swap_pool: usdc.pool,
+            max_slippage_bps,
+            enabled,
+        }
+        .data(),
+    };
+
+    env.send(&[ix], &[signer]).await
+}
+
+// New wallet holding `amount` USDC
+fn create_usdc_wallet(env: &mut TestEnv, usdc: &Usdc, amount: u64) -> Keypair {
+    let user = env.create_wallet();
+    env.set_account(&usdc.user_token(&user.pubkey()), token_account(&usdc.mint, &user.pubkey(), amount));
+    user
+}
+
+// register_with_token_deposit accounts, editable before sending
+struct TokenRegistration {
+    accounts: accounts::RegisterWithTokenDeposit,
+    remaining_accounts: Vec<AccountMeta>,
+}
+
+impl TokenRegistration {
+    fn instruction(&self, token_amount: u64) -> Instruction {
+        let mut metas: Vec<AccountMeta> = self.accounts.to_account_metas(None);
+        metas.extend_from_slice(&self.remaining_accounts);
+
+        Instruction {
+            program_id: matrix_system::ID,
+            accounts: metas,
+            data: instruction::RegisterWithTokenDeposit { token_amount }.data(),
+        }
+    }
+}
+
+async fn token_registration(env: &mut TestEnv, usdc: &Usdc, user: &Pubkey, referrer_wallet: &Pubkey) -> TokenRegistration {
+    let registration = env.registration(user, referrer_wallet, 0).await;
+
+    TokenRegistration {
+        accounts: accounts::RegisterWithTokenDeposit {
+            registration: registration.accounts,
+            accepted_token: usdc.accepted_token(),
+            source_mint: usdc.mint,
+            user_source_token: usdc.user_token(user),
+            token_price_feed: usdc.feed,
+            swap_pool: usdc.pool,
+            swap_a_vault: swap_vault(&usdc.mint),
+            swap_b_vault: swap_vault(&verified_addresses::WSOL_MINT),
+            swap_a_token_vault: usdc.a_token_vault,
+            swap_b_token_vault: usdc.b_token_vault,
+            swap_a_vault_lp_mint: Pubkey::new_unique(),
+            swap_b_vault_lp_mint: Pubkey::new_unique(),
+            swap_a_vault_lp: Pubkey::new_unique(),
+            swap_b_vault_lp: Pubkey::new_unique(),
+            swap_protocol_token_fee: Pubkey::new_unique(),
+            amm_program: verified_addresses::AMM_PROGRAM,
+        },
+        remaining_accounts: registration.remaining_accounts,
+    }
+}
+
+async fn register_with_usdc(
+    env: &mut TestEnv,
+    usdc: &Usdc,
+    user: &Keypair,
+    referrer_wallet: &Pubkey,
+    token_amount: u64,
+) -> std::result::Result<(), BanksClientError> {
+    let registration = token_registration(env, usdc, &user.pubkey(), referrer_wallet).await;
+    env.send(&[registration.instruction(token_amount)], &[user]).await
+}
+
+// SOL the mock AMM pays for `token_amount` USDC from a fresh fixture pool
+fn swapped_sol(token_amount: u64) -> u64 {
+    meteora::swap_output(POOL_USDC_RESERVE, POOL_WSOL_RESERVE, token_amount, SWAP_FEE_NUMERATOR, SWAP_FEE_DENOMINATOR).unwrap()
+}
+
+// ===== ACCEPTED TOKENS =====
+
+#[tokio::test]
+async fn set_accepted_token_records_the_swap_configuration() {
+    let (mut env, usdc) = usdc_env().await;
+
+    let accepted: AcceptedToken = env.anchor_data(&usdc.accepted_token()).await;
+    assert_eq!(accepted.mint, usdc.mint);
+    assert_eq!(accepted.price_feed, usdc.feed);
+    assert_eq!(accepted.swap_pool, usdc.pool);
+    assert_eq!(accepted.max_slippage_bps, SLIPPAGE_BPS);
+    assert!(accepted.enabled);
+
+    // The same entry is updated in place
+    set_accepted_token(&mut env, &usdc, MAX_SWAP_SLIPPAGE_BPS, false).await.unwrap();
+    let accepted: AcceptedToken = env.anchor_data(&usdc.accepted_token()).await;
+    assert_eq!(accepted.max_slippage_bps, MAX_SWAP_SLIPPAGE_BPS);
+    assert!(!accepted.enabled);
+}
+
+#[tokio::test]
+async fn rejects_invalid_accepted_token_updates() {
+    let (mut env, usdc) = usdc_env().await;
+
+    let result = set_accepted_token(&mut env, &usdc, MAX_SWAP_SLIPPAGE_BPS + 1, true).await;
+    assert_program_error(result, ErrorCode::InvalidSlippage);
+
+    let treasury = env.treasury.insecure_clone();
+    let result = send_set_accepted_token(&mut env, &usdc, &treasury, SLIPPAGE_BPS, true).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+}
+
+// ===== REGISTRATION =====
+
+#[tokio::test]
+async fn usdc_deposit_is_swapped_and_fills_slot1() {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    register_with_usdc(&mut env, &usdc, &user, &root, USDC_DEPOSIT).await.unwrap();
+
+    // The USDC went to the pool and the swapped SOL to vault B
+    let sol = swapped_sol(USDC_DEPOSIT);
+    assert_eq!(env.token_balance(&usdc.user_token(&user.pubkey())).await, 0);
+    assert_eq!(env.token_balance(&usdc.a_token_vault).await, POOL_USDC_RESERVE + USDC_DEPOSIT);
+    assert_eq!(env.token_balance(&usdc.b_token_vault).await, POOL_WSOL_RESERVE - sol);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + sol);
+
+    let user_account = env.user_account(&user.pubkey()).await;
+    assert!(user_account.is_registered);
+    assert_eq!(user_account.referrer, Some(user_pda(&root)));
+    assert_eq!(env.user_account(&root).await.chain.slots[0], Some(user.pubkey()));
+}
+
+#[tokio::test]
+async fn usdc_deposit_in_slot2_reserves_the_swapped_sol() {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+
+    register_with_usdc(&mut env, &usdc, &user, &root, USDC_DEPOSIT).await.unwrap();
+
+    let sol = swapped_sol(USDC_DEPOSIT);
+    let root_account = env.user_account(&root).await;
+    assert_eq!(root_account.reserved_sol, sol);
+    assert_eq!(env.lamports(&program_sol_vault()).await, sol);
+    assert_eq!(env.program_state().await.total_reserved_sol, sol);
+    assert_eq!(root_account.reserved_tokens, twap_donut_output(sol));
+}
+
+// USDC registration under the root with a 1% treasury fee, and the treasury WSOL ATA created
+async fn usdc_fee_env() -> (TestEnv, Usdc, Keypair) {
+    let (mut env, usdc) = usdc_env().await;
+    env.set_treasury_fee(100).await.unwrap();
+    let treasury = env.treasury.pubkey();
+    env.set_account(&wsol_ata(&treasury), native_token_account(&treasury, 0));
+
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+    (env, usdc, user)
+}
+
+#[tokio::test]
+async fn treasury_fee_on_a_token_deposit_is_paid_in_wsol() {
+    let (mut env, usdc, user) = usdc_fee_env().await;
+    let root = env.root.pubkey();
+    let treasury = env.treasury.pubkey();
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let mut registration = token_registration(&mut env, &usdc, &user.pubkey(), &root).await;
+    registration.accounts.registration.treasury_wsol_account = Some(wsol_ata(&treasury));
+    env.send(&[registration.instruction(USDC_DEPOSIT)], &[&user]).await.unwrap();
+
+    let sol = swapped_sol(USDC_DEPOSIT);
+    let fee = sol / 100;
+    assert_eq!(env.token_balance(&wsol_ata(&treasury)).await, fee);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + sol - fee);
+}
+
+#[tokio::test]
+async fn rejects_missing_or_forged_treasury_wsol_account() {
+    let (mut env, usdc, user) = usdc_fee_env().await;
+    let root = env.root.pubkey();
+
+    let result = register_with_usdc(&mut env, &usdc, &user, &root, USDC_DEPOSIT).await;
+    assert_program_error(result, ErrorCode::MissingTreasuryAccounts);
+
+    let other = Pubkey::new_unique();
+    env.set_account(&wsol_ata(&other), native_token_account(&other, 0));
+    let mut registration = token_registration(&mut env, &usdc, &user.pubkey(), &root).await;
+    registration.accounts.registration.treasury_wsol_account = Some(wsol_ata(&other));
+    let result = env.send(&[registration.instruction(USDC_DEPOSIT)], &[&user]).await;
+    assert_program_error(result, ErrorCode::TokenAccountNotCanonical);
+}
+
+#[tokio::test]
+async fn rejects_disabled_token() {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+    set_accepted_token(&mut env, &usdc, SLIPPAGE_BPS, false).await.unwrap();
+
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+    let result = register_with_usdc(&mut env, &usdc, &user, &root, USDC_DEPOSIT).await;
+    assert_program_error(result, ErrorCode::TokenNotAccepted);
+}
+
+#[tokio::test]
+async fn rejects_deposit_below_the_minimum_value() {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+
+    // 9.99 USDC against the 10 USD minimum
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+    let result = register_with_usdc(&mut env, &usdc, &user, &root, 9_990_000).await;
+    assert_program_error(result, ErrorCode::InsufficientDeposit);
+}
+
+#[tokio::test]
+async fn rejects_stale_token_price() {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+    set_usdc_price(&mut env, &usdc, USDC_PRICE, 86_401).await;
+
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+    let result = register_with_usdc(&mut env, &usdc, &user, &root, USDC_DEPOSIT).await;
+    assert_program_error(result, ErrorCode::PriceFeedTooOld);
+}
+
+#[tokio::test]
+async fn rejects_stale_sol_price() {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+    env.set_sol_price(SOL_PRICE, 86_401).await;
+
+    // The swap minimum isn't set from the default SOL price
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+    let result = register_with_usdc(&mut env, &usdc, &user, &root, USDC_DEPOSIT).await;
+    assert_program_error(result, ErrorCode::PriceFeedTooOld);
+    assert_eq!(env.token_balance(&usdc.user_token(&user.pubkey())).await, USDC_DEPOSIT);
+}
+
+#[tokio::test]
+async fn rejects_swap_below_the_oracle_minimum() {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+
+    // The pool pays 2% less SOL than the oracles imply, beyond the 1% slippage
+    set_usdc_pool(&mut env, &usdc, POOL_USDC_RESERVE, POOL_WSOL_RESERVE / 100 * 98);
+
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+    let result = register_with_usdc(&mut env, &usdc, &user, &root, USDC_DEPOSIT).await;
+    assert_program_error(result, ErrorCode::TokenSwapFailed);
+}
+
+// Substitute one swap account of a USDC registration and expect `error`
+async fn assert_token_substitution_fails(edit: impl FnOnce(&mut accounts::RegisterWithTokenDeposit), error: ErrorCode) {
+    let (mut env, usdc) = usdc_env().await;
+    let root = env.root.pubkey();
+    let user = create_usdc_wallet(&mut env, &usdc, USDC_DEPOSIT);
+
+    let mut registration = token_registration(&mut env, &usdc, &user.pubkey(), &root).await;
+    edit(&mut registration.accounts);
+
+    let result = env.send(&[registration.instruction(USDC_DEPOSIT)], &[&user]).await;
+    assert_program_error(result, error);
+}
+
+#[tokio::test]
+async fn rejects_substituted_swap_accounts() {
+    assert_token_substitution_fails(|r| r.token_price_feed = verified_addresses::SOL_USD_FEED, ErrorCode::InvalidPriceFeed).await;
+    assert_token_substitution_fails(|r| r.swap_pool = verified_addresses::POOL_ADDRESS, ErrorCode::InvalidPoolAddress).await;
+    assert_token_substitution_fails(|r| r.amm_program = verified_addresses::VAULT_PROGRAM, ErrorCode::InvalidAmmProgram).await;
+}
+
+#[tokio::test]
+async fn rejects_source_token_of_another_wallet() {
+    let other = Pubkey::new_unique();
+    assert_token_substitution_fails(
+        |r| r.user_source_token = anchor_spl::associated_token::get_associated_token_address(&other, &r.source_mint),
+        ErrorCode::TokenAccountInvalid,
+    ).await;
+}
@@ -0,0 +1,164 @@
+// register_without_referrer: the multisig-only root registration and every substituted account
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use common::*;
+use matrix_system::{accounts, instruction, verified_addresses, ErrorCode};
+use solana_sdk::{account::Account, instruction::Instruction, signature::Signer};
+
+#[tokio::test]
+async fn registers_root_and_deposits_to_vault_b() {
+    let mut env = TestEnv::new().await;
+    let user = env.create_wallet();
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+    let state_before = env.program_state().await;
+
+    env.register_root(&user, DEPOSIT).await.unwrap();
+
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + DEPOSIT);
+    assert_eq!(env.token_balance(&wsol_ata(&user.pubkey())).await, 0);
+
+    let user_account = env.user_account(&user.pubkey()).await;
+    assert!(user_account.is_registered);
+    assert_eq!(user_account.referrer, None);
+    assert_eq!(user_account.owner_wallet, user.pubkey());
+    assert_eq!(user_account.upline.id, state_before.next_upline_id);
+    assert_eq!(user_account.upline.depth, 1);
+    assert!(user_account.upline.upline.is_empty());
+    assert_eq!(user_account.chain.id, state_before.next_chain_id);
+    assert_eq!(user_account.chain.filled_slots, 0);
+
+    let state_after = env.program_state().await;
+    assert_eq!(state_after.next_upline_id, state_before.next_upline_id + 1);
+    assert_eq!(state_after.next_chain_id, state_before.next_chain_id + 1);
+}
+
+// Substitute one account of a root registration and expect `error`
+async fn assert_substitution_fails(edit: impl FnOnce(&mut accounts::RegisterWithoutReferrerDeposit), error: ErrorCode) {
+    let mut env = TestEnv::new().await;
+    let user = env.create_wallet();
+
+    let mut registration = env.root_registration(&user.pubkey());
+    edit(&mut registration);
+
+    let result = env.send_root_registration(&user, registration, DEPOSIT).await;
+    assert_program_error(result, error);
+}
+
+#[tokio::test]
+async fn rejects_caller_other_than_multisig_treasury() {
+    let mut env = TestEnv::new().await;
+    let user = env.create_wallet();
+    let impostor = env.create_wallet();
+
+    let mut registration = env.root_registration(&user.pubkey());
+    registration.owner = impostor.pubkey();
+
+    // Signed by the impostor in place of the treasury
+    env.treasury = impostor;
+    let result = env.send_root_registration(&user, registration, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+}
+
+#[tokio::test]
+async fn rejects_substituted_user_source_token() {
+    // Another wallet's WSOL ATA
+    let other = Pubkey::new_unique();
+    assert_substitution_fails(|r| r.user_source_token = wsol_ata(&other), ErrorCode::TokenAccountNotCanonical).await;
+
+    // The user's ATA for another mint
+    assert_substitution_fails(
+        |r| r.user_source_token = donut_ata(&r.user_wallet),
+        ErrorCode::TokenAccountNotCanonical,
+    ).await;
+}
+
+// Place `account` at the user's canonical WSOL ATA and register without wrapping any SOL
+async fn assert_forged_source_token_fails(forge: impl FnOnce(&Pubkey) -> Option<Account>, error: ErrorCode) {
+    let mut env = TestEnv::new().await;
+    let user = env.create_wallet();
+    if let Some(account) = forge(&user.pubkey()) {
+        env.set_account(&wsol_ata(&user.pubkey()), account);
+    }
+
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: env.root_registration(&user.pubkey()).to_account_metas(None),
+        data: instruction::RegisterWithoutReferrer { deposit_amount: DEPOSIT }.data(),
+    };
+    let treasury = env.treasury.insecure_clone();
+    let result = env.send(&[ix], &[&user, &treasury]).await;
+    assert_program_error(result, error);
+}
+
+#[tokio::test]
+async fn rejects_missing_user_source_token() {
+    assert_forged_source_token_fails(|_| None, ErrorCode::InvalidTokenAccount).await;
+}
+
+#[tokio::test]
+async fn rejects_forged_user_source_token() {
+    // Canonical address, but not owned by the token program
+    assert_forged_source_token_fails(
+        |user| {
+            let mut account = native_token_account(user, DEPOSIT);
+            account.owner = matrix_system::ID;
+            Some(account)
+        },
+        ErrorCode::InvalidTokenAccount,
+    ).await;
+
+    // Canonical address holding another wallet's WSOL
+    assert_forged_source_token_fails(
+        |_| Some(native_token_account(&Pubkey::new_unique(), DEPOSIT)),
+        ErrorCode::InvalidWalletForATA,
+    ).await;
+
+    // Canonical address holding DONUT
+    assert_forged_source_token_fails(
+        |user| Some(token_account(&verified_addresses::TOKEN_MINT, user, DEPOSIT)),
+        ErrorCode::InvalidTokenMintAddress,
+    ).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_wsol_mint() {
+    assert_substitution_fails(|r| r.wsol_mint = Pubkey::new_unique(), ErrorCode::InvalidTokenMintAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_token_mint() {
+    assert_substitution_fails(|r| r.token_mint = Pubkey::new_unique(), ErrorCode::InvalidTokenMintAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_pool() {
+    assert_substitution_fails(|r| r.pool = Pubkey::new_unique(), ErrorCode::InvalidPoolAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_b_vault() {
+    assert_substitution_fails(|r| r.b_vault = Pubkey::new_unique(), ErrorCode::InvalidVaultBAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_b_token_vault() {
+    assert_substitution_fails(|r| r.b_token_vault = Pubkey::new_unique(), ErrorCode::InvalidTokenBVaultAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_b_vault_lp_mint() {
+    assert_substitution_fails(|r| r.b_vault_lp_mint = Pubkey::new_unique(), ErrorCode::InvalidVaultBLpMintAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_b_vault_lp() {
+    assert_substitution_fails(|r| r.b_vault_lp = Pubkey::new_unique(), ErrorCode::InvalidVaultAddress).await;
+}
+
+#[tokio::test]
+async fn rejects_substituted_vault_program() {
+    // Any executable program other than the Meteora vault, and a plain address
+    assert_substitution_fails(|r| r.vault_program = verified_addresses::CHAINLINK_PROGRAM, ErrorCode::InvalidVaultProgram).await;
+    assert_substitution_fails(|r| r.vault_program = Pubkey::new_unique(), ErrorCode::InvalidVaultProgram).await;
+}
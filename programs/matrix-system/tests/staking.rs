@@ -0,0 +1,325 @@
+// staking: stake, unstake and claim, and the reward per share funded by slot-3 SOL payouts
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use common::*;
+use matrix_system::{
+    accounts, instruction, verified_addresses, ErrorCode, StakingPool, UserStake, MAX_STAKING_FEE_BPS,
+    REWARD_PER_SHARE_SCALE,
+};
+use solana_program_test::BanksClientError;
+use solana_sdk::{
+    instruction::Instruction,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+};
+
+// 10% of slot-3 SOL payouts go to stakers
+const STAKING_FEE_BPS: u16 = 1_000;
+
+fn staking_pool() -> Pubkey {
+    Pubkey::find_program_address(&[b"staking_pool"], &matrix_system::ID).0
+}
+
+fn staking_reward_vault() -> Pubkey {
+    Pubkey::find_program_address(&[b"staking_reward_vault"], &matrix_system::ID).0
+}
+
+fn staking_vault_authority() -> Pubkey {
+    Pubkey::find_program_address(&[b"staking_vault_authority"], &matrix_system::ID).0
+}
+
+fn staking_vault() -> Pubkey {
+    donut_ata(&staking_vault_authority())
+}
+
+fn user_stake(wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"user_stake", wallet.as_ref()], &matrix_system::ID).0
+}
+
+async fn initialize_staking_pool(env: &mut TestEnv, signer: &Keypair, staking_fee_bps: u16) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::InitializeStakingPool {
+            state: env.state,
+            staking_pool: staking_pool(),
+            staking_reward_vault: staking_reward_vault(),
+            token_mint: verified_addresses::TOKEN_MINT,
+            staking_vault: staking_vault(),
+            staking_vault_authority: staking_vault_authority(),
+            owner: signer.pubkey(),
+            donut_token_program: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::InitializeStakingPool { staking_fee_bps }.data(),
+    };
+
+    env.send(&[ix], &[signer]).await
+}
+
+async fn set_staking_fee(env: &mut TestEnv, signer: &Keypair, staking_fee_bps: u16) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::UpdateStakingFee {
+            state: env.state,
+            staking_pool: staking_pool(),
+            owner: signer.pubkey(),
+        }
+        .to_account_metas(None),
+        data: instruction::SetStakingFee { staking_fee_bps }.data(),
+    };
+
+    env.send(&[ix], &[signer]).await
+}
+
+async fn stake(env: &mut TestEnv, user: &Keypair, amount: u64) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::Stake {
+            staking_pool: staking_pool(),
+            user_stake: user_stake(&user.pubkey()),
+            user_wallet: user.pubkey(),
+            token_mint: verified_addresses::TOKEN_MINT,
+            user_token_account: donut_ata(&user.pubkey()),
+            staking_vault: staking_vault(),
+            staking_vault_authority: staking_vault_authority(),
+            donut_token_program: spl_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::Stake { amount }.data(),
+    };
+
+    env.send(&[ix], &[user]).await
+}
+
+async fn unstake(env: &mut TestEnv, user: &Keypair, amount: u64) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::Unstake {
+            staking_pool: staking_pool(),
+            user_stake: user_stake(&user.pubkey()),
+            user_wallet: user.pubkey(),
+            token_mint: verified_addresses::TOKEN_MINT,
+            user_token_account: donut_ata(&user.pubkey()),
+            staking_vault: staking_vault(),
+            staking_vault_authority: staking_vault_authority(),
+            donut_token_program: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::Unstake { amount }.data(),
+    };
+
+    env.send(&[ix], &[user]).await
+}
+
+async fn claim(env: &mut TestEnv, user: &Keypair) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::ClaimStakingRewards {
+            staking_pool: staking_pool(),
+            user_stake: user_stake(&user.pubkey()),
+            user_wallet: user.pubkey(),
+            staking_reward_vault: staking_reward_vault(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::Claim {}.data(),
+    };
+
+    env.send(&[ix], &[user]).await
+}
+
+// Environment with the staking pool created at STAKING_FEE_BPS
+async fn staking_env() -> TestEnv {
+    let mut env = TestEnv::new().await;
+    let owner = env.owner.insecure_clone();
+    initialize_staking_pool(&mut env, &owner, STAKING_FEE_BPS).await.unwrap();
+    env
+}
+
+// Funded wallet holding `amount` DONUT, staked
+async fn create_staker(env: &mut TestEnv, amount: u64) -> Keypair {
+    let staker = env.create_wallet();
+    env.set_account(
+        &donut_ata(&staker.pubkey()),
+        token_account(&verified_addresses::TOKEN_MINT, &staker.pubkey(), amount),
+    );
+    stake(env, &staker, amount).await.unwrap();
+    staker
+}
+
+// Fill the root's matrix, paying out its slot-2 reservation
+// Returns the SOL payout before the staking fee
+async fn slot3_payout(env: &mut TestEnv) -> u64 {
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+    let payout = env.user_account(&root).await.reserved_sol;
+
+    let user = env.create_wallet();
+    let mut registration = env.registration(&user.pubkey(), &root, DEPOSIT).await;
+    registration.accounts.staking_pool = Some(staking_pool());
+    registration.accounts.staking_reward_vault = Some(staking_reward_vault());
+    env.send_registration(&user, &registration).await.unwrap();
+
+    payout
+}
+
+async fn pool(env: &mut TestEnv) -> StakingPool {
+    env.anchor_data(&staking_pool()).await
+}
+
+async fn stake_of(env: &mut TestEnv, wallet: &Pubkey) -> UserStake {
+    env.anchor_data(&user_stake(wallet)).await
+}
+
+// ===== CONFIGURATION =====
+
+#[tokio::test]
+async fn initialize_creates_the_vaults_and_sets_the_fee() {
+    let mut env = staking_env().await;
+
+    assert_eq!(env.program_state().await.staking_fee_bps, STAKING_FEE_BPS);
+    assert_eq!(pool(&mut env).await.total_staked, 0);
+    assert_eq!(env.token_balance(&staking_vault()).await, 0);
+    assert_eq!(env.lamports(&staking_reward_vault()).await, Rent::default().minimum_balance(0));
+}
+
+#[tokio::test]
+async fn rejects_invalid_staking_configuration() {
+    let mut env = TestEnv::new().await;
+    let owner = env.owner.insecure_clone();
+    let treasury = env.treasury.insecure_clone();
+
+    let result = initialize_staking_pool(&mut env, &treasury, STAKING_FEE_BPS).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+    let result = initialize_staking_pool(&mut env, &owner, MAX_STAKING_FEE_BPS + 1).await;
+    assert_program_error(result, ErrorCode::InvalidStakingFee);
+
+    initialize_staking_pool(&mut env, &owner, 0).await.unwrap();
+    let result = set_staking_fee(&mut env, &treasury, STAKING_FEE_BPS).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+    let result = set_staking_fee(&mut env, &owner, MAX_STAKING_FEE_BPS + 1).await;
+    assert_program_error(result, ErrorCode::InvalidStakingFee);
+
+    set_staking_fee(&mut env, &owner, MAX_STAKING_FEE_BPS).await.unwrap();
+    assert_eq!(env.program_state().await.staking_fee_bps, MAX_STAKING_FEE_BPS);
+}
+
+// ===== STAKE AND UNSTAKE =====
+
+#[tokio::test]
+async fn stake_and_unstake_move_donut_through_the_vault() {
+    let mut env = staking_env().await;
+    let staker = create_staker(&mut env, 300).await;
+    let wallet = staker.pubkey();
+
+    assert_eq!(env.token_balance(&donut_ata(&wallet)).await, 0);
+    assert_eq!(env.token_balance(&staking_vault()).await, 300);
+    assert_eq!(stake_of(&mut env, &wallet).await.amount, 300);
+    assert_eq!(pool(&mut env).await.total_staked, 300);
+
+    unstake(&mut env, &staker, 100).await.unwrap();
+
+    assert_eq!(env.token_balance(&donut_ata(&wallet)).await, 100);
+    assert_eq!(env.token_balance(&staking_vault()).await, 200);
+    assert_eq!(stake_of(&mut env, &wallet).await.amount, 200);
+    assert_eq!(pool(&mut env).await.total_staked, 200);
+}
+
+#[tokio::test]
+async fn rejects_invalid_stake_amounts() {
+    let mut env = staking_env().await;
+    let staker = create_staker(&mut env, 300).await;
+
+    assert_program_error(stake(&mut env, &staker, 0).await, ErrorCode::InvalidStakeAmount);
+    assert_program_error(unstake(&mut env, &staker, 0).await, ErrorCode::InvalidStakeAmount);
+    assert_program_error(unstake(&mut env, &staker, 301).await, ErrorCode::InvalidStakeAmount);
+    assert_eq!(pool(&mut env).await.total_staked, 300);
+}
+
+// ===== REWARDS =====
+
+#[tokio::test]
+async fn slot3_payout_funds_the_reward_per_share() {
+    let mut env = staking_env().await;
+    let large = create_staker(&mut env, 300).await;
+    let small = create_staker(&mut env, 100).await;
+    let root = env.root.pubkey();
+    let root_lamports = env.lamports(&root).await;
+    let vault_lamports = env.lamports(&staking_reward_vault()).await;
+
+    let payout = slot3_payout(&mut env).await;
+
+    let fee = payout / 10;
+    let reward_per_share = fee as u128 * REWARD_PER_SHARE_SCALE / 400;
+    let staking_pool = pool(&mut env).await;
+    assert_eq!(staking_pool.reward_per_share, reward_per_share);
+    assert_eq!(staking_pool.total_rewards, fee);
+    assert_eq!(env.lamports(&staking_reward_vault()).await, vault_lamports + fee);
+    assert_eq!(env.lamports(&root).await, root_lamports + payout - fee);
+
+    // Each staker claims in proportion to the stake, rounded down
+    for (staker, amount) in [(&large, 300u128), (&small, 100u128)] {
+        let before = env.lamports(&staker.pubkey()).await;
+        claim(&mut env, staker).await.unwrap();
+        let earned = (amount * reward_per_share / REWARD_PER_SHARE_SCALE) as u64;
+        assert_eq!(env.lamports(&staker.pubkey()).await, before + earned);
+        assert_eq!(stake_of(&mut env, &staker.pubkey()).await.pending_rewards, 0);
+    }
+
+    assert_program_error(claim(&mut env, &large).await, ErrorCode::NothingToClaim);
+}
+
+#[tokio::test]
+async fn late_staker_earns_only_later_rewards() {
+    let mut env = staking_env().await;
+    let early = create_staker(&mut env, 100).await;
+    let first = slot3_payout(&mut env).await / 10;
+
+    let late = create_staker(&mut env, 100).await;
+    let second = slot3_payout(&mut env).await / 10;
+
+    // Unstaking settles the rewards, which stay claimable
+    unstake(&mut env, &early, 100).await.unwrap();
+    let first_share = first as u128 * REWARD_PER_SHARE_SCALE / 100;
+    let second_share = second as u128 * REWARD_PER_SHARE_SCALE / 200;
+    let early_earned = (100 * (first_share + second_share) / REWARD_PER_SHARE_SCALE) as u64;
+    assert_eq!(stake_of(&mut env, &early.pubkey()).await.pending_rewards, early_earned);
+
+    let before = env.lamports(&late.pubkey()).await;
+    claim(&mut env, &late).await.unwrap();
+    let late_earned = (100 * second_share / REWARD_PER_SHARE_SCALE) as u64;
+    assert_eq!(env.lamports(&late.pubkey()).await, before + late_earned);
+}
+
+#[tokio::test]
+async fn payout_without_stakers_is_paid_in_full() {
+    let mut env = staking_env().await;
+    let root = env.root.pubkey();
+    let root_lamports = env.lamports(&root).await;
+
+    let payout = slot3_payout(&mut env).await;
+
+    assert_eq!(env.lamports(&root).await, root_lamports + payout);
+    assert_eq!(pool(&mut env).await.reward_per_share, 0);
+}
+
+#[tokio::test]
+async fn rejects_slot3_without_the_staking_accounts() {
+    let mut env = staking_env().await;
+    create_staker(&mut env, 100).await;
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    let user = env.create_wallet();
+    let result = env.register(&user, &root, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::MissingStakingAccounts);
+}
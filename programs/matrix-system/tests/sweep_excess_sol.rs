@@ -0,0 +1,152 @@
+// sweep_excess_sol: treasury withdrawal of the program vault SOL no reservation is owed, and the
+// reserved SOL total reconciled after a state migration
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use common::*;
+use matrix_system::{accounts, instruction, ErrorCode, ProgramState};
+use solana_program_test::BanksClientError;
+use solana_sdk::{
+    instruction::Instruction,
+    rent::Rent,
+    signature::{Keypair, Signer},
+};
+
+// Unreserved SOL added to the program vault
+const EXTRA_SOL: u64 = 1_000_000_000;
+
+async fn sweep(env: &mut TestEnv, signer: &Keypair, amount: u64) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::SweepExcessSol {
+            state: env.state,
+            program_sol_vault: program_sol_vault(),
+            treasury: signer.pubkey(),
+            system_program: solana_sdk::system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::SweepExcessSol { amount }.data(),
+    };
+
+    env.send(&[ix], &[signer]).await
+}
+
+async fn reconcile(env: &mut TestEnv, signer: &Keypair, total_reserved_sol: u64) -> std::result::Result<(), BanksClientError> {
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::ReconcileReservedSol {
+            state: env.state,
+            program_sol_vault: program_sol_vault(),
+            owner: signer.pubkey(),
+        }
+        .to_account_metas(None),
+        data: instruction::ReconcileReservedSol { total_reserved_sol }.data(),
+    };
+
+    env.send(&[ix], &[signer]).await
+}
+
+// Root with a slot-2 reservation, and EXTRA_SOL in the vault that nothing is owed
+// Returns the reserved lamports
+async fn reserve_with_excess(env: &mut TestEnv) -> u64 {
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    env.register_new(&root).await;
+
+    let vault = program_sol_vault();
+    let lamports = env.lamports(&vault).await;
+    env.set_account(&vault, system_account(lamports + EXTRA_SOL));
+
+    let reserved = env.user_account(&root).await.reserved_sol;
+    assert!(reserved > 0);
+    assert_eq!(env.program_state().await.total_reserved_sol, reserved);
+    reserved
+}
+
+// Lamports the treasury may sweep
+async fn excess_sol(env: &mut TestEnv) -> u64 {
+    let reserved = env.program_state().await.total_reserved_sol;
+    env.lamports(&program_sol_vault()).await - reserved - Rent::default().minimum_balance(0)
+}
+
+// The third registration under the root pays out its reservation
+async fn assert_reservation_paid(env: &mut TestEnv) {
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+
+    assert_eq!(env.user_account(&root).await.reserved_sol, 0);
+    assert_eq!(env.program_state().await.total_reserved_sol, 0);
+}
+
+#[tokio::test]
+async fn sweep_leaves_the_reserved_sol() {
+    let mut env = TestEnv::new().await;
+    let reserved = reserve_with_excess(&mut env).await;
+    let treasury = env.treasury.insecure_clone();
+    let treasury_lamports = env.lamports(&treasury.pubkey()).await;
+
+    // The vault keeps its rent-exempt minimum as well
+    let excess = excess_sol(&mut env).await;
+    assert_eq!(excess, EXTRA_SOL - Rent::default().minimum_balance(0));
+    let result = sweep(&mut env, &treasury, excess + 1).await;
+    assert_program_error(result, ErrorCode::InsufficientExcessSol);
+
+    sweep(&mut env, &treasury, excess).await.unwrap();
+    assert_eq!(env.lamports(&program_sol_vault()).await, reserved + Rent::default().minimum_balance(0));
+    assert_eq!(env.lamports(&treasury.pubkey()).await, treasury_lamports + excess);
+
+    let result = sweep(&mut env, &treasury, 1).await;
+    assert_program_error(result, ErrorCode::InsufficientExcessSol);
+
+    assert_reservation_paid(&mut env).await;
+}
+
+#[tokio::test]
+async fn sweep_waits_for_the_reserved_sol_to_be_reconciled() {
+    let mut env = TestEnv::new().await;
+    let reserved = reserve_with_excess(&mut env).await;
+
+    // As left by migrate_state: the reservation is owed, but not counted
+    let mut state = env.program_state().await;
+    state.total_reserved_sol = 0;
+    state.reserved_sol_reconciled = false;
+    let address = env.state;
+    env.set_account(&address, anchor_account(&state, 8 + ProgramState::SIZE));
+
+    let treasury = env.treasury.insecure_clone();
+    let result = sweep(&mut env, &treasury, EXTRA_SOL).await;
+    assert_program_error(result, ErrorCode::ReservedSolNotReconciled);
+
+    let result = reconcile(&mut env, &treasury, reserved).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+
+    let owner = env.owner.insecure_clone();
+    let vault_lamports = env.lamports(&program_sol_vault()).await;
+    let result = reconcile(&mut env, &owner, vault_lamports + 1).await;
+    assert_program_error(result, ErrorCode::ReservedSolInvariantViolated);
+
+    reconcile(&mut env, &owner, reserved).await.unwrap();
+    let state = env.program_state().await;
+    assert!(state.reserved_sol_reconciled);
+    assert_eq!(state.total_reserved_sol, reserved);
+
+    let result = reconcile(&mut env, &owner, 0).await;
+    assert_program_error(result, ErrorCode::ReservedSolAlreadyReconciled);
+
+    // Only the unreserved SOL can be swept, and the reservation is still paid
+    let excess = excess_sol(&mut env).await;
+    sweep(&mut env, &treasury, excess).await.unwrap();
+    assert_eq!(env.lamports(&program_sol_vault()).await, reserved + Rent::default().minimum_balance(0));
+
+    assert_reservation_paid(&mut env).await;
+}
+
+#[tokio::test]
+async fn rejects_sweep_by_other_than_the_treasury() {
+    let mut env = TestEnv::new().await;
+    reserve_with_excess(&mut env).await;
+
+    let owner = env.owner.insecure_clone();
+    let result = sweep(&mut env, &owner, EXTRA_SOL).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+}
@@ -0,0 +1,99 @@
+// token_2022: slot-2 mints and slot-3 payouts with the DONUT mint owned by Token-2022
+mod common;
+
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token_2022::{self, spl_token_2022};
+use spl_token_2022::extension::StateWithExtensions;
+use common::*;
+use matrix_system::{verified_addresses, ErrorCode};
+use solana_sdk::signature::{Keypair, Signer};
+
+// DONUT ATA of a wallet under Token-2022
+fn donut_ata_2022(wallet: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(wallet, &verified_addresses::TOKEN_MINT, &token_2022::ID)
+}
+
+// Balance of a Token-2022 account, which may carry extensions after the base layout
+async fn balance_2022(env: &mut TestEnv, address: &Pubkey) -> u64 {
+    let account = env.account(address).await.unwrap();
+    StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .unwrap()
+        .base
+        .amount
+}
+
+// Hand the DONUT mint and the program token vault over to Token-2022
+// The root registration mints nothing, so both are still empty
+async fn token_2022_env() -> TestEnv {
+    let mut env = TestEnv::new().await;
+
+    let mut mint = env.account(&verified_addresses::TOKEN_MINT).await.unwrap();
+    mint.owner = token_2022::ID;
+    env.set_account(&verified_addresses::TOKEN_MINT, mint);
+
+    let mut vault = token_account(&verified_addresses::TOKEN_MINT, &vault_authority(), 0);
+    vault.owner = token_2022::ID;
+    env.set_account(&donut_ata_2022(&vault_authority()), vault);
+
+    env
+}
+
+async fn register_2022(env: &mut TestEnv, referrer_wallet: &Pubkey) -> Keypair {
+    let user = env.create_wallet();
+    let mut registration = env.registration(&user.pubkey(), referrer_wallet, DEPOSIT).await;
+    registration.accounts.donut_token_program = token_2022::ID;
+    registration.accounts.program_token_vault = donut_ata_2022(&vault_authority());
+    registration.accounts.referrer_token_account = donut_ata_2022(referrer_wallet);
+
+    env.send_registration(&user, &registration).await.unwrap();
+    user
+}
+
+#[tokio::test]
+async fn slot2_mints_through_token_2022() {
+    let mut env = token_2022_env().await;
+    let root = env.root.pubkey();
+    register_2022(&mut env, &root).await;
+    register_2022(&mut env, &root).await;
+
+    let reserved_tokens = env.user_account(&root).await.reserved_tokens;
+    assert_eq!(reserved_tokens, twap_donut_output(DEPOSIT));
+    assert_eq!(balance_2022(&mut env, &donut_ata_2022(&vault_authority())).await, reserved_tokens);
+    assert_eq!(env.mint_supply(&verified_addresses::TOKEN_MINT).await, reserved_tokens);
+}
+
+#[tokio::test]
+async fn slot3_pays_into_a_created_token_2022_ata() {
+    let mut env = token_2022_env().await;
+    let root = env.root.pubkey();
+    register_2022(&mut env, &root).await;
+    register_2022(&mut env, &root).await;
+    let reserved_tokens = env.user_account(&root).await.reserved_tokens;
+
+    register_2022(&mut env, &root).await;
+
+    let root_ata = env.account(&donut_ata_2022(&root)).await.unwrap();
+    assert_eq!(root_ata.owner, token_2022::ID);
+    assert_eq!(balance_2022(&mut env, &donut_ata_2022(&root)).await, reserved_tokens);
+    assert_eq!(balance_2022(&mut env, &donut_ata_2022(&vault_authority())).await, 0);
+    assert_eq!(env.user_account(&root).await.reserved_tokens, 0);
+}
+
+#[tokio::test]
+async fn rejects_token_program_that_does_not_own_the_mint() {
+    let mut env = token_2022_env().await;
+    let root = env.root.pubkey();
+
+    // SPL Token passed for a Token-2022 mint
+    let user = env.create_wallet();
+    let result = env.register(&user, &root, DEPOSIT).await;
+    assert_program_error(result, ErrorCode::InvalidTokenProgram);
+
+    // Token-2022 accounts with the ATAs derived for SPL Token
+    let mut registration = env.registration(&user.pubkey(), &root, DEPOSIT).await;
+    registration.accounts.donut_token_program = token_2022::ID;
+    registration.accounts.program_token_vault = donut_ata_2022(&vault_authority());
+    let result = env.send_registration(&user, &registration).await;
+    assert_program_error(result, ErrorCode::TokenAccountNotCanonical);
+}
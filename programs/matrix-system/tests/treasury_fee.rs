@@ -0,0 +1,98 @@
+// treasury_fee: the basis-point fee skimmed from SOL deposits before the slot logic, and its cap
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use common::*;
+use matrix_system::{
+    accounts, calculate_treasury_fee, instruction, verified_addresses, ErrorCode, MAX_TREASURY_FEE_BPS,
+};
+use solana_sdk::{instruction::Instruction, signature::Signer};
+
+// Registration under the root with the treasury wallet passed for the fee
+async fn register_with_fee(env: &mut TestEnv, deposit_amount: u64) -> Vec<String> {
+    let root = env.root.pubkey();
+    let treasury = env.treasury.pubkey();
+    let user = env.create_wallet();
+
+    let mut registration = env.registration(&user.pubkey(), &root, deposit_amount).await;
+    registration.accounts.treasury_wallet = Some(treasury);
+    let (_, logs) = env.send_profiled(&[registration.instruction()], &[&user]).await.unwrap();
+    logs
+}
+
+#[test]
+fn fee_is_rounded_down_in_favour_of_the_deposit() {
+    assert_eq!(calculate_treasury_fee(DEPOSIT, 0).unwrap(), 0);
+    assert_eq!(calculate_treasury_fee(DEPOSIT, 1).unwrap(), 20_000);
+    assert_eq!(calculate_treasury_fee(DEPOSIT, MAX_TREASURY_FEE_BPS).unwrap(), 10_000_000);
+    assert_eq!(calculate_treasury_fee(19_999, 5).unwrap(), 9);
+    assert_eq!(calculate_treasury_fee(u64::MAX, MAX_TREASURY_FEE_BPS).unwrap(), u64::MAX / 20);
+}
+
+#[tokio::test]
+async fn fee_at_the_cap_goes_to_the_treasury() {
+    let mut env = TestEnv::new().await;
+    env.set_treasury_fee(MAX_TREASURY_FEE_BPS).await.unwrap();
+    let treasury = env.treasury.pubkey();
+    let treasury_lamports = env.lamports(&treasury).await;
+    let vault_before = env.token_balance(&verified_addresses::B_TOKEN_VAULT).await;
+
+    let deposit = 2 * DEPOSIT;
+    let logs = register_with_fee(&mut env, deposit).await;
+
+    let fee = deposit / 20;
+    assert_eq!(env.lamports(&treasury).await, treasury_lamports + fee);
+    assert_eq!(env.token_balance(&verified_addresses::B_TOKEN_VAULT).await, vault_before + deposit - fee);
+
+    let message = format!("Treasury fee: {} of {} lamports ({} bps)", fee, deposit, MAX_TREASURY_FEE_BPS);
+    assert!(logs.iter().any(|log| log.ends_with(&message)));
+}
+
+#[tokio::test]
+async fn slot2_reserves_the_deposit_net_of_the_fee() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.register_new(&root).await;
+    env.set_treasury_fee(100).await.unwrap();
+
+    register_with_fee(&mut env, DEPOSIT).await;
+
+    let net = DEPOSIT - DEPOSIT / 100;
+    let root_account = env.user_account(&root).await;
+    assert_eq!(root_account.reserved_sol, net);
+    assert_eq!(root_account.reserved_tokens, twap_donut_output(net));
+    assert_eq!(env.program_state().await.total_reserved_sol, net);
+}
+
+#[tokio::test]
+async fn rejects_fee_above_the_cap() {
+    let mut env = TestEnv::new().await;
+    env.set_treasury_fee(MAX_TREASURY_FEE_BPS).await.unwrap();
+
+    let result = env.set_treasury_fee(MAX_TREASURY_FEE_BPS + 1).await;
+    assert_program_error(result, ErrorCode::InvalidTreasuryFee);
+    assert_eq!(env.program_state().await.treasury_fee_bps, MAX_TREASURY_FEE_BPS);
+
+    env.set_treasury_fee(0).await.unwrap();
+    assert_eq!(env.program_state().await.treasury_fee_bps, 0);
+}
+
+#[tokio::test]
+async fn rejects_fee_change_by_other_than_the_treasury() {
+    let mut env = TestEnv::new().await;
+    let owner = env.owner.insecure_clone();
+
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::UpdateConfig {
+            state: env.state,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: instruction::SetTreasuryFee { treasury_fee_bps: 100 }.data(),
+    };
+
+    let result = env.send(&[ix], &[&owner]).await;
+    assert_program_error(result, ErrorCode::NotAuthorized);
+    assert_eq!(env.program_state().await.treasury_fee_bps, 0);
+}
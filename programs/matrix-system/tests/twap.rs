@@ -0,0 +1,159 @@
+// twap: the price observation ring buffer and TWAP-only mint pricing, on the host
+// Bank tests of record_price are in record_price.rs; msg! can't run here once a bank has started
+mod common;
+
+use anchor_lang::prelude::*;
+use common::*;
+use matrix_system::{
+    calculate_twap_price, get_donut_tokens_amount, record_price_observation, ErrorCode, PoolReserves, ProgramState,
+    MAX_PRICE_OBSERVATIONS, MIN_TWAP_OBSERVATIONS, PRICE_SCALE,
+};
+
+// Window giving one sample every 100 seconds
+const WINDOW: u32 = 100 * MAX_PRICE_OBSERVATIONS as u32;
+const INTERVAL: i64 = 100;
+
+fn state() -> ProgramState {
+    let mut state = unsampled_program_state(&Pubkey::default(), &Pubkey::default());
+    state.twap_window = WINDOW;
+    state
+}
+
+// Pool holding `price` DONUT per SOL
+fn reserves_at(price: u64) -> PoolReserves {
+    PoolReserves {
+        token_a: price * 1_000,
+        token_b: 1_000,
+        trade_fee_numerator: TRADE_FEE_NUMERATOR,
+        trade_fee_denominator: TRADE_FEE_DENOMINATOR,
+    }
+}
+
+fn scaled(price: u64) -> u128 {
+    price as u128 * PRICE_SCALE
+}
+
+// ===== RING BUFFER =====
+
+#[test]
+fn first_observation_starts_the_buffer() {
+    let mut state = state();
+
+    assert!(record_price_observation(&mut state, &reserves_at(1_000), 500));
+
+    assert_eq!(state.observation_count, 1);
+    assert_eq!(state.observation_index, 0);
+    assert_eq!(state.price_observations[0].timestamp, 500);
+    assert_eq!(state.price_observations[0].price, scaled(1_000));
+    assert_eq!(state.price_observations[0].cumulative_price, 0);
+}
+
+#[test]
+fn observations_within_the_interval_are_skipped() {
+    let mut state = state();
+    record_price_observation(&mut state, &reserves_at(1_000), 0);
+
+    assert!(!record_price_observation(&mut state, &reserves_at(2_000), 0));
+    assert!(!record_price_observation(&mut state, &reserves_at(2_000), INTERVAL - 1));
+    assert!(!record_price_observation(&mut state, &reserves_at(2_000), -INTERVAL));
+    assert_eq!(state.observation_count, 1);
+
+    assert!(record_price_observation(&mut state, &reserves_at(2_000), INTERVAL));
+    assert_eq!(state.observation_count, 2);
+    assert_eq!(state.observation_index, 1);
+}
+
+#[test]
+fn empty_reserves_are_not_recorded() {
+    let mut state = state();
+    let mut reserves = reserves_at(1_000);
+    reserves.token_b = 0;
+
+    assert!(!record_price_observation(&mut state, &reserves, 0));
+    assert_eq!(state.observation_count, 0);
+}
+
+#[test]
+fn ring_buffer_wraps_and_keeps_the_cumulative_price() {
+    let mut state = state();
+    let samples = MAX_PRICE_OBSERVATIONS + 4;
+    for sample in 0..samples {
+        let recorded = record_price_observation(&mut state, &reserves_at(1_000 + sample as u64), sample as i64 * INTERVAL);
+        assert!(recorded);
+    }
+
+    assert_eq!(state.observation_count as usize, MAX_PRICE_OBSERVATIONS);
+    assert_eq!(state.observation_index as usize, (samples - 1) % MAX_PRICE_OBSERVATIONS);
+
+    // The newest samples overwrote the oldest; each cumulative adds the previous price over the interval
+    let oldest = (state.observation_index as usize + 1) % MAX_PRICE_OBSERVATIONS;
+    assert_eq!(state.price_observations[oldest].timestamp, (samples - MAX_PRICE_OBSERVATIONS) as i64 * INTERVAL);
+    for offset in 1..MAX_PRICE_OBSERVATIONS {
+        let previous = state.price_observations[(oldest + offset - 1) % MAX_PRICE_OBSERVATIONS];
+        let current = state.price_observations[(oldest + offset) % MAX_PRICE_OBSERVATIONS];
+
+        assert_eq!(current.timestamp - previous.timestamp, INTERVAL);
+        assert_eq!(current.cumulative_price, previous.cumulative_price + previous.price * INTERVAL as u128);
+    }
+}
+
+// ===== TWAP =====
+
+#[test]
+fn twap_needs_the_minimum_observations() {
+    let mut state = state();
+    for sample in 0..MIN_TWAP_OBSERVATIONS - 1 {
+        record_price_observation(&mut state, &reserves_at(1_000), sample as i64 * INTERVAL);
+    }
+    let now = MIN_TWAP_OBSERVATIONS as i64 * INTERVAL;
+
+    assert_eq!(calculate_twap_price(&state, now), None);
+    assert_program_error_code(get_donut_tokens_amount(Some(&reserves_at(1_000)), &state, DEPOSIT, now), ErrorCode::TwapUnavailable);
+
+    record_price_observation(&mut state, &reserves_at(1_000), now - INTERVAL);
+
+    assert_eq!(calculate_twap_price(&state, now), Some(scaled(1_000)));
+    assert!(get_donut_tokens_amount(Some(&reserves_at(1_000)), &state, DEPOSIT, now).is_ok());
+}
+
+#[test]
+fn twap_weights_prices_by_how_long_they_held() {
+    let mut state = state();
+    state.twap_window = 4 * INTERVAL as u32;
+    for (sample, price) in [1_000, 1_000, 2_000, 2_000].into_iter().enumerate() {
+        record_price_observation(&mut state, &reserves_at(price), sample as i64 * INTERVAL);
+    }
+
+    // 1,000 for two intervals, 2,000 for two (the last one held until now)
+    assert_eq!(calculate_twap_price(&state, 4 * INTERVAL), Some(scaled(1_500)));
+}
+
+#[test]
+fn mint_amount_ignores_the_spot_price() {
+    let mut state = state();
+    for sample in 0..MIN_TWAP_OBSERVATIONS {
+        record_price_observation(&mut state, &reserves_at(1_000), sample as i64 * INTERVAL);
+    }
+    let now = MIN_TWAP_OBSERVATIONS as i64 * INTERVAL;
+
+    // A pool pushed to ten times the DONUT per SOL mints the same amount
+    let at_twap = get_donut_tokens_amount(Some(&reserves_at(1_000)), &state, 1_000_000, now).unwrap();
+    let at_manipulated_spot = get_donut_tokens_amount(Some(&reserves_at(10_000)), &state, 1_000_000, now).unwrap();
+
+    assert_eq!(at_twap, 997_500_000);
+    assert_eq!(at_manipulated_spot, at_twap);
+}
+
+#[test]
+fn dust_and_missing_reserves_are_rejected() {
+    let state = program_state(&Pubkey::default(), &Pubkey::default());
+    let now = MIN_TWAP_OBSERVATIONS as i64 * TWAP_WINDOW as i64;
+
+    assert_program_error_code(get_donut_tokens_amount(None, &state, DEPOSIT, now), ErrorCode::PoolReservesUnavailable);
+    assert_program_error_code(get_donut_tokens_amount(Some(&pool_reserves()), &state, 0, now), ErrorCode::InvalidDonutAmount);
+    assert_eq!(get_donut_tokens_amount(Some(&pool_reserves()), &state, DEPOSIT, now).unwrap(), twap_donut_output(DEPOSIT));
+}
+
+fn assert_program_error_code(result: Result<u64>, expected: ErrorCode) {
+    assert_eq!(result.unwrap_err(), expected.into());
+}
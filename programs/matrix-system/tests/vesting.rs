@@ -0,0 +1,190 @@
+// vesting: each payout vests over its own period, and claims across several payouts
+mod common;
+
+use anchor_lang::{prelude::*, InstructionData};
+use common::*;
+use matrix_system::{accounts, instruction, ErrorCode, VestingAccount, MAX_VESTING_TRANCHES};
+use solana_program_test::BanksClientError;
+use solana_sdk::{instruction::Instruction, signature::Signer};
+
+const DURATION: i64 = 1_000;
+
+// ===== SCHEDULE =====
+
+#[test]
+fn payout_vests_linearly_over_its_period() {
+    let mut vesting = VestingAccount::default();
+    vesting.add_tokens(1_000, 100, DURATION).unwrap();
+
+    assert_eq!(vesting.vested_amount(100), 0);
+    assert_eq!(vesting.vested_amount(350), 250);
+    assert_eq!(vesting.vested_amount(1_100), 1_000);
+    assert_eq!(vesting.vested_amount(5_000), 1_000);
+}
+
+#[test]
+fn later_payout_keeps_the_end_of_earlier_ones() {
+    let mut vesting = VestingAccount::default();
+    vesting.add_tokens(1_000, 0, DURATION).unwrap();
+    vesting.add_tokens(2_000, 500, DURATION).unwrap();
+
+    assert_eq!(vesting.total_amount, 3_000);
+    assert_eq!(vesting.vested_amount(500), 500);
+    assert_eq!(vesting.vested_amount(750), 750 + 500);
+
+    // The first payout is fully vested on its original schedule
+    assert_eq!(vesting.vested_amount(1_000), 1_000 + 1_000);
+    assert_eq!(vesting.vested_amount(1_500), 3_000);
+}
+
+#[test]
+fn zero_duration_unlocks_immediately() {
+    let mut vesting = VestingAccount::default();
+    vesting.add_tokens(1_000, 0, DURATION).unwrap();
+    vesting.add_tokens(400, 500, 0).unwrap();
+
+    assert_eq!(vesting.vested_amount(500), 500 + 400);
+    assert_eq!(vesting.vested_amount(1_000), 1_400);
+}
+
+#[test]
+fn vested_payouts_free_their_tranche() {
+    let mut vesting = VestingAccount::default();
+    for index in 0..(2 * MAX_VESTING_TRANCHES as i64) {
+        vesting.add_tokens(1_000, index * DURATION, DURATION).unwrap();
+    }
+
+    let active = vesting.tranches.iter().filter(|tranche| tranche.amount > 0).count();
+    assert_eq!(active, 1);
+    assert_eq!(vesting.unlocked_amount, (2 * MAX_VESTING_TRANCHES as u64 - 1) * 1_000);
+}
+
+#[test]
+fn full_account_joins_the_tranches_ending_first() {
+    let mut vesting = VestingAccount::default();
+    for index in 0..MAX_VESTING_TRANCHES as i64 {
+        vesting.add_tokens(1_000, 0, DURATION + index).unwrap();
+    }
+    vesting.add_tokens(1_000, 0, 5 * DURATION).unwrap();
+
+    // The payout ending first now ends with the next one; the new payout keeps its own period
+    assert_eq!(vesting.total_amount, (MAX_VESTING_TRANCHES as u64 + 1) * 1_000);
+    assert!(vesting.tranches.iter().all(|tranche| tranche.end_time != DURATION));
+    let joined = vesting.tranches.iter().find(|tranche| tranche.end_time == DURATION + 1).unwrap();
+    assert_eq!(joined.amount, 2_000);
+    let added = vesting.tranches.iter().find(|tranche| tranche.end_time == 5 * DURATION).unwrap();
+    assert_eq!(added.amount, 1_000);
+    assert_eq!(vesting.vested_amount(5 * DURATION), vesting.total_amount);
+}
+
+#[test]
+fn claimable_excludes_claimed_tokens() {
+    let mut vesting = VestingAccount::default();
+    vesting.add_tokens(1_000, 0, DURATION).unwrap();
+    vesting.claimed_amount = 300;
+
+    assert_eq!(vesting.claimable_amount(500), 200);
+    assert_eq!(vesting.claimable_amount(100), 0);
+}
+
+#[test]
+fn total_overflow_is_rejected() {
+    let mut vesting = VestingAccount::default();
+    vesting.add_tokens(u64::MAX, 0, DURATION).unwrap();
+
+    let error = vesting.add_tokens(1, 10, DURATION).unwrap_err();
+    assert_eq!(error, ErrorCode::VestingOverflow.into());
+}
+
+// ===== CLAIMS =====
+
+// Claim the root's vested DONUT at `time`
+async fn claim_at(env: &mut TestEnv, time: i64) -> std::result::Result<(), BanksClientError> {
+    let root = env.root.pubkey();
+    let ix = Instruction {
+        program_id: matrix_system::ID,
+        accounts: accounts::ClaimVested {
+            vesting_account: vesting_pda(&root),
+            user_wallet: root,
+            token_mint: matrix_system::verified_addresses::TOKEN_MINT,
+            program_token_vault: program_token_vault(),
+            user_token_account: donut_ata(&root),
+            vault_authority: vault_authority(),
+            donut_token_program: spl_token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: solana_sdk::system_program::ID,
+        }
+        .to_account_metas(None),
+        data: instruction::ClaimVested {}.data(),
+    };
+
+    // A fresh blockhash keeps repeated claims from being deduplicated; the clock is set after
+    // it, since the new bank recomputes the timestamp
+    env.context.get_new_latest_blockhash().await.unwrap();
+    let now = env.now().await;
+    env.advance_clock(time - now).await;
+
+    let root = env.root.insecure_clone();
+    env.send(&[ix], &[&root]).await
+}
+
+// Fill the root's matrix so its slot-3 payout is credited to the vesting account
+async fn complete_root_matrix(env: &mut TestEnv) {
+    let root = env.root.pubkey();
+    for _ in 0..3 {
+        env.register_new(&root).await;
+    }
+}
+
+// Claim at `time` and check the wallet received what was claimable
+async fn claim_and_check(env: &mut TestEnv, time: i64) -> u64 {
+    let root = env.root.pubkey();
+    let vesting: VestingAccount = env.anchor_data(&vesting_pda(&root)).await;
+    let claimable = vesting.claimable_amount(time);
+    let balance_before = env.token_balance(&donut_ata(&root)).await;
+
+    claim_at(env, time).await.unwrap();
+
+    assert_eq!(env.token_balance(&donut_ata(&root)).await, balance_before + claimable);
+    let vesting: VestingAccount = env.anchor_data(&vesting_pda(&root)).await;
+    assert_eq!(vesting.claimed_amount, balance_before + claimable);
+    claimable
+}
+
+#[tokio::test]
+async fn claims_follow_each_payout_schedule() {
+    let mut env = TestEnv::new().await;
+    let root = env.root.pubkey();
+    env.set_vesting_duration(DURATION).await.unwrap();
+
+    complete_root_matrix(&mut env).await;
+    let first_start = env.now().await;
+    let first_amount = env.anchor_data::<VestingAccount>(&vesting_pda(&root)).await.total_amount;
+
+    env.advance_clock(DURATION / 2).await;
+    env.set_sol_price(SOL_PRICE, 0).await;
+    complete_root_matrix(&mut env).await;
+
+    let vesting: VestingAccount = env.anchor_data(&vesting_pda(&root)).await;
+    let second_amount = vesting.total_amount - first_amount;
+    assert!(second_amount > 0);
+    let active: Vec<_> = vesting.tranches.iter().filter(|tranche| tranche.amount > 0).collect();
+    assert_eq!(active.len(), 2);
+    assert_eq!(active[0].end_time, first_start + DURATION);
+    assert_eq!(active[1].end_time, first_start + DURATION / 2 + DURATION);
+
+    // Half of the first payout has vested
+    let second_start = first_start + DURATION / 2;
+    assert_eq!(claim_and_check(&mut env, second_start).await, first_amount / 2);
+
+    // The first payout ends on its own schedule, half of the second has vested
+    let claimed = claim_and_check(&mut env, first_start + DURATION).await;
+    assert_eq!(claimed, first_amount - first_amount / 2 + second_amount / 2);
+
+    claim_and_check(&mut env, second_start + DURATION).await;
+    assert_eq!(env.token_balance(&donut_ata(&root)).await, first_amount + second_amount);
+    assert_eq!(env.token_balance(&program_token_vault()).await, 0);
+
+    let result = claim_at(&mut env, second_start + DURATION + 1).await;
+    assert_program_error(result, ErrorCode::NothingToClaim);
+}